@@ -44,11 +44,20 @@ Node.prototype.dispatchEvent = function(type) {
 }
 
 Object.defineProperty(Node.prototype, 'innerHTML', {
+    get: function() {
+        return rustInnerHtmlGet(this.handle);
+    },
     set: function(s) {
         rustInnerHtmlSet(this.handle, s.toString());
     }
 });
 
+Object.defineProperty(Node.prototype, 'outerHTML', {
+    get: function() {
+        return rustOuterHtmlGet(this.handle);
+    }
+});
+
 function Event(type) {
     this.type = type
     this.do_default = true;
@@ -0,0 +1,13 @@
+//! Feeds arbitrary bytes to `CssParser::parse` and `CssParser::parse_rules`, which may reject the input but must not panic.
+//!
+//! Run with `cargo fuzz run css_parser` from the repository root.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zipsurf::css_parser::CssParser;
+
+fuzz_target!(|data: &[u8]| {
+    let style = String::from_utf8_lossy(data);
+    let _ = CssParser::new(&style).parse();
+    let _ = CssParser::new(&style).parse_rules();
+});
@@ -0,0 +1,34 @@
+//! Feeds arbitrary bytes to `HtmlParser`, which must produce a tree for any input.
+//!
+//! The first byte picks where the rest of the input is split in two: both halves go
+//! through the streaming `feed` and `end` path, as `Tab::load` drives it, and the whole
+//! input goes through `HtmlParser::parse`.
+//!
+//! Run with `cargo fuzz run html_parser` from the repository root.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zipsurf::html_parser::HtmlParser;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&split, rest)) = data.split_first() else {
+        return;
+    };
+    let body = String::from_utf8_lossy(rest).into_owned();
+    let mut offset = body.len() * split as usize / u8::MAX as usize;
+    while !body.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    let mut parser = HtmlParser::new(String::new());
+    for chunk in [&body[..offset], &body[offset..]] {
+        let mut script = parser.feed(chunk);
+        while script.is_some() {
+            script = parser.feed("");
+        }
+    }
+    while parser.end().is_some() {}
+    assert!(parser.document().is_some());
+
+    HtmlParser::new(body).parse();
+});
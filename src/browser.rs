@@ -5,7 +5,7 @@ use crate::tab::{self, DrawCommand, Tab, TabMessage};
 use crate::url::Url;
 use eframe::emath::Pos2;
 use eframe::epaint::{Color32, Stroke, StrokeKind};
-use egui::{Context, Painter, Ui, Vec2};
+use egui::{Context, Painter, Rect, Ui, Vec2};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -59,6 +59,13 @@ pub struct Browser {
     pub(crate) cookie_jar: Arc<RwLock<HashMap<String, (String, HashMap<String, String>)>>>,
     pub(crate) measure: Arc<std::sync::Mutex<MeasureTime>>,
     active_tab_scroll: f32,
+    /// The window title last sent to the windowing system, to avoid resending it every frame.
+    window_title: String,
+    /// The last pointer position sent to the current tab, relative to the page area,
+    /// or `None` if the pointer was outside it.
+    last_hover: Option<Pos2>,
+    /// The page area size last sent to the current tab, or `None` if none has been sent.
+    last_viewport: Option<Vec2>,
 }
 
 impl Browser {
@@ -72,7 +79,10 @@ impl Browser {
                 focus: None,
                 cookie_jar: cookie_jar.clone(),
                 measure: Arc::new(std::sync::Mutex::new(MeasureTime::new())),
-                active_tab_scroll: 0.0
+                active_tab_scroll: 0.0,
+                window_title: String::new(),
+                last_hover: None,
+                last_viewport: None,
             };
         let browser = Arc::new(RwLock::new(browser_obj));
 
@@ -97,6 +107,8 @@ impl Browser {
         Tab::send_message(tab.clone(), TabMessage::Load { url, body: None });
         self.tabs.push(tab.clone());
         self.current_tab = Some(tab.clone());
+        self.last_hover = None;
+        self.last_viewport = None;
     }
 
     pub fn load_first_tab(&mut self, url: Url) {
@@ -104,7 +116,7 @@ impl Browser {
         self.tabs[0].write().unwrap().tab_height = HEIGHT - self.chrome.borrow().bottom();
     }
 
-    fn setup_custom_fonts(ctx: &egui::Context) {
+    pub(crate) fn setup_custom_fonts(ctx: &egui::Context) {
         let mut fonts = egui::FontDefinitions::default();
 
         fonts.font_data.insert(
@@ -205,6 +217,70 @@ impl Browser {
                     Stroke::new(line.thickness, line.color)
                 );
             }
+            DrawCommand::DrawImage(image) => {
+                painter.image(
+                    image.texture.id(),
+                    image.rect.translate(Vec2::new(0.0, -scroll_y)),
+                    Rect::from_min_max(Pos2::new(0.0, 0.0), Pos2::new(1.0, 1.0)),
+                    Color32::WHITE,
+                );
+            }
+        }
+    }
+
+    /// Sets the window title to the current tab's `<title>`, followed by the browser's name.
+    fn update_window_title(&mut self, ctx: &Context) {
+        let page_title = self.current_tab.as_ref()
+            .and_then(|tab| tab.read().unwrap().title.clone())
+            .filter(|title| !title.is_empty());
+        let window_title = match page_title {
+            Some(title) => format!("{} - ZipSurf Browser", title),
+            None => "ZipSurf Browser".to_string(),
+        };
+        if window_title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(window_title.clone()));
+            self.window_title = window_title;
+        }
+    }
+
+    /// Forwards pointer movement and presses over the page area to the current tab, which
+    /// uses them for `:hover` and `:active`, and shows a pointing hand over links.
+    fn forward_pointer(&mut self, ui: &Ui) {
+        let Some(tab) = self.current_tab.clone() else {
+            return;
+        };
+        let chrome_bottom = self.chrome.borrow().bottom();
+        let hover = ui.input(|i| i.pointer.hover_pos())
+            .filter(|pos| pos.y >= chrome_bottom)
+            .map(|pos| pos - Vec2::new(0.0, chrome_bottom));
+
+        if hover != self.last_hover {
+            self.last_hover = hover;
+            Tab::send_message(tab.clone(), TabMessage::Hover { position: hover });
+        }
+        if let Some(position) = hover && ui.input(|i| i.pointer.primary_pressed()) {
+            Tab::send_message(tab.clone(), TabMessage::PointerDown { position });
+        }
+        if ui.input(|i| i.pointer.primary_released()) {
+            Tab::send_message(tab.clone(), TabMessage::PointerUp);
+        }
+
+        if hover.is_some() && tab.read().unwrap().hovering_link {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+        }
+    }
+
+    /// Tells the current tab when the page area below the chrome changes size, so that
+    /// media queries and viewport units are evaluated against the window.
+    fn forward_viewport(&mut self, ctx: &Context) {
+        let Some(tab) = self.current_tab.clone() else {
+            return;
+        };
+        let rect = ctx.content_rect();
+        let viewport = Vec2::new(rect.width(), rect.height() - self.chrome.borrow().bottom());
+        if self.last_viewport != Some(viewport) {
+            self.last_viewport = Some(viewport);
+            Tab::send_message(tab, TabMessage::Resize { viewport });
         }
     }
 
@@ -253,6 +329,8 @@ impl eframe::App for Browser {
         self.chrome.borrow_mut().init(ui.ctx());
         self.chrome.borrow_mut().draw(ui.ctx(), &*self.tabs, self.current_tab.as_ref());
 
+        self.update_window_title(ui.ctx());
+
         if let Some(tab) = self.current_tab.clone() {
             let mut sync_needed = false;
             let mut has_raf = false;
@@ -288,19 +366,26 @@ impl eframe::App for Browser {
                 
             }
 
+            if ui.input(|i| i.modifiers.command && i.key_pressed(egui::Key::S)) {
+                Tab::send_message(tab.clone(), TabMessage::SavePage);
+            }
+
             if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
                 self.chrome.borrow_mut().on_enter(tab.clone());
                 
             }
         }
 
+        self.forward_pointer(ui);
+        self.forward_viewport(ui.ctx());
+
         if ui.input(|i| i.pointer.primary_clicked()) {
             let pos = ui.input(|i| i.pointer.interact_pos()).unwrap();
 
             if pos.y < self.chrome.borrow().bottom() {
                 self.focus = None;
                 // 1. Get the action and drop the chrome borrow immediately
-                let action = self.chrome.borrow_mut().click(ui, pos, self.tabs.len());
+                let action = self.chrome.borrow_mut().click(pos, self.tabs.len());
 
                 // 2. Now handle the action. 'self' is no longer borrowed!
                 if let Some(action) = action {
@@ -311,6 +396,8 @@ impl eframe::App for Browser {
                         }
                         ChromeAction::SelectTab(index) => {
                             self.current_tab = Some(self.tabs[index].clone());
+                            self.last_hover = None;
+                            self.last_viewport = None;
                             self.active_tab_scroll = self.tabs[index].read().unwrap().scroll_y;
                         }
                         ChromeAction::GoBack => {
@@ -319,6 +406,11 @@ impl eframe::App for Browser {
                             }
                            
                         }
+                        ChromeAction::SavePage => {
+                            if let Some(tab) = &self.current_tab {
+                                Tab::send_message(tab.clone(), TabMessage::SavePage);
+                            }
+                        }
                     }
                 }
             }  else {
@@ -1,10 +1,13 @@
 use crate::layout::WIDTH;
-use crate::tab::{DrawCommand, DrawLine, DrawOutline, DrawRect, DrawText, Tab, TabMessage};
+use crate::tab::{DrawCommand, DrawImage, DrawLine, DrawOutline, DrawRect, DrawText, Tab, TabMessage, FAVICON_SIZE};
 use crate::url::Url;
 use eframe::epaint::{FontFamily, FontId};
-use egui::{Color32, Context, Pos2, Rect};
+use egui::{Color32, Galley, Pos2, Rect, Vec2};
 use std::sync::{Arc, RwLock};
 
+/// The width of each tab in the tab strip. Labels that do not fit are cut short.
+const TAB_WIDTH: f32 = 160.0;
+
 pub struct Chrome {
     font_id: Option<FontId>,
     line_height: f32,
@@ -16,6 +19,7 @@ pub struct Chrome {
     urlbar_top: f32,
     urlbar_bottom: f32,
     back_rect: Rect,
+    save_rect: Rect,
     address_rect: Rect,
     focus: Focus,
     pub(crate) address_bar: String,
@@ -30,11 +34,12 @@ pub enum ChromeAction {
     NewTab,
     SelectTab(usize),
     GoBack,
+    SavePage,
 }
 
 
 impl Chrome {
-    pub fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             font_id: None,
             line_height: 0.0,
@@ -47,6 +52,7 @@ impl Chrome {
             urlbar_bottom: 0.0,
             address_rect: Rect::ZERO,
             back_rect: Rect::ZERO,
+            save_rect: Rect::ZERO,
             focus: Focus::None,
             address_bar: String::new(),
         }
@@ -81,24 +87,58 @@ impl Chrome {
         let back_width = back_galley.size().x + 10.0;
         self.back_rect = Rect::from_two_pos(Pos2::new(self.padding, self.tabbar_bottom + self.padding),
                                            Pos2::new(self.padding + back_width, self.tabbar_bottom + self.padding + self.line_height));
+        let save_galley = ctx.fonts_mut(|f| f.layout("Save".into(),
+                                                     self.font_id.clone().unwrap(), Color32::BLACK, 0.0));
+        let save_width = save_galley.size().x + 10.0;
+        self.save_rect = Rect::from_two_pos(Pos2::new(WIDTH - self.padding - save_width, self.tabbar_bottom + self.padding),
+                                            Pos2::new(WIDTH - self.padding, self.tabbar_bottom + self.padding + self.line_height));
         self.address_rect = Rect::from_two_pos(Pos2::new(self.newtab_rect.right() + self.padding, self.tabbar_bottom
                                                           + self.padding),
-                                             Pos2::new(WIDTH - self.padding, self.tabbar_bottom + self.padding + self.line_height));
+                                             Pos2::new(self.save_rect.left() - self.padding, self.tabbar_bottom + self.padding + self.line_height));
 
 
 
     }
 
-    fn tab_rect(&self, ctx: &egui::Context, i: usize) -> Rect {
+    fn tab_rect(&self, i: usize) -> Rect {
         let tabs_start = self.newtab_rect.right() + self.padding;
-        let text_width = ctx.fonts_mut(|f| f.layout_no_wrap("Tab X".into(),
-                                                    self.font_id.clone().unwrap(), Color32::BLACK)).size().x;
-        let tab_width = text_width + 10.0;
+        let tab_width = TAB_WIDTH;
         let tab_rect = Rect::from_two_pos(Pos2::new(tabs_start + i as f32 * tab_width, self.tabbar_top),
                                           Pos2::new(tabs_start + (i + 1) as f32 * tab_width, self.tabbar_bottom));
         tab_rect
     }
 
+    /// Lays out `text` on one line, cutting it short with an ellipsis so that it fits
+    /// in `max_width`. Used for tab labels and the address bar, since page titles and
+    /// URLs can be arbitrarily long.
+    ///
+    /// The cut point is found by binary search over the number of characters kept, so
+    /// only a logarithmic number of layouts is needed.
+    fn truncated_galley(&self, ctx: &egui::Context, text: &str, max_width: f32, color: Color32) -> Arc<Galley> {
+        let font_id = self.font_id.clone().unwrap();
+        let layout = |text: String| ctx.fonts_mut(|f| f.layout_no_wrap(text, font_id.clone(), color));
+        let shortened = |kept: usize| {
+            let end = text.char_indices().nth(kept).map_or(text.len(), |(i, _)| i);
+            layout(format!("{}…", text[..end].trim_end()))
+        };
+
+        let galley = layout(text.to_string());
+        if galley.size().x <= max_width {
+            return galley;
+        }
+        // Invariant: keeping `low` characters fits (or `low` is 0), keeping `high` does not.
+        let (mut low, mut high) = (0, text.chars().count());
+        while high - low > 1 {
+            let middle = low + (high - low) / 2;
+            if shortened(middle).size().x <= max_width {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        shortened(low)
+    }
+
     pub fn draw(&mut self, ctx: &egui::Context, tabs: &[Arc<RwLock<Tab>>], current_tab: Option<&Arc<RwLock<Tab>>>) {
         self.draw_commands.clear();
         // Chrome-specific drawing logic would go here,
@@ -134,7 +174,7 @@ impl Chrome {
 
         // Draw each tab
         for (i, tab_rc) in tabs.iter().enumerate() {
-            let bounds= self.tab_rect(ctx, i);
+            let bounds= self.tab_rect(i);
             self.draw_commands.push(DrawCommand::DrawLine(
                 DrawLine {
                     from: bounds.left_top(),
@@ -152,13 +192,36 @@ impl Chrome {
                 }
             ));
 
+            let (label, favicon) = {
+                let tab = tab_rc.read().unwrap();
+                let label = match (&tab.title, &tab.url) {
+                    (Some(title), _) if !title.is_empty() => title.clone(),
+                    (_, Some(url)) => url.to_string(),
+                    _ => format!("Tab {:?}", i),
+                };
+                (label, tab.favicon.clone())
+            };
+
+            let mut label_left = bounds.left() + self.padding;
+            if let Some(texture) = favicon {
+                let icon_top = bounds.center().y - FAVICON_SIZE as f32 / 2.0;
+                self.draw_commands.push(DrawCommand::DrawImage(
+                    DrawImage {
+                        rect: Rect::from_min_size(Pos2::new(label_left, icon_top),
+                                                  Vec2::splat(FAVICON_SIZE as f32)),
+                        texture,
+                    }
+                ));
+                label_left += FAVICON_SIZE as f32 + self.padding;
+            }
+
+            let max_width = bounds.right() - self.padding - label_left;
             self.draw_commands.push(
                 DrawCommand::DrawText(
                     DrawText {
-                        x: bounds.left() + self.padding,
+                        x: label_left,
                         y: bounds.top() + self.padding,
-                        galley: ctx.fonts_mut(|f| f.layout_no_wrap(format!("Tab {:?}", i).into(),
-                                                           self.font_id.clone().unwrap(), Color32::BLACK))
+                        galley: self.truncated_galley(ctx, &label, max_width, Color32::BLACK),
                     }
                 )
             );
@@ -204,6 +267,23 @@ impl Chrome {
             }
         ));
 
+        self.draw_commands.push(DrawCommand::DrawOutline(
+            DrawOutline{
+                rect: self.save_rect,
+                color: Color32::BLACK,
+                thickness: 1.0,
+            }
+        ));
+
+        self.draw_commands.push(DrawCommand::DrawText(
+            DrawText {
+                x: self.save_rect.left() + self.padding,
+                y: self.save_rect.top(),
+                galley: ctx.fonts_mut(|f| f.layout_no_wrap("Save".into(),
+                                                           self.font_id.clone().unwrap(), Color32::BLACK))
+            }
+        ));
+
         self.draw_commands.push(DrawCommand::DrawOutline(
             DrawOutline{
                 rect: self.address_rect,
@@ -214,8 +294,11 @@ impl Chrome {
         ));
 
         let mut url: String = "".into();
+        let mut status = None;
         if let Some(current_tab) = current_tab {
-            url = current_tab.read().unwrap().url.clone().unwrap().to_string();
+            let tab = current_tab.read().unwrap();
+            url = tab.url.clone().unwrap().to_string();
+            status = tab.status.clone();
         }
 
         if self.focus == Focus::AddressBar {
@@ -239,12 +322,25 @@ impl Chrome {
                 }
             ))
         } else {
+            // The status of the last action, such as saving the page, is shown at the
+            // right end of the address bar, and the URL is cut short to make room for it.
+            let mut url_width = self.address_rect.width() - 2.0 * self.padding;
+            if let Some(status) = status {
+                let galley = self.truncated_galley(ctx, &status, url_width / 2.0, Color32::DARK_GRAY);
+                url_width -= galley.size().x + self.padding;
+                self.draw_commands.push(DrawCommand::DrawText(
+                    DrawText {
+                        x: self.address_rect.right() - self.padding - galley.size().x,
+                        y: self.address_rect.top(),
+                        galley,
+                    }
+                ));
+            }
             self.draw_commands.push(DrawCommand::DrawText(
                 DrawText {
                     x: self.address_rect.left() + self.padding,
                     y: self.address_rect.top(),
-                    galley: ctx.fonts_mut(|f| f.layout_no_wrap(url.parse().unwrap(),
-                                                               self.font_id.clone().unwrap(), Color32::BLACK))
+                    galley: self.truncated_galley(ctx, &url, url_width, Color32::BLACK),
                 }
             ))
         }
@@ -262,7 +358,7 @@ impl Chrome {
 
     }
 
-    pub fn click(&mut self, ctx: &Context, pos: Pos2, tab_count: usize) -> Option<ChromeAction> {
+    pub fn click(&mut self, pos: Pos2, tab_count: usize) -> Option<ChromeAction> {
         self.focus = Focus::None;
         if self.newtab_rect.contains(pos) {
             return Some(ChromeAction::NewTab);
@@ -271,6 +367,10 @@ impl Chrome {
         {
             return Some(ChromeAction::GoBack);
         }
+        if self.save_rect.contains(pos)
+        {
+            return Some(ChromeAction::SavePage);
+        }
         if self.address_rect.contains(pos) {
             self.focus = Focus::AddressBar;
             self.address_bar.clear();
@@ -278,7 +378,7 @@ impl Chrome {
 
         // Check if any existing tab was clicked
         for i in 0..tab_count {
-            if self.tab_rect(ctx, i).contains(pos) {
+            if self.tab_rect(i).contains(pos) {
                 return Some(ChromeAction::SelectTab(i));
             }
         }
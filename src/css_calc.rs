@@ -0,0 +1,314 @@
+//! The CSS math functions `calc()`, `min()`, `max()` and `clamp()`.
+//!
+//! An expression may mix units, as in `calc(100% - 2em)`. Styling computes the absolute
+//! and font-relative parts to pixels (`CalcExpr::map_lengths`), and the percentages are
+//! resolved later, once the size they refer to is known (`CalcExpr::evaluate`).
+//!
+//! Expressions are parsed from the tokens of `css_tokenizer`, so numbers, units and
+//! escapes are read exactly as in the rest of the style sheet.
+
+use crate::css_tokenizer::{tokenize, Token};
+use crate::style::Length;
+
+/// How deeply parentheses and math functions may nest in an expression.
+const MAX_NESTING_DEPTH: usize = 32;
+
+/// How many operators an expression may have. Each one nests the expression before it,
+/// so this bounds the depth of the `CalcExpr` tree along with `MAX_NESTING_DEPTH`.
+const MAX_OPERATORS: usize = 256;
+
+/// A parsed math function.
+///
+/// # Variants
+/// - `Number`: A plain number, as in the `2` of `calc(2 * 1em)`.
+/// - `Length`: A length or percentage.
+/// - `Sum`, `Difference`, `Product`, `Quotient`: The four operators.
+/// - `Min`, `Max`: `min()` and `max()` with their arguments.
+/// - `Clamp`: `clamp(minimum, value, maximum)`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum CalcExpr {
+    Number(f32),
+    Length(Length),
+    Sum(Box<CalcExpr>, Box<CalcExpr>),
+    Difference(Box<CalcExpr>, Box<CalcExpr>),
+    Product(Box<CalcExpr>, Box<CalcExpr>),
+    Quotient(Box<CalcExpr>, Box<CalcExpr>),
+    Min(Vec<CalcExpr>),
+    Max(Vec<CalcExpr>),
+    Clamp(Box<CalcExpr>, Box<CalcExpr>, Box<CalcExpr>),
+}
+
+/// Whether an expression is a plain number or a length. The operators only accept
+/// certain combinations, e.g. a length can be multiplied by a number but not by a length.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CalcType {
+    Number,
+    Length,
+}
+
+impl CalcExpr {
+    /// Parses a value that is a single `calc()`, `min()`, `max()` or `clamp()` function
+    /// whose result is a length or percentage.
+    ///
+    /// # Returns
+    /// - `Some(CalcExpr)` if the value is a valid length expression.
+    /// - `None` if it is not a math function, is malformed, or has a type error such as
+    ///   `calc(1px * 2px)`, `calc(1px + 2)` or a plain number result. An expression
+    ///   nested deeper than `MAX_NESTING_DEPTH`, or with more than `MAX_OPERATORS`
+    ///   operators, is malformed.
+    ///
+    /// # Notes
+    /// As in CSS, `+` and `-` need whitespace around them: `calc(1px -2px)` is two lengths
+    /// in a row, and so is invalid.
+    ///
+    /// # Example
+    /// ```
+    /// let expr = CalcExpr::parse("calc(100% - 2 * 8px)").unwrap();
+    /// ```
+    pub(crate) fn parse(value: &str) -> Option<CalcExpr> {
+        // Whitespace only matters to the tokenizer, which reads `-2px` as a number.
+        let tokens = tokenize(value).into_iter().filter(|token| *token != Token::Whitespace).collect();
+        let mut parser = CalcParser { tokens, index: 0, depth: 0, operators: 0 };
+        let (expr, kind) = match parser.tokens.first()? {
+            Token::Function(_) => parser.value()?,
+            _ => return None,
+        };
+        (parser.index == parser.tokens.len() && kind == CalcType::Length).then_some(expr)
+    }
+
+    /// Returns whether `value` starts with one of the math functions.
+    pub(crate) fn is_math_function(value: &str) -> bool {
+        let value = value.trim_start().to_ascii_lowercase();
+        ["calc(", "min(", "max(", "clamp("].iter().any(|f| value.starts_with(f))
+    }
+
+    /// Returns a copy of the expression with every length replaced by `f(length)`, e.g.
+    /// to compute `em` to pixels while keeping percentages.
+    pub(crate) fn map_lengths(&self, f: &impl Fn(Length) -> Length) -> CalcExpr {
+        let map = |e: &CalcExpr| Box::new(e.map_lengths(f));
+        match self {
+            CalcExpr::Number(n) => CalcExpr::Number(*n),
+            CalcExpr::Length(l) => CalcExpr::Length(f(*l)),
+            CalcExpr::Sum(a, b) => CalcExpr::Sum(map(a), map(b)),
+            CalcExpr::Difference(a, b) => CalcExpr::Difference(map(a), map(b)),
+            CalcExpr::Product(a, b) => CalcExpr::Product(map(a), map(b)),
+            CalcExpr::Quotient(a, b) => CalcExpr::Quotient(map(a), map(b)),
+            CalcExpr::Min(args) => CalcExpr::Min(args.iter().map(|a| a.map_lengths(f)).collect()),
+            CalcExpr::Max(args) => CalcExpr::Max(args.iter().map(|a| a.map_lengths(f)).collect()),
+            CalcExpr::Clamp(min, value, max) => CalcExpr::Clamp(map(min), map(value), map(max)),
+        }
+    }
+
+    /// Returns whether any length in the expression is a percentage.
+    pub(crate) fn has_percentage(&self) -> bool {
+        match self {
+            CalcExpr::Number(_) => false,
+            CalcExpr::Length(l) => matches!(l, Length::Percent(_)),
+            CalcExpr::Sum(a, b) | CalcExpr::Difference(a, b) | CalcExpr::Product(a, b) | CalcExpr::Quotient(a, b) => {
+                a.has_percentage() || b.has_percentage()
+            }
+            CalcExpr::Min(args) | CalcExpr::Max(args) => args.iter().any(CalcExpr::has_percentage),
+            CalcExpr::Clamp(min, value, max) => min.has_percentage() || value.has_percentage() || max.has_percentage(),
+        }
+    }
+
+    /// Evaluates the expression, with `length_px` converting each length to pixels.
+    ///
+    /// # Notes
+    /// Division by zero gives an infinite or NaN result; callers should check that the
+    /// result is finite. As in CSS, `clamp()` lets the minimum win over the maximum.
+    pub(crate) fn evaluate(&self, length_px: &impl Fn(Length) -> f32) -> f32 {
+        let eval = |e: &CalcExpr| e.evaluate(length_px);
+        match self {
+            CalcExpr::Number(n) => *n,
+            CalcExpr::Length(l) => length_px(*l),
+            CalcExpr::Sum(a, b) => eval(a) + eval(b),
+            CalcExpr::Difference(a, b) => eval(a) - eval(b),
+            CalcExpr::Product(a, b) => eval(a) * eval(b),
+            CalcExpr::Quotient(a, b) => eval(a) / eval(b),
+            CalcExpr::Min(args) => args.iter().map(eval).fold(f32::INFINITY, f32::min),
+            CalcExpr::Max(args) => args.iter().map(eval).fold(f32::NEG_INFINITY, f32::max),
+            CalcExpr::Clamp(min, value, max) => eval(value).min(eval(max)).max(eval(min)),
+        }
+    }
+}
+
+/// A recursive-descent parser over the tokens of a math expression.
+///
+/// # Fields
+/// - `tokens`: The tokens of the expression, without whitespace.
+/// - `index`: The position of the next token.
+/// - `depth`: How many parentheses and functions enclose the position.
+/// - `operators`: How many operators have been read.
+struct CalcParser {
+    tokens: Vec<Token>,
+    index: usize,
+    depth: usize,
+    operators: usize,
+}
+
+impl CalcParser {
+    /// Returns the next token without consuming it.
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index)
+    }
+
+    /// Consumes the next token if it is the punctuation or delimiter `c`.
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek().is_some_and(|token| token.is_char(c)) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parses `product (('+' | '-') product)*`. Both sides must have the same type.
+    fn sum(&mut self) -> Option<(CalcExpr, CalcType)> {
+        let (mut expr, kind) = self.product()?;
+        loop {
+            let plus = match self.peek() {
+                Some(Token::Delim('+')) => true,
+                Some(Token::Delim('-')) => false,
+                _ => return Some((expr, kind)),
+            };
+            self.operator()?;
+            let (rhs, rhs_kind) = self.product()?;
+            if rhs_kind != kind {
+                return None;
+            }
+            expr = if plus {
+                CalcExpr::Sum(Box::new(expr), Box::new(rhs))
+            } else {
+                CalcExpr::Difference(Box::new(expr), Box::new(rhs))
+            };
+        }
+    }
+
+    /// Parses `value (('*' | '/') value)*`. At most one side of a product may be a
+    /// length, and the divisor must be a number.
+    fn product(&mut self) -> Option<(CalcExpr, CalcType)> {
+        let (mut expr, mut kind) = self.value()?;
+        loop {
+            let times = match self.peek() {
+                Some(Token::Delim('*')) => true,
+                Some(Token::Delim('/')) => false,
+                _ => return Some((expr, kind)),
+            };
+            self.operator()?;
+            let (rhs, rhs_kind) = self.value()?;
+            if rhs_kind == CalcType::Length && (kind == CalcType::Length || !times) {
+                return None;
+            }
+            if rhs_kind == CalcType::Length {
+                kind = CalcType::Length;
+            }
+            expr = if times {
+                CalcExpr::Product(Box::new(expr), Box::new(rhs))
+            } else {
+                CalcExpr::Quotient(Box::new(expr), Box::new(rhs))
+            };
+        }
+    }
+
+    /// Consumes an operator, or returns `None` past `MAX_OPERATORS`.
+    fn operator(&mut self) -> Option<()> {
+        self.index += 1;
+        self.operators += 1;
+        (self.operators <= MAX_OPERATORS).then_some(())
+    }
+
+    /// Parses a number, a length, a parenthesized sum or a math function.
+    fn value(&mut self) -> Option<(CalcExpr, CalcType)> {
+        let token = self.peek()?.clone();
+        self.index += 1;
+        match token {
+            Token::Number { value, .. } if value.is_finite() => Some((CalcExpr::Number(value), CalcType::Number)),
+            Token::Percentage { value, .. } if value.is_finite() => Some((CalcExpr::Length(Length::Percent(value)), CalcType::Length)),
+            Token::Dimension { value, unit, .. } => Some((CalcExpr::Length(Length::with_unit(value, &unit)?), CalcType::Length)),
+            Token::OpenParen | Token::Function(_) if self.depth == MAX_NESTING_DEPTH => None,
+            Token::OpenParen => {
+                self.depth += 1;
+                let result = self.sum()?;
+                self.depth -= 1;
+                self.eat(')').then_some(result)
+            }
+            Token::Function(name) => {
+                let name = name.to_ascii_lowercase();
+                self.depth += 1;
+                let mut args = vec![self.sum()?];
+                while self.eat(',') {
+                    args.push(self.sum()?);
+                }
+                self.depth -= 1;
+                if !self.eat(')') {
+                    return None;
+                }
+                let kind = args[0].1;
+                if args.iter().any(|(_, k)| *k != kind) {
+                    return None;
+                }
+                let mut exprs = args.into_iter().map(|(e, _)| e);
+                let expr = match (name.as_str(), exprs.len()) {
+                    ("calc", 1) => exprs.next()?,
+                    ("min", _) => CalcExpr::Min(exprs.collect()),
+                    ("max", _) => CalcExpr::Max(exprs.collect()),
+                    ("clamp", 3) => CalcExpr::Clamp(Box::new(exprs.next()?), Box::new(exprs.next()?), Box::new(exprs.next()?)),
+                    _ => return None,
+                };
+                Some((expr, kind))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `value` and evaluates it with `1em` as `10px` and `100%` as `200px`.
+    fn evaluated(value: &str) -> Option<f32> {
+        let expr = CalcExpr::parse(value)?;
+        Some(expr.evaluate(&|length| match length {
+            Length::Em(em) => em * 10.0,
+            Length::Percent(percent) => percent * 2.0,
+            Length::Px(px) => px,
+            _ => f32::NAN,
+        }))
+    }
+
+    #[test]
+    fn operators_follow_precedence_and_types() {
+        assert_eq!(evaluated("calc(100% - 2 * 8px)"), Some(184.0));
+        assert_eq!(evaluated("CALC((1em + 2px) / 2)"), Some(6.0));
+        assert_eq!(evaluated("min(1em, 50%, max(3px, 4px))"), Some(4.0));
+        assert_eq!(evaluated("clamp(5px, 1px, 2px)"), Some(5.0));
+        for invalid in ["calc(1px * 2px)", "calc(1px + 2)", "calc(2 / 1px)", "calc(2 * 3)", "clamp(1px, 2px)", "calc(1px", "var(--a)"] {
+            assert_eq!(evaluated(invalid), None, "{} should be invalid", invalid);
+        }
+    }
+
+    #[test]
+    fn numbers_are_read_by_the_css_tokenizer() {
+        assert_eq!(evaluated("calc(1e1px + 2E-1em)"), Some(12.0));
+        assert_eq!(evaluated("calc(1p\\78  * 3)"), Some(3.0));
+        assert_eq!(evaluated("calc(1px - -2px)"), Some(3.0));
+        // `+` and `-` need whitespace around them.
+        assert_eq!(evaluated("calc(1px -2px)"), None);
+        assert_eq!(evaluated("calc(1px+2px)"), None);
+        assert_eq!(evaluated("calc(1e39px)"), None);
+    }
+
+    #[test]
+    fn deep_or_long_expressions_are_invalid() {
+        let nested = |depth: usize| format!("calc({}1px{})", "(".repeat(depth - 1), ")".repeat(depth - 1));
+        assert_eq!(evaluated(&nested(MAX_NESTING_DEPTH)), Some(1.0));
+        assert_eq!(evaluated(&nested(MAX_NESTING_DEPTH + 1)), None);
+        assert_eq!(evaluated(&nested(100_000)), None);
+
+        let long = |operators: usize| format!("calc(0px{})", " + 1px".repeat(operators));
+        assert_eq!(evaluated(&long(MAX_OPERATORS)), Some(MAX_OPERATORS as f32));
+        assert_eq!(evaluated(&long(100_000)), None);
+    }
+}
@@ -0,0 +1,436 @@
+//! The values of the CSS grid properties: track lists such as
+//! `200px repeat(auto-fill, minmax(120px, 1fr))`, the named areas of
+//! `grid-template-areas`, and the lines of `grid-row-start` and its siblings.
+//!
+//! Values are parsed from the tokens of `css_tokenizer`, with their lengths computed
+//! like those of any other property. The grid formatting context that uses them is in
+//! `layout::grid`.
+
+use crate::css_tokenizer::{Token, serialize, tokenize};
+use crate::style::{LengthContext, LengthPercentage};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// The most tracks a grid has along each axis. Larger line numbers, spans and repeat
+/// counts are clamped to it, as CSS Grid Layout allows, so that no page can make a
+/// grid with billions of tracks.
+pub(crate) const MAX_TRACKS: usize = 1000;
+
+/// A minimum or maximum size of a grid track.
+///
+/// # Variants
+/// - `Length`: A length, or a percentage of the grid container's content box.
+/// - `Fr`: A share of the space left once the other tracks are sized. Only valid as a
+///   maximum.
+/// - `Auto`: As a minimum, the min-content size of the track's items. As a maximum,
+///   their max-content size, past which the track may still stretch.
+/// - `MinContent`, `MaxContent`: The largest min-content or max-content size of the
+///   track's items.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum TrackBreadth {
+    Length(LengthPercentage),
+    Fr(f32),
+    Auto,
+    MinContent,
+    MaxContent,
+}
+
+/// The size of a grid track: a minimum and a maximum, as in `minmax()`. A single size
+/// is both, except `<n>fr`, which is `minmax(auto, <n>fr)`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TrackSize {
+    pub(crate) min: TrackBreadth,
+    pub(crate) max: TrackBreadth,
+}
+
+impl TrackSize {
+    /// The size of `auto` tracks, such as the implicit tracks by default.
+    pub(crate) const AUTO: TrackSize = TrackSize { min: TrackBreadth::Auto, max: TrackBreadth::Auto };
+}
+
+/// A track list, as in `grid-template-columns`. Line names are ignored.
+///
+/// # Fields
+/// - `tracks`: The tracks, with the integer `repeat()`s expanded.
+/// - `auto_repeat`: A `repeat(auto-fill, ...)` or `repeat(auto-fit, ...)` among them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct TrackList {
+    pub(crate) tracks: Vec<TrackSize>,
+    pub(crate) auto_repeat: Option<AutoRepeat>,
+}
+
+/// A `repeat()` whose count depends on the size of the grid container.
+///
+/// # Fields
+/// - `position`: Where in `TrackList::tracks` the repetitions go.
+/// - `tracks`: The tracks repeated.
+/// - `fit`: Whether it is `auto-fit`, whose repetitions collapse when no item is placed
+///   in them, rather than `auto-fill`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct AutoRepeat {
+    pub(crate) position: usize,
+    pub(crate) tracks: Vec<TrackSize>,
+    pub(crate) fit: bool,
+}
+
+impl TrackList {
+    /// Parses a track list: `none`, or track sizes and `repeat()`s, with at most one
+    /// `repeat(auto-fill, ...)` or `repeat(auto-fit, ...)`, whose tracks must then have
+    /// a fixed minimum or maximum.
+    ///
+    /// # Parameters
+    /// - `value`: The declared value.
+    /// - `context`: What relative lengths such as `em` refer to.
+    ///
+    /// # Returns
+    /// The track list, or `None` if `value` is invalid.
+    pub(crate) fn parse(value: &str, context: &LengthContext) -> Option<TrackList> {
+        if value.trim().eq_ignore_ascii_case("none") {
+            return Some(TrackList::default());
+        }
+        let mut parser = TrackParser::new(value, context);
+        let mut list = TrackList::default();
+        while let Some(token) = parser.peek() {
+            match token {
+                Token::Function(name) if name.eq_ignore_ascii_case("repeat") => {
+                    parser.index += 1;
+                    let (count, fit) = match parser.next()? {
+                        Token::Number { value, .. } if value >= 1.0 && value.fract() == 0.0 => (Some((value as usize).min(MAX_TRACKS)), false),
+                        Token::Ident(ident) if ident.eq_ignore_ascii_case("auto-fill") => (None, false),
+                        Token::Ident(ident) if ident.eq_ignore_ascii_case("auto-fit") => (None, true),
+                        _ => return None,
+                    };
+                    if parser.next()? != Token::Comma {
+                        return None;
+                    }
+                    let mut tracks = vec![];
+                    while parser.peek()? != Token::CloseParen {
+                        tracks.push(parser.track_size()?);
+                    }
+                    parser.index += 1;
+                    if tracks.is_empty() {
+                        return None;
+                    }
+                    match count {
+                        Some(count) => {
+                            for _ in 0..count {
+                                if list.tracks.len() >= MAX_TRACKS {
+                                    break;
+                                }
+                                list.tracks.extend(tracks.iter().cloned());
+                            }
+                        }
+                        None => {
+                            let fixed = |track: &TrackSize| matches!(track.min, TrackBreadth::Length(_))
+                                || matches!(track.max, TrackBreadth::Length(_));
+                            if list.auto_repeat.is_some() || !tracks.iter().all(fixed) {
+                                return None;
+                            }
+                            list.auto_repeat = Some(AutoRepeat { position: list.tracks.len(), tracks, fit });
+                        }
+                    }
+                }
+                _ => list.tracks.push(parser.track_size()?),
+            }
+        }
+        list.tracks.truncate(MAX_TRACKS);
+        if let Some(repeat) = &mut list.auto_repeat {
+            repeat.position = repeat.position.min(MAX_TRACKS);
+        }
+        (!list.tracks.is_empty() || list.auto_repeat.is_some()).then_some(list)
+    }
+
+    /// Returns the tracks of the list for a container of this size, with the automatic
+    /// repetition repeated as many times as fits, within `MAX_TRACKS`.
+    ///
+    /// # Parameters
+    /// - `available`: The size of the container's content box along the tracks' axis, or
+    ///   `None` if it is not known, in which case the repetition is used once.
+    /// - `gap`: The gap between tracks.
+    ///
+    /// # Returns
+    /// The tracks, and the positions of the repeated ones among them.
+    pub(crate) fn expand(&self, available: Option<f32>, gap: f32) -> (Vec<TrackSize>, Range<usize>) {
+        let Some(repeat) = &self.auto_repeat else {
+            return (self.tracks.clone(), 0..0);
+        };
+        // A track counts at its fixed maximum, or else at its fixed minimum.
+        let fixed_size = |track: &TrackSize, base: f32| match (&track.max, &track.min) {
+            (TrackBreadth::Length(length), TrackBreadth::Length(min)) => length.resolve(base).max(min.resolve(base)),
+            (TrackBreadth::Length(length), _) | (_, TrackBreadth::Length(length)) => length.resolve(base),
+            _ => 0.0,
+        };
+        let count = match available {
+            Some(available) => {
+                let others: f32 = self.tracks.iter().map(|track| fixed_size(track, available) + gap).sum();
+                let repeated: f32 = repeat.tracks.iter().map(|track| fixed_size(track, available) + gap).sum();
+                if repeated > 0.0 {
+                    (((available + gap - others) / repeated).floor() as usize).clamp(1, (MAX_TRACKS / repeat.tracks.len()).max(1))
+                } else {
+                    1
+                }
+            }
+            None => 1,
+        };
+        let mut tracks = self.tracks[..repeat.position].to_vec();
+        for _ in 0..count {
+            tracks.extend(repeat.tracks.iter().cloned());
+        }
+        tracks.extend(self.tracks[repeat.position..].iter().cloned());
+        (tracks, repeat.position..repeat.position + count * repeat.tracks.len())
+    }
+}
+
+/// Parses the track sizes of a `grid-auto-columns` or `grid-auto-rows`, which the
+/// implicit tracks cycle through.
+pub(crate) fn parse_auto_tracks(value: &str, context: &LengthContext) -> Option<Vec<TrackSize>> {
+    let mut parser = TrackParser::new(value, context);
+    let mut tracks = vec![];
+    while parser.peek().is_some() {
+        tracks.push(parser.track_size()?);
+    }
+    (!tracks.is_empty()).then_some(tracks)
+}
+
+/// The state of parsing a track list: its tokens without whitespace, and the position
+/// in them.
+struct TrackParser<'a> {
+    tokens: Vec<Token>,
+    index: usize,
+    context: &'a LengthContext,
+}
+
+impl<'a> TrackParser<'a> {
+    /// Tokenizes `value`, dropping whitespace, and line names in brackets.
+    fn new(value: &str, context: &'a LengthContext) -> Self {
+        let mut tokens = vec![];
+        let mut in_names = false;
+        for token in tokenize(value) {
+            match token {
+                Token::OpenSquare => in_names = true,
+                Token::CloseSquare => in_names = false,
+                Token::Whitespace => {}
+                _ if in_names => {}
+                token => tokens.push(token),
+            }
+        }
+        TrackParser { tokens, index: 0, context }
+    }
+
+    /// Returns the next token without consuming it.
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.index).cloned()
+    }
+
+    /// Consumes and returns the next token.
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek();
+        self.index += 1;
+        token
+    }
+
+    /// Parses a track size: a breadth, or `minmax(min, max)`.
+    fn track_size(&mut self) -> Option<TrackSize> {
+        if let Some(Token::Function(name)) = self.peek() && name.eq_ignore_ascii_case("minmax") {
+            self.index += 1;
+            let min = self.breadth()?;
+            if self.next()? != Token::Comma {
+                return None;
+            }
+            let max = self.breadth()?;
+            if self.next()? != Token::CloseParen || matches!(min, TrackBreadth::Fr(_)) {
+                return None;
+            }
+            return Some(TrackSize { min, max });
+        }
+        Some(match self.breadth()? {
+            TrackBreadth::Fr(fr) => TrackSize { min: TrackBreadth::Auto, max: TrackBreadth::Fr(fr) },
+            breadth => TrackSize { min: breadth.clone(), max: breadth },
+        })
+    }
+
+    /// Parses a track breadth: a non-negative length or percentage, a math function such
+    /// as `calc()`, `<n>fr`, `auto`, `min-content` or `max-content`.
+    fn breadth(&mut self) -> Option<TrackBreadth> {
+        let token = self.next()?;
+        let length = |tokens: &[Token]| LengthPercentage::parse(&serialize(tokens), self.context)
+            .filter(|length| !matches!(length, LengthPercentage::Px(px) if *px < 0.0))
+            .map(TrackBreadth::Length);
+        match &token {
+            Token::Ident(ident) => match ident.to_ascii_lowercase().as_str() {
+                "auto" => Some(TrackBreadth::Auto),
+                "min-content" => Some(TrackBreadth::MinContent),
+                "max-content" => Some(TrackBreadth::MaxContent),
+                _ => None,
+            },
+            Token::Dimension { value, unit, .. } if unit.eq_ignore_ascii_case("fr") => {
+                (*value >= 0.0).then_some(TrackBreadth::Fr(*value))
+            }
+            Token::Dimension { .. } | Token::Percentage { .. } | Token::Number { .. } => length(std::slice::from_ref(&token)),
+            Token::Function(_) => {
+                // A math function: its tokens up to the matching parenthesis.
+                let start = self.index - 1;
+                let mut depth = 1;
+                while depth > 0 {
+                    match self.next()? {
+                        Token::Function(_) | Token::OpenParen => depth += 1,
+                        Token::CloseParen => depth -= 1,
+                        _ => {}
+                    }
+                }
+                let tokens = self.tokens[start..self.index].to_vec();
+                length(&tokens)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The named areas of `grid-template-areas`.
+///
+/// # Fields
+/// - `rows`, `columns`: The number of rows and columns that the areas' strings span,
+///   which are part of the explicit grid.
+/// - `areas`: The areas by name.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct GridTemplateAreas {
+    pub(crate) rows: usize,
+    pub(crate) columns: usize,
+    pub(crate) areas: HashMap<String, GridArea>,
+}
+
+/// The tracks that a named grid area covers, counted from `0`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct GridArea {
+    pub(crate) rows: Range<usize>,
+    pub(crate) columns: Range<usize>,
+}
+
+impl GridTemplateAreas {
+    /// Parses `grid-template-areas`: one string per row, each with one name per column,
+    /// or `.` for a cell that belongs to no area.
+    ///
+    /// # Returns
+    /// - `Some(None)` for `none`.
+    /// - `Some(Some(areas))` if every row has the same number of columns and every area
+    ///   is a rectangle.
+    /// - `None` otherwise.
+    pub(crate) fn parse(value: &str) -> Option<Option<GridTemplateAreas>> {
+        if value.trim().eq_ignore_ascii_case("none") {
+            return Some(None);
+        }
+        let mut rows: Vec<Vec<Option<String>>> = vec![];
+        for token in tokenize(value) {
+            match token {
+                Token::String(row) => rows.push(row.split_ascii_whitespace()
+                    .map(|cell| (!cell.chars().all(|c| c == '.')).then(|| cell.to_string()))
+                    .collect()),
+                Token::Whitespace => {}
+                _ => return None,
+            }
+        }
+        let columns = rows.first()?.len();
+        if columns == 0 || rows.iter().any(|row| row.len() != columns) {
+            return None;
+        }
+
+        let mut areas: HashMap<String, GridArea> = HashMap::new();
+        let mut cells: HashMap<&str, usize> = HashMap::new();
+        for (row, names) in rows.iter().enumerate() {
+            for (column, name) in names.iter().enumerate() {
+                let Some(name) = name else {
+                    continue;
+                };
+                *cells.entry(name).or_default() += 1;
+                let area = areas.entry(name.clone()).or_insert(GridArea { rows: row..row + 1, columns: column..column + 1 });
+                area.rows = area.rows.start.min(row)..area.rows.end.max(row + 1);
+                area.columns = area.columns.start.min(column)..area.columns.end.max(column + 1);
+            }
+        }
+        // An area is a rectangle if it has as many cells as its bounds.
+        let is_rectangle = |(name, area): (&String, &GridArea)| cells[name.as_str()] == area.rows.len() * area.columns.len();
+        if !areas.iter().all(is_rectangle) {
+            return None;
+        }
+        Some(Some(GridTemplateAreas { rows: rows.len(), columns, areas }))
+    }
+}
+
+/// The value of `grid-row-start`, `grid-row-end`, `grid-column-start` or
+/// `grid-column-end`: where an item starts or ends in the grid.
+///
+/// # Variants
+/// - `Auto`: Decided by auto-placement, or one track after the start.
+/// - `Line`: A grid line, counted from `1`, or from the last line of the explicit grid
+///   backwards if negative.
+/// - `Span`: So many tracks from the other end.
+/// - `Area`: The start or end line of the named area of `grid-template-areas`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum GridLine {
+    Auto,
+    Line(i32),
+    Span(u32),
+    Area(String),
+}
+
+impl GridLine {
+    /// Parses `auto`, a non-zero integer, `span <n>` or an area name. Lines and spans
+    /// past `MAX_TRACKS` are clamped to it.
+    pub(crate) fn parse(value: &str) -> Option<GridLine> {
+        let words: Vec<&str> = value.split_ascii_whitespace().collect();
+        let limit = MAX_TRACKS as i64;
+        let span = |count: &str| count.parse::<u64>().ok()
+            .filter(|count| *count >= 1)
+            .map(|count| GridLine::Span(count.min(limit as u64) as u32));
+        match words.as_slice() {
+            [word] if word.eq_ignore_ascii_case("auto") => Some(GridLine::Auto),
+            [word] if word.eq_ignore_ascii_case("span") => None,
+            [word] => match word.parse::<i64>() {
+                Ok(line) => (line != 0).then_some(GridLine::Line(line.clamp(-limit, limit) as i32)),
+                Err(_) => is_identifier(word).then(|| GridLine::Area(word.to_string())),
+            },
+            [keyword, count] | [count, keyword] if keyword.eq_ignore_ascii_case("span") => span(count),
+            _ => None,
+        }
+    }
+}
+
+/// Returns whether `word` is a CSS identifier, such as an area name.
+fn is_identifier(word: &str) -> bool {
+    matches!(tokenize(word).as_slice(), [Token::Ident(_)])
+}
+
+/// The value of `grid-auto-flow`: how items without a position are placed.
+///
+/// # Fields
+/// - `column`: Whether they fill the grid column by column rather than row by row.
+/// - `dense`: Whether each search starts from the start of the grid, filling holes
+///   that earlier items left, rather than after the last placed item.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct GridAutoFlow {
+    pub(crate) column: bool,
+    pub(crate) dense: bool,
+}
+
+impl GridAutoFlow {
+    /// Parses `row`, `column` or `dense`, or `dense` with one of the others.
+    pub(crate) fn parse(value: &str) -> Option<GridAutoFlow> {
+        let mut flow = GridAutoFlow::default();
+        let (mut direction, mut dense) = (false, false);
+        for word in value.split_ascii_whitespace() {
+            match word.to_ascii_lowercase().as_str() {
+                "row" if !direction => direction = true,
+                "column" if !direction => {
+                    direction = true;
+                    flow.column = true;
+                }
+                "dense" if !dense => {
+                    dense = true;
+                    flow.dense = true;
+                }
+                _ => return None,
+            }
+        }
+        (direction || dense).then_some(flow)
+    }
+}
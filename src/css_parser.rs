@@ -1,29 +1,77 @@
 use std::collections::HashMap;
-use crate::selector::Selector;
-use crate::selector::SelectorType::{Descendant, Tag};
+use crate::css_shorthand;
+use crate::css_tokenizer::{Token, matching_paren, serialize, tokenize};
+use crate::media::MediaQueryList;
+use crate::style::ComputedStyle;
+use crate::selector::{AttributeMatcher, Selector};
+use crate::selector::SelectorType::{
+    Active, Attribute, Checked, Child, Class, Compound, Descendant, Empty, FirstChild, Focus, FocusVisible,
+    Hover, Id, Is, LastChild, NextSibling, Not, NthChild, Root, SubsequentSibling, Tag, Universal, Visited,
+};
 
-/// Represents a CSS parser that processes a vector of characters to parse CSS styles.
+/// A CSS declaration value, and whether it was marked `!important`.
+pub type Declaration = (String, bool);
+
+/// The declarations of a block: each property with its value, in source order, so
+/// that a later declaration overrides an earlier one of the same or an overlapping
+/// property (such as `margin-left` after `margin`).
+pub type Declarations = Vec<(String, Declaration)>;
+
+/// A style rule: a selector and the declarations that apply to the elements it
+/// matches.
+pub type StyleRule = (Selector, Declarations);
+
+/// A rule of a style sheet, as returned by `CssParser::parse_rules`.
+///
+/// # Variants
+/// - `Style`: An ordinary style rule. Rules inside an `@supports` block whose condition
+///   holds are returned as plain style rules; the others are dropped.
+/// - `Media`: An `@media` block, whose rules apply while `queries` match.
+/// - `Import`: An `@import` of the style sheet at `url` (not yet resolved), which
+///   applies while `queries` match.
+/// - `FontFace`: The descriptors of an `@font-face` rule, such as `font-family` and `src`.
+#[derive(Clone)]
+pub enum CssRule {
+    Style(StyleRule),
+    Media { queries: MediaQueryList, rules: Vec<CssRule> },
+    Import { url: String, queries: MediaQueryList },
+    FontFace(HashMap<String, String>),
+}
+
+/// How deeply rules and selectors may nest: how many `@media` and `@supports` blocks
+/// may enclose one another, how many `:not()` and `:is()` may, and how many combinators
+/// a selector may chain, each of which nests the selector before it. Past it the rule
+/// or selector is invalid, so that a hostile style sheet cannot overflow the stack.
+const MAX_NESTING_DEPTH: usize = 32;
+
+/// Represents a CSS parser that processes the tokens of a style sheet.
 ///
 /// The `CssParser` struct contains the following fields:
 ///
-/// - `style`: A `Vec<char>` that holds the characters of the CSS style to be parsed.
-/// - `index`: A `usize` value that represents the current position of the parser within the `style` vector.
+/// - `tokens`: The tokens of the style sheet, from the CSS Syntax Level 3 tokenizer in
+///   `css_tokenizer`. Comments and escapes are already gone at this point.
+/// - `index`: A `usize` value that represents the current position of the parser within the `tokens` vector.
+/// - `depth`: How many `@media` and `@supports` blocks, or `:not()` and `:is()`,
+///   enclose the current position, up to `MAX_NESTING_DEPTH`.
+///
+/// # Error recovery
+///
+/// The parser recovers from errors the way browsers do: an invalid declaration is
+/// dropped up to the next `;`, an invalid rule is dropped with its whole `{}` block, and
+/// parsing continues with what follows. Blocks, parentheses and brackets are always
+/// skipped as a whole, so a `}` inside a string or a nested block cannot end a rule early.
 ///
 /// # Example
 ///
 /// ```rust
-/// let css_string = "body { color: black; }".chars().collect();
-/// let mut parser = CssParser {
-///     style: css_string,
-///     index: 0,
-/// };
-///
-/// // Example usage of the parser
-/// assert_eq!(parser.style[parser.index], 'b');
+/// let mut parser = CssParser::new("body { color: black; } /* a comment */");
+/// let rules = parser.parse().unwrap();
+/// assert_eq!(rules.len(), 1);
 /// ```
 pub struct CssParser {
-    style: Vec<char>,
+    tokens: Vec<Token>,
     index: usize,
+    depth: usize,
 }
 
 impl CssParser {
@@ -31,164 +79,75 @@ impl CssParser {
     ///
     /// # Arguments
     ///
-    /// * `styles` - A string slice representing the styles to be stored in the instance.
+    /// * `styles` - A string slice with a style sheet, or a fragment of one such as the
+    ///   declarations of a `style` attribute.
     ///
     /// # Returns
     ///
     /// * A new instance of the struct initialized with the provided styles, where:
-    ///   - `style` is a `Vec<char>` containing individual characters from the input `styles`.
-    ///   - `index` is initialized to `0`.
+    ///   - `tokens` holds the tokens of `styles`.
+    ///   - `index` and `depth` are initialized to `0`.
     ///
     /// # Example
     ///
     /// ```rust
-    /// let styles = "bold italic";
-    /// let instance = MyStruct::new(styles);
-    /// assert_eq!(instance.style, vec!['b', 'o', 'l', 'd', ' ', 'i', 't', 'a', 'l', 'i', 'c']);
+    /// let instance = CssParser::new("p { color: red }");
     /// assert_eq!(instance.index, 0);
     /// ```
     pub fn new(styles: &str) -> Self {
         Self {
-            style: styles.chars().collect(),
-            index: 0
+            tokens: tokenize(styles),
+            index: 0,
+            depth: 0,
         }
     }
 
-    /// Skips over all consecutive whitespace characters in the `style` field starting
-    /// from the current `index` position. Updates the `index` field to point to the
-    /// first non-whitespace character or the end of the `style` field.
-    ///
-    /// # Behavior
-    /// - Iterates through the `style` field character by character, starting at the
-    ///   current `index`.
-    /// - Checks if each character is a whitespace character using `.is_whitespace()`.
-    /// - Increments the `index` for each whitespace character encountered.
-    /// - Stops when a non-whitespace character is found or the end of the `style` field
-    ///   is reached.
-    ///
-    /// # Parameters
-    /// This function operates on a mutable reference to `self`.
+    /// Returns the current token without consuming it, or `None` at the end.
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index)
+    }
+
+    /// Skips over all consecutive whitespace tokens starting from the current `index`
+    /// position. Updates the `index` field to point to the first other token or the end
+    /// of the `tokens` field.
     ///
     /// # Example
     /// ```rust
-    /// let mut parser = Parser {
-    ///     style: "   example",
-    ///     index: 0,
-    /// };
+    /// let mut parser = CssParser::new("   example");
     /// parser.whitespace();
-    /// assert_eq!(parser.index, 3); // Skips the leading whitespaces
+    /// assert_eq!(parser.index, 1); // Skips the leading whitespace token
     /// ```
     ///
     /// # Notes
-    /// - Assumes that `self.style` is a valid string slice and `index` is properly
-    ///   initialized within its bounds to avoid panics.
-    /// - Does not modify the `style` field, only the `index`.
+    /// - Comments never reach the parser, so `a/**/ b` has a single whitespace token.
     fn whitespace(&mut self) {
-        while self.index < self.style.len() && self.style[self.index].is_whitespace() {
+        while self.peek() == Some(&Token::Whitespace) {
             self.index += 1;
         }
     }
 
-    /// Extracts a single word from the `style` field, starting at the current position of `self.index`.
+    /// Attempts to match the current token with the provided literal.
     ///
-    /// A word is defined as a sequence of alphanumeric characters or the characters `#`, `-`, `.`, `%`.
-    /// The function iterates through the `style` slice starting from `self.index`, collecting characters
-    /// that match the word criteria until it encounters whitespace or an invalid character.
+    /// This function checks if the token at the `index` position is the punctuation or
+    /// delimiter `literal`, such as `:`, `{` or `>`. If it matches, the `index` is
+    /// incremented. If it doesn't match or the index is out of bounds, an error is returned.
     ///
     /// # Returns
     ///
-    /// - `Ok(String)` containing the extracted word if parsing is successful.
-    /// - `Err(String)` with an error message if no valid word is found at the current starting position.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error in the following scenarios:
-    /// - If there is no valid word at the current position (`self.index`).
+    /// * `Ok(())` if the current token matches the given literal and the index is successfully advanced.
+    /// * `Err(String)` if the current token does not match the given literal or the index is out of bounds.
+    ///   The error contains a message indicating the expected literal.
     ///
     /// # Example
     ///
-    /// ```
-    /// let mut parser = SomeParser {
-    ///     style: vec!['h', 'e', 'l', 'l', 'o', ' ', 'w', 'o', 'r', 'l', 'd'],
-    ///     index: 0,
-    /// };
-    ///
-    /// // Parsing the first word
-    /// let word = parser.word().unwrap();
-    /// assert_eq!(word, "hello");
-    ///
-    /// // Move the index past the whitespace
-    /// parser.index += 1;
-    ///
-    /// // Parsing the next word
-    /// let second_word = parser.word().unwrap();
-    /// assert_eq!(second_word, "world");
-    /// ```
-    ///
-    /// # Assumptions
-    ///
-    /// - The `style` property is a slice of characters (`Vec<char>`).
-    /// - The `index` field tracks the current parsing position in the `style` slice.
-    fn word(&mut self) -> Result<String, String> {
-        let start = self.index;
-        while self.index < self.style.len() && !self.style[self.index].is_whitespace() {
-            if self.style[self.index].is_alphanumeric() ||
-                "#-.%".contains(self.style[self.index]) {
-                self.index += 1;
-            } else {
-                break;
-            }
-        }
-        if self.index <= start {
-            return Err("Parsing error: Expected word".to_string());
-        }
-        Ok(self.style[start..self.index].iter().collect())
-    }
-
     /// ```rust
-    /// Attempts to match the current character in the `style` string with the provided literal.
-    ///
-    /// This function checks if the current character at the `index` position in the `style` vector
-    /// matches the provided `literal` character. If it matches, the `index` is incremented. If it
-    /// doesn't match or the index is out of bounds, an error is returned.
-    ///
-    /// # Arguments
-    ///
-    /// * `literal` - A `char` representing the literal character to match against the current
-    ///               character in the `style` vector.
-    ///
-    /// # Returns
-    ///
-    /// * `Ok(())` if the current character matches the given literal and the index is successfully advanced.
-    /// * `Err(String)` if the current character does not match the given literal or the index is out of bounds.
-    ///                The error contains a message indicating the expected literal.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error in the following cases:
-    /// - The `index` exceeds the bounds of the `style` string.
-    /// - The current character in the `style` string does not match the provided `literal`.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// let mut parser = Parser {
-    ///     style: vec!['a', 'b', 'c'],
-    ///     index: 0,
-    /// };
-    ///
-    /// assert_eq!(parser.literal('a'), Ok(()));
-    /// assert_eq!(parser.literal('b'), Ok(()));
-    /// assert_eq!(parser.literal('x'), Err("Parsing error: Expected literal 'x'".to_string()));
-    /// ```
-    ///
-    /// # Assumptions
-    ///
-    /// This function assumes the `self.style` field is a `Vec<char>` and `self.index` is a valid position or
-    /// potential position in the vector.
+    /// let mut parser = CssParser::new("a:b");
+    /// assert_eq!(parser.literal(':'), Err("Parsing error: Expected literal ':'".to_string()));
+    /// parser.index = 1;
+    /// assert_eq!(parser.literal(':'), Ok(()));
     /// ```
     fn literal(&mut self, literal: char) -> Result<(), String> {
-        if !(self.index < self.style.len() && (self.style[self.index] == literal)) {
+        if !self.peek().is_some_and(|token| token.is_char(literal)) {
             return Err(format!("Parsing error: Expected literal '{}'", literal))
         }
         self.index += 1;
@@ -196,199 +155,227 @@ impl CssParser {
         Ok(())
     }
 
-    /// Parses a pair of words separated by a colon (`:`), while allowing
-    /// optional whitespace around the colon. Each word must conform to
-    /// the parsing logic defined by the `word()` method.
+    /// Consumes a component value: a single token, or a whole block or function with
+    /// everything nested in it, and appends its tokens to `out`.
     ///
-    /// # Returns
+    /// # Notes
+    /// - A block that is never closed runs to the end of the tokens, as in CSS.
+    /// - Nested blocks are tracked with a stack rather than by recursion, so that no
+    ///   depth of nesting can overflow the call stack.
+    fn component_value(&mut self, out: &mut Vec<Token>) {
+        // The tokens that close the blocks and functions left open, innermost last.
+        let mut open: Vec<Token> = vec![];
+        while let Some(token) = self.peek().cloned() {
+            self.index += 1;
+            if open.last() == Some(&token) {
+                open.pop();
+            } else if let Some(closing) = token.closing() {
+                open.push(closing);
+            }
+            out.push(token);
+            if open.is_empty() {
+                return;
+            }
+        }
+    }
+
+    /// Skips a component value, as `component_value` without keeping its tokens.
+    fn skip_component_value(&mut self) {
+        self.component_value(&mut vec![]);
+    }
+
+    /// Consumes component values up to, but not including, a `;` or `}` that is not
+    /// nested in a block, or the end of the tokens, and returns their tokens.
+    fn until_end_of_declaration(&mut self) -> Vec<Token> {
+        let mut tokens = vec![];
+        while let Some(token) = self.peek() {
+            if token.is_char(';') || token.is_char('}') {
+                break;
+            }
+            self.component_value(&mut tokens);
+        }
+        tokens
+    }
+
+    /// Parses a declaration: a property name, a colon and a value, optionally followed by
+    /// `!important`.
     ///
-    /// Returns a `Result` containing a tuple `(String, String)`, where:
-    /// - The first `String` (property) is converted to lowercase.
-    /// - The second `String` (value) remains in its original form.
+    /// # Returns
     ///
-    /// If parsing fails at any step, an `Err(String)` will be returned
-    /// containing an error message.
+    /// Returns a `Result` containing a tuple `(String, String, bool)`, where:
+    /// - The first `String` (property) is converted to lowercase, except for custom
+    ///   properties (`--name`), whose names are case-sensitive.
+    /// - The second `String` (value) is the value's tokens written back as CSS text, such
+    ///   as `1px solid black`, with comments removed and whitespace collapsed.
+    /// - The `bool` is `true` if the value is followed by `!important`.
     ///
     /// # Steps
-    /// 1. Invokes the `word()` method to parse the first word (`prop`).
-    /// 2. Consumes any additional whitespace after the first word.
-    /// 3. Expects and consumes the `:` literal character.
-    /// 4. Consumes any additional whitespace after the colon.
-    /// 5. Invokes the `word()` method again to parse the second word (`val`).
-    /// 6. Converts the first word (`prop`) to lowercase and returns the tuple.
+    /// 1. Consumes everything up to the `;` or `}` that ends the declaration, so that the
+    ///    caller can continue after it whether or not the declaration is valid.
+    /// 2. Expects an identifier, optional whitespace and a `:`.
+    /// 3. Takes `!important` off the end of the value. Whitespace is allowed before and
+    ///    after the `!`, and `important` is matched case-insensitively.
     ///
     /// # Errors
-    /// - If the `word()` method fails to parse either the property or value.
-    /// - If the `literal(':')` check fails to match the expected colon.
+    /// - If the declaration does not start with an identifier and a colon.
+    /// - If the value is empty, or contains a bad string or URL token.
+    /// - If a `!` is followed by anything but `important`.
     ///
     /// # Example
     /// ```rust
-    /// let mut parser = MyParser::new("key : value");
+    /// let mut parser = CssParser::new("key : value /* note */ ! IMPORTANT");
     /// let result = parser.pair();
-    /// assert_eq!(result, Ok(("key".to_string(), "value".to_string())));
+    /// assert_eq!(result, Ok(("key".to_string(), "value".to_string(), true)));
     /// ```
-    fn pair(&mut self) -> Result<(String, String), String> {
-        let prop = self.word()?;
-        self.whitespace();
-        self.literal(':')?;
-        self.whitespace();
-        let val = self.word()?;
-        Ok((prop.to_lowercase(), val))
+    fn pair(&mut self) -> Result<(String, String, bool), String> {
+        let tokens = self.until_end_of_declaration();
+        let mut tokens = tokens.as_slice();
+        let Some((Token::Ident(prop), rest)) = tokens.split_first() else {
+            return Err("Parsing error: Expected property name".to_string());
+        };
+        tokens = trim_whitespace(rest);
+        let Some((_, rest)) = tokens.split_first().filter(|(colon, _)| colon.is_char(':')) else {
+            return Err(format!("Parsing error: Expected ':' after '{}'", prop));
+        };
+        let mut value = trim_whitespace(rest);
+
+        let mut important = false;
+        if let [before @ .., Token::Delim('!'), last] | [before @ .., Token::Delim('!'), Token::Whitespace, last] = value
+            && last.is_ident("important") {
+            important = true;
+            value = trim_whitespace(before);
+        }
+        if value.is_empty() {
+            return Err("Parsing error: Expected value".to_string());
+        }
+        if value.iter().any(|t| matches!(t, Token::BadString | Token::BadUrl | Token::Delim('!'))) {
+            return Err(format!("Parsing error: Invalid value for '{}'", prop));
+        }
+        // Custom property names are case-sensitive.
+        let prop = if prop.starts_with("--") { prop.clone() } else { prop.to_lowercase() };
+        Ok((prop, serialize(value), important))
     }
 
 
-    /// Parses and extracts key-value pairs from a structured style body until
-    /// the closing brace (`}`) is encountered or an error is handled.
+    /// Parses the declarations of a block, or of a `style` attribute, until the closing
+    /// brace (`}`) or the end of the tokens.
+    ///
+    /// This method processes the tokens starting from the current position (`self.index`)
+    /// and returns the declarations in source order. The `}` is left for the caller.
+    ///
+    /// # Error recovery
+    /// An invalid declaration is dropped up to the next `;`, as in browsers, and the
+    /// declarations after it are kept. Nested at-rules are skipped with their block.
     ///
-    /// This method processes the contents of the `self.style` field starting
-    /// from the current position (`self.index`) and returns a `HashMap` containing
-    /// the key-value pairs. If parsing errors occur, they are handled using the
-    /// `handle_body_error` function, which determines whether parsing should terminate.
+    /// # Shorthands
+    /// Shorthand properties such as `margin` or `border` are expanded into their
+    /// longhands here, in place, and an invalid shorthand is dropped like any other
+    /// invalid declaration. A shorthand with `var()` stays whole, at its position among
+    /// the others, until `ComputedStyle::compute` expands it.
     ///
     /// # Returns
-    /// - `Ok(HashMap<String, String>)`: A hashmap containing the extracted key-value pairs.
-    /// - `Err(String)`: An error message if parsing fails unrecoverably.
+    /// Always `Ok`; the `Result` is kept for the callers that propagate it.
     ///
-    /// # Behavior
-    /// - Processes until the end of the body (`'}`) or until an unrecoverable error occurs.
-    /// - Key-value pairs are separated by semicolons (`;`), and the method skips any
-    ///   whitespace between pairs or after the semicolons.
-    /// - Calls `self.pair` to parse each key-value pair.
-    /// - If `self.pair` or `self.literal(';')` returns an error, the error is handled
-    ///   using `self.handle_body_error`.
-    ///
-    /// # Error Handling
-    /// - If an error occurs during pair extraction or semicolon parsing,
-    ///   `self.handle_body_error` determines if the error is recoverable. If
-    ///   unrecoverable, parsing terminates and the current state is returned.
-    ///
-    /// # Examples
+    /// # Example
     /// ```
-    /// # use std::collections::HashMap;
-    /// # use your_module::YourStruct;
-    /// let mut parser = YourStruct::new("{key1: value1; key2: value2;}");
-    /// let result = parser.body();
-    /// assert!(result.is_ok());
-    ///
-    /// let map = result.unwrap();
-    /// assert_eq!(map.get("key1"), Some(&"value1".to_string()));
-    /// assert_eq!(map.get("key2"), Some(&"value2".to_string()));
+    /// let mut parser = CssParser::new("key1: value1; key2 value2; key3: value3");
+    /// let declarations = parser.body().unwrap();
+    /// assert_eq!(declarations, vec![
+    ///     ("key1".to_string(), ("value1".to_string(), false)),
+    ///     ("key3".to_string(), ("value3".to_string(), false)),
+    /// ]);
     /// ```
-    pub(crate) fn body(&mut self) -> Result<HashMap<String, String>, String> {
-        let mut pairs = HashMap::<String, String>::new();
-        while self.index < self.style.len() && self.style[self.index] != '}' {
-            let pair = self.pair();
-            if pair.is_err() {
-                let should_break = self.handle_body_error();
-                if should_break {
-                    break;
+    pub(crate) fn body(&mut self) -> Result<Declarations, String> {
+        let mut pairs = Declarations::new();
+        loop {
+            self.whitespace();
+            match self.peek() {
+                None => break,
+                Some(token) if token.is_char('}') => break,
+                Some(token) if token.is_char(';') => {
+                    self.index += 1;
+                    continue;
+                }
+                Some(Token::AtKeyword(_)) => {
+                    self.skip_at_rule();
+                    continue;
                 }
+                _ => {}
             }
-            let pair_unwrapped = pair?;
-            pairs.insert(pair_unwrapped.0, pair_unwrapped.1);
-            self.whitespace();
-            if self.literal(';').is_err() {
-                let should_break = self.handle_body_error();
-                if should_break {
-                    break;
+            let Ok((property, value, important)) = self.pair() else {
+                continue;
+            };
+            // A shorthand with `var()` can only be expanded once the variables are
+            // substituted, so it is kept whole until `ComputedStyle::compute`.
+            let expanded = if value.to_ascii_lowercase().contains("var(") {
+                None
+            } else {
+                css_shorthand::expand(&property, &value)
+            };
+            match expanded {
+                Some(Ok(longhands)) => {
+                    for (longhand, value) in longhands {
+                        declare(&mut pairs, longhand, (value, important));
+                    }
                 }
+                // An invalid shorthand is dropped like any other invalid declaration.
+                Some(Err(_)) => {}
+                None => declare(&mut pairs, property, (value, important)),
             }
-            self.whitespace();
         }
         Ok(
             pairs
         )
     }
 
-    /// Handles errors encountered while parsing the body of a construct.
-    ///
-    /// This method attempts to recover from a parsing error by ignoring tokens until
-    /// one of the specified delimiters (`;` or `}`) is encountered. If the delimiter `;`
-    /// is found, the method consumes it, skips any subsequent whitespace, and signals
-    /// that the error recovery process completed without further issues. If no such
-    /// delimiter is found (or if the delimiter is `}`), the method determines that it
-    /// cannot recover further.
-    ///
-    /// # Returns
-    /// * `false` - if the parsing error was recovered successfully and a `;` was found.
-    /// * `true` - if recovery was impossible (or if `}` was encountered).
+    /// Parses a comma-separated list of selectors, such as `h1, h2.title`, stopping at the
+    /// first token that cannot continue the list (normally `{` or `)`).
     ///
-    /// # Panics
-    /// This method will panic with a "TODO: panic message" if the `literal` method
-    /// fails to consume and match the `;` character.
-    ///
-    /// # Example
-    /// ```rust
-    /// let mut parser = Parser::new();
-    /// assert!(parser.handle_body_error()); // Example usage
-    /// ```
-    fn handle_body_error(&mut self) -> bool {
-        let why = self.ignore_until(vec![';', '}']);
-        if why == Some(';') {
-            self.literal(';').expect("TODO: panic message");
+    /// # Errors
+    /// Returns `Err` if any selector in the list is invalid or unsupported. As in CSS,
+    /// one bad selector invalidates the whole list.
+    pub(crate) fn selector_list(&mut self) -> Result<Vec<Selector>, String> {
+        let mut selectors = vec![];
+        loop {
+            self.whitespace();
+            selectors.push(self.selector()?);
             self.whitespace();
-            return false;
+            if self.literal(',').is_err() {
+                return Ok(selectors);
+            }
         }
-        true
     }
 
-    /// Advances the internal index until one of the specified characters (`literals`)
-    /// is found in the `style` string. If a matching character is encountered, it is returned.
-    /// If none of the characters in `literals` are found by the time the end of the `style`
-    /// string is reached, the method returns `None`.
-    ///
-    /// # Arguments
-    ///
-    /// * `literals` - A vector of characters to look for in the `style` string.
-    ///
-    /// # Returns
-    ///
-    /// * `Option<char>` - The first matching character wrapped in `Some` if found,
-    ///   or `None` if no match is encountered.
-    ///
-    /// # Behavior
-    ///
-    /// * The method increments `self.index` as it loops through the characters in the `style` string.
-    /// * If the end of the `style` string is reached before finding a matching character,
-    ///   `None` is returned.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// let mut parser = YourStruct { style: "abcde".to_string(), index: 0 };
-    /// let result = parser.ignore_until(vec!['c', 'e']);
-    /// assert_eq!(result, Some('c'));
-    /// assert_eq!(parser.index, 2); // `index` stops at the position of the match
-    ///
-    /// let result = parser.ignore_until(vec!['x', 'y']);
-    /// assert_eq!(result, None);
-    /// assert_eq!(parser.index, 5); // `index` reaches the end of the string
-    /// ```
-    fn ignore_until(&mut self, literals: Vec<char>) -> Option<char> {
-        while self.index < self.style.len() {
-            if literals.contains(&self.style[self.index]) {
-                return Some(self.style[self.index]);
-            } else {
-                self.index += 1;
-            }
+    /// Parses a whole string as a selector list, as `querySelectorAll` does. Unlike
+    /// `selector_list`, anything left over after the list is an error.
+    pub(crate) fn selector_list_only(&mut self) -> Result<Vec<Selector>, String> {
+        let selectors = self.selector_list()?;
+        if let Some(token) = self.peek() {
+            return Err(format!("Parsing error: Unexpected '{}' in selector", serialize(std::slice::from_ref(token))));
         }
-        None
+        Ok(selectors)
     }
 
-    /// Parses a CSS-like selector from the current style string and constructs a `Selector` object with
-    /// either a single tag or a descendant hierarchy of tags.
+    /// Parses a CSS selector from the current tokens: a chain of compound selectors
+    /// joined by combinators.
     ///
     /// # Details
-    /// This function builds a hierarchical representation of a selector by repeatedly parsing individual
-    /// tags and constructing a `Selector` object. The hierarchy constructed represents descendant
-    /// relationships in the selector (e.g., for selectors such as `div span`, `div` is the ancestor of `span`).
+    /// Each compound selector is a type selector (`div`) or the universal selector (`*`),
+    /// optionally followed by any number of class (`.note`), ID (`#main`), attribute
+    /// (`[data-x="1"]`) and pseudo-class (`:first-child`) selectors, written without spaces
+    /// in between: `div.note#main`. A compound selector may also start directly with one
+    /// of the latter. Names may contain escapes, as in `.a\:b`.
+    ///
+    /// Compound selectors are joined by whitespace (descendant), `>` (child), `+` (next
+    /// sibling) or `~` (subsequent sibling) combinators. The chain is built left to right
+    /// into nested selectors, so for `div > p span`, `div > p` is the ancestor of `span`.
     ///
-    /// The parsing stops when a `{` character is encountered or when the end of the style is reached. Any
-    /// unnecessary whitespace in the selector string is ignored.
+    /// The parsing stops at a `{`, `,` or `)` token or at the end of the tokens.
     ///
     /// # Errors
-    /// If the function encounters an error in parsing the selector, it will return a `Result::Err` with
-    /// an appropriate error message.
+    /// Returns `Err` if the selector contains anything that is not supported, such as
+    /// pseudo-elements or unknown pseudo-classes, so that the caller can drop the whole rule,
+    /// or if it chains more than `MAX_NESTING_DEPTH` combinators.
     ///
     /// # Returns
     /// Returns a `Result`:
@@ -397,94 +384,416 @@ impl CssParser {
     ///
     /// # Example
     /// ```rust
-    /// // Assume `self` is configured with a proper style string and methods.
-    /// let selector = self.selector();
-    /// match selector {
-    ///     Ok(result) => println!("Parsed selector: {:?}", result),
-    ///     Err(err) => eprintln!("Error parsing selector: {}", err),
-    /// }
+    /// let selector = CssParser::new("ul > li:nth-child(2n+1) a[href^=\"https:\"]").selector();
     /// ```
     ///
-    /// # Prerequisites
-    /// - The `self.word()` function must be able to properly parse valid tags.
-    /// - The `self.whitespace()` function must handle whitespace clearing in the input.
-    ///
     /// # Notes
-    /// - The function assumes that `self.style` holds the input style string, and `self.index` tracks the
-    ///   current parsing position.
-    /// - Each tag in the hierarchy is converted to lowercase to ensure case-insensitive matching.
-    ///
-    /// # Dependencies
-    /// This function depends on the following structures/classes:
-    /// - `Selector`: Represents the selector being constructed.
-    /// - `Tag { tag: String }`: Represents an individual tag in the selector.
-    /// - `Descendant { ancestor: Box<Selector>, descendant: Box<Selector> }`: Represents a descendant
-    ///   relationship between two selectors.
-    ///
-    /// See the implementation of `Selector`, `Tag`, and `Descendant` for more details.
+    /// - Type selectors and attribute names are converted to lowercase, since HTML tag and
+    ///   attribute names are case-insensitive. Class names, IDs and attribute values are not.
     pub(crate) fn selector(&mut self) -> Result<Selector, String> {
-        let mut out = Selector { selector: Tag {tag: self.word()?.to_lowercase()} };
-        self.whitespace();
-        while self.index < self.style.len() && self.style[self.index] != '{' {
-            let tag = self.word()?;
-            let descendant = Selector { selector: Tag {tag: tag.to_lowercase()} };
-            out = Selector { selector: Descendant {ancestor: Box::from(out),
-                descendant: Box::from(descendant)}}
+        let mut out = self.compound_selector()?;
+        let mut combinators = 0;
+        loop {
+            let before_whitespace = self.index;
+            self.whitespace();
+            let combinator = match self.peek() {
+                None => break,
+                Some(token) if token.is_char('{') || token.is_char(',') || token.is_char(')') => break,
+                Some(&Token::Delim(c @ ('>' | '+' | '~'))) => {
+                    self.index += 1;
+                    self.whitespace();
+                    c
+                }
+                Some(_) if self.index > before_whitespace => ' ',
+                Some(token) => return Err(format!("Parsing error: Unexpected '{}' in selector", serialize(std::slice::from_ref(token)))),
+            };
+
+            combinators += 1;
+            if combinators > MAX_NESTING_DEPTH {
+                return Err("Parsing error: Too many combinators in selector".to_string());
+            }
+            let next = Box::from(self.compound_selector()?);
+            let previous = Box::from(out);
+            out = Selector { selector: match combinator {
+                '>' => Child { parent: previous, child: next },
+                '+' => NextSibling { previous, next },
+                '~' => SubsequentSibling { previous, next },
+                _ => Descendant { ancestor: previous, descendant: next },
+            }};
         }
         Ok(out)
     }
 
-    /// Parses a set of rules from the internal `style` representation and returns the result.
+    /// Parses a compound selector such as `div.note#main[data-x="1"]`. A compound made of
+    /// a single simple selector is returned as that simple selector.
+    fn compound_selector(&mut self) -> Result<Selector, String> {
+        let mut selectors = vec![];
+        match self.peek() {
+            Some(Token::Delim('*')) => {
+                self.index += 1;
+                selectors.push(Selector { selector: Universal });
+            }
+            Some(Token::Ident(tag)) => {
+                selectors.push(Selector { selector: Tag { tag: tag.to_lowercase() } });
+                self.index += 1;
+            }
+            _ => {}
+        }
+
+        loop {
+            match self.peek() {
+                Some(Token::Delim('.')) => {
+                    self.index += 1;
+                    selectors.push(Selector { selector: Class { class: self.identifier()? } });
+                }
+                Some(Token::Hash { value, is_id: true }) => {
+                    selectors.push(Selector { selector: Id { id: value.clone() } });
+                    self.index += 1;
+                }
+                Some(Token::OpenSquare) => {
+                    selectors.push(self.attribute_selector()?);
+                }
+                Some(Token::Colon) => {
+                    selectors.push(self.pseudo_class()?);
+                }
+                _ => break,
+            }
+        }
+
+        match selectors.len() {
+            0 => Err("Parsing error: Expected selector".to_string()),
+            1 => Ok(selectors.remove(0)),
+            _ => Ok(Selector { selector: Compound { selectors } }),
+        }
+    }
+
+    /// Parses an attribute selector: `[name]`, or `[name op value]` where `op` is one of
+    /// `=`, `~=`, `|=`, `^=`, `$=` and `*=`, `value` is an identifier or a quoted string,
+    /// and an optional `i` or `s` flag before the `]` makes the comparison ASCII
+    /// case-insensitive or case-sensitive.
+    fn attribute_selector(&mut self) -> Result<Selector, String> {
+        self.literal('[')?;
+        self.whitespace();
+        let name = self.identifier()?.to_lowercase();
+        self.whitespace();
+
+        if self.literal(']').is_ok() {
+            return Ok(Selector { selector: Attribute { name, matcher: AttributeMatcher::Exists, case_insensitive: false } });
+        }
+
+        let operator = match self.peek() {
+            Some(&Token::Delim(c @ ('~' | '|' | '^' | '$' | '*'))) => {
+                self.index += 1;
+                Some(c)
+            }
+            _ => None,
+        };
+        self.literal('=')?;
+        self.whitespace();
+        let value = match self.peek() {
+            Some(Token::String(value)) => {
+                let value = value.clone();
+                self.index += 1;
+                value
+            }
+            _ => self.identifier()?,
+        };
+        self.whitespace();
+
+        let mut case_insensitive = false;
+        if let Some(Token::Ident(flag)) = self.peek()
+            && (flag.eq_ignore_ascii_case("i") || flag.eq_ignore_ascii_case("s")) {
+            case_insensitive = flag.eq_ignore_ascii_case("i");
+            self.index += 1;
+            self.whitespace();
+        }
+        self.literal(']')?;
+
+        let matcher = match operator {
+            None => AttributeMatcher::Equals(value),
+            Some('~') => AttributeMatcher::Includes(value),
+            Some('|') => AttributeMatcher::DashMatch(value),
+            Some('^') => AttributeMatcher::Prefix(value),
+            Some('$') => AttributeMatcher::Suffix(value),
+            _ => AttributeMatcher::Substring(value),
+        };
+        Ok(Selector { selector: Attribute { name, matcher, case_insensitive } })
+    }
+
+    /// Parses a pseudo-class: the structural `:first-child`, `:last-child`,
+    /// `:nth-child(an+b)`, `:root` and `:empty`, the logical `:not(list)` and `:is(list)`,
+    /// or the dynamic `:hover`, `:active`, `:focus`, `:focus-visible`, `:visited` and
+    /// `:checked`. Pseudo-elements (`::before`) and other pseudo-classes are rejected, as
+    /// are `:not()` and `:is()` nested deeper than `MAX_NESTING_DEPTH`.
+    fn pseudo_class(&mut self) -> Result<Selector, String> {
+        self.literal(':')?;
+        let selector = match self.peek().cloned() {
+            Some(Token::Ident(name)) => {
+                self.index += 1;
+                match name.to_lowercase().as_str() {
+                    "first-child" => FirstChild,
+                    "last-child" => LastChild,
+                    "root" => Root,
+                    "empty" => Empty,
+                    "hover" => Hover,
+                    "active" => Active,
+                    "focus" => Focus,
+                    "focus-visible" => FocusVisible,
+                    "visited" => Visited,
+                    "checked" => Checked,
+                    _ => return Err(format!("Parsing error: Unsupported pseudo-class ':{}'", name)),
+                }
+            }
+            Some(Token::Function(name)) => {
+                self.index += 1;
+                let name = name.to_lowercase();
+                let selector = match name.as_str() {
+                    "nth-child" => {
+                        let mut argument = vec![];
+                        while self.peek().is_some_and(|t| !t.is_char(')')) {
+                            self.component_value(&mut argument);
+                        }
+                        let argument = serialize(&argument);
+                        let (a, b) = parse_nth(&argument)
+                            .ok_or(format!("Parsing error: Invalid :nth-child argument '{}'", argument))?;
+                        NthChild { a, b }
+                    }
+                    "not" | "is" => {
+                        if self.depth == MAX_NESTING_DEPTH {
+                            return Err(format!("Parsing error: ':{}()' nested too deeply", name));
+                        }
+                        self.depth += 1;
+                        let selectors = self.selector_list();
+                        self.depth -= 1;
+                        let selectors = selectors?;
+                        if name == "not" { Not { selectors } } else { Is { selectors } }
+                    }
+                    _ => return Err(format!("Parsing error: Unsupported pseudo-class ':{}()'", name)),
+                };
+                self.literal(')')?;
+                selector
+            }
+            _ => return Err("Parsing error: Expected pseudo-class name".to_string()),
+        };
+        Ok(Selector { selector })
+    }
+
+    /// Reads an identifier token, such as a class or attribute name.
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                self.index += 1;
+                Ok(name)
+            }
+            _ => Err("Parsing error: Expected identifier".to_string()),
+        }
+    }
+
+    /// Parses a set of rules from the tokens and returns the result.
     ///
     /// # Returns
     ///
-    /// * `Ok(Vec<(Selector, HashMap<String, String>)>)` - A vector of tuples where each tuple contains:
+    /// * `Ok(Vec<StyleRule>)` - A vector of tuples where each tuple contains:
     ///   - A `Selector` representing a CSS selector.
-    ///   - A `HashMap<String, String>` representing a collection of style properties for the selector.
+    ///   - The `Declarations` of its block: each property with its value and its
+    ///     `!important` flag, in source order.
     /// * `Err(String)` - A string specifying the reason for the failure during parsing.
     ///
     /// # Behavior
     ///
-    /// This method iterates through the `style` data, attempting to parse CSS-style rules.
+    /// This method iterates through the tokens, attempting to parse CSS-style rules.
     /// It repeatedly calls an internal parsing method (`parse_internal`) to extract selectors and style
-    /// properties. If parsing fails during this process:
-    /// - It attempts to recover by skipping content until the next '}' character.
-    /// - If no recovery point is found, it halts further parsing and returns the rules collected so far.
-    ///
-    /// After successfully parsing a rule, it expects a closing '}' character and processes any whitespace following it.
-    ///
-    /// # Errors
+    /// properties. If a rule fails to parse, its selector and its whole block are skipped,
+    /// nested blocks included, and parsing resumes after it.
     ///
-    /// If the `literal` method fails or the parsing logic encounters an unrecoverable issue,
-    /// the method will return an error containing a descriptive string.
+    /// Only the style rules that always apply are returned: those at the top level and
+    /// those inside an `@supports` block whose condition holds. `@media`, `@import` and
+    /// `@font-face` rules are dropped; use `parse_rules` to keep them.
     ///
     /// # Example
     ///
     /// ```rust
-    /// let mut parser = Parser::new("a { color: red; } b { font-size: 16px; }");
+    /// let mut parser = CssParser::new("a { color: red; } /* } */ b { font-size: 16px; }");
     /// let parsed_rules = parser.parse();
     /// assert!(parsed_rules.is_ok());
     /// let rules = parsed_rules.unwrap();
     /// assert_eq!(rules.len(), 2);
     /// ```
-    pub fn parse(&mut self) -> Result<Vec<(Selector, HashMap<String, String>)>, String> {
-        let mut rules = Vec::<(Selector, HashMap<String, String>)>::new();
-        while self.index < self.style.len() {
-            let result = self.parse_internal(&mut rules);
-            match result {
-                Ok(_) => (),
-                Err(_err) =>  {
-                    let why = self.ignore_until(Vec::from(['}']));
-                    if why == Some('}') {
-                        self.literal('}')?;
-                        self.whitespace();
-                    } else {
-                        break
+    pub fn parse(&mut self) -> Result<Vec<StyleRule>, String> {
+        let rules = self
+            .parse_rules()
+            .into_iter()
+            .filter_map(|rule| match rule {
+                CssRule::Style(rule) => Some(rule),
+                _ => None,
+            })
+            .collect();
+        Ok(rules)
+    }
+
+    /// Parses the whole style sheet, including its at-rules.
+    ///
+    /// # Returns
+    /// The rules of the sheet in source order. Unlike `parse`, which only returns the
+    /// style rules that always apply, this keeps `@media` blocks, `@import`s and
+    /// `@font-face` rules, which the caller evaluates against the page.
+    ///
+    /// # Notes
+    /// - `@import` is only honored before any other rule except `@charset`, as in CSS.
+    /// - Unknown at-rules are skipped with their block, so a nested `}` cannot end the
+    ///   rule early.
+    /// - `@media` and `@supports` blocks nested deeper than `MAX_NESTING_DEPTH` are
+    ///   skipped with their contents.
+    pub fn parse_rules(&mut self) -> Vec<CssRule> {
+        let mut imports_allowed = true;
+        self.rule_list(true, &mut imports_allowed)
+    }
+
+    /// Parses rules until the end of the sheet or, inside a block (`top_level` false),
+    /// until the `}` that closes it, which is left for the caller.
+    fn rule_list(&mut self, top_level: bool, imports_allowed: &mut bool) -> Vec<CssRule> {
+        let mut rules = Vec::new();
+        loop {
+            match self.peek() {
+                None => break,
+                Some(Token::Whitespace) => {
+                    self.index += 1;
+                    continue;
+                }
+                // `<!--` and `-->` are allowed around a style sheet, for old browsers that
+                // showed the contents of `<style>`.
+                Some(Token::Cdo | Token::Cdc) if top_level => {
+                    self.index += 1;
+                    continue;
+                }
+                Some(Token::CloseCurly) if !top_level => break,
+                Some(Token::AtKeyword(_)) => {
+                    self.at_rule(&mut rules, imports_allowed);
+                    continue;
+                }
+                _ => {}
+            }
+
+            *imports_allowed = false;
+            let start = self.index;
+            let mut style_rules = Vec::new();
+            match self.parse_internal(&mut style_rules) {
+                Ok(()) => rules.extend(style_rules.into_iter().map(CssRule::Style)),
+                Err(_) => {
+                    self.index = start;
+                    self.skip_rule(top_level);
+                }
+            }
+        }
+        rules
+    }
+
+    /// Parses an at-rule starting at its `@keyword` and appends what it produces to `rules`.
+    ///
+    /// # Parameters
+    /// - `rules`: The rules of the enclosing block.
+    /// - `imports_allowed`: Whether an `@import` may still appear; cleared by every rule
+    ///   other than `@import` and `@charset`.
+    fn at_rule(&mut self, rules: &mut Vec<CssRule>, imports_allowed: &mut bool) {
+        let Some(Token::AtKeyword(name)) = self.peek().cloned() else {
+            return;
+        };
+        self.index += 1;
+        let name = name.to_ascii_lowercase();
+        let prelude = self.prelude();
+        if !self.peek().is_some_and(|token| token.is_char('{')) {
+            // The statement ends with `;`, or at a `}` or the end of the sheet.
+            if self.peek().is_some_and(|token| token.is_char(';')) {
+                self.index += 1;
+            }
+            match name.as_str() {
+                "import" if *imports_allowed => {
+                    if let Some((url, queries)) = parse_import_prelude(&prelude) {
+                        rules.push(CssRule::Import { url, queries });
                     }
                 }
+                "charset" | "import" => {}
+                _ => *imports_allowed = false,
             }
+            return;
+        }
+
+        *imports_allowed = false;
+        match name.as_str() {
+            "media" | "supports" if self.depth == MAX_NESTING_DEPTH => {
+                self.skip_component_value();
+                return;
+            }
+            "media" | "supports" => {
+                self.index += 1;
+                self.depth += 1;
+                let nested = self.rule_list(false, imports_allowed);
+                self.depth -= 1;
+                if name == "media" {
+                    let queries = MediaQueryList::parse(&serialize(&prelude));
+                    rules.push(CssRule::Media { queries, rules: nested });
+                } else if supports_condition(&serialize(&prelude)).unwrap_or(false) {
+                    rules.extend(nested);
+                }
+            }
+            "font-face" => {
+                self.index += 1;
+                // `body` always stops at the `}` that closes the block, or at the end.
+                if let Ok(body) = self.body() {
+                    let descriptors = body.into_iter().map(|(name, (value, _))| (name, value)).collect();
+                    rules.push(CssRule::FontFace(descriptors));
+                }
+            }
+            _ => {
+                self.skip_component_value();
+                return;
+            }
+        }
+        if self.peek().is_some_and(|token| token.is_char('}')) {
+            self.index += 1;
+        }
+    }
+
+    /// Skips an at-rule that is not allowed where it appears, such as one nested in a
+    /// declaration block: its prelude, then its block or its `;`.
+    fn skip_at_rule(&mut self) {
+        self.index += 1;
+        self.prelude();
+        if self.peek().is_some_and(|token| token.is_char('{') || token.is_char(';')) {
+            self.skip_component_value();
+        }
+    }
+
+    /// Reads the prelude of an at-rule: the component values up to its `{` or `;`, or
+    /// up to a `}` that closes the enclosing block. Leading and trailing whitespace is
+    /// dropped, and the `{`, `;` or `}` is not consumed.
+    fn prelude(&mut self) -> Vec<Token> {
+        let mut prelude = vec![];
+        while let Some(token) = self.peek() {
+            if token.is_char('{') || token.is_char(';') || token.is_char('}') {
+                break;
+            }
+            self.component_value(&mut prelude);
+        }
+        trim_whitespace(&prelude).to_vec()
+    }
+
+    /// Skips a style rule that failed to parse: its prelude up to `{` and the block after
+    /// it. Inside a block (`top_level` false), a `}` before any `{` closes the enclosing
+    /// block, so it ends the rule without being consumed. At the top level it is part of
+    /// the prelude, as in CSS.
+    fn skip_rule(&mut self, top_level: bool) {
+        while let Some(token) = self.peek() {
+            if token.is_char('{') {
+                self.skip_component_value();
+                return;
+            }
+            if token.is_char('}') && !top_level {
+                return;
+            }
+            self.skip_component_value();
         }
-        Ok(rules)
     }
 
     /// Parses a CSS-like rule and adds it to the provided vector of rules.
@@ -493,19 +802,20 @@ impl CssParser {
     /// style declarations, then appends them together as a tuple to the provided
     /// vector. The input is parsed in the following steps:
     ///
-    /// 1. Skips leading whitespace characters.
-    /// 2. Parses the selector using the `selector` method.
+    /// 1. Skips leading whitespace tokens.
+    /// 2. Parses the comma-separated selector list using the `selector_list` method.
     /// 3. Verifies and consumes the opening '{' literal.
-    /// 4. Skips any additional whitespace.
-    /// 5. Extracts the body (key-value style declarations) using the `body` method.
-    /// 6. Verifies and consumes the closing '}' literal.
-    /// 7. Pushes the parsed `(selector, body)` pair into the `rules` vector.
+    /// 4. Extracts the body (key-value style declarations) using the `body` method.
+    /// 5. Verifies and consumes the closing '}' literal. A block left open at the end of
+    ///    the style sheet is closed implicitly, as in CSS.
+    /// 6. Pushes a `(selector, body)` pair into the `rules` vector for each selector in
+    ///    the list, so that `h1, h2 { ... }` becomes two rules with the same body.
     ///
     /// # Parameters
     ///
     /// - `rules`: A mutable reference to a vector of tuples, where each tuple
-    ///   consists of a `Selector` (representing the selector for a rule) and a
-    ///   `HashMap<String, String>` (representing the style key-value declarations).
+    ///   consists of a `Selector` (representing the selector for a rule) and its
+    ///   `Declarations` (representing the style declarations).
     ///
     /// # Returns
     ///
@@ -516,13 +826,12 @@ impl CssParser {
     ///
     /// An error will be returned if any of the following parsing steps fail:
     /// - Selector parsing (`self.selector` returns an error).
-    /// - Literal checking (`self.literal('{')` or `self.literal('}')` fails).
-    /// - Body parsing (`self.body` returns an error).
+    /// - Literal checking (`self.literal('{')` fails).
     ///
     /// # Example
     ///
     /// ```rust
-    /// let mut parser = MyParser::new();
+    /// let mut parser = CssParser::new("p { color: red }");
     /// let mut rules = Vec::new();
     ///
     /// match parser.parse_internal(&mut rules) {
@@ -536,26 +845,319 @@ impl CssParser {
     /// }
     /// ```
     ///
-    /// # Notes
-    ///
-    /// This method assumes that `self.whitespace`, `self.selector`, `self.literal`,
-    /// and `self.body` are implemented appropriately and will handle their
-    /// respective tasks with robustness.
-    ///
     /// # Dependencies
     ///
     /// - `Selector`: A type that represents a CSS selector.
-    /// - `HashMap<String, String>`: The data structure used to store key-value
-    ///   pairs of style declarations.
-    fn parse_internal(&mut self, rules: &mut Vec<(Selector, HashMap<String, String>)>) -> Result<(), String> {
+    /// - `Declarations`: The data structure used to store the style declarations, in
+    ///   source order.
+    fn parse_internal(&mut self, rules: &mut Vec<StyleRule>) -> Result<(), String> {
         self.whitespace();
-        let selector = self.selector()?;
+        let selectors = self.selector_list()?;
         self.literal('{')?;
-        self.whitespace();
         let body = self.body()?;
-        self.literal('}')?;
-        rules.push((selector, body));
+        if self.peek().is_some() {
+            self.literal('}')?;
+        }
+        for selector in selectors {
+            rules.push((selector, body.clone()));
+        }
         Ok(())
     }
 }
 
+/// Returns `tokens` without leading and trailing whitespace tokens.
+fn trim_whitespace(tokens: &[Token]) -> &[Token] {
+    let start = tokens.iter().position(|t| *t != Token::Whitespace).unwrap_or(tokens.len());
+    let end = tokens.iter().rposition(|t| *t != Token::Whitespace).map_or(start, |end| end + 1);
+    &tokens[start..end]
+}
+
+/// Parses the prelude of `@import`: a `url()` or a string, then an optional media query
+/// list. Returns `None` if there is no URL.
+fn parse_import_prelude(prelude: &[Token]) -> Option<(String, MediaQueryList)> {
+    let (url, rest) = match prelude {
+        [Token::Url(url) | Token::String(url), rest @ ..] => (url.clone(), rest),
+        [Token::Function(name), rest @ ..] if name.eq_ignore_ascii_case("url") => {
+            let end = rest.iter().position(|t| *t == Token::CloseParen)?;
+            let [Token::String(url)] = trim_whitespace(&rest[..end]) else {
+                return None;
+            };
+            (url.clone(), &rest[end + 1..])
+        }
+        _ => return None,
+    };
+    if url.is_empty() {
+        return None;
+    }
+    Some((url, MediaQueryList::parse(&serialize(rest))))
+}
+
+/// Evaluates the condition of an `@supports` rule, such as
+/// `(display: grid) and (not (color: foo))` or `selector(:is(a))`.
+///
+/// # Returns
+/// `Some(result)`, or `None` if the condition is malformed, in which case the rule is
+/// dropped as in CSS.
+pub(crate) fn supports_condition(condition: &str) -> Option<bool> {
+    let condition = condition.trim();
+    if let Some(rest) = strip_keyword(condition, "not") {
+        let (value, rest) = supports_in_parens(rest)?;
+        return rest.trim().is_empty().then_some(!value);
+    }
+
+    let (mut result, mut rest) = supports_in_parens(condition)?;
+    let mut operator = None;
+    while !rest.trim().is_empty() {
+        let trimmed = rest.trim_start();
+        let (keyword, after) = if let Some(after) = strip_keyword(trimmed, "and") {
+            ("and", after)
+        } else if let Some(after) = strip_keyword(trimmed, "or") {
+            ("or", after)
+        } else {
+            return None;
+        };
+        // `and` and `or` cannot be mixed without parentheses.
+        if operator.is_some_and(|operator| operator != keyword) {
+            return None;
+        }
+        operator = Some(keyword);
+        let (value, after) = supports_in_parens(after)?;
+        result = if keyword == "and" { result && value } else { result || value };
+        rest = after;
+    }
+    Some(result)
+}
+
+/// Evaluates one parenthesized part of an `@supports` condition and returns its value
+/// with the text after it.
+fn supports_in_parens(text: &str) -> Option<(bool, &str)> {
+    let text = text.trim_start();
+    if text.get(..9).is_some_and(|head| head.eq_ignore_ascii_case("selector(")) {
+        let end = 9 + matching_paren(&text[9..])?;
+        let supported = CssParser::new(&text[9..end]).selector_list_only().is_ok();
+        return Some((supported, &text[end + 1..]));
+    }
+    let inner_text = text.strip_prefix('(')?;
+    let end = matching_paren(inner_text)?;
+    let inner = inner_text[..end].trim();
+    let rest = &inner_text[end + 1..];
+    let is_condition = inner.starts_with('(')
+        || strip_keyword(inner, "not").is_some()
+        || inner.to_ascii_lowercase().starts_with("selector(");
+    let value = if is_condition {
+        supports_condition(inner)?
+    } else if let Some((property, value)) = inner.split_once(':') {
+        let property = property.trim();
+        let property = if property.starts_with("--") { property.to_string() } else { property.to_ascii_lowercase() };
+        let value = value.trim();
+        !value.is_empty() && ComputedStyle::supports(&property, value)
+    } else {
+        // Unknown syntax in parentheses is false rather than invalid.
+        false
+    };
+    Some((value, rest))
+}
+
+/// Adds a declaration after those of a block, replacing an earlier one for the same
+/// property unless only the earlier one is `!important`, which wins within the block
+/// as it would across rules.
+fn declare(pairs: &mut Declarations, property: String, declaration: Declaration) {
+    if let Some(index) = pairs.iter().position(|(name, _)| *name == property) {
+        if pairs[index].1.1 && !declaration.1 {
+            return;
+        }
+        pairs.remove(index);
+    }
+    pairs.push((property, declaration));
+}
+
+/// Strips a keyword such as `not` from the start of `text` if it is followed by
+/// whitespace or `(`.
+fn strip_keyword<'a>(text: &'a str, keyword: &str) -> Option<&'a str> {
+    let head = text.get(..keyword.len())?;
+    let rest = &text[keyword.len()..];
+    (head.eq_ignore_ascii_case(keyword) && rest.starts_with(|c: char| c.is_whitespace() || c == '('))
+        .then_some(rest)
+}
+
+/// Parses the argument of `:nth-child()`: `odd`, `even`, an integer `b`, or `an+b` in any
+/// of its forms (`2n+1`, `-n + 3`, `n`, `+4n`), and returns `(a, b)`.
+fn parse_nth(argument: &str) -> Option<(i32, i32)> {
+    let argument = argument.trim().to_ascii_lowercase();
+    match argument.as_str() {
+        "odd" => return Some((2, 1)),
+        "even" => return Some((2, 0)),
+        _ => {}
+    }
+
+    let Some(n_index) = argument.find('n') else {
+        return argument.parse::<i32>().ok().map(|b| (0, b));
+    };
+    let a = match &argument[..n_index] {
+        "" | "+" => 1,
+        "-" => -1,
+        a => a.parse::<i32>().ok()?,
+    };
+    let rest = argument[n_index + 1..].trim();
+    if rest.is_empty() {
+        return Some((a, 0));
+    }
+    let (sign, digits) = if let Some(digits) = rest.strip_prefix('+') {
+        (1, digits.trim())
+    } else if let Some(digits) = rest.strip_prefix('-') {
+        (-1, digits.trim())
+    } else {
+        return None;
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((a, sign * digits.parse::<i32>().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::{ColorScheme, MediaContext, MediaType};
+    use crate::style::parse_font_family;
+    use egui::Vec2;
+
+    /// Returns the value of the last declaration of `property` in the style rules of `css`.
+    fn value(css: &str, property: &str) -> Option<String> {
+        CssParser::new(css).parse().unwrap().into_iter()
+            .flat_map(|(_, declarations)| declarations)
+            .filter(|(name, _)| name == property)
+            .map(|(_, (value, _))| value)
+            .next_back()
+    }
+
+    #[test]
+    fn deeply_nested_blocks_are_skipped() {
+        let css = format!("p {{ color: green; x: {} }}", "[(".repeat(100_000));
+        assert_eq!(value(&css, "color").as_deref(), Some("green"));
+        let css = format!("@unknown {} p {{ color: red }}", "{".repeat(100_000));
+        assert!(CssParser::new(&css).parse_rules().is_empty());
+    }
+
+    #[test]
+    fn an_invalid_selector_drops_the_whole_list() {
+        for list in ["span, p:bogus", "span, > p", "span,", ", span", "span p:nth-child(2n+)"] {
+            assert!(CssParser::new(list).selector_list_only().is_err(), "{} should be invalid", list);
+        }
+        assert_eq!(CssParser::new("span, p:is(.a, .b) > em").selector_list_only().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn deeply_nested_selectors_are_rejected() {
+        let nested = |depth: usize| format!("{}p{}", ":is(".repeat(depth), ")".repeat(depth));
+        assert!(CssParser::new(&nested(MAX_NESTING_DEPTH)).selector_list_only().is_ok());
+        assert!(CssParser::new(&nested(MAX_NESTING_DEPTH + 1)).selector_list_only().is_err());
+
+        let css = format!("{}p{} {{ color: red }} p {{ color: green }}", ":not(".repeat(1000), ")".repeat(1000));
+        assert_eq!(CssParser::new(&css).parse_rules().len(), 1);
+        assert_eq!(value(&css, "color").as_deref(), Some("green"));
+    }
+
+    #[test]
+    fn long_selector_chains_are_rejected() {
+        let chain = |combinators: usize| format!("{}p", "div > ".repeat(combinators));
+        assert!(CssParser::new(&chain(MAX_NESTING_DEPTH)).selector_list_only().is_ok());
+        assert!(CssParser::new(&chain(MAX_NESTING_DEPTH + 1)).selector_list_only().is_err());
+
+        let css = format!("{} {{ color: red }} p {{ color: green }}", chain(100_000));
+        assert_eq!(value(&css, "color").as_deref(), Some("green"));
+    }
+
+    #[test]
+    fn imports_are_only_honored_before_other_rules() {
+        let rules = CssParser::new("@charset \"utf-8\"; @import url(\"a.css\") screen; @import 'b.css';
+            p { color: red; } @import url(c.css);").parse_rules();
+        let imports: Vec<_> = rules.iter().filter_map(|rule| match rule {
+            CssRule::Import { url, queries } => Some((url.clone(), queries.clone())),
+            _ => None,
+        }).collect();
+        assert_eq!(imports.len(), 2);
+        assert_eq!(imports[0].0, "a.css");
+        assert_eq!(imports[1].0, "b.css");
+
+        let context = MediaContext {
+            media_type: MediaType::Print,
+            viewport: Vec2::new(800.0, 600.0),
+            color_scheme: ColorScheme::Light,
+        };
+        assert!(!imports[0].1.matches(&context));
+        assert!(imports[1].1.matches(&context));
+    }
+
+    #[test]
+    fn font_faces_keep_their_descriptors() {
+        let rules = CssParser::new("@font-face { font-family: 'My Font'; src: local(My), url(my.woff2) format('woff2'), url(\"my.ttf\"); }")
+            .parse_rules();
+        let [CssRule::FontFace(descriptors)] = rules.as_slice() else {
+            panic!("expected a single @font-face rule");
+        };
+        assert_eq!(parse_font_family(&descriptors["font-family"]), Some(vec!["my font".to_string()]));
+        assert_eq!(descriptors["src"], "local(My), url(\"my.woff2\") format(\"woff2\"), url(\"my.ttf\")");
+    }
+
+    #[test]
+    fn unknown_at_rules_are_skipped_with_their_nested_blocks() {
+        let css = "@keyframes spin { from { color: red; } to { color: red; } }
+            @unknown \"}\";
+            p { color: blue; }
+            @media (min-width: 100px) { @page { margin: 1in } p { color: green; } }";
+        let rules = CssParser::new(css).parse_rules();
+        assert_eq!(rules.len(), 2);
+        assert!(matches!(&rules[0], CssRule::Style(_)));
+        let CssRule::Media { rules, .. } = &rules[1] else {
+            panic!("expected an @media rule");
+        };
+        assert_eq!(rules.len(), 1);
+    }
+
+    #[test]
+    fn deeply_nested_conditional_rules_are_skipped() {
+        let nested = |depth: usize| format!("{}p {{ color: green }}{}", "@media screen { @supports (color: red) { ".repeat(depth), "} } ".repeat(depth));
+        let css = nested(MAX_NESTING_DEPTH / 2);
+        let mut rules = CssParser::new(&css).parse_rules();
+        for _ in 0..MAX_NESTING_DEPTH / 2 {
+            let [CssRule::Media { rules: nested, .. }] = rules.as_slice() else {
+                panic!("expected a single @media rule");
+            };
+            rules = nested.clone();
+        }
+        assert!(matches!(rules.as_slice(), [CssRule::Style(_)]));
+
+        let css = format!("{} p {{ color: blue }}", nested(10_000));
+        let rules = CssParser::new(&css).parse_rules();
+        assert!(matches!(rules.as_slice(), [CssRule::Media { .. }, CssRule::Style(_)]));
+        assert_eq!(value(&css, "color").as_deref(), Some("blue"));
+    }
+
+    #[test]
+    fn supports_rules_apply_only_when_supported() {
+        let css = "@supports (font-weight: bold) and (not (color: nonsense)) { p { color: blue; } }
+            @supports (font-weight: heavy) or (colour: red) { p { color: red; } }";
+        assert_eq!(value(css, "color").as_deref(), Some("blue"));
+
+        let css = "@supports selector(p:is(.a, .b)) { p { color: blue; } }
+            @supports selector(p::nonsense) { p { color: red; } }
+            @supports (color: red) and (color: red) or (color: red) { p { color: red; } }";
+        assert_eq!(value(css, "color").as_deref(), Some("blue"));
+    }
+
+    #[test]
+    fn parse_errors_drop_only_the_bad_declaration_or_rule() {
+        let css = "p { color: \"unterminated\n; color: green; background: url(a b); font-size: 20px }";
+        assert_eq!(value(css, "color").as_deref(), Some("green"));
+        assert_eq!(value(css, "background-color"), None);
+        assert_eq!(value(css, "font-size").as_deref(), Some("20px"));
+
+        let css = "p { color: red; @nested { color: blue } font-size: 30px }
+            p::before, p { color: red } p:unknown { color: red } p { } p { color: green";
+        let rules = CssParser::new(css).parse().unwrap();
+        assert_eq!(rules.len(), 3);
+        assert_eq!(value(css, "color").as_deref(), Some("green"));
+        assert_eq!(value(css, "font-size").as_deref(), Some("30px"));
+    }
+}
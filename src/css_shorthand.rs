@@ -0,0 +1,623 @@
+//! Expansion of CSS shorthand properties into their longhands.
+//!
+//! Layout and styling only read longhand properties such as `margin-top` or
+//! `font-size`, so `CssParser::body` expands shorthands like `margin: 0 auto` or
+//! `font: italic bold 14px/1.4 sans-serif` as it parses them. Longhands that a
+//! shorthand leaves out are reset to their initial values, as in CSS, so that
+//! `border: 1px solid` also resets `border-*-color`.
+
+use crate::css_grid::GridLine;
+
+/// The CSS-wide keywords, which every shorthand passes on unchanged to all of its longhands.
+const CSS_WIDE_KEYWORDS: &[&str] = &["inherit", "initial", "unset", "revert"];
+
+/// The values of `border-style` and its longhands.
+const BORDER_STYLES: &[&str] = &[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+/// The keyword values of `border-width` and its longhands.
+const BORDER_WIDTHS: &[&str] = &["thin", "medium", "thick"];
+
+/// The keyword values of `font-size`.
+const FONT_SIZES: &[&str] = &[
+    "xx-small", "x-small", "small", "medium", "large", "x-large", "xx-large", "xxx-large",
+    "smaller", "larger",
+];
+
+/// The keyword values of `font-stretch` that can appear in the `font` shorthand.
+const FONT_STRETCHES: &[&str] = &[
+    "ultra-condensed", "extra-condensed", "condensed", "semi-condensed",
+    "semi-expanded", "expanded", "extra-expanded", "ultra-expanded",
+];
+
+/// The keyword values of `list-style-position`.
+const LIST_STYLE_POSITIONS: &[&str] = &["inside", "outside"];
+
+/// The keyword values of `background-repeat`.
+const BACKGROUND_REPEATS: &[&str] = &["repeat", "repeat-x", "repeat-y", "no-repeat", "space", "round"];
+
+/// The keyword values of `background-attachment`.
+const BACKGROUND_ATTACHMENTS: &[&str] = &["scroll", "fixed", "local"];
+
+/// The keywords of `background-position`.
+const BACKGROUND_POSITIONS: &[&str] = &["left", "right", "top", "bottom", "center"];
+
+/// The values of `flex-direction`.
+const FLEX_DIRECTIONS: &[&str] = &["row", "row-reverse", "column", "column-reverse"];
+
+/// The values of `flex-wrap`.
+const FLEX_WRAPS: &[&str] = &["nowrap", "wrap", "wrap-reverse"];
+
+/// The four sides of a box, in the order that 1-to-4-value shorthands list them.
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+/// Expands `property: value` into longhand declarations.
+///
+/// # Returns
+/// - `None` if `property` is not a shorthand this module knows; the declaration is
+///   then kept as written.
+/// - `Some(Ok(longhands))` with every longhand of the shorthand, in a fixed order.
+/// - `Some(Err(..))` if the value is not valid for the shorthand, in which case the
+///   whole declaration must be dropped, as CSS does for invalid declarations.
+///
+/// # Example
+/// ```
+/// let longhands = expand("margin", "0 auto").unwrap().unwrap();
+/// // [("margin-top", "0"), ("margin-right", "auto"), ("margin-bottom", "0"), ("margin-left", "auto")]
+/// ```
+pub(crate) fn expand(property: &str, value: &str) -> Option<Result<Vec<(String, String)>, String>> {
+    let longhands = longhands(property)?;
+    let value = value.trim();
+    if CSS_WIDE_KEYWORDS.iter().any(|k| value.eq_ignore_ascii_case(k)) {
+        let keyword = value.to_ascii_lowercase();
+        return Some(Ok(longhands.into_iter().map(|l| (l, keyword.clone())).collect()));
+    }
+
+    let tokens = split_tokens(value);
+    let expanded = match property {
+        "margin" | "padding" => box_sides(&tokens, |side| format!("{}-{}", property, side)),
+        "inset" => box_sides(&tokens, |side| side.to_string()),
+        "border-width" | "border-style" | "border-color" => {
+            let part = &property["border-".len()..];
+            box_sides(&tokens, |side| format!("border-{}-{}", side, part))
+        }
+        "border" => border(&tokens, &SIDES),
+        "border-top" | "border-right" | "border-bottom" | "border-left" => {
+            border(&tokens, &[&property["border-".len()..]])
+        }
+        "font" => font(&tokens),
+        "background" => background(&tokens),
+        "list-style" => list_style(&tokens),
+        "flex" => flex(&tokens),
+        "flex-flow" => flex_flow(&tokens),
+        "gap" => gap(&tokens),
+        "grid-row" | "grid-column" | "grid-area" => grid_lines(&tokens, &longhands),
+        _ => return None,
+    };
+    Some(expanded.map_err(|e| format!("Invalid value '{}' for '{}': {}", value, property, e)))
+}
+
+/// Returns the longhands of `property`, or `None` if it is not a supported shorthand.
+fn longhands(property: &str) -> Option<Vec<String>> {
+    let per_side = |f: &dyn Fn(&str) -> String| SIDES.iter().map(|side| f(side)).collect::<Vec<_>>();
+    let border_side = |side: &str| ["width", "style", "color"].iter()
+        .map(|part| format!("border-{}-{}", side, part))
+        .collect::<Vec<_>>();
+
+    Some(match property {
+        "margin" | "padding" => per_side(&|side| format!("{}-{}", property, side)),
+        "inset" => per_side(&|side| side.to_string()),
+        "border-width" | "border-style" | "border-color" => {
+            let part = &property["border-".len()..];
+            per_side(&|side| format!("border-{}-{}", side, part))
+        }
+        "border" => SIDES.iter().flat_map(|side| border_side(side)).collect(),
+        "border-top" | "border-right" | "border-bottom" | "border-left" => border_side(&property["border-".len()..]),
+        "font" => ["font-style", "font-variant", "font-weight", "font-stretch", "font-size", "line-height", "font-family"]
+            .iter().map(|l| l.to_string()).collect(),
+        "background" => ["background-color", "background-image", "background-repeat", "background-attachment", "background-position"]
+            .iter().map(|l| l.to_string()).collect(),
+        "list-style" => ["list-style-type", "list-style-position", "list-style-image"]
+            .iter().map(|l| l.to_string()).collect(),
+        "flex" => ["flex-grow", "flex-shrink", "flex-basis"].iter().map(|l| l.to_string()).collect(),
+        "flex-flow" => ["flex-direction", "flex-wrap"].iter().map(|l| l.to_string()).collect(),
+        "gap" => ["row-gap", "column-gap"].iter().map(|l| l.to_string()).collect(),
+        "grid-row" | "grid-column" => ["start", "end"].iter().map(|edge| format!("{}-{}", property, edge)).collect(),
+        "grid-area" => ["grid-row-start", "grid-column-start", "grid-row-end", "grid-column-end"]
+            .iter().map(|l| l.to_string()).collect(),
+        _ => return None,
+    })
+}
+
+/// Expands a 1-to-4-value box shorthand such as `margin: 1px 2px`: one value applies to
+/// all sides, two to top/bottom and right/left, three to top, right/left and bottom,
+/// and four to top, right, bottom and left.
+fn box_sides(tokens: &[String], name: impl Fn(&str) -> String) -> Result<Vec<(String, String)>, String> {
+    let values = match tokens {
+        [all] => [all, all, all, all],
+        [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+        [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+        [top, right, bottom, left] => [top, right, bottom, left],
+        _ => return Err(format!("expected 1 to 4 values, got {}", tokens.len())),
+    };
+    Ok(SIDES.iter().zip(values).map(|(side, value)| (name(side), value.clone())).collect())
+}
+
+/// Expands `border` or `border-<side>`: a width, a style and a color in any order, each
+/// optional. Omitted parts are reset to `medium`, `none` and `currentcolor`.
+fn border(tokens: &[String], sides: &[&str]) -> Result<Vec<(String, String)>, String> {
+    let (mut width, mut style, mut color) = (None, None, None);
+    for token in tokens {
+        let lower = token.to_ascii_lowercase();
+        if style.is_none() && BORDER_STYLES.contains(&lower.as_str()) {
+            style = Some(lower);
+        } else if width.is_none() && (BORDER_WIDTHS.contains(&lower.as_str()) || is_length(token)) {
+            width = Some(token.clone());
+        } else if color.is_none() && is_color(token) {
+            color = Some(token.clone());
+        } else {
+            return Err(format!("unexpected '{}'", token));
+        }
+    }
+    if tokens.is_empty() {
+        return Err("expected a value".to_string());
+    }
+
+    let width = width.unwrap_or("medium".to_string());
+    let style = style.unwrap_or("none".to_string());
+    let color = color.unwrap_or("currentcolor".to_string());
+    Ok(sides.iter().flat_map(|side| [
+        (format!("border-{}-width", side), width.clone()),
+        (format!("border-{}-style", side), style.clone()),
+        (format!("border-{}-color", side), color.clone()),
+    ]).collect())
+}
+
+/// Expands `font: [style || variant || weight || stretch] size[/line-height] family`.
+/// The size and the family are required; everything else is reset to `normal`.
+fn font(tokens: &[String]) -> Result<Vec<(String, String)>, String> {
+    let (mut style, mut variant, mut weight, mut stretch) = (None, None, None, None);
+    let mut index = 0;
+    while index < tokens.len() {
+        let lower = tokens[index].to_ascii_lowercase();
+        match lower.as_str() {
+            // `normal` can stand for any of the four; it leaves them at `normal` either way.
+            "normal" => {}
+            "italic" | "oblique" if style.is_none() => style = Some(lower),
+            "small-caps" if variant.is_none() => variant = Some(lower),
+            "bold" | "bolder" | "lighter" if weight.is_none() => weight = Some(lower),
+            w if weight.is_none() && is_font_weight_number(w) => weight = Some(lower),
+            s if stretch.is_none() && FONT_STRETCHES.contains(&s) => stretch = Some(lower),
+            _ => break,
+        }
+        index += 1;
+    }
+
+    let size_token = tokens.get(index).ok_or("expected a font size")?;
+    let (size, mut line_height) = match size_token.split_once('/') {
+        Some((size, line_height)) => (size.to_string(), (!line_height.is_empty()).then(|| line_height.to_string())),
+        None => (size_token.clone(), None),
+    };
+    if !(is_length(&size) || FONT_SIZES.contains(&size.to_ascii_lowercase().as_str())) {
+        return Err(format!("expected a font size, got '{}'", size));
+    }
+    index += 1;
+
+    // The line height may also be written as a separate token: `14px / 1.4` or `14px /1.4`.
+    if line_height.is_none() && (size_token.ends_with('/') || tokens.get(index).is_some_and(|t| t.starts_with('/'))) {
+        let token = tokens.get(index).ok_or("expected a line height")?;
+        let rest = token.trim_start_matches('/');
+        if rest.is_empty() {
+            index += 1;
+            line_height = Some(tokens.get(index).ok_or("expected a line height")?.clone());
+        } else {
+            line_height = Some(rest.to_string());
+        }
+        index += 1;
+    }
+
+    let family = tokens[index..].join(" ");
+    if family.is_empty() {
+        return Err("expected a font family".to_string());
+    }
+
+    let normal = || "normal".to_string();
+    Ok(vec![
+        ("font-style".to_string(), style.unwrap_or_else(normal)),
+        ("font-variant".to_string(), variant.unwrap_or_else(normal)),
+        ("font-weight".to_string(), weight.unwrap_or_else(normal)),
+        ("font-stretch".to_string(), stretch.unwrap_or_else(normal)),
+        ("font-size".to_string(), size),
+        ("line-height".to_string(), line_height.unwrap_or_else(normal)),
+        ("font-family".to_string(), family),
+    ])
+}
+
+/// Expands a single-layer `background`: a color, an image, a repeat style, an
+/// attachment and a position, in any order. Omitted parts are reset to `transparent`,
+/// `none`, `repeat`, `scroll` and `0% 0%`.
+fn background(tokens: &[String]) -> Result<Vec<(String, String)>, String> {
+    if tokens.iter().any(|t| t == ",") || tokens.iter().any(|t| t.ends_with(',')) {
+        return Err("multiple background layers are not supported".to_string());
+    }
+    let (mut color, mut image, mut repeat, mut attachment) = (None, None, None, None);
+    let mut position = Vec::new();
+    for token in tokens {
+        let lower = token.to_ascii_lowercase();
+        if image.is_none() && (lower == "none" || lower.starts_with("url(") || lower.contains("gradient(")) {
+            image = Some(token.clone());
+        } else if repeat.is_none() && BACKGROUND_REPEATS.contains(&lower.as_str()) {
+            repeat = Some(lower);
+        } else if attachment.is_none() && BACKGROUND_ATTACHMENTS.contains(&lower.as_str()) {
+            attachment = Some(lower);
+        } else if BACKGROUND_POSITIONS.contains(&lower.as_str()) || is_length(token) {
+            position.push(token.clone());
+        } else if color.is_none() && is_color(token) {
+            color = Some(token.clone());
+        } else {
+            return Err(format!("unexpected '{}'", token));
+        }
+    }
+    if tokens.is_empty() {
+        return Err("expected a value".to_string());
+    }
+
+    Ok(vec![
+        ("background-color".to_string(), color.unwrap_or("transparent".to_string())),
+        ("background-image".to_string(), image.unwrap_or("none".to_string())),
+        ("background-repeat".to_string(), repeat.unwrap_or("repeat".to_string())),
+        ("background-attachment".to_string(), attachment.unwrap_or("scroll".to_string())),
+        ("background-position".to_string(), if position.is_empty() { "0% 0%".to_string() } else { position.join(" ") }),
+    ])
+}
+
+/// Expands `list-style`: a marker type, a position and an image, in any order. Omitted
+/// parts are reset to `disc`, `outside` and `none`. A `none` that is not needed for the
+/// image is the marker type.
+fn list_style(tokens: &[String]) -> Result<Vec<(String, String)>, String> {
+    let (mut kind, mut position, mut image) = (None, None, None);
+    let mut nones = 0;
+    for token in tokens {
+        let lower = token.to_ascii_lowercase();
+        if lower == "none" {
+            nones += 1;
+        } else if position.is_none() && LIST_STYLE_POSITIONS.contains(&lower.as_str()) {
+            position = Some(lower);
+        } else if image.is_none() && lower.starts_with("url(") {
+            image = Some(token.clone());
+        } else if kind.is_none() && !lower.contains('(') {
+            kind = Some(token.clone());
+        } else {
+            return Err(format!("unexpected '{}'", token));
+        }
+    }
+    if tokens.is_empty() {
+        return Err("expected a value".to_string());
+    }
+
+    // Each `none` fills whichever of the type and the image is still unset.
+    for _ in 0..nones {
+        if kind.is_none() {
+            kind = Some("none".to_string());
+        } else if image.is_none() {
+            image = Some("none".to_string());
+        } else {
+            return Err("too many 'none' values".to_string());
+        }
+    }
+
+    Ok(vec![
+        ("list-style-type".to_string(), kind.unwrap_or("disc".to_string())),
+        ("list-style-position".to_string(), position.unwrap_or("outside".to_string())),
+        ("list-style-image".to_string(), image.unwrap_or("none".to_string())),
+    ])
+}
+
+/// Expands `flex`. The keywords `none`, `auto` and `initial` stand for `0 0 auto`,
+/// `1 1 auto` and `0 1 auto`. Otherwise the value is `grow [shrink] [basis]` or
+/// `basis`, where an omitted grow or shrink is `1` and an omitted basis is `0%`.
+fn flex(tokens: &[String]) -> Result<Vec<(String, String)>, String> {
+    let (grow, shrink, basis) = match tokens {
+        [keyword] if keyword.eq_ignore_ascii_case("none") => ("0".to_string(), "0".to_string(), "auto".to_string()),
+        [keyword] if keyword.eq_ignore_ascii_case("auto") => ("1".to_string(), "1".to_string(), "auto".to_string()),
+        [grow] if is_number(grow) => (grow.clone(), "1".to_string(), "0%".to_string()),
+        [basis] if is_flex_basis(basis) => ("1".to_string(), "1".to_string(), basis.clone()),
+        [grow, shrink] if is_number(grow) && is_number(shrink) => (grow.clone(), shrink.clone(), "0%".to_string()),
+        [grow, basis] if is_number(grow) && is_flex_basis(basis) => (grow.clone(), "1".to_string(), basis.clone()),
+        [basis, grow] if is_flex_basis(basis) && is_number(grow) => (grow.clone(), "1".to_string(), basis.clone()),
+        [grow, shrink, basis] if is_number(grow) && is_number(shrink) && is_flex_basis(basis) => {
+            (grow.clone(), shrink.clone(), basis.clone())
+        }
+        [basis, grow, shrink] if is_flex_basis(basis) && is_number(grow) && is_number(shrink) => {
+            (grow.clone(), shrink.clone(), basis.clone())
+        }
+        _ => return Err("expected 'none', 'auto' or '<grow> <shrink> <basis>'".to_string()),
+    };
+    Ok(vec![
+        ("flex-grow".to_string(), grow),
+        ("flex-shrink".to_string(), shrink),
+        ("flex-basis".to_string(), basis),
+    ])
+}
+
+/// Expands `flex-flow`: a `flex-direction` and a `flex-wrap`, in any order, either of
+/// which may be omitted.
+fn flex_flow(tokens: &[String]) -> Result<Vec<(String, String)>, String> {
+    let (mut direction, mut wrap) = (None, None);
+    for token in tokens {
+        let lower = token.to_ascii_lowercase();
+        if direction.is_none() && FLEX_DIRECTIONS.contains(&lower.as_str()) {
+            direction = Some(lower);
+        } else if wrap.is_none() && FLEX_WRAPS.contains(&lower.as_str()) {
+            wrap = Some(lower);
+        } else {
+            return Err(format!("unexpected '{}'", token));
+        }
+    }
+    if tokens.is_empty() {
+        return Err("expected a value".to_string());
+    }
+    Ok(vec![
+        ("flex-direction".to_string(), direction.unwrap_or("row".to_string())),
+        ("flex-wrap".to_string(), wrap.unwrap_or("nowrap".to_string())),
+    ])
+}
+
+/// Expands `gap`: one value for both gaps, or the row gap then the column gap.
+fn gap(tokens: &[String]) -> Result<Vec<(String, String)>, String> {
+    let is_gap = |token: &String| is_length(token) || token.eq_ignore_ascii_case("normal");
+    let (row, column) = match tokens {
+        [both] if is_gap(both) => (both.clone(), both.clone()),
+        [row, column] if is_gap(row) && is_gap(column) => (row.clone(), column.clone()),
+        _ => return Err("expected '<row-gap> [<column-gap>]'".to_string()),
+    };
+    Ok(vec![("row-gap".to_string(), row), ("column-gap".to_string(), column)])
+}
+
+/// Expands `grid-row`, `grid-column` or `grid-area`: grid lines separated by `/`, in
+/// the order of `longhands`.
+///
+/// # Notes
+/// An omitted line is the same area name as the line it pairs with (the start line for
+/// an end, the row start for the column start), or `auto` if that is not a name.
+fn grid_lines(tokens: &[String], longhands: &[String]) -> Result<Vec<(String, String)>, String> {
+    let joined = tokens.join(" ");
+    let lines: Vec<&str> = joined.split('/').map(str::trim).collect();
+    if lines.len() > longhands.len() || lines.iter().any(|line| GridLine::parse(line).is_none()) {
+        return Err(format!("expected up to {} grid lines separated by '/'", longhands.len()));
+    }
+    let mut values: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+    for index in values.len()..longhands.len() {
+        // `grid-area` pairs each end with its start and the column start with the row
+        // start; the others pair the end with the start.
+        let pair = if index == 3 { 1 } else { 0 };
+        let value = match GridLine::parse(&values[pair]) {
+            Some(GridLine::Area(_)) => values[pair].clone(),
+            _ => "auto".to_string(),
+        };
+        values.push(value);
+    }
+    Ok(longhands.iter().cloned().zip(values).collect())
+}
+
+/// Splits a value into whitespace-separated tokens, keeping parenthesized groups such
+/// as `rgb(0, 0, 0)` and quoted strings together.
+fn split_tokens(value: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    for c in value.chars() {
+        match c {
+            '"' | '\'' if quote.is_none() => quote = Some(c),
+            c if Some(c) == quote => quote = None,
+            '(' if quote.is_none() => depth += 1,
+            ')' if quote.is_none() => depth = depth.saturating_sub(1),
+            c if c.is_whitespace() && depth == 0 && quote.is_none() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Returns `true` for a number, like the `2` in `flex: 2`.
+fn is_number(token: &str) -> bool {
+    token.parse::<f32>().is_ok_and(|n| n.is_finite() && n >= 0.0)
+}
+
+/// Returns `true` for a length or percentage (`12px`, `1.5em`, `50%`, `0`), or a math
+/// function such as `calc(...)` that may produce one.
+fn is_length(token: &str) -> bool {
+    let lower = token.to_ascii_lowercase();
+    if ["calc(", "min(", "max(", "clamp(", "var("].iter().any(|f| lower.starts_with(f)) {
+        return true;
+    }
+    let number_end = lower
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+        .unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(number_end);
+    if number.parse::<f32>().is_err() {
+        return false;
+    }
+    match unit {
+        "" => number.parse::<f32>().is_ok_and(|n| n == 0.0),
+        "%" => true,
+        unit => ["px", "em", "rem", "ex", "ch", "vw", "vh", "vmin", "vmax", "pt", "pc", "in", "cm", "mm", "q"].contains(&unit),
+    }
+}
+
+/// Returns `true` for a valid `flex-basis`: a length, `auto` or `content`.
+fn is_flex_basis(token: &str) -> bool {
+    is_length(token) || token.eq_ignore_ascii_case("auto") || token.eq_ignore_ascii_case("content")
+}
+
+/// Returns `true` for a numeric font weight between 1 and 1000.
+fn is_font_weight_number(token: &str) -> bool {
+    token.parse::<f32>().is_ok_and(|w| (1.0..=1000.0).contains(&w))
+}
+
+/// Returns `true` for a color: anything `csscolorparser` understands, or `currentcolor`.
+fn is_color(token: &str) -> bool {
+    token.eq_ignore_ascii_case("currentcolor") || csscolorparser::parse(token).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expands `property: value`, which must be valid, into `name: value` strings.
+    fn expanded(property: &str, value: &str) -> Vec<String> {
+        expand(property, value)
+            .unwrap_or_else(|| panic!("{} is not a shorthand", property))
+            .unwrap_or_else(|e| panic!("{}", e))
+            .into_iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect()
+    }
+
+    /// Returns `true` if `property: value` is dropped as invalid.
+    fn is_invalid(property: &str, value: &str) -> bool {
+        matches!(expand(property, value), Some(Err(_)))
+    }
+
+    #[test]
+    fn longhands_are_kept_as_written() {
+        assert!(expand("margin-top", "1px").is_none());
+        assert!(expand("font-size", "nonsense").is_none());
+    }
+
+    #[test]
+    fn css_wide_keywords_apply_to_every_longhand() {
+        assert_eq!(expanded("flex-flow", "INHERIT"), ["flex-direction: inherit", "flex-wrap: inherit"]);
+        assert_eq!(expanded("border-top", "unset"), ["border-top-width: unset", "border-top-style: unset", "border-top-color: unset"]);
+    }
+
+    #[test]
+    fn border_takes_a_width_style_and_color_in_any_order() {
+        let all = expanded("border", "red 2px dashed");
+        assert_eq!(all.len(), 12);
+        for side in SIDES {
+            for longhand in [format!("border-{}-width: 2px", side), format!("border-{}-style: dashed", side),
+                format!("border-{}-color: red", side)] {
+                assert!(all.contains(&longhand), "{} missing from {:?}", longhand, all);
+            }
+        }
+        assert_eq!(expanded("border-left", "SOLID"),
+            ["border-left-width: medium", "border-left-style: solid", "border-left-color: currentcolor"]);
+        assert_eq!(expanded("border-bottom", "thin rgb(0, 0, 0)"),
+            ["border-bottom-width: thin", "border-bottom-style: none", "border-bottom-color: rgb(0, 0, 0)"]);
+        assert_eq!(expanded("border-width", "1px 2px 3px"),
+            ["border-top-width: 1px", "border-right-width: 2px", "border-bottom-width: 3px", "border-left-width: 2px"]);
+        assert_eq!(expanded("border-style", "solid none"),
+            ["border-top-style: solid", "border-right-style: none", "border-bottom-style: solid", "border-left-style: none"]);
+        for invalid in ["", "solid solid", "1px 2px", "red blue", "solid wavy", "1px solid red 0"] {
+            assert!(is_invalid("border", invalid), "{:?} should be invalid", invalid);
+        }
+    }
+
+    #[test]
+    fn font_needs_a_size_and_a_family_after_the_other_parts() {
+        assert_eq!(expanded("font", "italic bold 14px/1.4 \"Open Sans\", sans-serif"), [
+            "font-style: italic", "font-variant: normal", "font-weight: bold", "font-stretch: normal",
+            "font-size: 14px", "line-height: 1.4", "font-family: \"Open Sans\", sans-serif",
+        ]);
+        assert_eq!(expanded("font", "small-caps 600 condensed large serif"), [
+            "font-style: normal", "font-variant: small-caps", "font-weight: 600", "font-stretch: condensed",
+            "font-size: large", "line-height: normal", "font-family: serif",
+        ]);
+        for value in ["12px / 2 serif", "12px /2 serif", "12px/ 2 serif"] {
+            assert!(expanded("font", value).contains(&"line-height: 2".to_string()), "{}", value);
+        }
+        assert_eq!(expanded("font", "normal normal 1em monospace")[..3],
+            ["font-style: normal", "font-variant: normal", "font-weight: normal"]);
+        // The size and family are required, and the style, variant, weight and stretch
+        // must come before the size.
+        for invalid in ["", "serif", "12px", "bold serif", "serif 12px", "italic italic 12px serif", "12px/ serif", "big serif"] {
+            assert!(is_invalid("font", invalid), "{:?} should be invalid", invalid);
+        }
+        // Anything after the size is the family, even keywords that belong before it.
+        assert_eq!(expanded("font", "12px bold serif").last().unwrap(), "font-family: bold serif");
+    }
+
+    #[test]
+    fn flex_keywords_and_numbers_expand_to_grow_shrink_and_basis() {
+        let flex = |value: &str| expanded("flex", value);
+        assert_eq!(flex("none"), ["flex-grow: 0", "flex-shrink: 0", "flex-basis: auto"]);
+        assert_eq!(flex("AUTO"), ["flex-grow: 1", "flex-shrink: 1", "flex-basis: auto"]);
+        assert_eq!(flex("2"), ["flex-grow: 2", "flex-shrink: 1", "flex-basis: 0%"]);
+        assert_eq!(flex("30px"), ["flex-grow: 1", "flex-shrink: 1", "flex-basis: 30px"]);
+        assert_eq!(flex("2 3"), ["flex-grow: 2", "flex-shrink: 3", "flex-basis: 0%"]);
+        assert_eq!(flex("2 content"), ["flex-grow: 2", "flex-shrink: 1", "flex-basis: content"]);
+        assert_eq!(flex("10% 2"), ["flex-grow: 2", "flex-shrink: 1", "flex-basis: 10%"]);
+        assert_eq!(flex("1 0 calc(50% - 1em)"), ["flex-grow: 1", "flex-shrink: 0", "flex-basis: calc(50% - 1em)"]);
+        assert_eq!(flex("auto 0 2"), ["flex-grow: 0", "flex-shrink: 2", "flex-basis: auto"]);
+        for invalid in ["", "-1", "1 -1", "1 2 3", "1px 2px", "none 1", "1 2 3px 4"] {
+            assert!(is_invalid("flex", invalid), "{:?} should be invalid", invalid);
+        }
+    }
+
+    #[test]
+    fn flex_flow_takes_a_direction_and_a_wrap_in_any_order() {
+        assert_eq!(expanded("flex-flow", "column"), ["flex-direction: column", "flex-wrap: nowrap"]);
+        assert_eq!(expanded("flex-flow", "Wrap-Reverse row-reverse"), ["flex-direction: row-reverse", "flex-wrap: wrap-reverse"]);
+        assert_eq!(expanded("flex-flow", "wrap"), ["flex-direction: row", "flex-wrap: wrap"]);
+        for invalid in ["", "row column", "wrap nowrap", "row wrap row", "diagonal"] {
+            assert!(is_invalid("flex-flow", invalid), "{:?} should be invalid", invalid);
+        }
+    }
+
+    #[test]
+    fn gap_sets_the_row_gap_then_the_column_gap() {
+        assert_eq!(expanded("gap", "10px"), ["row-gap: 10px", "column-gap: 10px"]);
+        assert_eq!(expanded("gap", "1em 5%"), ["row-gap: 1em", "column-gap: 5%"]);
+        assert_eq!(expanded("gap", "normal 0"), ["row-gap: normal", "column-gap: 0"]);
+        for invalid in ["", "1px 2px 3px", "red", "auto", "5"] {
+            assert!(is_invalid("gap", invalid), "{:?} should be invalid", invalid);
+        }
+    }
+
+    #[test]
+    fn list_style_fills_each_none_in_turn() {
+        assert_eq!(expanded("list-style", "square inside"),
+            ["list-style-type: square", "list-style-position: inside", "list-style-image: none"]);
+        assert_eq!(expanded("list-style", "url(dot.png) none"),
+            ["list-style-type: none", "list-style-position: outside", "list-style-image: url(dot.png)"]);
+        assert_eq!(expanded("list-style", "none none"),
+            ["list-style-type: none", "list-style-position: outside", "list-style-image: none"]);
+        assert_eq!(expanded("list-style", "OUTSIDE"),
+            ["list-style-type: disc", "list-style-position: outside", "list-style-image: none"]);
+        for invalid in ["", "none none none", "disc square", "url(a.png) url(b.png)", "square none none"] {
+            assert!(is_invalid("list-style", invalid), "{:?} should be invalid", invalid);
+        }
+    }
+
+    #[test]
+    fn grid_line_shorthands_fill_omitted_lines() {
+        assert_eq!(expanded("grid-row", "1 / 3"), ["grid-row-start: 1", "grid-row-end: 3"]);
+        assert_eq!(expanded("grid-column", "span 2"), ["grid-column-start: span 2", "grid-column-end: auto"]);
+        assert_eq!(expanded("grid-column", "main"), ["grid-column-start: main", "grid-column-end: main"]);
+        assert_eq!(expanded("grid-area", "header"), [
+            "grid-row-start: header", "grid-column-start: header", "grid-row-end: header", "grid-column-end: header",
+        ]);
+        assert_eq!(expanded("grid-area", "2 / side"), [
+            "grid-row-start: 2", "grid-column-start: side", "grid-row-end: auto", "grid-column-end: side",
+        ]);
+        assert_eq!(expanded("grid-area", "1 / 2 / 3 / span 4"), [
+            "grid-row-start: 1", "grid-column-start: 2", "grid-row-end: 3", "grid-column-end: span 4",
+        ]);
+        for invalid in ["1 / 2 / 3", "/ 2", "span", "1 2 3"] {
+            assert!(is_invalid("grid-row", invalid), "{:?} should be invalid", invalid);
+        }
+        assert!(is_invalid("grid-area", "1 / 2 / 3 / 4 / 5"));
+    }
+}
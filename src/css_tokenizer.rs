@@ -0,0 +1,625 @@
+//! The tokenizer of CSS Syntax Module Level 3, which `CssParser` is built on.
+//!
+//! The whole style sheet is turned into a list of tokens up front. Comments are dropped
+//! here, and escapes in identifiers, strings and URLs are resolved, so the parser never
+//! sees them. Declaration values are handed to `ComputedStyle` as text again, through
+//! `serialize`.
+
+/// A CSS token.
+///
+/// # Variants
+/// - `Ident`, `Function`, `AtKeyword`: `color`, `rgb(` and `@media`, with the name unescaped.
+///   A `Function` is followed by its arguments and a `CloseParen`.
+/// - `Hash`: `#main`. `is_id` is `true` if the name is a valid identifier, as ID selectors
+///   require; `#123` is a valid color but not an ID.
+/// - `String`, `Url`: The unescaped contents of `"..."` and of an unquoted `url(...)`.
+///   `url("...")` is a `Function` containing a `String` instead.
+/// - `BadString`, `BadUrl`: A string with an unescaped newline and a malformed `url(`.
+///   Declarations containing them are invalid.
+/// - `Number`, `Percentage`, `Dimension`: Numbers, with their source text in `repr` so
+///   that they serialize unchanged.
+/// - `Delim`: Any other single character, such as `.`, `>` or `!`.
+/// - `Cdo`, `Cdc`: `<!--` and `-->`, which are ignored at the top level of a style sheet.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Function(String),
+    AtKeyword(String),
+    Hash { value: String, is_id: bool },
+    String(String),
+    BadString,
+    Url(String),
+    BadUrl,
+    Delim(char),
+    Number { value: f32, repr: String },
+    Percentage { value: f32, repr: String },
+    Dimension { value: f32, repr: String, unit: String },
+    Whitespace,
+    Cdo,
+    Cdc,
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquare,
+    CloseSquare,
+    OpenParen,
+    CloseParen,
+    OpenCurly,
+    CloseCurly,
+}
+
+impl Token {
+    /// Returns `true` if the token is the punctuation or delimiter `c`, so that the parser
+    /// can match `:` or `{` the same way it matches `>` or `!`.
+    pub fn is_char(&self, c: char) -> bool {
+        match self {
+            Token::Delim(delim) => *delim == c,
+            Token::Colon => c == ':',
+            Token::Semicolon => c == ';',
+            Token::Comma => c == ',',
+            Token::OpenSquare => c == '[',
+            Token::CloseSquare => c == ']',
+            Token::OpenParen => c == '(',
+            Token::CloseParen => c == ')',
+            Token::OpenCurly => c == '{',
+            Token::CloseCurly => c == '}',
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the token is the identifier `name`, ignoring ASCII case.
+    pub fn is_ident(&self, name: &str) -> bool {
+        matches!(self, Token::Ident(ident) if ident.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the token that closes a block opened by this token: `)` for `(` and
+    /// functions, `]` for `[` and `}` for `{`.
+    pub fn closing(&self) -> Option<Token> {
+        match self {
+            Token::OpenParen | Token::Function(_) => Some(Token::CloseParen),
+            Token::OpenSquare => Some(Token::CloseSquare),
+            Token::OpenCurly => Some(Token::CloseCurly),
+            _ => None,
+        }
+    }
+
+    /// Writes the token as CSS text, escaping what needs it.
+    fn write(&self, out: &mut String) {
+        match self {
+            Token::Ident(name) => out.push_str(&escape_identifier(name)),
+            Token::Function(name) => {
+                out.push_str(&escape_identifier(name));
+                out.push('(');
+            }
+            Token::AtKeyword(name) => {
+                out.push('@');
+                out.push_str(&escape_identifier(name));
+            }
+            Token::Hash { value, is_id } => {
+                out.push('#');
+                if *is_id {
+                    out.push_str(&escape_identifier(value));
+                } else {
+                    value.chars().for_each(|c| escape_name_char(c, out));
+                }
+            }
+            Token::String(value) => out.push_str(&quote_string(value)),
+            Token::Url(value) => {
+                out.push_str("url(");
+                out.push_str(&quote_string(value));
+                out.push(')');
+            }
+            Token::BadString | Token::BadUrl => {}
+            Token::Delim(c) => out.push(*c),
+            Token::Number { repr, .. } => out.push_str(repr),
+            Token::Percentage { repr, .. } => {
+                out.push_str(repr);
+                out.push('%');
+            }
+            Token::Dimension { repr, unit, .. } => {
+                out.push_str(repr);
+                // A unit like `e3` would be read back as the exponent of the number.
+                let mut chars = unit.chars().skip(1);
+                let looks_like_exponent = unit.starts_with(['e', 'E']) && match chars.next() {
+                    Some('-') => chars.next().is_some_and(|c| c.is_ascii_digit()),
+                    c => c.is_some_and(|c| c.is_ascii_digit()),
+                };
+                if looks_like_exponent {
+                    out.push('\\');
+                    out.push_str(&format!("{:x} ", unit.chars().next().unwrap() as u32));
+                    unit.chars().skip(1).for_each(|c| escape_name_char(c, out));
+                } else {
+                    out.push_str(&escape_identifier(unit));
+                }
+            }
+            Token::Whitespace => out.push(' '),
+            Token::Cdo => out.push_str("<!--"),
+            Token::Cdc => out.push_str("-->"),
+            Token::Colon => out.push(':'),
+            Token::Semicolon => out.push(';'),
+            Token::Comma => out.push(','),
+            Token::OpenSquare => out.push('['),
+            Token::CloseSquare => out.push(']'),
+            Token::OpenParen => out.push('('),
+            Token::CloseParen => out.push(')'),
+            Token::OpenCurly => out.push('{'),
+            Token::CloseCurly => out.push('}'),
+        }
+    }
+}
+
+/// Turns a list of tokens back into CSS text, such as a declaration value for
+/// `ComputedStyle`. Whitespace tokens become single spaces, and a space is put between
+/// tokens that would otherwise read back as one (as after a comment in `1px/**/solid`).
+pub fn serialize(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 && needs_separator(&tokens[i - 1], token) {
+            out.push(' ');
+        }
+        token.write(&mut out);
+    }
+    out
+}
+
+/// Returns `true` if `next` written right after `previous` would be tokenized differently,
+/// following the table in CSS Syntax's serialization section.
+fn needs_separator(previous: &Token, next: &Token) -> bool {
+    let word_like = matches!(next,
+        Token::Ident(_) | Token::Function(_) | Token::Url(_) | Token::BadUrl | Token::Delim('-')
+        | Token::Number { .. } | Token::Percentage { .. } | Token::Dimension { .. });
+    match previous {
+        Token::Ident(_) => word_like || matches!(next, Token::Cdc | Token::OpenParen),
+        Token::AtKeyword(_) | Token::Hash { .. } | Token::Dimension { .. } => word_like || matches!(next, Token::Cdc),
+        Token::Delim('#' | '-') => word_like,
+        Token::Number { .. } => (word_like && !matches!(next, Token::Delim('-'))) || matches!(next, Token::Delim('%')),
+        Token::Delim('@') => matches!(next,
+            Token::Ident(_) | Token::Function(_) | Token::Url(_) | Token::BadUrl | Token::Delim('-')),
+        Token::Delim('.' | '+') => matches!(next,
+            Token::Number { .. } | Token::Percentage { .. } | Token::Dimension { .. }),
+        Token::Delim('/') => matches!(next, Token::Delim('*')),
+        _ => false,
+    }
+}
+
+/// Returns `name` as a CSS identifier, escaping the characters that cannot appear in one
+/// as written.
+fn escape_identifier(name: &str) -> String {
+    let mut out = String::new();
+    let chars: Vec<char> = name.chars().collect();
+    if chars == ['-'] {
+        return "\\-".to_string();
+    }
+    for (i, &c) in chars.iter().enumerate() {
+        let starts_with_digit = c.is_ascii_digit() && (i == 0 || (i == 1 && chars[0] == '-'));
+        if starts_with_digit {
+            out.push_str(&format!("\\{:x} ", c as u32));
+        } else {
+            escape_name_char(c, &mut out);
+        }
+    }
+    out
+}
+
+/// Writes a character of a name, escaping it if it is not a name character.
+fn escape_name_char(c: char, out: &mut String) {
+    match c {
+        '\0' => out.push('\u{FFFD}'),
+        '\u{1}'..='\u{1f}' | '\u{7f}' => out.push_str(&format!("\\{:x} ", c as u32)),
+        c if is_name_char(c) => out.push(c),
+        c => {
+            out.push('\\');
+            out.push(c);
+        }
+    }
+}
+
+/// Returns `value` in double quotes, escaping quotes, backslashes and newlines.
+fn quote_string(value: &str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\0' => out.push('\u{FFFD}'),
+            '\u{1}'..='\u{1f}' | '\u{7f}' => out.push_str(&format!("\\{:x} ", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Returns the byte index of the `)` that closes an already opened parenthesis, in
+/// CSS text that is matched without tokenizing it again, such as a `var()` reference
+/// or a media feature.
+pub(crate) fn matching_paren(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(i),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Tokenizes a style sheet, or a fragment of one such as a `style` attribute.
+///
+/// # Notes
+/// - Newlines are normalized and NUL characters replaced first, as the spec's input
+///   preprocessing does.
+/// - Tokenizing never fails: malformed input produces `BadString`, `BadUrl` or `Delim`
+///   tokens, which the parser treats as errors in the construct they appear in.
+pub fn tokenize(input: &str) -> Vec<Token> {
+    let input = input.replace("\r\n", "\n").replace(['\r', '\u{c}'], "\n").replace('\0', "\u{FFFD}");
+    let mut tokenizer = Tokenizer { input: input.chars().collect(), index: 0 };
+    let mut tokens = Vec::new();
+    while let Some(token) = tokenizer.next_token() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// The state of `tokenize`: the preprocessed input and the position in it.
+struct Tokenizer {
+    input: Vec<char>,
+    index: usize,
+}
+
+impl Tokenizer {
+    /// Returns the character `offset` positions ahead, or `None` past the end.
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.input.get(self.index + offset).copied()
+    }
+
+    /// Consumes the next token, or returns `None` at the end of the input.
+    fn next_token(&mut self) -> Option<Token> {
+        self.skip_comments();
+        let c = self.peek(0)?;
+        let token = match c {
+            c if is_whitespace(c) => {
+                while self.peek(0).is_some_and(is_whitespace) {
+                    self.index += 1;
+                }
+                return Some(Token::Whitespace);
+            }
+            '"' | '\'' => {
+                self.index += 1;
+                return Some(self.string(c));
+            }
+            '#' if self.peek(1).is_some_and(is_name_char) || is_valid_escape(self.peek(1), self.peek(2)) => {
+                self.index += 1;
+                let is_id = would_start_identifier(self.peek(0), self.peek(1), self.peek(2));
+                return Some(Token::Hash { value: self.name(), is_id });
+            }
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '[' => Token::OpenSquare,
+            ']' => Token::CloseSquare,
+            '{' => Token::OpenCurly,
+            '}' => Token::CloseCurly,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            ';' => Token::Semicolon,
+            '+' | '.' if self.starts_number() => return Some(self.numeric()),
+            '-' if self.starts_number() => return Some(self.numeric()),
+            '-' if self.peek(1) == Some('-') && self.peek(2) == Some('>') => {
+                self.index += 3;
+                return Some(Token::Cdc);
+            }
+            '-' if would_start_identifier(Some(c), self.peek(1), self.peek(2)) => return Some(self.ident_like()),
+            '<' if self.peek(1) == Some('!') && self.peek(2) == Some('-') && self.peek(3) == Some('-') => {
+                self.index += 4;
+                return Some(Token::Cdo);
+            }
+            '@' if would_start_identifier(self.peek(1), self.peek(2), self.peek(3)) => {
+                self.index += 1;
+                return Some(Token::AtKeyword(self.name()));
+            }
+            '\\' if is_valid_escape(Some(c), self.peek(1)) => return Some(self.ident_like()),
+            c if c.is_ascii_digit() => return Some(self.numeric()),
+            c if is_name_start_char(c) => return Some(self.ident_like()),
+            c => Token::Delim(c),
+        };
+        self.index += 1;
+        Some(token)
+    }
+
+    /// Skips any comments at the current position. An unterminated comment runs to the
+    /// end of the input.
+    fn skip_comments(&mut self) {
+        while self.peek(0) == Some('/') && self.peek(1) == Some('*') {
+            self.index += 2;
+            while self.index < self.input.len() && !(self.peek(0) == Some('*') && self.peek(1) == Some('/')) {
+                self.index += 1;
+            }
+            self.index = (self.index + 2).min(self.input.len());
+        }
+    }
+
+    /// Returns `true` if a number starts at the current position.
+    fn starts_number(&self) -> bool {
+        let digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+        match self.peek(0) {
+            Some('+' | '-') => digit(self.peek(1)) || (self.peek(1) == Some('.') && digit(self.peek(2))),
+            Some('.') => digit(self.peek(1)),
+            c => digit(c),
+        }
+    }
+
+    /// Consumes a number and returns its value and source text.
+    fn number(&mut self) -> (f32, String) {
+        let start = self.index;
+        let digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+        if matches!(self.peek(0), Some('+' | '-')) {
+            self.index += 1;
+        }
+        while digit(self.peek(0)) {
+            self.index += 1;
+        }
+        if self.peek(0) == Some('.') && digit(self.peek(1)) {
+            self.index += 1;
+            while digit(self.peek(0)) {
+                self.index += 1;
+            }
+        }
+        if matches!(self.peek(0), Some('e' | 'E'))
+            && (digit(self.peek(1)) || (matches!(self.peek(1), Some('+' | '-')) && digit(self.peek(2)))) {
+            self.index += 2;
+            while digit(self.peek(0)) {
+                self.index += 1;
+            }
+        }
+        let repr: String = self.input[start..self.index].iter().collect();
+        (repr.parse().unwrap_or(0.0), repr)
+    }
+
+    /// Consumes a number, percentage or dimension.
+    fn numeric(&mut self) -> Token {
+        let (value, repr) = self.number();
+        if would_start_identifier(self.peek(0), self.peek(1), self.peek(2)) {
+            Token::Dimension { value, repr, unit: self.name() }
+        } else if self.peek(0) == Some('%') {
+            self.index += 1;
+            Token::Percentage { value, repr }
+        } else {
+            Token::Number { value, repr }
+        }
+    }
+
+    /// Consumes an identifier, a function name or a `url(...)`.
+    fn ident_like(&mut self) -> Token {
+        let name = self.name();
+        if self.peek(0) != Some('(') {
+            return Token::Ident(name);
+        }
+        self.index += 1;
+        if !name.eq_ignore_ascii_case("url") {
+            return Token::Function(name);
+        }
+        // `url("...")` is an ordinary function with a string argument.
+        let mut offset = 0;
+        while self.peek(offset).is_some_and(is_whitespace) {
+            offset += 1;
+        }
+        if matches!(self.peek(offset), Some('"' | '\'')) {
+            return Token::Function(name);
+        }
+        self.url()
+    }
+
+    /// Consumes the rest of an unquoted `url(...)` after the `(`.
+    fn url(&mut self) -> Token {
+        let mut value = String::new();
+        while self.peek(0).is_some_and(is_whitespace) {
+            self.index += 1;
+        }
+        loop {
+            let Some(c) = self.peek(0) else {
+                return Token::Url(value);
+            };
+            self.index += 1;
+            match c {
+                ')' => return Token::Url(value),
+                c if is_whitespace(c) => {
+                    while self.peek(0).is_some_and(is_whitespace) {
+                        self.index += 1;
+                    }
+                    if matches!(self.peek(0), None | Some(')')) {
+                        self.index = (self.index + 1).min(self.input.len());
+                        return Token::Url(value);
+                    }
+                    self.skip_bad_url();
+                    return Token::BadUrl;
+                }
+                '"' | '\'' | '(' => {
+                    self.skip_bad_url();
+                    return Token::BadUrl;
+                }
+                c if is_non_printable(c) => {
+                    self.skip_bad_url();
+                    return Token::BadUrl;
+                }
+                '\\' if is_valid_escape(Some(c), self.peek(0)) => value.push(self.escape()),
+                '\\' => {
+                    self.skip_bad_url();
+                    return Token::BadUrl;
+                }
+                c => value.push(c),
+            }
+        }
+    }
+
+    /// Skips the rest of a malformed `url(...)`, up to and including its `)`.
+    fn skip_bad_url(&mut self) {
+        while let Some(c) = self.peek(0) {
+            self.index += 1;
+            match c {
+                ')' => return,
+                '\\' if is_valid_escape(Some(c), self.peek(0)) => {
+                    self.escape();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Consumes a string after its opening `quote`. An unescaped newline ends it as a
+    /// `BadString`; the end of the input ends it normally.
+    fn string(&mut self, quote: char) -> Token {
+        let mut value = String::new();
+        while let Some(c) = self.peek(0) {
+            match c {
+                c if c == quote => {
+                    self.index += 1;
+                    return Token::String(value);
+                }
+                '\n' => return Token::BadString,
+                '\\' => {
+                    self.index += 1;
+                    match self.peek(0) {
+                        None => {}
+                        // An escaped newline continues the string on the next line.
+                        Some('\n') => self.index += 1,
+                        Some(_) => value.push(self.escape()),
+                    }
+                }
+                c => {
+                    self.index += 1;
+                    value.push(c);
+                }
+            }
+        }
+        Token::String(value)
+    }
+
+    /// Consumes an escape after its backslash: up to six hex digits and one optional
+    /// whitespace, or any other single character.
+    fn escape(&mut self) -> char {
+        let Some(c) = self.peek(0) else {
+            return '\u{FFFD}';
+        };
+        self.index += 1;
+        if !c.is_ascii_hexdigit() {
+            return c;
+        }
+        let mut hex = String::from(c);
+        while hex.len() < 6 && self.peek(0).is_some_and(|c| c.is_ascii_hexdigit()) {
+            hex.push(self.peek(0).unwrap());
+            self.index += 1;
+        }
+        if self.peek(0).is_some_and(is_whitespace) {
+            self.index += 1;
+        }
+        u32::from_str_radix(&hex, 16).ok()
+            .filter(|&code| code != 0)
+            .and_then(char::from_u32)
+            .unwrap_or('\u{FFFD}')
+    }
+
+    /// Consumes a name: name characters and escapes.
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek(0) {
+            if is_name_char(c) {
+                name.push(c);
+                self.index += 1;
+            } else if is_valid_escape(Some(c), self.peek(1)) {
+                self.index += 1;
+                name.push(self.escape());
+            } else {
+                break;
+            }
+        }
+        name
+    }
+}
+
+/// Returns `true` for the whitespace of CSS: spaces, tabs and (normalized) newlines.
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n')
+}
+
+/// Returns `true` if `c` can start an identifier: a letter, `_` or a non-ASCII character.
+fn is_name_start_char(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+/// Returns `true` if `c` can appear in an identifier after its start.
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c) || c.is_ascii_digit() || c == '-'
+}
+
+/// Returns `true` for the control characters that make a `url(...)` malformed.
+fn is_non_printable(c: char) -> bool {
+    matches!(c, '\u{0}'..='\u{8}' | '\u{b}' | '\u{e}'..='\u{1f}' | '\u{7f}')
+}
+
+/// Returns `true` if `first` and `second` start an escape: a backslash not followed by a
+/// newline.
+fn is_valid_escape(first: Option<char>, second: Option<char>) -> bool {
+    first == Some('\\') && second.is_some_and(|c| c != '\n')
+}
+
+/// Returns `true` if the three characters would start an identifier.
+fn would_start_identifier(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+    match first {
+        Some('-') => second.is_some_and(|c| is_name_start_char(c) || c == '-') || is_valid_escape(second, third),
+        Some('\\') => is_valid_escape(first, second),
+        Some(c) => is_name_start_char(c),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an identifier token.
+    fn ident(name: &str) -> Token {
+        Token::Ident(name.to_string())
+    }
+
+    #[test]
+    fn comments_are_dropped() {
+        assert_eq!(tokenize("/* p { color: red } */.a/* } */b"), vec![Token::Delim('.'), ident("a"), ident("b")]);
+        assert_eq!(tokenize("a/**/ b"), vec![ident("a"), Token::Whitespace, ident("b")]);
+        // An unterminated comment runs to the end of the input.
+        assert_eq!(tokenize("a /* b"), vec![ident("a"), Token::Whitespace]);
+    }
+
+    #[test]
+    fn strings_end_only_at_their_own_quote() {
+        assert_eq!(tokenize("\"x\\\";}\""), vec![Token::String("x\";}".to_string())]);
+        assert_eq!(tokenize("'a\"b'"), vec![Token::String("a\"b".to_string())]);
+        // An escaped newline continues the string; an unescaped one ends it as a bad string.
+        assert_eq!(tokenize("'a\\\nb'"), vec![Token::String("ab".to_string())]);
+        assert_eq!(tokenize("'a\nb"), vec![Token::BadString, Token::Whitespace, ident("b")]);
+    }
+
+    #[test]
+    fn escapes_are_resolved_in_names() {
+        assert_eq!(tokenize(".a\\:b"), vec![Token::Delim('.'), ident("a:b")]);
+        assert_eq!(tokenize("r\\65 d"), vec![ident("red")]);
+        assert_eq!(
+            tokenize("1\\65 m"),
+            vec![Token::Dimension { value: 1.0, repr: "1".to_string(), unit: "em".to_string() }],
+        );
+        assert_eq!(tokenize("#\\31 x"), vec![Token::Hash { value: "1x".to_string(), is_id: true }]);
+    }
+
+    #[test]
+    fn urls_are_unquoted_or_functions() {
+        assert_eq!(tokenize("url( a.png )"), vec![Token::Url("a.png".to_string())]);
+        assert_eq!(
+            tokenize("url(\"a b\")"),
+            vec![Token::Function("url".to_string()), Token::String("a b".to_string()), Token::CloseParen],
+        );
+        assert_eq!(tokenize("url(a b)"), vec![Token::BadUrl]);
+    }
+}
@@ -2,6 +2,7 @@ use std::{cell::RefCell, sync::Arc};
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::RwLock;
+use crate::entities::decode_character_references;
 use crate::node::{Element, HtmlNode, HtmlNodeType, Text};
 
 /// ```
@@ -36,7 +37,7 @@ use crate::node::{Element, HtmlNode, HtmlNodeType, Text};
 /// }
 /// ```
 /// ```
-const VOID_TAGS: [&str; 14] = ["area", "base", "br", "col", "embed", "hr", "img", "input", "link",
+pub(crate) const VOID_TAGS: [&str; 14] = ["area", "base", "br", "col", "embed", "hr", "img", "input", "link",
     "meta", "param", "source", "track", "wbr"];
 
 /// A constant array of HTML tag names commonly used within the `<head>` section of a document.
@@ -61,236 +62,1100 @@ const VOID_TAGS: [&str; 14] = ["area", "base", "br", "col", "embed", "hr", "img"
 /// }
 /// ```
 const HEAD_TAGS: [&str; 7] = ["title", "meta", "link", "style", "script", "noscript", "base"];
+
+/// Elements whose contents are tokenized as raw text: everything up to the matching end
+/// tag is literal text, and character references are not decoded.
+pub(crate) const RAW_TEXT_TAGS: [&str; 6] = ["script", "style", "xmp", "iframe", "noembed", "noframes"];
+
+/// Elements whose contents are tokenized as escapable raw text: like raw text, except that
+/// character references are decoded.
+const ESCAPABLE_RAW_TEXT_TAGS: [&str; 2] = ["textarea", "title"];
+
+/// The heading elements, `<h1>` through `<h6>`.
+const HEADING_TAGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Formatting elements. These are tracked in the list of active formatting elements, so
+/// that misnested markup can be repaired by the adoption agency algorithm and elements
+/// closed implicitly can be reopened.
+const FORMATTING_TAGS: &[&str] = &["a", "b", "big", "code", "em", "font", "i", "nobr", "s", "small",
+    "strike", "strong", "tt", "u"];
+
+/// Elements in the "special" category of the HTML specification. An end tag for some other
+/// element never closes one of these, and they act as the furthest block in the adoption
+/// agency algorithm.
+const SPECIAL_TAGS: &[&str] = &["address", "applet", "area", "article", "aside", "base", "basefont",
+    "bgsound", "blockquote", "body", "br", "button", "caption", "center", "col", "colgroup", "dd",
+    "details", "dir", "div", "dl", "dt", "embed", "fieldset", "figcaption", "figure", "footer",
+    "form", "frame", "frameset", "h1", "h2", "h3", "h4", "h5", "h6", "head", "header", "hgroup",
+    "hr", "html", "iframe", "img", "input", "keygen", "li", "link", "listing", "main", "marquee",
+    "menu", "meta", "nav", "noembed", "noframes", "noscript", "object", "ol", "p", "param",
+    "plaintext", "pre", "script", "search", "section", "select", "source", "style", "summary",
+    "table", "tbody", "td", "template", "textarea", "tfoot", "th", "thead", "title", "tr", "track",
+    "ul", "wbr", "xmp"];
+
+/// Special elements that still reopen the active formatting elements before they are
+/// inserted, because they are rendered inline.
+const INLINE_SPECIAL_TAGS: &[&str] = &["applet", "area", "br", "button", "embed", "img", "input",
+    "keygen", "marquee", "object", "select", "textarea", "wbr"];
+
+/// Start tags that close an open `<p>` element.
+const CLOSES_P_TAGS: &[&str] = &["address", "article", "aside", "blockquote", "center", "details",
+    "dialog", "dir", "div", "dl", "dd", "dt", "fieldset", "figcaption", "figure", "footer", "form",
+    "h1", "h2", "h3", "h4", "h5", "h6", "header", "hgroup", "hr", "li", "listing", "main", "menu",
+    "nav", "ol", "p", "plaintext", "pre", "search", "section", "summary", "table", "ul", "xmp"];
+
+/// Elements whose end tags may be omitted, and are generated when an enclosing element closes.
+const IMPLIED_END_TAGS: &[&str] = &["dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc"];
+
+/// Elements that bound the default scope: an element outside of them is not "in scope".
+const SCOPE_BARRIERS: &[&str] = &["applet", "caption", "html", "marquee", "object", "table", "td",
+    "template", "th"];
+
+/// The barriers of the button scope, used to find an open `<p>`.
+const BUTTON_SCOPE_BARRIERS: &[&str] = &["applet", "button", "caption", "html", "marquee", "object",
+    "table", "td", "template", "th"];
+
+/// The barriers of the list item scope, used to find an open `<li>`.
+const LIST_ITEM_SCOPE_BARRIERS: &[&str] = &["applet", "caption", "html", "marquee", "object", "ol",
+    "table", "td", "template", "th", "ul"];
+
+/// The barriers of the table scope, used to find open table parts.
+const TABLE_SCOPE_BARRIERS: &[&str] = &["html", "table", "template"];
+
+/// Start tags for the parts of a table, which are only honored inside a `<table>`.
+const TABLE_STRUCTURE_TAGS: &[&str] = &["caption", "col", "colgroup", "tbody", "td", "tfoot", "th",
+    "thead", "tr"];
+
+/// Elements whose children are restricted to table parts. Anything else inserted into them
+/// is foster-parented.
+const TABLE_CONTEXT_TAGS: &[&str] = &["table", "tbody", "tfoot", "thead", "tr"];
+
+/// Elements that may be inserted directly into a table context element.
+const TABLE_CONTENT_TAGS: &[&str] = &["caption", "col", "colgroup", "script", "style", "tbody", "td",
+    "template", "tfoot", "th", "thead", "tr"];
+
 /// ```rust
 /// A simple HTML parser structure designed to hold and manipulate the body content of an HTML document.
 ///
 /// # Fields
 ///
 /// * `body` - A `String` representing the inner HTML content or the body of an HTML document.
+///   Only used by `parse`; streamed documents are passed to `feed` instead.
+/// * `unfinished` - The stack of open elements.
+/// * `active_formatting` - The list of active formatting elements, with `None` entries as
+///   markers (pushed for table cells and captions).
+/// * `root` - The root element, once it has been created.
+/// * `input` - Characters that have been fed to the parser but not tokenized yet, because
+///   they end in the middle of a tag, comment or raw text element.
+/// * `text` - Text that has been tokenized but not inserted yet. Text is only inserted
+///   once the next piece of markup (or the end of the input) is reached, so that a chunk
+///   boundary never splits a word or a character reference.
+/// * `raw_text_element` - The name of the raw text element whose contents are being
+///   tokenized, and whether character references in it are decoded.
+/// * `pending_script` - A `<script>` element that was just closed. Tokenization pauses
+///   until the caller has run it, as for a parser-blocking script.
+/// * `at_eof` - Whether the end of the input has been reached.
+/// * `head` - The `<head>` element, once it has been created, explicitly or not. Any
+///   later `<head>` start tag is ignored, and head elements such as `<title>` that
+///   come after `</head>` but before the body go back into it.
 ///
 /// # Examples
 ///
 /// ```rust
-/// let parser = HtmlParser {
-///     body: "<div>Hello, World!</div>".to_string(),
-/// };
+/// let parser = HtmlParser::new("<div>Hello, World!</div>".to_string());
 ///
 /// println!("{}", parser.body); // Outputs: <div>Hello, World!</div>
 /// ```
 /// struct
 pub struct HtmlParser {
     pub(crate) body: String,
-    pub(crate) unfinished: Vec<Arc<RwLock<HtmlNode>>>
+    pub(crate) unfinished: Vec<Arc<RwLock<HtmlNode>>>,
+    active_formatting: Vec<Option<Arc<RwLock<HtmlNode>>>>,
+    root: Option<Arc<RwLock<HtmlNode>>>,
+    input: Vec<char>,
+    text: String,
+    raw_text_element: Option<(String, bool)>,
+    pending_script: Option<Arc<RwLock<HtmlNode>>>,
+    at_eof: bool,
+    head: Option<Arc<RwLock<HtmlNode>>>,
 }
 
 impl HtmlParser {
-    /// ```rust
-    /// Parses the `body` field of the struct and converts it into a collection of `HtmlNode` objects.
-    ///
-    /// This method processes an HTML-like string stored in the `body` field of the struct,
-    /// and identifies both text and element nodes. The parsing logic detects whether the
-    /// current context is inside an HTML tag or outside (plain text) by processing characters
-    /// one by one.
-    ///
-    /// Different cases are handled during parsing:
-    /// - Characters between `<` and `>` are treated as part of an element (tag).
-    /// - Characters outside `<` and `>` are treated as text.
-    /// - If the `buffer` stores any text or tag information before a new state begins,
-    ///   it is pushed to the `output` as a new `HtmlNode`.
-    ///
-    /// # Node Types
-    /// - `HtmlNodeType::Text`: Created when plain text (outside of tags) is processed.
-    /// - `HtmlNodeType::Element`: Created when an element/tag is processed.
-    ///
-    /// # Fields Involved
-    /// - `buffer`: Temporarily holds the text or tag content being processed.
-    /// - `in_tag`: A flag determining whether the current position is inside a tag.
-    /// - `output`: Stores the parsed `HtmlNode` objects that represent the structured
-    ///   content of the `body`.
-    ///
-    /// # Notes
-    /// - If the `body` ends with plain text that is not followed by a tag, the method ensures
-    ///   the remaining text in the `buffer` is pushed to the `output` as a `HtmlNodeType::Text`.
-    /// - The behavior assumes the input is well-formed and does not explicitly handle errors
-    ///   like mismatched or incomplete tags.
-    ///
-    /// # Example (Pseudo Code)
+    /// Creates a parser for the HTML document `body`.
+    ///
+    /// Use `HtmlParser::new(String::new())` together with `feed` and `end` to parse a
+    /// document that arrives in chunks.
+    pub fn new(body: String) -> HtmlParser {
+        HtmlParser {
+            body,
+            unfinished: vec![],
+            active_formatting: vec![],
+            root: None,
+            input: vec![],
+            text: String::new(),
+            raw_text_element: None,
+            pending_script: None,
+            at_eof: false,
+            head: None,
+        }
+    }
+
+    /// Parses the `body` field of the struct into a tree of `HtmlNode` objects and returns its root.
+    ///
+    /// This feeds the whole body at once and does not run scripts: parser-blocking
+    /// `<script>` elements are skipped over. `Tab` streams documents through `feed` and
+    /// `end` instead, so that it can render partial documents and run scripts.
+    ///
+    /// Parsing never fails: like a real browser, the parser recovers from any malformed
+    /// input, so every sequence of characters produces a tree rooted at an `<html>` element.
+    pub fn parse(&mut self) -> Arc<RwLock<HtmlNode>> {
+        let body = std::mem::take(&mut self.body);
+        let mut script = self.feed(&body);
+        while script.is_some() {
+            script = self.feed("");
+        }
+        while self.end().is_some() {}
+        self.finish()
+    }
+
+    /// Appends a chunk of the document to the input and tokenizes as much of it as possible.
+    ///
+    /// Markup that is cut off by the end of the chunk (e.g. `<a hr`) stays in the input
+    /// until the next chunk completes it.
+    ///
+    /// # Returns
+    /// `Some(script)` if tokenization paused after the end tag of a `<script>` element.
+    /// The caller should run the script and then call `feed` again (with an empty chunk if
+    /// nothing new has arrived) to resume parsing. `None` once the input is used up.
+    pub fn feed(&mut self, chunk: &str) -> Option<Arc<RwLock<HtmlNode>>> {
+        self.input.extend(chunk.chars());
+        self.tokenize();
+        self.pending_script.take()
+    }
+
+    /// Signals the end of the input: whatever is left is tokenized, unterminated markup is
+    /// handled as the specification requires, and all open elements are closed.
+    ///
+    /// # Returns
+    /// `Some(script)` if tokenization paused on a `<script>` element, in which case the
+    /// caller should run it and call `end` again. `None` once the document is complete.
+    pub fn end(&mut self) -> Option<Arc<RwLock<HtmlNode>>> {
+        self.at_eof = true;
+        self.tokenize();
+        if let Some(script) = self.pending_script.take() {
+            return Some(script);
+        }
+        self.finish();
+        None
+    }
+
+    /// Adds the text tokenized so far to the tree, so that a partial render shows it
+    /// without waiting for the next piece of markup.
+    ///
+    /// A trailing `&` that may start a character reference is kept back until more input
+    /// arrives, as is text that is only white space. Later text is appended to the same
+    /// text node, so the flush does not split words.
+    pub fn flush_pending_text(&mut self) {
+        let keep = match self.text.rfind('&') {
+            Some(amp) if !self.text[amp..].contains(|c: char| c == ';' || c.is_whitespace()) => amp,
+            _ => self.text.len(),
+        };
+        if self.text[..keep].chars().all(|c| c.is_ascii_whitespace()) {
+            return;
+        }
+        let rest = self.text.split_off(keep);
+        self.flush_text();
+        self.text = rest;
+    }
+
+    /// Returns the root of the tree built so far, if any markup has been parsed yet.
+    ///
+    /// The tree is updated in place as more input is fed, so a partially parsed document
+    /// can be styled and rendered while the rest of it is still arriving.
+    pub fn document(&self) -> Option<Arc<RwLock<HtmlNode>>> {
+        self.root.clone()
+    }
+
+    /// Returns `true` if `node` is still on the stack of open elements, i.e. its end tag
+    /// has not been parsed yet.
+    pub(crate) fn is_open(&self, node: &Arc<RwLock<HtmlNode>>) -> bool {
+        self.unfinished.iter().any(|n| Arc::ptr_eq(n, node))
+    }
+
+    /// Runs the tokenizer over the pending input.
+    ///
+    /// The tokenizer is a simplified version of the HTML5 tokenizer. It walks the input one
+    /// character at a time and switches between the following states:
+    ///
+    /// - **Data**: plain text, with character references decoded, up to the next `<` that
+    ///   starts a tag, comment or other markup declaration. A `<` that cannot start one
+    ///   (e.g. `a < b`) is kept as text.
+    /// - **Tag**: everything up to the closing `>`. A `>` inside a quoted attribute value
+    ///   does not end the tag.
+    /// - **Raw text** (`script`, `style`, `xmp`, `iframe`, `noembed`, `noframes`): everything
+    ///   up to the matching end tag is taken literally, without decoding references.
+    /// - **Escapable raw text** (`textarea`, `title`): like raw text, but character references
+    ///   are decoded.
+    /// - **Comment** (`<!-- ... -->`): skipped, even if the comment contains markup.
+    /// - **DOCTYPE** (`<!DOCTYPE ...>`) and bogus comments (`<?...>`, `<!...>`): skipped.
+    /// - **CDATA** (`<![CDATA[ ... ]]>`): emitted as text inside `<svg>` and `<math>`, and
+    ///   treated as a bogus comment elsewhere, as the specification requires.
+    ///
+    /// Text and tags are handed to `add_text` and `add_tag`, which build the tree.
+    ///
+    /// Tokenization stops early when the input ends in the middle of a token (unless the
+    /// end of the input has been reached), or when a `<script>` element has been closed.
+    ///
+    /// # Example
     /// Given an input like:
     /// ```html
-    /// <div>Hello World</div>
-    /// ```
-    /// - The method creates an `HtmlNode` for the opening `<div>`.
-    /// - It creates a `Text` node for the content `Hello World`.
-    /// - It creates an `HtmlNode` for the closing `</div>`.
-    ///
-    /// No explicit return value is defined in the current implementation.
+    /// <script>if (a < b) { go(); }</script><!-- <p>not a paragraph</p> -->
     /// ```
-    pub(crate) fn parse(&mut self) -> Arc<RwLock<HtmlNode>> {
-        let mut buffer = String::new();
-        let mut in_tag = false;
-        let chars: Vec<_> = self.body.chars().collect();
-        let mut iter = chars.iter();
-        while let Some(c) = iter.next() {
-            match c {
-                '<' => {
-                    in_tag = true;
-                    if !buffer.is_empty() {
-                        self.add_text(&buffer);
+    /// the `script` element gets a single text child containing `if (a < b) { go(); }`,
+    /// and the comment produces nothing.
+    fn tokenize(&mut self) {
+        let chars = std::mem::take(&mut self.input);
+        let mut i = 0;
+        while self.pending_script.is_none() {
+            if let Some((tag_name, decode)) = self.raw_text_element.clone() {
+                match self.raw_text(&chars, i, &tag_name, decode) {
+                    Some(next) => {
+                        i = next;
+                        self.raw_text_element = None;
                     }
-                    buffer.clear();
+                    None => break,
                 }
-                '>' => {
-                    in_tag = false;
-                    self.add_tag(&buffer);
-                    buffer.clear();
+            }
+
+            if i >= chars.len() {
+                break;
+            }
+            if chars[i] != '<' {
+                self.text.push(chars[i]);
+                i += 1;
+                continue;
+            }
+
+            let next = chars.get(i + 1).copied();
+            let markup_start = match next {
+                Some('!') | Some('?') => true,
+                Some('/') => match chars.get(i + 2) {
+                    Some(_) => true,
+                    None if !self.at_eof => break,
+                    None => false,
+                },
+                Some(c) => c.is_ascii_alphabetic(),
+                None if !self.at_eof => break,
+                None => false,
+            };
+            if !markup_start {
+                self.text.push('<');
+                i += 1;
+                continue;
+            }
+
+            let end = match next {
+                Some('!') => self.markup_declaration(&chars, i + 2),
+                Some('?') => self.skip_bogus_comment(&chars, i + 1),
+                Some('/') => match chars[i + 2] {
+                    c if c.is_ascii_alphabetic() => self.tag(&chars, i + 1),
+                    '>' => Some(i + 3),
+                    _ => self.skip_bogus_comment(&chars, i + 2),
                 },
-                _ => {
-                    buffer.push(*c);
+                _ => self.tag(&chars, i + 1),
+            };
+            match end {
+                Some(end) => i = end,
+                None => break,
+            }
+        }
+
+        if self.at_eof && i >= chars.len() {
+            self.flush_text();
+        }
+        self.input = chars[i.min(chars.len())..].to_vec();
+    }
+
+    /// Decodes the pending text, adds it to the tree and clears the buffer.
+    fn flush_text(&mut self) {
+        if !self.text.is_empty() {
+            let text = decode_character_references(&self.text, false);
+            self.text.clear();
+            self.add_text(&text);
+        }
+    }
+
+    /// Tokenizes a tag whose name starts at `start` (just past the `<`), adds it to the
+    /// tree and returns the index of the first character after it.
+    ///
+    /// If the tag opens a raw text or escapable raw text element, the tokenizer switches
+    /// to the matching state for its contents. If it closes a `<script>` element, the
+    /// script becomes the `pending_script`.
+    ///
+    /// Returns `None` if the tag is cut off by the end of the available input.
+    fn tag(&mut self, chars: &[char], start: usize) -> Option<usize> {
+        let mut buffer = String::new();
+        let mut quote: Option<char> = None;
+        let mut after_equals = false;
+        let mut i = start;
+        loop {
+            let Some(&c) = chars.get(i) else {
+                // EOF inside a tag: the tokenizer drops the tag.
+                return self.at_eof.then_some(i);
+            };
+            i += 1;
+            if let Some(q) = quote {
+                if c == q {
+                    quote = None;
                 }
+                buffer.push(c);
+                continue;
+            }
+            if c == '>' {
+                break;
+            }
+            if (c == '"' || c == '\'') && after_equals {
+                quote = Some(c);
+            }
+            if !c.is_whitespace() {
+                after_equals = c == '=';
             }
+            buffer.push(c);
         }
-        if !in_tag && !buffer.is_empty() {
-            self.add_text(&buffer);
+
+        self.flush_text();
+        let tag_name = get_attributes(&buffer).tag;
+        let open_script = self.unfinished.last()
+            .filter(|node| tag_name == "/script" && self::tag_name(node) == "script")
+            .cloned();
+        self.add_tag(&buffer);
+
+        if RAW_TEXT_TAGS.contains(&tag_name.as_str()) {
+            self.raw_text_element = Some((tag_name, false));
+        } else if ESCAPABLE_RAW_TEXT_TAGS.contains(&tag_name.as_str()) {
+            self.raw_text_element = Some((tag_name, true));
+        } else if let Some(script) = open_script
+            && !self.is_open(&script) {
+            self.pending_script = Some(script);
         }
-        self.finish()
+        Some(i)
     }
 
-    fn add_text(&mut self, text: &String)  {
-        if text.trim().is_empty() {return;}
-        self.implicit_tags(None);
-        match self.unfinished.last_mut() {
-            None => {
-                panic!("No parent node found for text node");
+    /// Consumes the contents of a raw text element starting at `start`, up to (but not
+    /// including) the matching `</tag_name` end tag, and adds them as a single text node.
+    ///
+    /// Character references are only decoded when `decode` is set (escapable raw text).
+    /// Returns `None`, without consuming anything, if the end tag has not arrived yet.
+    fn raw_text(&mut self, chars: &[char], start: usize, tag_name: &str, decode: bool) -> Option<usize> {
+        let end_tag: Vec<char> = format!("</{}", tag_name).chars().collect();
+        let mut i = start;
+        while i < chars.len() {
+            if chars[i] == '<' && is_end_tag_at(chars, i, &end_tag) {
+                break;
             }
-            Some(parent) => {
-                let node = HtmlNode::new(HtmlNodeType::Text(Text{text: text.clone()}),
-                                         Some(Arc::clone(&parent)));
-                parent.write().unwrap().children.push(Arc::new(RwLock::new(node)));
+            i += 1;
+        }
+        if !self.at_eof && i + end_tag.len() >= chars.len() {
+            // The end tag (or the character that terminates its name) is still to come.
+            return None;
+        }
+
+        let content: String = chars[start..i].iter().collect();
+        if !content.is_empty() {
+            if decode {
+                self.add_text(&decode_character_references(&content, false));
+            } else {
+                self.add_text(&content);
             }
         }
+        Some(i)
+    }
+
+    /// Handles a markup declaration (`<!...`) whose body starts at `start`, just past the
+    /// `<!`, and returns the index of the first character after it, or `None` if it is
+    /// cut off by the end of the available input.
+    fn markup_declaration(&mut self, chars: &[char], start: usize) -> Option<usize> {
+        if !self.at_eof && ["--", "doctype", "[CDATA["].iter()
+            .any(|pattern| is_prefix_at(chars, start, pattern)) {
+            // Not enough input yet to tell which kind of declaration this is.
+            return None;
+        }
+        if starts_with_at(chars, start, "--", false) {
+            self.flush_text();
+            return skip_comment(chars, start + 2).or(self.at_eof.then_some(chars.len()));
+        }
+        if starts_with_at(chars, start, "doctype", true) {
+            return self.skip_bogus_comment(chars, start);
+        }
+        if starts_with_at(chars, start, "[CDATA[", false) && self.in_foreign_content() {
+            let content_start = start + "[CDATA[".len();
+            let mut i = content_start;
+            while i < chars.len() && !starts_with_at(chars, i, "]]>", false) {
+                i += 1;
+            }
+            if i >= chars.len() && !self.at_eof {
+                return None;
+            }
+            self.flush_text();
+            let content: String = chars[content_start..i].iter().collect();
+            if !content.is_empty() {
+                self.add_text(&content);
+            }
+            return Some(usize::min(i + 3, chars.len()));
+        }
+        self.skip_bogus_comment(chars, start)
+    }
+
+    /// Skips a bogus comment starting at `start`, flushing any text before it. Returns
+    /// `None` if its closing `>` has not arrived yet.
+    fn skip_bogus_comment(&mut self, chars: &[char], start: usize) -> Option<usize> {
+        let end = skip_bogus_comment(chars, start).or(self.at_eof.then_some(chars.len()))?;
+        self.flush_text();
+        Some(end)
+    }
+
+    /// Returns `true` if the current insertion point is inside an `<svg>` or `<math>` element.
+    fn in_foreign_content(&self) -> bool {
+        self.unfinished.iter().any(|n| {
+            matches!(&n.read().unwrap().node_type,
+                HtmlNodeType::Element(e) if e.tag == "svg" || e.tag == "math")
+        })
+    }
+
+    /// Adds a text node at the appropriate place for inserting a node.
+    ///
+    /// Whitespace-only text is kept inside the body, since it separates inline content
+    /// and is significant in `<pre>`, but dropped before the body and directly inside
+    /// table elements, where it never renders. As in HTML5, a newline right after a
+    /// `<pre>`, `<listing>` or `<textarea>` start tag is dropped. Text right after a text
+    /// node, such as text flushed early by `flush_pending_text`, is appended to it.
+    ///
+    /// Before the text is inserted, any implied `<html>`, `<head>` and `<body>` elements
+    /// are created and the active formatting elements are reconstructed, so that e.g.
+    /// the `y` in `<p><b>x<p>y` is bold too.
+    fn add_text(&mut self, text: &String)  {
+        if text.chars().all(|c| c.is_ascii_whitespace())
+            && (!self.has_open("body") || TABLE_CONTEXT_TAGS.contains(&self.current_tag().as_str())) {
+            return;
+        }
+
+        let mut text = text.as_str();
+        let starts_block = matches!(self.current_tag().as_str(), "pre" | "listing" | "textarea")
+            && self.unfinished.last().is_some_and(|n| n.read().unwrap().children.is_empty());
+        if starts_block {
+            text = text.strip_prefix("\r\n").or(text.strip_prefix('\n')).unwrap_or(text);
+            if text.is_empty() {
+                return;
+            }
+        }
+
+        self.implicit_tags(None);
+        self.reconstruct_active_formatting_elements();
+        if let Some(target) = self.unfinished.last()
+            && !TABLE_CONTEXT_TAGS.contains(&tag_name(target).as_str())
+            && let Some(last) = target.read().unwrap().children.last()
+            && let HtmlNodeType::Text(previous) = &mut last.write().unwrap().node_type {
+            previous.text.push_str(text);
+            return;
+        }
+        let node = Arc::new(RwLock::new(HtmlNode::new(HtmlNodeType::Text(Text{text: text.to_string()}), None)));
+        self.insert_node(node);
     }
 
     /// Adds a tag to the HTML document structure being built.
     ///
-    /// This function processes a given tag by analyzing its type and
-    /// appropriately modifies the internal state of the structure. It handles
-    /// standard, void, and closing tags while maintaining a stack of
-    /// unfinished nodes.
+    /// This function parses the tag with `get_attributes`, inserts any implied
+    /// `<html>`, `<head>` and `<body>` elements through `implicit_tags`, and then hands
+    /// the tag to `start_tag` or `end_tag`, which implement a reduced version of the
+    /// HTML5 tree-construction rules:
     ///
-    /// # Parameters
-    /// - `tag`: A string slice representing the HTML tag to be processed.
+    /// - Implied end tags: a new `<p>`, `<li>`, `<dd>`, `<dt>` or `<option>` closes the
+    ///   previous one, and block-level start tags close an open `<p>`.
+    /// - End tags are matched against the stack of open elements (`unfinished`); stray
+    ///   end tags that match nothing are ignored.
+    /// - Misnested formatting elements (`<b><i></b></i>`) are repaired with the adoption
+    ///   agency algorithm, and formatting elements closed implicitly are reopened for the
+    ///   content that follows.
+    /// - Content that is misplaced inside a `<table>` is foster-parented, i.e. moved in
+    ///   front of the table, and missing `<tbody>` and `<tr>` elements are created.
     ///
-    /// # Behavior
-    /// 1. Parses the tag using the `get_attributes` function to extract its attributes
-    ///    and determine its type.
-    /// 2. If the tag starts with `!` (e.g., an HTML comment or doctype), no further processing is done, and the function returns early.
-    /// 3. If the tag starts with `/` (a closing tag):
-    ///     - If there is only one node in the `unfinished` stack, the function returns.
-    ///     - If a parent node exists in the `unfinished` stack, the current node is popped
-    ///       and added as a child of its parent.
-    ///     - If no parent node is found, the function panics with an error message.
-    /// 4. If the tag belongs to the `VOID_TAGS` set (e.g., `<img>`, `<br>`), it does the following:
-    ///     - Ensures there is a current parent node in the `unfinished` stack.
-    ///     - Creates a new `HtmlNode` for the void tag and appends it as a child to the current parent.
-    ///     - Panics if no parent node is found.
-    /// 5. For standard (non-void, non-closing) tags:
-    ///     - Determines the parent node from the `unfinished` stack, if one exists.
-    ///     - Creates a new `HtmlNode` for the tag and pushes it onto the `unfinished` stack.
-    ///
-    /// # Errors
-    /// - Panics if a closing tag is processed without an appropriate parent node in the `unfinished` stack.
-    /// - Panics if a void tag is processed without a parent node in the `unfinished` stack.
-    ///
-    /// # Notes
-    /// - The function utilizes the `implicit_tags` method with the current tag for specific behavior
-    ///   customization.
-    /// - The `VOID_TAGS` set is used as a reference for identifying self-closing tags.
+    /// # Parameters
+    /// - `tag`: The contents of the tag, without the surrounding `<` and `>`.
     ///
     /// # Example
     /// ```
-    /// let mut parser = HtmlParser::new();
-    /// parser.add_tag("<div>");
-    /// parser.add_tag("<img src='image.png'>");
-    /// parser.add_tag("</div>");
+    /// let mut parser = HtmlParser::new("<ul><li>one<li>two</ul>".to_string());
+    /// // Produces <ul><li>one</li><li>two</li></ul>.
+    /// let root = parser.parse();
     /// ```
-    ///
-    /// The above example constructs an HTML structure equivalent to:
-    /// ```html
-    /// <div>
-    ///     <img src="image.png" />
-    /// </div>
     fn add_tag(&mut self, tag: &str) {
         let element = get_attributes(tag);
 
-        if element.tag.starts_with('!') {
+        if element.tag.is_empty() || element.tag == "/" || element.tag.starts_with('!') {
             return;
         }
 
         self.implicit_tags(Some(&*element.tag));
 
-        if element.tag.starts_with('/') {
-            if self.unfinished.len() == 1 { return; }
-            match self.unfinished.pop() {
-                None => {
-                    panic!("No parent node found for closing tag");
+        match element.tag.strip_prefix('/') {
+            Some(name) => {
+                let name = name.to_string();
+                self.end_tag(&name);
+            }
+            None => self.start_tag(element),
+        }
+    }
+
+    /// Processes a start tag, following the "in body" and "in table" insertion modes.
+    fn start_tag(&mut self, element: Element) {
+        let tag = element.tag.clone();
+        let tag = tag.as_str();
+        match tag {
+            "html" if !self.unfinished.is_empty() => {
+                // A second <html> only contributes its attributes to the root element.
+                if let Some(root) = self.root.clone()
+                    && let HtmlNodeType::Element(e) = &mut root.write().unwrap().node_type {
+                    for (key, value) in element.attributes {
+                        e.attributes.entry(key).or_insert(value);
+                    }
+                }
+                return;
+            }
+            "head" if self.head.is_some() || self.has_open("body") => return,
+            "body" if self.has_open("body") => return,
+            _ => {}
+        }
+
+        if TABLE_STRUCTURE_TAGS.contains(&tag) {
+            self.table_structure_start_tag(element);
+            return;
+        }
+
+        if CLOSES_P_TAGS.contains(&tag) {
+            self.close_p_in_button_scope();
+        }
+
+        match tag {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" if HEADING_TAGS.contains(&self.current_tag().as_str()) => {
+                self.unfinished.pop();
+            }
+            "li" => self.close_list_item(&["li"]),
+            "dd" | "dt" => self.close_list_item(&["dd", "dt"]),
+            "option" if self.current_tag() == "option" => {
+                self.unfinished.pop();
+            }
+            "optgroup" => {
+                if self.current_tag() == "option" {
+                    self.unfinished.pop();
+                }
+                if self.current_tag() == "optgroup" {
+                    self.unfinished.pop();
                 }
-                Some(node) => {
-                    let parent = self.unfinished.last_mut().unwrap();
-                    parent.write().unwrap().children.push(Arc::clone(&node));
+            }
+            "button" if self.in_scope(&["button"], SCOPE_BARRIERS) => {
+                self.generate_implied_end_tags(None);
+                self.pop_until(&["button"]);
+            }
+            "a" => {
+                // An <a> inside another <a> closes the outer one.
+                let open_anchor = self.active_formatting_after_marker("a");
+                if let Some(anchor) = open_anchor {
+                    self.adoption_agency("a");
+                    self.remove_active_formatting_element(&anchor);
+                    self.unfinished.retain(|n| !Arc::ptr_eq(n, &anchor));
+                }
+            }
+            "table" if TABLE_CONTEXT_TAGS.contains(&self.current_tag().as_str()) => {
+                // A <table> directly inside a table closes the outer one.
+                self.pop_until(&["table"]);
+            }
+            _ => {}
+        }
+
+        if FORMATTING_TAGS.contains(&tag) || !SPECIAL_TAGS.contains(&tag) || INLINE_SPECIAL_TAGS.contains(&tag) {
+            self.reconstruct_active_formatting_elements();
+        }
+
+        let node = self.insert_element(element);
+        if FORMATTING_TAGS.contains(&tag) {
+            self.active_formatting.push(Some(node));
+        }
+    }
+
+    /// Processes a start tag for one of the table structure elements (`caption`,
+    /// `colgroup`, `col`, `tbody`, `thead`, `tfoot`, `tr`, `td` and `th`).
+    ///
+    /// Outside of a table these tags are ignored, as the specification requires. Inside
+    /// a table, open cells and rows are closed as needed, and the implied `<tbody>`,
+    /// `<tr>` and `<colgroup>` elements are created.
+    fn table_structure_start_tag(&mut self, element: Element) {
+        if !self.in_scope(&["table"], TABLE_SCOPE_BARRIERS) {
+            return;
+        }
+        let tag = element.tag.clone();
+        if tag != "td" && tag != "th" && self.in_scope(&["td", "th"], TABLE_SCOPE_BARRIERS) {
+            self.close_cell();
+        }
+        if self.in_scope(&["caption"], TABLE_SCOPE_BARRIERS) {
+            self.pop_until(&["caption"]);
+            self.clear_active_formatting_to_marker();
+        }
+        match tag.as_str() {
+            "caption" | "colgroup" | "tbody" | "thead" | "tfoot" => {
+                self.clear_stack_back_to(&["table"]);
+                self.insert_element(element);
+                if tag == "caption" {
+                    self.active_formatting.push(None);
                 }
             }
-        } else if VOID_TAGS.contains(&element.tag.as_str()) {
-             match self.unfinished.last_mut() {
-                None => {
-                    panic!("No parent node found for void tag");
+            "col" => {
+                if self.current_tag() != "colgroup" {
+                    self.clear_stack_back_to(&["table"]);
+                    self.insert_element(implied_element("colgroup"));
                 }
-                Some(parent) => {
-                    let node = HtmlNode::new(
-                        HtmlNodeType::Element(element), Some(Arc::clone(&parent)));
-                    parent.write().unwrap().children.push(Arc::new(RwLock::new(node)));
+                self.insert_element(element);
+            }
+            "tr" => {
+                self.clear_stack_back_to(&["tbody", "thead", "tfoot", "table"]);
+                if self.current_tag() == "table" {
+                    self.insert_element(implied_element("tbody"));
                 }
+                self.insert_element(element);
             }
-        } else {
-            let parent = match self.unfinished.is_empty() {
-                false => {
-                    Some(self.unfinished.last().unwrap().clone())
+            _ => {
+                // td and th
+                if self.in_scope(&["td", "th"], TABLE_SCOPE_BARRIERS) {
+                    self.close_cell();
                 }
-                true => {None}
-            };
-            let node = HtmlNode::new(
-                HtmlNodeType::Element(element), parent);
-            self.unfinished.push(Arc::new(RwLock::new(node)));
+                self.clear_stack_back_to(&["tr", "tbody", "thead", "tfoot", "table"]);
+                if self.current_tag() == "table" {
+                    self.insert_element(implied_element("tbody"));
+                }
+                if self.current_tag() != "tr" {
+                    self.insert_element(implied_element("tr"));
+                }
+                self.insert_element(element);
+                self.active_formatting.push(None);
+            }
         }
     }
 
-    /// ```rust
-    /// Finalizes the construction of an HTML tree by collapsing all unfinished nodes
-    /// into a single structured tree and returns the root node.
+    /// Processes an end tag, following the "in body" and "in table" insertion modes.
     ///
-    /// This method works by iteratively popping nodes from the `unfinished` stack,
-    /// attaching each node as a child to its parent (the last node in the stack),
-    /// until only one root node remains. The root node is then returned.
+    /// End tags that do not match an element in scope are ignored.
+    fn end_tag(&mut self, tag: &str) {
+        match tag {
+            "html" | "body" => {
+                // The body stays open; any content after </body> still belongs in it.
+            }
+            "head" => {
+                if self.current_tag() == "head" {
+                    self.unfinished.pop();
+                }
+            }
+            "br" => self.start_tag(implied_element("br")),
+            "p" => {
+                if !self.in_scope(&["p"], BUTTON_SCOPE_BARRIERS) {
+                    // A stray </p> produces an empty paragraph.
+                    self.insert_element(implied_element("p"));
+                }
+                self.generate_implied_end_tags(Some("p"));
+                self.pop_until(&["p"]);
+            }
+            "li" => {
+                if self.in_scope(&["li"], LIST_ITEM_SCOPE_BARRIERS) {
+                    self.generate_implied_end_tags(Some("li"));
+                    self.pop_until(&["li"]);
+                }
+            }
+            "dd" | "dt" => {
+                if self.in_scope(&[tag], SCOPE_BARRIERS) {
+                    self.generate_implied_end_tags(Some(tag));
+                    self.pop_until(&[tag]);
+                }
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                if self.in_scope(HEADING_TAGS, SCOPE_BARRIERS) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(HEADING_TAGS);
+                }
+            }
+            "td" | "th" | "caption" => {
+                if self.in_scope(&[tag], TABLE_SCOPE_BARRIERS) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&[tag]);
+                    self.clear_active_formatting_to_marker();
+                }
+            }
+            "tr" | "tbody" | "thead" | "tfoot" | "table" | "colgroup" => {
+                if self.in_scope(&[tag], TABLE_SCOPE_BARRIERS) {
+                    if self.in_scope(&["td", "th"], TABLE_SCOPE_BARRIERS) {
+                        self.close_cell();
+                    }
+                    self.pop_until(&[tag]);
+                }
+            }
+            _ if FORMATTING_TAGS.contains(&tag) => {
+                if !self.adoption_agency(tag) {
+                    self.any_other_end_tag(tag);
+                }
+            }
+            _ if SPECIAL_TAGS.contains(&tag) => {
+                if self.in_scope(&[tag], SCOPE_BARRIERS) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&[tag]);
+                }
+            }
+            _ => self.any_other_end_tag(tag),
+        }
+    }
+
+    /// The "any other end tag" steps: closes the nearest open element named `tag`, unless
+    /// a special element (such as a `<div>`) is open inside it, in which case the end
+    /// tag is ignored.
+    fn any_other_end_tag(&mut self, tag: &str) {
+        for index in (0..self.unfinished.len()).rev() {
+            let node_tag = tag_name(&self.unfinished[index]);
+            if node_tag == tag {
+                self.generate_implied_end_tags(Some(tag));
+                self.unfinished.truncate(index);
+                return;
+            }
+            if SPECIAL_TAGS.contains(&node_tag.as_str()) {
+                return;
+            }
+        }
+    }
+
+    /// Runs a reduced version of the adoption agency algorithm for the end tag of the
+    /// formatting element `tag`.
+    ///
+    /// The algorithm repairs misnested markup such as `<b>1<p>2</b>3</p>`: the part of
+    /// the formatting element that ended up inside the block (`<p>`) is cloned into it,
+    /// producing `<b>1</b><p><b>2</b>3</p>`.
     ///
     /// # Returns
+    /// `false` if there is no such formatting element in the list of active formatting
+    /// elements, in which case the end tag must be treated like any other end tag.
+    fn adoption_agency(&mut self, tag: &str) -> bool {
+        for _ in 0..8 {
+            let Some(formatting_element) = self.active_formatting_after_marker(tag) else {
+                return false;
+            };
+
+            let Some(formatting_index) = self.unfinished.iter().position(|n| Arc::ptr_eq(n, &formatting_element)) else {
+                // Closed implicitly already; just forget about it.
+                self.remove_active_formatting_element(&formatting_element);
+                return true;
+            };
+            if !self.in_scope(&[tag], SCOPE_BARRIERS) {
+                return true;
+            }
+
+            let furthest_block_index = (formatting_index + 1..self.unfinished.len())
+                .find(|i| SPECIAL_TAGS.contains(&tag_name(&self.unfinished[*i]).as_str()));
+            let Some(furthest_block_index) = furthest_block_index else {
+                self.unfinished.truncate(formatting_index);
+                self.remove_active_formatting_element(&formatting_element);
+                return true;
+            };
+            let furthest_block = self.unfinished[furthest_block_index].clone();
+            let common_ancestor = self.unfinished[formatting_index.saturating_sub(1)].clone();
+
+            let mut bookmark = self.active_formatting_position(&formatting_element).unwrap_or(0);
+            let mut last_node = furthest_block.clone();
+            let mut node_index = furthest_block_index;
+            let mut inner_loop_counter = 0;
+            loop {
+                inner_loop_counter += 1;
+                node_index -= 1;
+                let node = self.unfinished[node_index].clone();
+                if Arc::ptr_eq(&node, &formatting_element) {
+                    break;
+                }
+                let Some(list_index) = self.active_formatting_position(&node) else {
+                    self.unfinished.remove(node_index);
+                    continue;
+                };
+                if inner_loop_counter > 3 {
+                    self.active_formatting.remove(list_index);
+                    self.unfinished.remove(node_index);
+                    continue;
+                }
+
+                let replacement = clone_element(&node);
+                self.active_formatting[list_index] = Some(replacement.clone());
+                self.unfinished[node_index] = replacement.clone();
+                if Arc::ptr_eq(&last_node, &furthest_block) {
+                    bookmark = list_index + 1;
+                }
+                detach(&last_node);
+                append_child(&replacement, last_node);
+                last_node = replacement;
+            }
+
+            detach(&last_node);
+            self.insert_node_in(&common_ancestor, last_node);
+
+            let new_element = clone_element(&formatting_element);
+            let children = std::mem::take(&mut furthest_block.write().unwrap().children);
+            for child in children {
+                append_child(&new_element, child);
+            }
+            append_child(&furthest_block, new_element.clone());
+
+            if let Some(old_index) = self.active_formatting_position(&formatting_element) {
+                self.active_formatting.remove(old_index);
+                if old_index < bookmark {
+                    bookmark -= 1;
+                }
+            }
+            let bookmark = bookmark.min(self.active_formatting.len());
+            self.active_formatting.insert(bookmark, Some(new_element.clone()));
+
+            self.unfinished.retain(|n| !Arc::ptr_eq(n, &formatting_element));
+            let furthest_block_index = self.unfinished.iter()
+                .position(|n| Arc::ptr_eq(n, &furthest_block))
+                .unwrap_or(self.unfinished.len() - 1);
+            self.unfinished.insert(furthest_block_index + 1, new_element);
+        }
+        true
+    }
+
+    /// Reopens formatting elements that were closed implicitly, e.g. by a `</p>`, so that
+    /// they also apply to the content that follows.
+    fn reconstruct_active_formatting_elements(&mut self) {
+        let is_open = |parser: &Self, entry: &Option<Arc<RwLock<HtmlNode>>>| match entry {
+            None => true,
+            Some(node) => parser.unfinished.iter().any(|n| Arc::ptr_eq(n, node)),
+        };
+
+        let Some(last) = self.active_formatting.last() else { return };
+        if is_open(self, last) {
+            return;
+        }
+
+        let mut index = self.active_formatting.len() - 1;
+        while index > 0 && !is_open(self, &self.active_formatting[index - 1]) {
+            index -= 1;
+        }
+
+        for i in index..self.active_formatting.len() {
+            let Some(old) = self.active_formatting[i].clone() else { continue };
+            let element = match &old.read().unwrap().node_type {
+                HtmlNodeType::Element(e) => e.clone(),
+                HtmlNodeType::Text(_) => continue,
+            };
+            let node = self.insert_element(element);
+            self.active_formatting[i] = Some(node);
+        }
+    }
+
+    /// Creates a node for `element`, inserts it at the appropriate place and, unless it
+    /// is a void element, pushes it onto the stack of open elements.
+    fn insert_element(&mut self, element: Element) -> Arc<RwLock<HtmlNode>> {
+        let is_void = VOID_TAGS.contains(&element.tag.as_str());
+        let is_head = element.tag == "head";
+        let node = Arc::new(RwLock::new(HtmlNode::new(HtmlNodeType::Element(element), None)));
+        if self.unfinished.is_empty() {
+            self.root = Some(node.clone());
+        } else {
+            self.insert_node(node.clone());
+        }
+        if !is_void {
+            self.unfinished.push(node.clone());
+        }
+        if is_head {
+            self.head = Some(node.clone());
+        }
+        node
+    }
+
+    /// Inserts `node` at the appropriate place, i.e. as the last child of the current
+    /// node, or foster-parented if the current node is a table element.
+    fn insert_node(&mut self, node: Arc<RwLock<HtmlNode>>) {
+        let Some(target) = self.unfinished.last().or(self.root.as_ref()).cloned() else {
+            return;
+        };
+        self.insert_node_in(&target, node);
+    }
+
+    /// Inserts `node` as the last child of `target`, unless `target` is a table element
+    /// and `node` cannot appear there, in which case `node` is foster-parented: it is
+    /// inserted just before the table in the table's parent.
+    fn insert_node_in(&mut self, target: &Arc<RwLock<HtmlNode>>, node: Arc<RwLock<HtmlNode>>) {
+        let allowed_in_table = match &node.read().unwrap().node_type {
+            HtmlNodeType::Element(e) => TABLE_CONTENT_TAGS.contains(&e.tag.as_str()),
+            HtmlNodeType::Text(_) => false,
+        };
+        if allowed_in_table || !TABLE_CONTEXT_TAGS.contains(&tag_name(target).as_str()) {
+            append_child(target, node);
+            return;
+        }
+
+        let table_index = self.unfinished.iter().rposition(|n| tag_name(n) == "table");
+        match table_index {
+            Some(index) if index > 0 => {
+                let table = self.unfinished[index].clone();
+                let table_parent = table.read().unwrap().parent.clone();
+                match table_parent {
+                    Some(parent) => {
+                        let mut parent_ref = parent.write().unwrap();
+                        let position = parent_ref.children.iter()
+                            .position(|c| Arc::ptr_eq(c, &table))
+                            .unwrap_or(parent_ref.children.len());
+                        node.write().unwrap().parent = Some(parent.clone());
+                        parent_ref.children.insert(position, node);
+                    }
+                    None => append_child(&self.unfinished[index - 1].clone(), node),
+                }
+            }
+            _ => append_child(target, node),
+        }
+    }
+
+    /// Returns the tag name of the current node (the top of the stack of open elements).
+    fn current_tag(&self) -> String {
+        self.unfinished.last().map(tag_name).unwrap_or_default()
+    }
+
+    /// Returns `true` if an element named `tag` is on the stack of open elements.
+    fn has_open(&self, tag: &str) -> bool {
+        self.unfinished.iter().any(|n| tag_name(n) == tag)
+    }
+
+    /// Returns `true` if one of `tags` is in scope, i.e. open without any of the
+    /// `barriers` (such as `table` or `td`) between it and the current node.
+    fn in_scope(&self, tags: &[&str], barriers: &[&str]) -> bool {
+        for node in self.unfinished.iter().rev() {
+            let node_tag = tag_name(node);
+            if tags.contains(&node_tag.as_str()) {
+                return true;
+            }
+            if barriers.contains(&node_tag.as_str()) {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Pops elements off the stack of open elements until one of `tags` has been popped.
+    /// The root element is never popped.
+    fn pop_until(&mut self, tags: &[&str]) {
+        while self.unfinished.len() > 1 {
+            let node = self.unfinished.pop().unwrap();
+            if tags.contains(&tag_name(&node).as_str()) {
+                break;
+            }
+        }
+    }
+
+    /// Pops elements off the stack of open elements until the current node is one of
+    /// `tags` (or the root element).
+    fn clear_stack_back_to(&mut self, tags: &[&str]) {
+        while self.unfinished.len() > 1 && !tags.contains(&self.current_tag().as_str()) {
+            self.unfinished.pop();
+        }
+    }
+
+    /// Pops elements whose end tags are implied (`p`, `li`, `dd`, `option` and friends)
+    /// off the stack, except for an element named `except`.
+    fn generate_implied_end_tags(&mut self, except: Option<&str>) {
+        loop {
+            let current = self.current_tag();
+            if !IMPLIED_END_TAGS.contains(&current.as_str()) || Some(current.as_str()) == except {
+                break;
+            }
+            self.unfinished.pop();
+        }
+    }
+
+    /// Closes an open `<p>` element, if there is one in button scope.
+    fn close_p_in_button_scope(&mut self) {
+        if self.in_scope(&["p"], BUTTON_SCOPE_BARRIERS) {
+            self.generate_implied_end_tags(Some("p"));
+            self.pop_until(&["p"]);
+        }
+    }
+
+    /// Closes the innermost open list item named one of `tags` before a new one starts,
+    /// unless a special element other than `address`, `div` or `p` is open inside it.
+    fn close_list_item(&mut self, tags: &[&str]) {
+        for index in (0..self.unfinished.len()).rev() {
+            let node_tag = tag_name(&self.unfinished[index]);
+            if tags.contains(&node_tag.as_str()) {
+                self.generate_implied_end_tags(Some(&node_tag));
+                self.unfinished.truncate(index);
+                return;
+            }
+            if SPECIAL_TAGS.contains(&node_tag.as_str()) && !["address", "div", "p"].contains(&node_tag.as_str()) {
+                return;
+            }
+        }
+    }
+
+    /// Closes the open table cell, along with anything left open inside it.
+    fn close_cell(&mut self) {
+        self.generate_implied_end_tags(None);
+        self.pop_until(&["td", "th"]);
+        self.clear_active_formatting_to_marker();
+    }
+
+    /// Returns the last formatting element named `tag` after the last marker in the list
+    /// of active formatting elements.
+    fn active_formatting_after_marker(&self, tag: &str) -> Option<Arc<RwLock<HtmlNode>>> {
+        for entry in self.active_formatting.iter().rev() {
+            match entry {
+                None => return None,
+                Some(node) if tag_name(node) == tag => return Some(node.clone()),
+                Some(_) => {}
+            }
+        }
+        None
+    }
+
+    /// Returns the index of `node` in the list of active formatting elements.
+    fn active_formatting_position(&self, node: &Arc<RwLock<HtmlNode>>) -> Option<usize> {
+        self.active_formatting.iter()
+            .position(|entry| matches!(entry, Some(n) if Arc::ptr_eq(n, node)))
+    }
+
+    /// Removes `node` from the list of active formatting elements.
+    fn remove_active_formatting_element(&mut self, node: &Arc<RwLock<HtmlNode>>) {
+        if let Some(index) = self.active_formatting_position(node) {
+            self.active_formatting.remove(index);
+        }
+    }
+
+    /// Removes entries from the list of active formatting elements up to and including
+    /// the last marker, when a table cell or caption is closed.
+    fn clear_active_formatting_to_marker(&mut self) {
+        while let Some(entry) = self.active_formatting.pop() {
+            if entry.is_none() {
+                break;
+            }
+        }
+    }
+
+    /// ```rust
+    /// Finalizes the construction of an HTML tree and returns the root node.
     ///
-    /// A reference-counted `Rc<RefCell<HtmlNode>>` representing the root of the completed HTML tree.
+    /// Nodes are attached to their parents as soon as they are created, so all that is
+    /// left to do is to close every element that is still open.
     ///
-    /// # Panics
+    /// # Returns
     ///
-    /// This function will panic if the `unfinished` stack is empty when it attempts
-    /// to `pop`. It assumes that there is at least one node in the `unfinished` stack
-    /// when it is called.
+    /// A reference-counted `Arc<RwLock<HtmlNode>>` representing the root of the completed HTML tree.
     /// ```
     fn finish(&mut self) -> Arc<RwLock<HtmlNode>> {
-        if self.unfinished.is_empty() {
+        if self.root.is_none() {
+            self.unfinished.clear();
             self.implicit_tags(None);
         }
-        while self.unfinished.len() > 1 {
-            let node = self.unfinished.pop().unwrap();
-            let parent = self.unfinished.last_mut().unwrap();
-            parent.write().unwrap().children.push(Arc::clone(&node));
-        }
-        self.unfinished.pop().unwrap()
+        self.unfinished.clear();
+        self.active_formatting.clear();
+        self.root.clone().unwrap_or_else(|| Arc::new(RwLock::new(
+            HtmlNode::new(HtmlNodeType::Element(implied_element("html")), None))))
     }
 
     /// Handles the insertion of implicit tags when parsing an HTML document structure.
@@ -300,36 +1165,24 @@ impl HtmlParser {
     /// # Arguments
     ///
     /// * `tag` - An optional reference to a string slice representing the currently
-    ///   processed tag. If `tag` is `None`, this function determines what adjustments
-    ///   to make based on the state of the `unfinished` stack alone.
+    ///   processed tag, or `None` when text is being inserted.
     ///
     /// # Behavior
     ///
-    /// This function implements the following logic:
+    /// Based on the open elements and the incoming tag:
+    /// - If no tags are open and the incoming tag is not "html", an `<html>` element is created.
+    /// - If only the `<html>` element is open and the incoming tag requires either a `<head>`
+    ///   or `<body>` context (depending on the tag), the appropriate element is created.
+    ///   Text always requires a `<body>`. A `<head>` that was already closed is reopened
+    ///   rather than created again.
+    /// - If both `<html>` and `<head>` are open and the tag (or text) should not appear
+    ///   within `<head>`, the `<head>` element is closed. A second `<head>` leaves it
+    ///   open, since it is ignored.
     ///
-    /// 1. Iterates over the `unfinished` stack (which represents the hierarchy of open
-    ///    tags being parsed) to extract the tag names of open elements, skipping text
-    ///    nodes.
-    /// 2. Constructs a list of open tags (`open_tags`) to determine the current parsing context.
-    /// 3. Based on the HTML parsing context and the incoming tag:
-    ///    - If no tags are open and the incoming tag is not "html", it adds a `<html>` tag.
-    ///    - If only the `<html>` tag is open and the incoming tag requires either a `<head>`
-    ///      or `<body>` context (depending on the tag), it adds the appropriate tag.
-    ///    - If both `<html>` and `<head>` tags are open and the tag should not appear
-    ///      within `<head>`, it closes the `<head>` tag by adding a `</head>` tag.
-    /// 4. Repeats the adjustment logic in a loop until no further implicit tags need to be added.
-    ///
-    /// # Notes
-    ///
-    /// * This function relies on the presence of `unfinished`, a stack-like structure
-    ///   of open HTML nodes being processed, and `HEAD_TAGS`, a predefined set of tags
-    ///   that are valid within the `<head>` element.
-    /// * Invokes `self.add_tag(tag)` to programmatically add the necessary implicit tags
-    ///   to maintain the document's structure.
+    /// This repeats until no further implicit tags need to be added.
     ///
     /// # Example
     ///
-    /// Assume `HEAD_TAGS = ["title", "meta", "link", "style"]`.
     /// When parsing the following snippet:
     ///
     /// ```html
@@ -349,31 +1202,25 @@ impl HtmlParser {
     ///   </body>
     /// </html>
     /// ```
-    ///
-    /// This mechanism ensures the document's structure adheres to HTML standards.
     fn implicit_tags(&mut self, tag: Option<&str>) {
         loop {
-            let open_tags = self.unfinished.iter().map(|n| {
-                match &n.read().unwrap().node_type {
-                    HtmlNodeType::Element(ele) => {
-                        Some(ele.tag.clone())
+            let open_tags = self.unfinished.iter().map(tag_name).collect::<Vec<_>>();
+
+            if open_tags.is_empty() && tag != Some("html") {
+                self.insert_element(implied_element("html"));
+            } else if open_tags == ["html"] && !matches!(tag, Some("head" | "body" | "/html")) {
+                if tag.is_some_and(|t| HEAD_TAGS.contains(&t)) {
+                    match self.head.clone() {
+                        Some(head) => self.unfinished.push(head),
+                        None => {
+                            self.insert_element(implied_element("head"));
+                        }
                     }
-                    HtmlNodeType::Text(_) => {None}
-                }
-            }).filter(|t| t.is_some())
-                .map(|t| t.unwrap())
-                .collect::<Vec<_>>();
-
-            if open_tags.is_empty() && tag.is_some() && tag.unwrap() != "html" {
-                self.add_tag("html");
-            } else if open_tags == ["html"] && tag.is_some() && match tag.unwrap() {"head" | "body" | "/html" => false, _ => true} {
-                if HEAD_TAGS.contains(&tag.unwrap()) {
-                    self.add_tag("head");
                 } else {
-                    self.add_tag("body");
+                    self.insert_element(implied_element("body"));
                 }
-            } else if open_tags == ["html", "head"] && tag.is_some() && !HEAD_TAGS.contains(&tag.unwrap()) && tag.unwrap() != "/head" {
-                self.add_tag("/head");
+            } else if open_tags == ["html", "head"] && !tag.is_some_and(|t| HEAD_TAGS.contains(&t) || t == "head" || t == "/head") {
+                self.unfinished.pop();
             } else {
                 break;
             }
@@ -397,7 +1244,8 @@ impl HtmlParser {
 /// Returns an `Element` struct containing the tag name and a `HashMap`
 /// of attribute keys and values. The tag name and attribute keys are
 /// converted to lowercase for consistency. Quotation marks around attribute
-/// values are trimmed (if present).
+/// values are trimmed (if present), and character references in the values
+/// (e.g. `&amp;`) are decoded.
 ///
 /// # Behavior
 ///
@@ -431,8 +1279,8 @@ impl HtmlParser {
 ///
 /// # Errors
 ///
-/// No explicit error handling is implemented; malformed input may lead
-/// to unexpected behavior, such as panics or invalid attribute values.
+/// The function never fails: an empty tag (`<>`) yields an empty tag name, which
+/// `add_tag` ignores, and an unterminated quote only loses its opening quote mark.
 ///
 /// ```
 /// ```
@@ -463,17 +1311,18 @@ fn get_attributes(text: &str) -> Element {
         parts.push(current);
     }
 
-    let tag = parts[0].to_lowercase();
+    // A self-closing slash (`<br/>`, `<img src=a.png />`) is not part of the tag name
+    // and is not an attribute.
+    let tag = parts.first().map(|t| t.to_lowercase()).unwrap_or_default();
+    let tag = match tag.strip_suffix('/') {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => tag,
+    };
     let mut attributes = HashMap::<String, String>::new();
-    for attrpair in parts[1..].iter() {
-        if attrpair.contains('=') {
-            let kv: Vec<&str> = attrpair.splitn(2, '=').collect();
-            let key = kv[0].to_string();
-            let mut value = kv[1].to_string();
-            if value.len() >= 2 && (value.starts_with('\'') || value.starts_with('"')) {
-                value = value[1..value.len()-1].to_string();
-            }
-            attributes.insert(key.to_lowercase(), value);
+    for attrpair in parts.iter().skip(1).filter(|part| part.as_str() != "/") {
+        if let Some((key, value)) = attrpair.split_once('=') {
+            let value = unquote(value);
+            attributes.insert(key.to_lowercase(), decode_character_references(value, true));
         } else {
             attributes.insert(attrpair.to_string().to_lowercase(), "".to_string());
         }
@@ -482,3 +1331,262 @@ fn get_attributes(text: &str) -> Element {
     Element{tag: tag.into(),
         attributes}
 }
+
+
+/// Returns `true` if `chars[index..]` starts with `pattern`, optionally ignoring ASCII case.
+fn starts_with_at(chars: &[char], index: usize, pattern: &str, ignore_case: bool) -> bool {
+    let mut i = index;
+    for p in pattern.chars() {
+        match chars.get(i) {
+            Some(c) if *c == p || (ignore_case && c.eq_ignore_ascii_case(&p)) => i += 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Returns `true` if `chars[index..]` is an end tag `end_tag` (e.g. `</script`) that is
+/// terminated by whitespace, `/`, `>` or the end of the input, comparing case-insensitively.
+fn is_end_tag_at(chars: &[char], index: usize, end_tag: &[char]) -> bool {
+    for (offset, expected) in end_tag.iter().enumerate() {
+        match chars.get(index + offset) {
+            Some(c) if c.eq_ignore_ascii_case(expected) => {}
+            _ => return false,
+        }
+    }
+    match chars.get(index + end_tag.len()) {
+        None => true,
+        Some(c) => c.is_whitespace() || *c == '/' || *c == '>',
+    }
+}
+
+/// Skips a comment whose body starts at `start` (just past the `<!--`) and returns the
+/// index of the first character after it.
+///
+/// Follows the HTML5 comment states: the comment ends at `-->` or `--!>`, the abrupt
+/// forms `<!-->` and `<!--->` are empty comments. Returns `None` if the comment is not
+/// terminated within `chars`; at the end of the input it then runs to the end.
+fn skip_comment(chars: &[char], start: usize) -> Option<usize> {
+    if starts_with_at(chars, start, ">", false) {
+        return Some(start + 1);
+    }
+    if starts_with_at(chars, start, "->", false) {
+        return Some(start + 2);
+    }
+    let mut i = start;
+    while i < chars.len() {
+        if starts_with_at(chars, i, "-->", false) {
+            return Some(i + 3);
+        }
+        if starts_with_at(chars, i, "--!>", false) {
+            return Some(i + 4);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Skips a bogus comment (or DOCTYPE) up to and including the next `>` and returns the
+/// index of the first character after it, or `None` if there is no `>` in `chars`.
+fn skip_bogus_comment(chars: &[char], start: usize) -> Option<usize> {
+    let mut i = start;
+    while i < chars.len() {
+        if chars[i] == '>' {
+            return Some(i + 1);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Returns `true` if the input from `index` to the end is a proper prefix of `pattern`
+/// (compared case-insensitively), i.e. more input could still complete the pattern.
+fn is_prefix_at(chars: &[char], index: usize, pattern: &str) -> bool {
+    let rest = &chars[index.min(chars.len())..];
+    rest.len() < pattern.chars().count()
+        && rest.iter().zip(pattern.chars()).all(|(c, p)| c.eq_ignore_ascii_case(&p))
+}
+
+/// Removes the quotes around an attribute value, if it has any. An unterminated quote
+/// (`"value`) is dropped as well.
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(rest) = value.strip_prefix(quote) {
+            return rest.strip_suffix(quote).unwrap_or(rest);
+        }
+    }
+    value
+}
+
+/// Returns the tag name of `node`, or an empty string for a text node.
+pub(crate) fn tag_name(node: &Arc<RwLock<HtmlNode>>) -> String {
+    match &node.read().unwrap().node_type {
+        HtmlNodeType::Element(e) => e.tag.clone(),
+        HtmlNodeType::Text(_) => String::new(),
+    }
+}
+
+/// Creates an attribute-less element for a tag that is implied by the markup.
+fn implied_element(tag: &str) -> Element {
+    Element { tag: tag.to_string(), attributes: HashMap::new() }
+}
+
+/// Creates a detached copy of the element `node`, with the same attributes but no children.
+fn clone_element(node: &Arc<RwLock<HtmlNode>>) -> Arc<RwLock<HtmlNode>> {
+    let node_type = node.read().unwrap().node_type.clone();
+    Arc::new(RwLock::new(HtmlNode::new(node_type, None)))
+}
+
+/// Appends `child` to the children of `parent`, updating its parent pointer.
+fn append_child(parent: &Arc<RwLock<HtmlNode>>, child: Arc<RwLock<HtmlNode>>) {
+    child.write().unwrap().parent = Some(parent.clone());
+    parent.write().unwrap().children.push(child);
+}
+
+/// Removes `node` from the children of its parent, if it has one.
+fn detach(node: &Arc<RwLock<HtmlNode>>) {
+    let parent = node.write().unwrap().parent.take();
+    if let Some(parent) = parent {
+        parent.write().unwrap().children.retain(|c| !Arc::ptr_eq(c, node));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_serializer::document_html;
+
+    /// Parses `html` and returns the serialization of the whole document.
+    fn parsed_html(html: &str) -> String {
+        document_html(&HtmlParser::new(html.to_string()).parse())
+    }
+
+    /// Parses `html` in two chunks split at `offset` through `feed` and `end`, skipping
+    /// scripts, and returns the serialization of the whole document.
+    fn streamed_html(html: &str, offset: usize) -> String {
+        let mut parser = HtmlParser::new(String::new());
+        for chunk in [&html[..offset], &html[offset..]] {
+            let mut script = parser.feed(chunk);
+            while script.is_some() {
+                script = parser.feed("");
+            }
+        }
+        while parser.end().is_some() {}
+        document_html(&parser.document().expect("no document after end"))
+    }
+
+    #[test]
+    fn empty_tags_are_text() {
+        assert_eq!(parsed_html("<>"), "<!DOCTYPE html><html><body>&lt;&gt;</body></html>");
+        assert_eq!(parsed_html("<p>a<>b</>c"), "<!DOCTYPE html><html><body><p>a&lt;&gt;bc</p></body></html>");
+    }
+
+    #[test]
+    fn stray_end_tags_are_ignored() {
+        assert_eq!(parsed_html("</x>"), "<!DOCTYPE html><html><body></body></html>");
+        assert_eq!(parsed_html("<p>a</x>b</p></div></p>"), "<!DOCTYPE html><html><body><p>ab</p><p></p></body></html>");
+    }
+
+    #[test]
+    fn the_input_may_end_with_no_open_elements() {
+        let mut parser = HtmlParser::new(String::new());
+        assert!(parser.end().is_none());
+        assert_eq!(document_html(&parser.document().unwrap()), "<!DOCTYPE html><html><body></body></html>");
+        assert_eq!(parsed_html(""), "<!DOCTYPE html><html><body></body></html>");
+        assert_eq!(parsed_html("<p>x</p></body></html></html>x"), "<!DOCTYPE html><html><body><p>x</p>x</body></html>");
+    }
+
+    #[test]
+    fn a_document_fed_in_chunks_parses_as_a_whole() {
+        let html = "<!DOCTYPE html><title>a &amp; b</title><p class='x y'>one &copy two<b>three<i>four</b>five</i>
+            <script>if (a < b) {}</script><table><tr><td>cell</table><!-- c --><textarea>&lt;</textarea>";
+        let whole = parsed_html(html);
+        for offset in (0..=html.len()).filter(|&offset| html.is_char_boundary(offset)) {
+            assert_eq!(streamed_html(html, offset), whole, "split at {}", offset);
+        }
+    }
+
+    /// Returns the element of the tree at `root` with the given `id`.
+    fn element_by_id(root: &Arc<RwLock<HtmlNode>>, id: &str) -> Arc<RwLock<HtmlNode>> {
+        HtmlNode::tree_to_vec(root.clone(), &mut vec![]).iter()
+            .find(|node| matches!(&node.read().unwrap().node_type,
+                HtmlNodeType::Element(e) if e.attributes.get("id").is_some_and(|value| value == id)))
+            .cloned()
+            .unwrap_or_else(|| panic!("no element with id={}", id))
+    }
+
+    /// Returns the text of the children of `node` that are text nodes.
+    fn text_content(node: &Arc<RwLock<HtmlNode>>) -> String {
+        node.read().unwrap().children.iter()
+            .filter_map(|child| match &child.read().unwrap().node_type {
+                HtmlNodeType::Text(text) => Some(text.text.clone()),
+                HtmlNodeType::Element(_) => None,
+            })
+            .collect()
+    }
+
+    /// Returns the value of the attribute `name` of the element `node`.
+    fn attribute(node: &Arc<RwLock<HtmlNode>>, name: &str) -> Option<String> {
+        match &node.read().unwrap().node_type {
+            HtmlNodeType::Element(element) => element.attributes.get(name).cloned(),
+            HtmlNodeType::Text(_) => None,
+        }
+    }
+
+    #[test]
+    fn character_references_are_decoded_in_text_and_attributes() {
+        let root = HtmlParser::new("<a id=link href=\"?a=1&copy=2&amp;b\" title='&copy x'>&lt;&copy=2&#x41;</a>".to_string()).parse();
+        let link = element_by_id(&root, "link");
+        assert_eq!(text_content(&link), "<\u{a9}=2A");
+        assert_eq!(attribute(&link, "href").as_deref(), Some("?a=1&copy=2&b"));
+        assert_eq!(attribute(&link, "title").as_deref(), Some("\u{a9} x"));
+    }
+
+    #[test]
+    fn raw_text_elements_end_only_at_their_end_tag() {
+        let root = HtmlParser::new("<script id=s>if (a < b && c > d) { x('</p><!--'); }</scripty></SCRIPT ><style id=c>a > b { }</style>
+            <textarea id=t>&lt;b&gt; <i></textarea ><title id=n>a &amp; <b>b</b></title>".to_string()).parse();
+        assert_eq!(text_content(&element_by_id(&root, "s")), "if (a < b && c > d) { x('</p><!--'); }</scripty>");
+        assert_eq!(text_content(&element_by_id(&root, "c")), "a > b { }");
+        // Escapable raw text decodes character references, but still holds no tags.
+        assert_eq!(text_content(&element_by_id(&root, "t")), "<b> <i>");
+        assert_eq!(text_content(&element_by_id(&root, "n")), "a & <b>b</b>");
+    }
+
+    #[test]
+    fn comments_cdata_and_doctypes_are_tokenized() {
+        assert_eq!(parsed_html("<!DOCTYPE html PUBLIC \"x\"><p>a<!-- <p>b</p> -- > --->c<!---->d</p>"),
+            "<!DOCTYPE html><html><body><p>acd</p></body></html>");
+        // CDATA is text in foreign content, and a bogus comment in HTML.
+        assert_eq!(parsed_html("<svg><![CDATA[a<b>]]></svg><p><![CDATA[x]]>y</p><?php echo 1 ?>"),
+            "<!DOCTYPE html><html><body><svg>a&lt;b&gt;</svg><p>y</p></body></html>");
+    }
+
+    #[test]
+    fn a_document_has_a_single_head() {
+        assert_eq!(parsed_html("<head><head><title>t</title>"), "<!DOCTYPE html><html><head><title>t</title></head></html>");
+        assert_eq!(parsed_html("<head></head><head><title>t</title></head><p>x"),
+            "<!DOCTYPE html><html><head><title>t</title></head><body><p>x</p></body></html>");
+        assert_eq!(parsed_html("<title>t</title><head><meta charset=utf-8>"),
+            "<!DOCTYPE html><html><head><title>t</title><meta charset=\"utf-8\"></head></html>");
+    }
+
+    #[test]
+    fn misnested_formatting_elements_are_adopted() {
+        let body = |html: &str| parsed_html(html).replace("<!DOCTYPE html><html><body>", "").replace("</body></html>", "");
+        assert_eq!(body("<b><i></b></i>"), "<b><i></i></b>");
+        assert_eq!(body("<b>1<i>2</b>3</i>4"), "<b>1<i>2</i></b><i>3</i>4");
+        assert_eq!(body("<b>1<p>2</b>3</p>"), "<b>1</b><p><b>2</b>3</p>");
+        assert_eq!(body("<p><b>x<p>y"), "<p><b>x</b></p><p><b>y</b></p>");
+    }
+
+    #[test]
+    fn misplaced_table_content_is_foster_parented() {
+        let body = |html: &str| parsed_html(html).replace("<!DOCTYPE html><html><body>", "").replace("</body></html>", "");
+        assert_eq!(body("<table><tr><td>a</td></tr>x<b>y</b></table>"),
+            "x<b>y</b><table><tbody><tr><td>a</td></tr></tbody></table>");
+        // Missing row groups and rows are created around cells.
+        assert_eq!(body("<div><table> z <td>a<td>b</table></div>"),
+            "<div> z <table><tbody><tr><td>a</td><td>b</td></tr></tbody></table></div>");
+    }
+}
@@ -0,0 +1,199 @@
+use std::sync::{Arc, RwLock};
+use crate::html_parser::{RAW_TEXT_TAGS, VOID_TAGS, tag_name};
+use crate::node::{HtmlNode, HtmlNodeType};
+
+/// Serializes the children of `node` to HTML markup, as the DOM `innerHTML` getter does.
+///
+/// This follows the HTML fragment serialization algorithm of the HTML5 specification:
+///
+/// - Elements are written as a start tag with their attributes, their serialized
+///   children and an end tag. Void elements (`<br>`, `<img>`, ...) get no end tag and
+///   their children, if any, are skipped.
+/// - Text is escaped (`&`, `<`, `>` and U+00A0), except inside raw text elements such
+///   as `<script>` and `<style>`, where it is written as is.
+/// - Attribute values are always double-quoted, with `&`, `"`, `<`, `>` and U+00A0 escaped.
+///
+/// # Notes
+/// `HtmlNode` keeps attributes in a `HashMap`, which does not remember their source
+/// order, so attributes are written sorted by name to keep the output stable.
+///
+/// # Example
+/// ```
+/// let mut parser = HtmlParser::new("<p class=a>1 &lt; 2<br></p>".to_string());
+/// let root = parser.parse();
+/// // "<head></head><body><p class=\"a\">1 &lt; 2<br></p></body>"
+/// let markup = inner_html(&root);
+/// ```
+pub(crate) fn inner_html(node: &Arc<RwLock<HtmlNode>>) -> String {
+    let mut out = String::new();
+    serialize_children(node, &mut out);
+    out
+}
+
+/// Serializes `node` itself, including its children, as the DOM `outerHTML` getter does.
+///
+/// See [`inner_html`] for the serialization rules.
+pub(crate) fn outer_html(node: &Arc<RwLock<HtmlNode>>) -> String {
+    let mut out = String::new();
+    let parent_tag = node.read().unwrap().parent.as_ref().map(tag_name);
+    serialize_node(node, parent_tag.as_deref(), &mut out);
+    out
+}
+
+/// Serializes a whole document, starting with a `<!DOCTYPE html>` declaration.
+///
+/// This is what "Save page" writes to disk, so that the saved file parses back in
+/// standards mode.
+pub(crate) fn document_html(root: &Arc<RwLock<HtmlNode>>) -> String {
+    format!("<!DOCTYPE html>{}", outer_html(root))
+}
+
+/// Appends the serialization of each child of `node` to `out`.
+fn serialize_children(node: &Arc<RwLock<HtmlNode>>, out: &mut String) {
+    let node_ref = node.read().unwrap();
+    let tag = match &node_ref.node_type {
+        HtmlNodeType::Element(e) => e.tag.clone(),
+        HtmlNodeType::Text(_) => return,
+    };
+    if VOID_TAGS.contains(&tag.as_str()) {
+        return;
+    }
+    for child in &node_ref.children {
+        serialize_node(child, Some(&tag), out);
+    }
+}
+
+/// Appends the serialization of `node` to `out`. `parent_tag` decides whether text is
+/// escaped, since the contents of raw text elements must not be.
+fn serialize_node(node: &Arc<RwLock<HtmlNode>>, parent_tag: Option<&str>, out: &mut String) {
+    let element = match &node.read().unwrap().node_type {
+        HtmlNodeType::Text(t) => {
+            if parent_tag.is_some_and(|tag| RAW_TEXT_TAGS.contains(&tag) || tag == "plaintext") {
+                out.push_str(&t.text);
+            } else {
+                out.push_str(&escape(&t.text, false));
+            }
+            return;
+        }
+        HtmlNodeType::Element(e) => e.clone(),
+    };
+
+    out.push('<');
+    out.push_str(&element.tag);
+    let mut attributes = element.attributes.iter().collect::<Vec<_>>();
+    attributes.sort();
+    for (name, value) in attributes {
+        out.push(' ');
+        out.push_str(name);
+        out.push_str("=\"");
+        out.push_str(&escape(value, true));
+        out.push('"');
+    }
+    out.push('>');
+
+    if VOID_TAGS.contains(&element.tag.as_str()) {
+        return;
+    }
+
+    serialize_children(node, out);
+    out.push_str("</");
+    out.push_str(&element.tag);
+    out.push('>');
+}
+
+/// Escapes a string for use in text (`in_attribute == false`) or in a double-quoted
+/// attribute value (`in_attribute == true`).
+fn escape(text: &str, in_attribute: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '\u{a0}' => out.push_str("&nbsp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if in_attribute => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_parser::HtmlParser;
+
+    fn parse(html: &str) -> Arc<RwLock<HtmlNode>> {
+        HtmlParser::new(html.to_string()).parse()
+    }
+
+    /// Returns the first element of the tree under `root` whose tag is `tag`.
+    fn first_element(root: &Arc<RwLock<HtmlNode>>, tag: &str) -> Arc<RwLock<HtmlNode>> {
+        HtmlNode::tree_to_vec(root.clone(), &mut vec![]).iter()
+            .find(|node| matches!(&node.read().unwrap().node_type, HtmlNodeType::Element(e) if e.tag == tag))
+            .cloned()
+            .unwrap_or_else(|| panic!("no <{}> element", tag))
+    }
+
+    /// Returns whether the trees under `a` and `b` have the same elements, attributes
+    /// and text, in the same order.
+    fn same_tree(a: &Arc<RwLock<HtmlNode>>, b: &Arc<RwLock<HtmlNode>>) -> bool {
+        let (a, b) = (a.read().unwrap(), b.read().unwrap());
+        let same_node = match (&a.node_type, &b.node_type) {
+            (HtmlNodeType::Element(a), HtmlNodeType::Element(b)) => a.tag == b.tag && a.attributes == b.attributes,
+            (HtmlNodeType::Text(a), HtmlNodeType::Text(b)) => a.text == b.text,
+            _ => false,
+        };
+        same_node && a.children.len() == b.children.len()
+            && a.children.iter().zip(&b.children).all(|(a, b)| same_tree(a, b))
+    }
+
+    #[test]
+    fn text_and_attribute_values_are_escaped() {
+        let root = parse("<p title='a \"b\" &amp; <c>&nbsp;'>1 &lt; 2 &amp;&nbsp;&gt; \"q\"</p>");
+        let p = first_element(&root, "p");
+        assert_eq!(inner_html(&p), "1 &lt; 2 &amp;&nbsp;&gt; \"q\"");
+        assert_eq!(outer_html(&p), "<p title=\"a &quot;b&quot; &amp; &lt;c&gt;&nbsp;\">1 &lt; 2 &amp;&nbsp;&gt; \"q\"</p>");
+    }
+
+    #[test]
+    fn attributes_are_written_sorted_by_name() {
+        let root = parse("<a id=x href=/ class=c data-b=''>y</a>");
+        assert_eq!(outer_html(&first_element(&root, "a")), "<a class=\"c\" data-b=\"\" href=\"/\" id=\"x\">y</a>");
+    }
+
+    #[test]
+    fn void_elements_have_no_end_tag_and_no_children() {
+        let root = parse("<p>a<br>b<img src=x.png><input value=v></p>");
+        let p = first_element(&root, "p");
+        assert_eq!(inner_html(&p), "a<br>b<img src=\"x.png\"><input value=\"v\">");
+
+        // Children added to a void element by a script are not written.
+        let br = first_element(&root, "br");
+        let text = HtmlNode::new(HtmlNodeType::Text(crate::node::Text { text: "lost".to_string() }), Some(br.clone()));
+        br.write().unwrap().children.push(Arc::new(RwLock::new(text)));
+        assert_eq!(outer_html(&br), "<br>");
+        assert_eq!(inner_html(&br), "");
+    }
+
+    #[test]
+    fn raw_text_children_are_not_escaped() {
+        let root = parse("<script>if (a < b && c > \"d\") {}</script><style>a > b { content: \"&\" }</style><p>a < b</p>");
+        assert_eq!(outer_html(&first_element(&root, "script")), "<script>if (a < b && c > \"d\") {}</script>");
+        assert_eq!(inner_html(&first_element(&root, "style")), "a > b { content: \"&\" }");
+        assert_eq!(inner_html(&first_element(&root, "p")), "a &lt; b");
+    }
+
+    #[test]
+    fn a_saved_document_parses_back_to_the_same_tree() {
+        let root = parse("<!doctype html><title>A &amp; B</title><style>p > a { }</style>
+            <p class=x title='\"q\" &lt;'>one&nbsp;<b>two</b><br>three &lt;four&gt;</p>
+            <ul><li>1<li>2</ul><table><tr><td>cell</table><textarea>&lt;t&gt;</textarea>
+            <script>document.write('</p>' < 1)</script>");
+        let saved = document_html(&root);
+        assert!(saved.starts_with("<!DOCTYPE html><html><head><title>"));
+        let reparsed = parse(&saved);
+        assert!(same_tree(&root, &reparsed), "{} parsed back as {}", saved, document_html(&reparsed));
+        assert_eq!(document_html(&reparsed), saved);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::css_parser::CssParser;
 use crate::html_parser::HtmlParser;
+use crate::html_serializer::{inner_html, outer_html};
 use crate::node::{HtmlNode, HtmlNodeType};
 use crate::tab::{Tab, TabMessage};
 use crate::task::Task;
@@ -76,8 +77,14 @@ impl JsContext {
             let nodes_for_query = nodes_clone.clone();
 
             let log = |str: String| {println!("{}", str)};
-            let query_selector_all = move |selector_str: String| {
-                let selector = CssParser::new(selector_str.as_str()).selector().unwrap();
+            let query_selector_all = move |ctx: rquickjs::Ctx, selector_str: String| -> rquickjs::Result<Vec<usize>> {
+                let selectors = match CssParser::new(selector_str.as_str()).selector_list_only() {
+                    Ok(selectors) => selectors,
+                    Err(e) => {
+                        let message = format!("SyntaxError: '{}' is not a valid selector: {}", selector_str, e);
+                        return Err(ctx.throw(rquickjs::Value::from_string(rquickjs::String::from_str(ctx.clone(), &message).unwrap())));
+                    }
+                };
                 let nodes_ref = tab_for_query.read().unwrap().nodes.clone();
 
                 if let Some(root) = nodes_ref {
@@ -86,7 +93,7 @@ impl JsContext {
 
                     let mut matched_indices = vec![];
                     for nd in elements {
-                        if selector.matches(nd.clone()) {
+                        if selectors.iter().any(|selector| selector.matches(nd.clone())) {
                             let index = {
                                 let mut registry = nodes_for_query.write().unwrap();
                                 let idx = registry.len();
@@ -97,9 +104,9 @@ impl JsContext {
                         }
                     }
 
-                    return matched_indices;
+                    return Ok(matched_indices);
                 }
-                vec![]
+                Ok(vec![])
             };
 
             let get_attribute = move |handle: usize, attrib: String| -> String {
@@ -118,10 +125,7 @@ impl JsContext {
             let nodes_for_inner_html = nodes.clone();
             let ihs_tab = tab.clone();
             let inner_html_set = move |handle: usize, html: String| {
-                let mut parser = HtmlParser {
-                    body: format!("<html><body>{}</body></html>", html.clone()),
-                    unfinished: vec![],
-                };
+                let mut parser = HtmlParser::new(format!("<html><body>{}</body></html>", html.clone()));
                 let parsed = parser.parse();
                 let new_node_parent = &parsed.read().unwrap().children.first().unwrap().clone();
                 let new_children = new_node_parent.read().unwrap().children.clone();
@@ -140,11 +144,23 @@ impl JsContext {
                         child.write().unwrap().parent = Some(element_rc.clone());
                     }
 
-                    ihs_tab.write().unwrap().render();
+                    let mut tab = ihs_tab.write().unwrap();
+                    tab.invalidate_style(&element_rc);
+                    tab.render();
                 };
+            let nodes_for_inner_html_get = nodes.clone();
+            let inner_html_get = move |handle: usize| -> String {
+                let nodes = nodes_for_inner_html_get.read().unwrap();
+                nodes.get(handle).map(inner_html).unwrap_or_default()
+            };
+            let nodes_for_outer_html_get = nodes.clone();
+            let outer_html_get = move |handle: usize| -> String {
+                let nodes = nodes_for_outer_html_get.read().unwrap();
+                nodes.get(handle).map(outer_html).unwrap_or_default()
+            };
             let xml_tab = tab.clone();
-            let xml_http_request_send = move |ctx: rquickjs::Ctx, _method: String, mut url: String, body: String, is_async: bool, handle: usize| -> rquickjs::Result<String> {
-                let full_url = xml_tab.read().unwrap().url.clone().unwrap().resolve(url.as_mut_str());
+            let xml_http_request_send = move |ctx: rquickjs::Ctx, _method: String, url: String, body: String, is_async: bool, handle: usize| -> rquickjs::Result<String> {
+                let full_url = xml_tab.read().unwrap().resolve_url(&url);
                 if Tab::allowed_request(xml_tab.clone(), full_url.clone().unwrap()) == false {
                     return Err(ctx.throw(rquickjs::Value::from_string(rquickjs::String::from_str(ctx.clone(), "CORS request blocked").unwrap())));
                 }
@@ -227,6 +243,8 @@ impl JsContext {
             ctx.globals().set("rustGetAttribute", Function::new(ctx.clone(), get_attribute).unwrap()).unwrap();
             ctx.globals().set("rustLog", Function::new(ctx.clone(), log).unwrap()).unwrap();
             ctx.globals().set("rustInnerHtmlSet", Function::new(ctx.clone(), inner_html_set).unwrap()).unwrap();
+            ctx.globals().set("rustInnerHtmlGet", Function::new(ctx.clone(), inner_html_get).unwrap()).unwrap();
+            ctx.globals().set("rustOuterHtmlGet", Function::new(ctx.clone(), outer_html_get).unwrap()).unwrap();
             ctx.globals().set("rustQuerySelectorAll", Function::new(ctx.clone(), query_selector_all).unwrap()).unwrap();
             ctx.globals().set("rustXmlHttpRequestSend", Function::new(ctx.clone(), xml_http_request_send).unwrap()).unwrap();
             ctx.globals().set("rustSetTimeout", Function::new(ctx.clone(), set_timeout).unwrap()).unwrap();
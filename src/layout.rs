@@ -1,5 +1,6 @@
-﻿use crate::layout::LayoutMode::{Block, Inline};
+﻿use crate::layout::LayoutMode::{Block, Flex, Grid, Inline, Table};
 use crate::node::{HtmlNode, HtmlNodeType};
+use crate::style::{BorderStyle, BoxSizing, ComputedStyle, Display, Edges, FontStyle, JustifyContent, LengthPercentage, ListStyleType, Side, WhiteSpace};
 use crate::tab::{DrawCommand, DrawLine, DrawRect, DrawText};
 use eframe::emath::Pos2;
 use eframe::epaint::{Color32, FontFamily, FontId};
@@ -8,23 +9,28 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use std::sync::{Arc, RwLock};
 
+mod flex;
+mod grid;
+mod table;
+
 pub const HSTEP: f32 = 13.0;
 pub const VSTEP: f32 = 17.0;
 
 pub const WIDTH: f32 = 800.0;
 pub const HEIGHT: f32 = 600.0;
 
-const BLOCK_ELEMENTS: [&str; 37] =  [
-    "html", "body", "article", "section", "nav", "aside",
-    "h1", "h2", "h3", "h4", "h5", "h6", "hgroup", "header",
-    "footer", "address", "p", "hr", "pre", "blockquote",
-    "ol", "ul", "menu", "li", "dl", "dt", "dd", "figure",
-    "figcaption", "main", "div", "table", "form", "fieldset",
-    "legend", "details", "summary"
-];
-
 const INPUT_WIDTH_PX: f32 = 200.0;
 
+/// The width of the containing block that content is laid out in to measure its
+/// max-content width: wide enough that no line wraps.
+const MAX_CONTENT_PROBE_WIDTH: f32 = 1.0e6;
+
+/// Returns the egui font family name that a web font from `@font-face` is registered
+/// under, following the built-in `sans`, `sansbold`, `sansitalic` naming.
+pub(crate) fn web_font_name(family: &str, bold: bool, italic: bool) -> String {
+    format!("webfont-{}{}{}", family, if bold { "bold" } else { "" }, if italic { "italic" } else { "" })
+}
+
 /// Represents a node in the layout tree, which corresponds to an element in the
 /// HTML document and stores layout-related information for rendering.
 ///
@@ -42,10 +48,6 @@ const INPUT_WIDTH_PX: f32 = 200.0;
 ///                layout nodes. This represents the hierarchical relationships between
 ///                nodes, allowing traversal of the layout tree.
 ///
-/// * `previous` - An `Option` containing a reference-counted, mutable reference to the
-///                previous sibling layout node, if it exists. This facilitates sibling
-///                traversal within the tree.
-///
 /// * `content` - Specifies the type of content this layout node represents. This could be
 ///               information about text, inline elements, or blocks, as represented by
 ///               the `LayoutNodeType` enum.
@@ -58,7 +60,6 @@ pub struct LayoutNode {
     pub(crate) node: Arc<RwLock<HtmlNode>>,
     pub(crate) parent: Option<Arc<RwLock<LayoutNode>>>,
     children: Vec<Arc<RwLock<LayoutNode>>>,
-    previous: Option<Arc<RwLock<LayoutNode>>>,
     pub(crate) content: LayoutNodeType,
     pub(crate) position: Option<Vec2>,
     pub(crate) size: Option<Vec2>,
@@ -90,7 +91,6 @@ impl LayoutNode {
     /// - `node`: The provided HTML node reference.
     /// - `parent`: Set to `None`, indicating no parent node.
     /// - `children`: An empty vector, as no child nodes are present initially.
-    /// - `previous`: Set to `None`, indicating no sibling node.
     /// - `content`: Set to `LayoutNodeType::Document`, specifying its type as a document node.
     /// - `display_list`: An empty vector wrapped in a reference-counted and mutable container
     ///   (`Rc<RefCell>`), intended for storing renderable items.
@@ -101,7 +101,6 @@ impl LayoutNode {
             node,
             parent: None,
             children: Vec::new(),
-            previous: None,
             content: LayoutNodeType::Document,
             position: None,
             size: None
@@ -123,7 +122,6 @@ impl LayoutNode {
     /// - Inherits the HTML node of the parent block (`parent.borrow().node.clone()`).
     /// - Sets its `parent` field to the provided parent node.
     /// - Initializes an empty list of `children`.
-    /// - Sets the `previous` field to `None`, with the potential to add sibling line logic in the future.
     /// - Configures the `content` field using the `LineLayout` type, with `max_ascent` and `max_descent`
     ///   initialized to `0.0`.
     /// - Creates an empty `display_list` for rendering purposes.
@@ -142,7 +140,6 @@ impl LayoutNode {
             node, // Use the passed HTML node
             parent: Some(parent),
             children: Vec::new(),
-            previous: None,
             content: LayoutNodeType::Line(LineLayout { max_ascent: 0.0, max_descent: 0.0 }),
             position: Some(Vec2::ZERO),
             size: Some(Vec2::ZERO),
@@ -181,7 +178,6 @@ impl LayoutNode {
     /// is configured with the following properties:
     /// - References the `LayoutNode` of the parent node.
     /// - No children (an empty vector).
-    /// - No previous sibling.
     /// - `content` set to `LayoutNodeType::Text` with the provided `text_layout`.
     /// - An empty display list, ready to be populated during rendering.
     /// - Starting position as `Vec2::ZERO`, relative to a line.
@@ -212,7 +208,6 @@ impl LayoutNode {
             node, // Use the passed HTML node
             parent: Some(parent),
             children: Vec::new(),
-            previous: None,
             content: LayoutNodeType::Text(text_layout),
             position: Some(Vec2::ZERO),
             size: Some(size),
@@ -224,7 +219,6 @@ impl LayoutNode {
     /// # Parameters
     /// - `node`: An `Arc<RwLock<HtmlNode>>` representing the HTML node associated with this layout node.
     /// - `parent`: An optional `Arc<RwLock<LayoutNode>>` representing the parent layout node, or `None` if this is a root layout node.
-    /// - `previous`: An optional `Arc<RwLock<LayoutNode>>` representing the previous sibling layout node, or `None` if there is no previous sibling.
     /// - `context`: A `Context` struct containing necessary information such as styling, configuration, or environment details for layout computations.
     ///
     /// # Returns
@@ -246,11 +240,10 @@ impl LayoutNode {
     /// ```rust
     /// let html_node = Rc::new(RefCell::new(HtmlNode::new("div")));
     /// let context = Context::default();
-    /// let layout_node = LayoutNode::new_block(html_node, None, None, context);
+    /// let layout_node = LayoutNode::new_block(html_node, None, context);
     /// ```
     pub fn new_block(node: Arc<RwLock<HtmlNode>>,
                      parent: Option<Arc<RwLock<LayoutNode>>>,
-                     previous: Option<Arc<RwLock<LayoutNode>>>,
                      context: Context) -> Arc<RwLock<LayoutNode>> {
         Arc::new(RwLock::new(Self {
             node,
@@ -267,182 +260,448 @@ impl LayoutNode {
                     cursor_x: 0.0,
                     font_id: FontId::default(),
                     space_width: 0.0,
+                    margin: Edges::default(),
+                    border: Edges::default(),
+                    padding: Edges::default(),
+                    anonymous_content: None,
                 }
             ),
-            previous,
             position: None,
             size: None
         }))
     }
 
+    /// Creates an anonymous block box: a block that no element generates, which wraps a
+    /// run of inline-level content between block-level siblings, as the text in
+    /// `<div>text<p>paragraph</p></div>`.
+    ///
+    /// # Parameters
+    /// - `node`: The element whose content it wraps, which clicks on the block reach.
+    /// - `content`: The inline-level nodes it wraps, in order.
+    /// - `parent`: The parent layout node.
+    /// - `context`: The egui context, for measuring text.
+    ///
+    /// # Notes
+    /// The block has the initial value of every box property, so it has no margins,
+    /// borders or paddings, and it paints no background.
+    fn new_anonymous_block(node: Arc<RwLock<HtmlNode>>,
+                           content: Vec<Arc<RwLock<HtmlNode>>>,
+                           parent: Arc<RwLock<LayoutNode>>,
+                           context: Context) -> Arc<RwLock<LayoutNode>> {
+        let block = Self::new_block(node, Some(parent), context);
+        if let LayoutNodeType::Block(block_layout) = &mut block.write().unwrap().content {
+            block_layout.anonymous_content = Some(content);
+        }
+        block
+    }
+
 
 
-    /// Performs layout processing on a `LayoutNode` based on its type (`Block` or `Document`)
-    /// and updates its display list accordingly.
+    /// Lays out the document: places the root element's box in the page and, through
+    /// `layout_block`, every box under it.
     ///
     /// # Parameters
-    /// - `node`: A `Rc<RefCell<LayoutNode>>` representing the layout node to be processed.
-    /// - `context`: A `Context` object providing relevant layout context or configuration.
-    ///
-    /// # Functionality
-    /// 1. **Determine Node Type**:
-    ///    - Checks whether the `LayoutNode` is of type `Block` or `Document` by pattern matching the `content` field.
-    ///
-    /// 2. **Handle Block Nodes**:
-    ///    - Prepares `inner_node_ptr` by cloning the `node` reference.
-    ///    - Retrieves a mutable borrow of the `node` to perform updates.
-    ///    - Updates the block layout:
-    ///      - Invokes `update_font()` on the block layout.
-    ///      - Recursively performs layout logic using `recurse(inner_node_ptr)`.
-    ///      - Flushes the block's line buffer using `flush_line()`.
-    ///    - Updates the `display_list` property with a clone of the block's display list.
-    ///    - Drops the mutable borrow once updates are complete.
-    ///
-    /// 3. **Handle Document Nodes**:
-    ///    - Clones the `inner_node_ptr` for the document node.
-    ///    - Creates a new `Block` node as a child using `Self::new_block()` with the current context.
-    ///    - Updates the `children` of the current node with the newly created child.
-    ///    - Recursively processes the child node by calling `Self::layout()` to layout its children.
-    ///    - Merges the child's display list with the parent node's display list to ensure proper rendering.
+    /// - `node`: The `Document` layout node, with no children yet.
+    /// - `context`: The egui context, for measuring text.
+    ///
+    /// # Behavior
+    /// The page is `WIDTH - 2 * HSTEP` wide and starts at `(HSTEP, VSTEP)`. The root
+    /// element always generates a block, whatever its `display` other than `none`. Its
+    /// margins never collapse, so the document's height is that of the root's margin box.
     ///
     /// # Notes
-    /// - Borrowing is carefully managed to ensure mutable and immutable borrows do not coexist,
-    ///   using scoped blocks or temporary variables.
-    /// - The `display_list` is synchronously updated between parent and child nodes to maintain a proper rendering hierarchy.
+    /// Layout nodes that are not documents are laid out by their parents, so calling this
+    /// on them does nothing.
+    pub fn layout(node: Arc<RwLock<LayoutNode>>, context: Context) {
+        if !matches!(node.read().unwrap().content, LayoutNodeType::Document) {
+            return;
+        }
+        let inner_node_ptr = node.read().unwrap().node.clone();
+        let width = WIDTH - 2.0 * HSTEP;
+        node.write().unwrap().position = Some(Vec2::new(HSTEP, VSTEP));
+        node.write().unwrap().size = Some(Vec2::new(width, 0.0));
+        if display(&inner_node_ptr) == Some(Display::None) {
+            return;
+        }
+
+        let child = Self::new_block(inner_node_ptr, Some(node.clone()), context.clone());
+        node.write().unwrap().children.push(child.clone());
+
+        let containing_block = ContainingBlock { x: HSTEP, width, height: None };
+        let outcome = Self::layout_block(&child, &context, containing_block, VSTEP, CollapsedMargin::default(), BlockRole::Root);
+        let child = child.read().unwrap();
+        let bottom = child.position.unwrap().y + child.size.unwrap().y + outcome.margin.resolve();
+        node.write().unwrap().size = Some(Vec2::new(width, bottom - VSTEP));
+    }
+
+    /// Lays out a block box and its subtree with the CSS 2.1 block box model.
     ///
-    /// # Dependencies
-    /// - Requires `LayoutNode` to have the following properties:
-    ///   - `content`: Enum of type `LayoutNodeType` (with variants like `Block` and `Document`).
-    ///   - `node`: A reference or pointer to additional node data.
-    ///   - `children`: A mutable vector to store child nodes.
-    ///   - `display_list`: A reference-counted, mutable display list specific to the node.
+    /// # Parameters
+    /// - `node`: The block's layout node, with no children yet.
+    /// - `context`: The egui context, for measuring text.
+    /// - `containing_block`: The content box of the parent, which percentages refer to.
+    /// - `y`: Where the block's top margin starts: the bottom border edge of the previous
+    ///   block, or the top of the parent's content box.
+    /// - `incoming`: The margins above `y` that are still to be collapsed with this
+    ///   block's top margin, such as the previous sibling's bottom margin.
+    /// - `role`: How the parent lays out the block, which decides what its `auto` width
+    ///   and margins mean and whether its margins collapse.
     ///
-    /// # Example
-    /// ```rust
-    /// let layout_node = Rc::new(RefCell::new(LayoutNode::new_document()));
-    /// let context = Context::new();
-    /// MyLayoutEngine::layout(layout_node, context);
-    /// ```
+    /// # Returns
+    /// The margin below the block, still to be collapsed with what follows, and whether
+    /// the block collapsed through (see below).
     ///
-    /// In the example above, a `Document` node is processed using the `layout` function,
-    /// which creates child blocks, updates their layout, and synchronizes display lists.
-    pub fn layout(node: Arc<RwLock<LayoutNode>>, context: Context) {
-        // 1. Identify what type of node we are dealing with.
-        // We use a scoped block or a temporary variable to ensure the borrow is dropped immediately.
-        let is_block = matches!(node.read().unwrap().content, LayoutNodeType::Block(_));
-        let is_doc = matches!(node.read().unwrap().content, LayoutNodeType::Document);
-
-        if is_block {
-            // Prepare data needed for the block logic
-            let inner_node_ptr = node.read().unwrap().node.clone();
-
-            let mode = Self::layout_mode(inner_node_ptr.clone());
-            let y: f32;
-            if node.read().unwrap().previous.is_some() {
-                y = node.read().unwrap().previous.clone().unwrap().read().unwrap().position.unwrap().y
-                    + node.read().unwrap().previous.clone().unwrap().read().unwrap().size.unwrap().y;
-            } else {
-                y = node.read().unwrap().parent.clone().unwrap().read().unwrap().position.unwrap().y;
+    /// # Behavior
+    /// - The block's `position` and `size` are its border box. Its margins, borders and
+    ///   paddings are stored in its `BlockLayout`; its children and lines are laid out
+    ///   in its content box.
+    /// - The children's `display` decides what the block contains, see `layout_mode`:
+    ///   either only block-level boxes, with runs of inline-level content between them
+    ///   wrapped in anonymous blocks, or only lines, or for a flex or grid container, its
+    ///   items (see `flex::layout_flex` and `grid::layout_grid`), or for a table, its
+    ///   captions, rows and cells (see `table::layout_table`).
+    /// - `width: auto` fills the containing block, or for an inline-block or a flex or
+    ///   grid item whose container leaves it its width, shrinks to fit its content (see
+    ///   `max_content_width`) without exceeding the containing block. A specified width
+    ///   (of the border box with `box-sizing: border-box`) is clamped by `min-width` and
+    ///   `max-width`. A table is never narrower than its columns need, and with
+    ///   `width: auto` shrinks to fit them (see `table::content_widths`). In block
+    ///   formatting contexts, the space left goes to the `auto`
+    ///   horizontal margins, shared equally if both are `auto`, which centers the block.
+    ///   If no margin is `auto`, `margin-right` is ignored.
+    /// - `height: auto` is the height of the content. Percentage heights only apply when
+    ///   the containing block has a specified height, and are `auto` otherwise.
+    /// - Vertical margins collapse: adjoining margins become the largest positive margin
+    ///   plus the most negative one. Adjoining are the margins of siblings, the top
+    ///   margin of a block and of its first child if the block has no top border or
+    ///   padding, and the bottom margin of a block with `height: auto` and of its last
+    ///   child if it has no bottom border or padding. An empty block with neither
+    ///   borders, paddings nor a height collapses through: its top and bottom margins
+    ///   adjoin each other and the margins around it.
+    /// - Percentage margins and paddings, vertical ones included, refer to the width of
+    ///   the containing block.
+    fn layout_block(node: &Arc<RwLock<LayoutNode>>,
+                    context: &Context,
+                    containing_block: ContainingBlock,
+                    y: f32,
+                    incoming: CollapsedMargin,
+                    role: BlockRole) -> BlockOutcome {
+        let inner_node_ptr = node.read().unwrap().node.clone();
+        let anonymous_content = match &node.read().unwrap().content {
+            LayoutNodeType::Block(block_layout) => block_layout.anonymous_content.clone(),
+            _ => None,
+        };
+        let (style, mode) = match &anonymous_content {
+            Some(content) => (anonymous_style(), Inline(content.clone())),
+            None => (inner_node_ptr.read().unwrap().style.clone(), Self::layout_mode(&inner_node_ptr)),
+        };
+
+        // Horizontal sizes, which do not depend on the content.
+        let cb_width = containing_block.width;
+        let padding = style.padding.map(|padding| padding.resolve(cb_width));
+        let (border, mut margin) = match role {
+            // Cells have no margins, and their table decides their borders.
+            BlockRole::Cell { border, .. } => (border, Edges::all(0.0)),
+            _ => (style.border_width, style.margin.map(|margin| margin.as_ref().map_or(0.0, |m| m.resolve(cb_width)))),
+        };
+        let edges_x = padding.horizontal() + border.horizontal();
+        let edges_y = padding.vertical() + border.vertical();
+        let (sizing_x, sizing_y) = match style.box_sizing {
+            BoxSizing::ContentBox => (0.0, 0.0),
+            BoxSizing::BorderBox => (edges_x, edges_y),
+        };
+        let min_width = (style.min_width.resolve(cb_width) - sizing_x).max(0.0);
+        let max_width = style.max_width.as_ref().map_or(f32::INFINITY, |max| (max.resolve(cb_width) - sizing_x).max(0.0));
+        let available_width = (cb_width - margin.horizontal() - edges_x).max(0.0);
+        let width = match (&style.width, role) {
+            (_, BlockRole::Item { width: Some(width) } | BlockRole::Cell { width, .. }) => width,
+            (Some(width), _) => (width.resolve(cb_width) - sizing_x).max(0.0),
+            (None, BlockRole::InlineBlock | BlockRole::Item { width: None }) => {
+                Self::max_content_width(&inner_node_ptr, anonymous_content.clone(), context).min(available_width)
             }
-            let x = node.read().unwrap().parent.clone().unwrap().read().unwrap().position.unwrap().x;
-            let width = node.read().unwrap().parent.clone().unwrap().read().unwrap().size.unwrap().x;
-            let pos = Some(Vec2::new(x, y));
-            let size = Some(Vec2::new(width, 0.0));
-            {
-                let mut node_borrow = node.write().unwrap();
-                node_borrow.position = pos;
-                node_borrow.size = size;
+            (None, _) => available_width,
+        }.min(max_width).max(min_width);
+        let width = match mode {
+            Table(_) => {
+                let (min_content, max_content) = table::content_widths(&inner_node_ptr, context);
+                let shrinks = style.width.is_none() && matches!(role, BlockRole::InFlow | BlockRole::Root);
+                let width = if shrinks { max_content.min(available_width).min(max_width).max(min_width) } else { width };
+                width.max(min_content)
             }
-            match mode {
-                Block => {
-
-                    let mut previous: Option<Arc<RwLock<LayoutNode>>> = None;
-                    for child in inner_node_ptr.read().unwrap().children.clone(){
-                        let next = Self::new_block(child.clone(),
-                                                   Some(node.clone()),
-                                                   previous.clone(),
-                                                   context.clone());
-                        node.write().unwrap().children.push(next.clone());
-                        previous = Some(next);
-                    }
-                }
-                Inline => {
-                    let outer_node_ptr = node.clone();
-                    // Re-borrow mutably only for the block work
-                    let mut node_borrow = node.write().unwrap();
-
-                    // Destructure to split borrows, allowing simultaneous access to fields
-                    let LayoutNode {
-                        ref mut content,
-                        ref mut position,
-                        ref mut size,
-                        ref node,
-                        ref mut children,
-                        ..
-                    } = *node_borrow;
-
-                    if let LayoutNodeType::Block(block_layout) = content {
-
-                        // 3. Create Composer with the split references
-                        let mut composer = BlockComposer {
-                            layout: block_layout,
-                            children: children, // Pass the Vec<Rc> directly
-                            parent_ptr: outer_node_ptr.clone(), // Pass the Rc<RefCell> (the original `node` variable) for parentage
-                            block_html_node: node.clone(),      // Pass the HTML node Rc
-                            outer_position: position,
-                            outer_size: size,
-                            current_line_nodes: Vec::new(),
-                        };
-
-                        composer.update_font();
-                        composer.recurse(inner_node_ptr);
-                        composer.flush_line();
-
-                        node_borrow.size = Some(Vec2::new(size.unwrap().x, block_layout.cursor_y));
-                    }
-                }
+            _ => width,
+        };
+        let remaining = cb_width - width - edges_x - margin.horizontal();
+        match (style.margin.left.is_none(), style.margin.right.is_none()) {
+            _ if matches!(role, BlockRole::InlineBlock | BlockRole::Item { .. } | BlockRole::Cell { .. }) => {}
+            (true, true) => {
+                margin.left = (remaining / 2.0).max(0.0);
+                margin.right = remaining - margin.left;
             }
+            (true, false) => margin.left = remaining.max(0.0),
+            (false, true) => margin.right = remaining,
+            // Over-constrained: margin-right gives way.
+            (false, false) => margin.right += remaining,
+        }
 
-            for layout in node.read().unwrap().children.iter() {
-                Self::layout(layout.clone(), context.clone());
+        // Vertical sizes that do not depend on the content. Percentages of an auto
+        // height do not apply.
+        let resolve_height = |length: &LengthPercentage| match length {
+            LengthPercentage::Px(px) => Some(*px),
+            length => containing_block.height.map(|height| length.resolve(height)),
+        };
+        let height = style.height.as_ref().and_then(resolve_height).map(|h| (h - sizing_y).max(0.0));
+        let min_height = resolve_height(&style.min_height).map_or(0.0, |h| (h - sizing_y).max(0.0));
+        let max_height = style.max_height.as_ref().and_then(resolve_height).map_or(f32::INFINITY, |h| (h - sizing_y).max(0.0));
+
+        // Neither a block that starts a formatting context, nor a flex or grid container
+        // or a table, shares its margins with its content.
+        let isolated = role != BlockRole::InFlow;
+        let contains_flow = !matches!(mode, Flex(_) | Grid(_) | Table(_));
+        let collapses_top = !isolated && contains_flow && border.top + padding.top == 0.0;
+        let collapses_bottom = !isolated && contains_flow && border.bottom + padding.bottom == 0.0 && height.is_none();
+        let x = containing_block.x + margin.left;
+        let incoming = if isolated { CollapsedMargin::default() } else { incoming };
+        let top_margin = incoming.with(margin.top);
+        let mut top = y + top_margin.resolve();
+        {
+            let mut node_borrow = node.write().unwrap();
+            node_borrow.position = Some(Vec2::new(x, top));
+            node_borrow.size = Some(Vec2::new(width + edges_x, 0.0));
+            if let LayoutNodeType::Block(block_layout) = &mut node_borrow.content {
+                block_layout.margin = margin;
+                block_layout.border = border;
+                block_layout.padding = padding;
             }
-
-            match mode {
-                Inline => {
-
+        }
+        let content_position = Vec2::new(x + border.left + padding.left, top + border.top + padding.top);
+
+        // Lay out the content. `cursor` is the bottom of the content so far, `pending`
+        // the margins after it that have not been collapsed yet, and `first_top` the
+        // border top of the first child that did not collapse through.
+        let (mut cursor, mut pending) = if collapses_top {
+            (y, top_margin)
+        } else {
+            (content_position.y, CollapsedMargin::default())
+        };
+        let mut first_top: Option<f32> = None;
+        let is_grid = matches!(mode, Grid(_));
+        match mode {
+            Block(child_boxes) => {
+                let child_containing_block = ContainingBlock { x: content_position.x, width, height };
+                for child_box in child_boxes {
+                    let next = match child_box {
+                        ChildBox::Block(child) => Self::new_block(child, Some(node.clone()), context.clone()),
+                        ChildBox::Anonymous(content) => {
+                            Self::new_anonymous_block(inner_node_ptr.clone(), content, node.clone(), context.clone())
+                        }
+                    };
+                    node.write().unwrap().children.push(next.clone());
+                    let outcome = Self::layout_block(&next, context, child_containing_block, cursor, pending, BlockRole::InFlow);
+                    pending = outcome.margin;
+                    if !outcome.collapsed_through {
+                        let next_borrow = next.read().unwrap();
+                        let next_top = next_borrow.position.unwrap().y;
+                        first_top.get_or_insert(next_top);
+                        cursor = next_top + next_borrow.size.unwrap().y;
+                    }
                 }
-                Block => {
-                    let mut node_borrow = node.write().unwrap();
+            }
+            Inline(inline_content) => {
+                let outer_node_ptr = node.clone();
+                let mut node_borrow = node.write().unwrap();
 
-                    // 1. Calculate height based on children
-                    let total_height: f32 = node_borrow.children
-                        .iter()
-                        .map(|c| c.read().unwrap().size.unwrap().y)
-                        .sum();
+                // Destructure to split borrows, allowing simultaneous access to fields
+                let LayoutNode {
+                    ref mut content,
+                    ref node,
+                    ref mut children,
+                    ..
+                } = *node_borrow;
+
+                if let LayoutNodeType::Block(block_layout) = content {
+                    let mut composer = BlockComposer {
+                        layout: block_layout,
+                        children,
+                        parent_ptr: outer_node_ptr.clone(),
+                        block_html_node: node.clone(),
+                        content_position,
+                        content_width: width,
+                        current_line_nodes: Vec::new(),
+                        pending_space: false,
+                    };
+
+                    composer.update_font();
+                    for child in inline_content {
+                        composer.recurse(child);
+                    }
+                    composer.flush_line();
 
-                    node_borrow.size = Some(Vec2::new(size.unwrap().x, total_height));
+                    // Lines of text end any collapsing of the margins around them.
+                    if block_layout.cursor_y > 0.0 {
+                        first_top = Some(top);
+                        cursor = content_position.y + block_layout.cursor_y;
+                        pending = CollapsedMargin::default();
+                    }
                 }
             }
+            Flex(items) | Grid(items) => {
+                let container = ContentBox {
+                    position: content_position,
+                    width,
+                    height,
+                    min_height,
+                    max_height,
+                };
+                let layout_items = if is_grid { grid::layout_grid } else { flex::layout_flex };
+                let content_height = layout_items(node, context, &style, items, container);
+                first_top = Some(top);
+                cursor = content_position.y + content_height;
+            }
+            Table(children) => {
+                let container = ContentBox {
+                    position: content_position,
+                    width,
+                    height,
+                    min_height,
+                    max_height,
+                };
+                let content_height = table::layout_table(node, context, &style, children, container);
+                first_top = Some(top);
+                cursor = content_position.y + content_height;
+            }
+        }
 
+        let mut outcome = BlockOutcome { margin: CollapsedMargin::default().with(margin.bottom), collapsed_through: false };
+        let content_height = if collapses_top && first_top.is_none() {
+            // Every child collapsed through: so does this block if nothing gives it a
+            // height, and otherwise the margins collapsed so far end above it.
+            top = y + pending.resolve();
+            if collapses_bottom && min_height == 0.0 {
+                outcome = BlockOutcome { margin: pending.with(margin.bottom), collapsed_through: true };
+            }
+            0.0
+        } else {
+            if collapses_top {
+                top = first_top.unwrap();
+            }
+            let content_top = top + border.top + padding.top;
+            if collapses_bottom {
+                outcome.margin = pending.with(margin.bottom);
+                cursor - content_top
+            } else {
+                cursor + pending.resolve() - content_top
+            }
+        };
+        let height = height.unwrap_or(content_height).min(max_height).max(min_height);
 
-            // Borrow is dropped here when node_borrow goes out of scope
-        } else if is_doc {
-            let inner_node_ptr = node.read().unwrap().node.clone();
+        let mut node_borrow = node.write().unwrap();
+        node_borrow.position = Some(Vec2::new(x, top));
+        node_borrow.size = Some(Vec2::new(width + edges_x, height + edges_y));
+        outcome
+    }
 
-            node.write().unwrap().position = Some(Vec2::new(HSTEP, VSTEP));
-            node.write().unwrap().size = Some(Vec2::new(WIDTH - 2.0*HSTEP, 0.0));
-            // Create the child
-            let child = Self::new_block(inner_node_ptr, Some(node.clone()), None, context.clone());
+    /// Returns the max-content width of an element: the width of its content box when
+    /// no line wraps.
+    ///
+    /// # Parameters
+    /// - `node`: The element.
+    /// - `anonymous_content`: For an anonymous block, the inline-level nodes it wraps;
+    ///   `node` is then the element they belong to.
+    /// - `context`: The egui context, for measuring text.
+    ///
+    /// # Notes
+    /// The content is laid out in a very wide containing block, and measured from the
+    /// left of the content box to the right of its furthest text, input, inline-block
+    /// or block with a width in pixels. The right margins, borders and paddings of the
+    /// blocks in between are not counted. Flex and grid containers are measured from
+    /// their items instead (see `flex::max_content_width` and `grid::max_content_width`),
+    /// since the free space of the wide containing block would push their items to its
+    /// far end, and tables from their columns (see `table::content_widths`).
+    fn max_content_width(node: &Arc<RwLock<HtmlNode>>,
+                         anonymous_content: Option<Vec<Arc<RwLock<HtmlNode>>>>,
+                         context: &Context) -> f32 {
+        if anonymous_content.is_none() {
+            match Self::layout_mode(node) {
+                Flex(_) => return flex::max_content_width(node, context),
+                Grid(_) => return grid::max_content_width(node, context),
+                Table(_) => return table::content_widths(node, context).1,
+                _ => {}
+            }
+        }
+        Self::probe_content_width(node, anonymous_content, context, MAX_CONTENT_PROBE_WIDTH)
+    }
+
+    /// Returns the min-content width of an element: the width of its content box when
+    /// every line wraps where it can, which is that of its widest word, input or block
+    /// with a width in pixels.
+    ///
+    /// # Notes
+    /// The content is laid out in a containing block `0` wide, and measured as in
+    /// `max_content_width`. Tables are measured from their columns.
+    fn min_content_width(node: &Arc<RwLock<HtmlNode>>,
+                         anonymous_content: Option<Vec<Arc<RwLock<HtmlNode>>>>,
+                         context: &Context) -> f32 {
+        if anonymous_content.is_none() && matches!(Self::layout_mode(node), Table(_)) {
+            return table::content_widths(node, context).0;
+        }
+        Self::probe_content_width(node, anonymous_content, context, 0.0)
+    }
 
-            // Update children list and drop borrow immediately
-            node.write().unwrap().children.push(child.clone());
+    /// Lays out an element in a containing block `probe_width` wide, and returns the
+    /// width of its content, see `max_content_width`.
+    fn probe_content_width(node: &Arc<RwLock<HtmlNode>>,
+                           anonymous_content: Option<Vec<Arc<RwLock<HtmlNode>>>>,
+                           context: &Context,
+                           probe_width: f32) -> f32 {
+        let block = Self::new_block(node.clone(), None, context.clone());
+        if let LayoutNodeType::Block(block_layout) = &mut block.write().unwrap().content {
+            block_layout.anonymous_content = anonymous_content;
+        }
+        let containing_block = ContainingBlock { x: 0.0, width: probe_width, height: None };
+        Self::layout_block(&block, context, containing_block, 0.0, CollapsedMargin::default(), BlockRole::InFlow);
 
-            // Perform recursive layout (node is currently unborrowed)
-            Self::layout(child.clone(), context);
-            let child_size = child.read().unwrap().size.unwrap();
+        let block = block.read().unwrap();
+        let LayoutNodeType::Block(block_layout) = &block.content else {
+            return 0.0;
+        };
+        let content_left = block.position.unwrap().x + block_layout.border.left + block_layout.padding.left;
+        let max_content = probe_width == MAX_CONTENT_PROBE_WIDTH;
+        let right = block.children.iter()
+            .filter_map(|child| Self::content_right(child, context, max_content))
+            .fold(content_left, f32::max);
+        (right - content_left).max(0.0)
+    }
 
-            node.write().unwrap().size = Some(Vec2::new(WIDTH - 2.0 * HSTEP, child_size.y));
+    /// Returns the right edge of the furthest content in a subtree laid out by
+    /// `probe_content_width`, or `None` if it has none.
+    ///
+    /// # Parameters
+    /// - `max_content`: Whether the subtree was laid out to measure its max-content
+    ///   width, in which case flex and grid containers are measured from their items.
+    fn content_right(node: &Arc<RwLock<LayoutNode>>, context: &Context, max_content: bool) -> Option<f32> {
+        let layout = node.read().unwrap();
+        let border_box_right = layout.position.unwrap().x + layout.size.unwrap().x;
+        match &layout.content {
+            LayoutNodeType::Text(_) | LayoutNodeType::Input(_) => return Some(border_box_right),
+            LayoutNodeType::Block(block_layout) if block_layout.anonymous_content.is_none() => {
+                let style = layout.node.read().unwrap().style.clone();
+                if matches!(style.width, Some(LengthPercentage::Px(_)))
+                    || matches!(style.display, Display::InlineBlock | Display::InlineFlex | Display::InlineGrid
+                        | Display::Table | Display::InlineTable) {
+                    return Some(border_box_right);
+                }
+                let content_left = layout.position.unwrap().x + block_layout.border.left + block_layout.padding.left;
+                match Self::layout_mode(&layout.node) {
+                    Flex(_) if max_content => return Some(content_left + flex::max_content_width(&layout.node, context)),
+                    Grid(_) if max_content => return Some(content_left + grid::max_content_width(&layout.node, context)),
+                    _ => {}
+                }
+            }
+            _ => {}
         }
+        layout.children.iter()
+            .filter_map(|child| Self::content_right(child, context, max_content))
+            .reduce(f32::max)
     }
 
     /// Generates a sequence of drawing commands (`Vec<DrawCommand>`) for rendering elements
@@ -468,7 +727,13 @@ impl LayoutNode {
     /// * If a valid color is found and has a non-zero alpha (i.e., not fully transparent),
     ///   a `DrawRect` command is created for rendering a rectangle that represents the background.
     /// * The rectangle's position and size are determined using the `position` and `size` properties
-    ///   of the layout node.
+    ///   of the layout node, which are those of its border box.
+    ///
+    /// ### Borders
+    /// * The borders are painted over the background, see `borders()`.
+    ///
+    /// ### List Markers
+    /// * A block with `display: list-item` paints its marker, see `list_marker()`.
     ///
     /// ### Text Contents
     /// * If the layout mode for the block is `Inline`, iterates over the `display_list` and generates
@@ -503,7 +768,7 @@ impl LayoutNode {
     /// # Potential Improvements
     /// * Gracefully handle cases where position or size is `None` to avoid runtime panics.
     /// * Implement additional drawing logic for `Block` mode if required.
-    /// * Add support for more styles and attributes (e.g., borders, shadows) in future enhancements.
+    /// * Add support for more styles and attributes (e.g., shadows) in future enhancements.
     ///
     /// # Output Format
     /// The result is a list of drawing commands that downstream subsystems or renderers can consume
@@ -516,28 +781,13 @@ impl LayoutNode {
 
             LayoutNodeType::Block(_blk) => {
                 if let HtmlNodeType::Element(_ele) = &self.node.read().unwrap().node_type {
-                    let node_borrow = self.node.read().unwrap();
-                    let bgcolor = node_borrow.style.get("background-color")
-                        .or_else(|| node_borrow.style.get("background"))
-                        .unwrap_or(&"transparent".to_string()).clone();
-
-                    if let Ok(color_parse) = csscolorparser::parse(&bgcolor) {
-                        if let Ok(color) = Color32::from_hex(&color_parse.to_css_hex()) {
-                            if color.a() > 0 {
-                                // Blocks are absolute, so we ignore 'offset' for the rect position
-                                // and just use self.position.
-                                let pos = self.position.unwrap_or(Vec2::ZERO);
-                                let size = self.size.unwrap_or(Vec2::ZERO);
-
-                                let radius = self.node.read().unwrap().style.get("border-radius").unwrap_or(&"0px".to_string()).replace("px", "").parse::<f32>().unwrap_or(0.0);
-                                cmds.push(DrawCommand::DrawRect(DrawRect {
-                                    rect: Rect::from_two_pos(pos.to_pos2(), (pos + size).to_pos2()),
-                                    color,
-                                    radius
-                                }));
-                            }
-                        }
+                    // Blocks are absolute, so we ignore 'offset' for the rect position
+                    // and just use self.position.
+                    if let Some(rect) = self.background() {
+                        cmds.push(DrawCommand::DrawRect(rect));
                     }
+                    cmds.append(&mut self.borders());
+                    cmds.append(&mut self.list_marker());
                 }
             },
 
@@ -556,22 +806,8 @@ impl LayoutNode {
                 }));
             },
             LayoutNodeType::Input(input) => {
-                let bgcolor = self.node.read().unwrap().style.get("background-color")
-                    .unwrap_or(&"transparent".to_string()).clone();
-                if let Ok(color_parse) = csscolorparser::parse(&bgcolor) {
-                    if let Ok(color) = Color32::from_hex(&color_parse.to_css_hex()) {
-                        if color.a() > 0 {
-                            let pos = self.position.unwrap_or(Vec2::ZERO);
-                            let size = self.size.unwrap_or(Vec2::ZERO);
-                            let radius = self.node.read().unwrap().style.get("border-radius").unwrap_or(&"0px".to_string()).replace("px", "").parse::<f32>().unwrap_or(0.0);
-
-                            cmds.push(DrawCommand::DrawRect(DrawRect {
-                                rect: Rect::from_two_pos(pos.to_pos2(), (pos + size).to_pos2()),
-                                color,
-                                radius
-                            }));
-                        }
-                    }
+                if let Some(rect) = self.background() {
+                    cmds.push(DrawCommand::DrawRect(rect));
                 }
 
                 let node_borrow = self.node.read().unwrap();
@@ -615,6 +851,115 @@ impl LayoutNode {
     }
 
 
+    /// Returns the rectangle that paints the node's `background-color`, or `None` if the
+    /// background is fully transparent.
+    ///
+    /// # Notes
+    /// A percentage `border-radius` is relative to the smaller side of the box, since the
+    /// corners of a `DrawRect` are all circular with the same radius.
+    fn background(&self) -> Option<DrawRect> {
+        let node = self.node.read().unwrap();
+        let color = node.style.background_color;
+        if color.a() == 0 {
+            return None;
+        }
+        let pos = self.position.unwrap_or(Vec2::ZERO);
+        let size = self.size.unwrap_or(Vec2::ZERO);
+        Some(DrawRect {
+            rect: Rect::from_two_pos(pos.to_pos2(), (pos + size).to_pos2()),
+            color,
+            radius: node.style.border_radius.resolve(size.x.min(size.y)),
+        })
+    }
+
+    /// Returns the commands that paint a block's borders, with their styles and colors.
+    ///
+    /// # Notes
+    /// - Corners are not mitered: the top and bottom borders span the whole border box,
+    ///   and the left and right ones fit between them. `border-radius` only rounds the
+    ///   background.
+    /// - `dotted` borders are drawn as squares as wide as the border, and `dashed` ones as
+    ///   dashes three times as long. `double` draws two lines a third of the width each.
+    ///   `groove`, `ridge`, `inset` and `outset` draw the border color darker or lighter
+    ///   to look carved or raised.
+    fn borders(&self) -> Vec<DrawCommand> {
+        let LayoutNodeType::Block(block) = &self.content else {
+            return vec![];
+        };
+        let node = self.node.read().unwrap();
+        let pos = self.position.unwrap_or(Vec2::ZERO).to_pos2();
+        let size = self.size.unwrap_or(Vec2::ZERO);
+        let outer = Rect::from_min_size(pos, size);
+        let widths = block.border;
+
+        let mut cmds = vec![];
+        for side in [Side::Top, Side::Right, Side::Bottom, Side::Left] {
+            let width = *widths.get(side);
+            let style = *node.style.border_style.get(side);
+            if width <= 0.0 || style.is_none() {
+                continue;
+            }
+            let color = node.style.border_color.get(side).unwrap_or(node.style.color);
+            let strip = match side {
+                Side::Top => Rect::from_min_max(outer.min, Pos2::new(outer.max.x, outer.min.y + width)),
+                Side::Bottom => Rect::from_min_max(Pos2::new(outer.min.x, outer.max.y - width), outer.max),
+                Side::Left => Rect::from_min_max(
+                    Pos2::new(outer.min.x, outer.min.y + widths.top),
+                    Pos2::new(outer.min.x + width, outer.max.y - widths.bottom)),
+                Side::Right => Rect::from_min_max(
+                    Pos2::new(outer.max.x - width, outer.min.y + widths.top),
+                    Pos2::new(outer.max.x, outer.max.y - widths.bottom)),
+            };
+            paint_border_side(&mut cmds, strip, side, style, color);
+        }
+        cmds
+    }
+
+    /// Returns the commands that paint the marker of a list item, left of its first line.
+    ///
+    /// # Notes
+    /// - Markers are drawn as shapes rather than text, in the text color and a third of
+    ///   the font size: a filled circle for `disc`, an outline for `circle` and a filled
+    ///   square for `square`. `list-style-type: none` draws nothing.
+    /// - Markers are always outside the content box, and centered on the first line, or
+    ///   on where it would be if the item has none.
+    fn list_marker(&self) -> Vec<DrawCommand> {
+        let LayoutNodeType::Block(block) = &self.content else {
+            return vec![];
+        };
+        let node = self.node.read().unwrap();
+        if block.anonymous_content.is_some() || node.style.display != Display::ListItem {
+            return vec![];
+        }
+        let font_size = node.style.font_size;
+        let pos = self.position.unwrap_or(Vec2::ZERO);
+        let content_left = pos.x + block.border.left + block.padding.left;
+        let content_top = pos.y + block.border.top + block.padding.top;
+        let center_y = first_line(self)
+            .map(|line| line.center().y)
+            .unwrap_or(content_top + font_size * 0.625);
+        let size = font_size / 3.0;
+        let rect = Rect::from_center_size(
+            Pos2::new(content_left - font_size * 0.5 - size / 2.0, center_y),
+            Vec2::splat(size));
+        let color = node.style.color;
+        match node.style.list_style_type {
+            ListStyleType::None => vec![],
+            ListStyleType::Disc => vec![DrawCommand::DrawRect(DrawRect { rect, color, radius: size / 2.0 })],
+            ListStyleType::Square => vec![DrawCommand::DrawRect(DrawRect { rect, color, radius: 0.0 })],
+            ListStyleType::Circle => {
+                const SEGMENTS: usize = 12;
+                let point = |i: usize| {
+                    let angle = i as f32 * std::f32::consts::TAU / SEGMENTS as f32;
+                    rect.center() + Vec2::angled(angle) * size / 2.0
+                };
+                (0..SEGMENTS)
+                    .map(|i| DrawCommand::DrawLine(DrawLine { from: point(i), to: point(i + 1), color, thickness: 1.0 }))
+                    .collect()
+            }
+        }
+    }
+
     /// Recursively traverses the layout tree and populates the display list with draw commands.
     ///
     /// # Arguments
@@ -678,67 +1023,79 @@ impl LayoutNode {
         }
     }
 
-    /// ```rust
-    /// Determines the layout mode for a given HTML node by analyzing its type and children.
-    ///
-    /// # Arguments
-    /// * `layout_node` - A reference-counted, mutable `HtmlNode` object wrapped in `RefCell`.
+    /// Determines what the block box of an element contains, from the `display` of its
+    /// children.
     ///
     /// # Returns
-    /// * `LayoutMode` - Either `Block` or `Inline`, based on the analysis of the node type and its children.
-    ///
-    /// # Behavior
-    /// - For nodes of type `HtmlNodeType::Element`:
-    ///     - If any child is an element and its tag is a block element (`BLOCK_ELEMENTS`), the function returns `Block`.
-    ///     - If the node has children but none of them satisfy the above condition, it returns `Inline`.
-    ///     - If the node has no children, it defaults to `Block`.
-    /// - For nodes of type `HtmlNodeType::Text`, the function returns `Inline`.
+    /// - `Block` if a child is block-level (see `is_block_level`): the boxes of the
+    ///   block-level children, with each run of inline-level children between them
+    ///   wrapped in an anonymous block box.
+    /// - `Inline` otherwise: the children, to lay out in lines. Inputs and buttons lay
+    ///   out themselves on a line, even with `display: block`.
+    /// - `Flex` for a flex container, whatever its children: every child element, and
+    ///   every run of text between them that is not only white space, is a flex item.
+    /// - `Grid` for a grid container, whose items are gathered the same way.
+    /// - `Table` for a table, whatever its children: they are sorted into captions, row
+    ///   groups, rows and cells by `table::layout_table`.
     ///
     /// # Notes
-    /// - `BLOCK_ELEMENTS` is assumed to be a predefined collection of block-level HTML tags.
-    /// - This function uses interior mutability with `RefCell` to enable borrowing and modification of the `HtmlNode`.
-    ///
-    /// # Examples
-    /// ```
-    /// let html_node = Rc::new(RefCell::new(HtmlNode::new(HtmlNodeType::Element(Element::new("div")))));
-    /// let mode = layout_mode(html_node.clone());
-    /// assert_eq!(mode, LayoutMode::Block);
-    /// ```
-    ///
-    /// ```
-    /// let text_node = Rc::new(RefCell::new(HtmlNode::new(HtmlNodeType::Text("Hello".to_string()))));
-    /// let mode = layout_mode(text_node.clone());
-    /// assert_eq!(mode, LayoutMode::Inline);
-    /// ```
-    /// ```
-    fn layout_mode(layout_node: Arc<RwLock<HtmlNode>>) -> LayoutMode {
-        match layout_node.read().unwrap().node_type {
-            HtmlNodeType::Element(_) => {
-                if layout_node.read().unwrap().children.iter().any(|c| {
-                    match &c.read().unwrap().node_type {
-                        HtmlNodeType::Element(ele) => {
-                            BLOCK_ELEMENTS.contains(&&*ele.tag)
-                        },
-                        _ => false
-                    }
-                }) { Block}
-                else if !layout_node.read().unwrap().children.is_empty() ||
-                    matches!(layout_node.read().unwrap().node_type,
-                        HtmlNodeType::Element(ref ele) if ele.tag == "input") {
-                    Inline
+    /// Children with `display: none` generate nothing, and the children of those with
+    /// `display: contents` take their place. See `box_children`.
+    fn layout_mode(node: &Arc<RwLock<HtmlNode>>) -> LayoutMode {
+        if matches!(&node.read().unwrap().node_type,
+            HtmlNodeType::Element(ele) if ele.tag == "input" || ele.tag == "button") {
+            return Inline(vec![node.clone()]);
+        }
+        let children = box_children(node);
+        let container = display(node);
+        if matches!(container, Some(Display::Table | Display::InlineTable)) {
+            return Table(children);
+        }
+        if matches!(container, Some(Display::Flex | Display::InlineFlex | Display::Grid | Display::InlineGrid)) {
+            let mut items = vec![];
+            let mut run = vec![];
+            for child in children {
+                if display(&child).is_some() {
+                    flush_text_run(&mut run, &mut items);
+                    items.push(ChildBox::Block(child));
+                } else {
+                    run.push(child);
                 }
-                else {
-                    Block
+            }
+            flush_text_run(&mut run, &mut items);
+            return match container {
+                Some(Display::Grid | Display::InlineGrid) => Grid(items),
+                _ => Flex(items),
+            };
+        }
+
+        if !children.iter().any(is_block_level) {
+            return Inline(children);
+        }
+
+        let mut boxes = vec![];
+        let mut run = vec![];
+        for child in children {
+            if is_block_level(&child) {
+                if !run.is_empty() {
+                    boxes.push(ChildBox::Anonymous(std::mem::take(&mut run)));
                 }
+                boxes.push(ChildBox::Block(child));
+            } else {
+                run.push(child);
             }
-            HtmlNodeType::Text(_) => {Inline}
         }
+        if !run.is_empty() {
+            boxes.push(ChildBox::Anonymous(run));
+        }
+        Block(boxes)
     }
 
     pub fn should_paint(&self) -> bool {
         match self.content {
             LayoutNodeType::Document => true,
-            LayoutNodeType::Block(_) => {
+            LayoutNodeType::Block(ref block) => {
+                block.anonymous_content.is_none() &&
                 matches!(self.node.read().unwrap().node_type,
                         HtmlNodeType::Element(ref ele) if ele.tag != "input") &&
                     matches!(self.node.read().unwrap().node_type,
@@ -751,9 +1108,336 @@ impl LayoutNode {
     }
 }
 
+/// What a block box contains, see `LayoutNode::layout_mode`.
 enum LayoutMode {
-    Inline,
-    Block
+    /// Lines of inline content, laid out from these nodes.
+    Inline(Vec<Arc<RwLock<HtmlNode>>>),
+    /// Block-level boxes, stacked vertically.
+    Block(Vec<ChildBox>),
+    /// The items of a flex container, in document order.
+    Flex(Vec<ChildBox>),
+    /// The items of a grid container, in document order.
+    Grid(Vec<ChildBox>),
+    /// The children of a table.
+    Table(Vec<Arc<RwLock<HtmlNode>>>),
+}
+
+/// A block-level box in a block container, or an item of a flex or grid container.
+enum ChildBox {
+    /// The box of a block-level element.
+    Block(Arc<RwLock<HtmlNode>>),
+    /// An anonymous block box around a run of inline-level nodes.
+    Anonymous(Vec<Arc<RwLock<HtmlNode>>>),
+}
+
+/// Wraps a run of text nodes between the children of a flex container into an anonymous
+/// flex item, unless the run is only white space, which generates nothing. Empties `run`.
+fn flush_text_run(run: &mut Vec<Arc<RwLock<HtmlNode>>>, items: &mut Vec<ChildBox>) {
+    let is_white_space = run.iter().all(|text| match &text.read().unwrap().node_type {
+        HtmlNodeType::Text(text) => text.text.chars().all(|c| c.is_ascii_whitespace()),
+        HtmlNodeType::Element(_) => false,
+    });
+    let run = std::mem::take(run);
+    if !is_white_space {
+        items.push(ChildBox::Anonymous(run));
+    }
+}
+
+/// Returns the style of an anonymous box: the initial value of every property, and no
+/// borders, since their initial style is `none`.
+fn anonymous_style() -> ComputedStyle {
+    ComputedStyle { border_width: Edges::all(0.0), ..ComputedStyle::default() }
+}
+
+/// Returns the `display` of an element, or `None` for a text node.
+fn display(node: &Arc<RwLock<HtmlNode>>) -> Option<Display> {
+    let node = node.read().unwrap();
+    match node.node_type {
+        HtmlNodeType::Element(_) => Some(node.style.display),
+        HtmlNodeType::Text(_) => None,
+    }
+}
+
+/// Returns the children of `node` that generate boxes in its box, in order: children
+/// with `display: none` are left out, and those with `display: contents` are replaced
+/// by their own children.
+fn box_children(node: &Arc<RwLock<HtmlNode>>) -> Vec<Arc<RwLock<HtmlNode>>> {
+    let mut children = vec![];
+    for child in node.read().unwrap().children.iter() {
+        match display(child) {
+            Some(Display::None) => {}
+            Some(Display::Contents) => children.extend(box_children(child)),
+            _ => children.push(child.clone()),
+        }
+    }
+    children
+}
+
+/// Returns whether `node` generates a block-level box: it has `display: block`,
+/// `list-item`, `flex`, `grid` or `table`, or is a part of a table (outside one, it is
+/// laid out as a block), or it is an inline element with a block-level box inside.
+///
+/// # Notes
+/// CSS splits an inline element around a block inside it, into the inline content
+/// before the block, the block and the inline content after. The element is laid out
+/// as a block instead, which places its content the same way.
+fn is_block_level(node: &Arc<RwLock<HtmlNode>>) -> bool {
+    match display(node) {
+        Some(Display::Block | Display::ListItem | Display::Flex | Display::Grid | Display::Table
+            | Display::TableRowGroup | Display::TableHeaderGroup | Display::TableFooterGroup
+            | Display::TableRow | Display::TableCell | Display::TableCaption) => true,
+        Some(Display::Inline | Display::Contents) => box_children(node).iter().any(is_block_level),
+        Some(Display::InlineBlock | Display::InlineFlex | Display::InlineGrid | Display::InlineTable
+            | Display::TableColumnGroup | Display::TableColumn | Display::None) | None => false,
+    }
+}
+
+/// Returns the box of the first line of a block, in its first child blocks if it
+/// contains blocks.
+fn first_line(block: &LayoutNode) -> Option<Rect> {
+    let first = block.children.first()?.read().unwrap();
+    match first.content {
+        LayoutNodeType::Line(_) => Some(Rect::from_min_size(first.position?.to_pos2(), first.size?)),
+        LayoutNodeType::Block(_) => first_line(&first),
+        _ => None,
+    }
+}
+
+/// Returns the margin box of a laid out block.
+fn margin_box(node: &Arc<RwLock<LayoutNode>>) -> Rect {
+    let node = node.read().unwrap();
+    let border_box = Rect::from_min_size(node.position.unwrap_or(Vec2::ZERO).to_pos2(), node.size.unwrap_or(Vec2::ZERO));
+    match &node.content {
+        LayoutNodeType::Block(block) => Rect::from_min_max(
+            border_box.min - Vec2::new(block.margin.left, block.margin.top),
+            border_box.max + Vec2::new(block.margin.right, block.margin.bottom)),
+        _ => border_box,
+    }
+}
+
+/// Moves a laid out subtree by `delta`.
+fn translate(node: &Arc<RwLock<LayoutNode>>, delta: Vec2) {
+    let mut node = node.write().unwrap();
+    if let Some(position) = node.position.as_mut() {
+        *position += delta;
+    }
+    for child in &node.children {
+        translate(child, delta);
+    }
+}
+
+/// The box that a block's percentages refer to and that it is placed in: the content
+/// box of its parent.
+///
+/// # Fields
+/// - `x`: The left edge of the content box.
+/// - `width`: The width of the content box.
+/// - `height`: The height of the content box if the parent specifies it, or `None` if
+///   it depends on the content, in which case percentage heights are `auto`.
+#[derive(Clone, Copy, Debug)]
+struct ContainingBlock {
+    x: f32,
+    width: f32,
+    height: Option<f32>,
+}
+
+/// How a block box is laid out by its parent, see `LayoutNode::layout_block`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BlockRole {
+    /// A block-level box, stacked with its siblings in a block formatting context.
+    InFlow,
+    /// The box of the root element, which starts the page's block formatting context.
+    Root,
+    /// An inline-block, laid out on a line. It starts its own block formatting context.
+    InlineBlock,
+    /// An item of a flex or grid container, which starts its own block formatting
+    /// context. Its container decides the `width` of its content box, or leaves it to
+    /// `None`, in which case it is sized like an inline-block.
+    Item { width: Option<f32> },
+    /// A table cell, which starts its own block formatting context and has no margins.
+    /// Its table decides the `width` of its content box, and its `border` widths, which
+    /// border collapsing may change.
+    Cell { width: f32, border: Edges<f32> },
+}
+
+/// The content box of a flex or grid container, which its items are laid out in.
+///
+/// # Fields
+/// - `position`: The top left corner of the content box.
+/// - `width`: The width of the content box.
+/// - `height`: The height of the content box if the container specifies it, or `None`
+///   if it depends on the items.
+/// - `min_height`, `max_height`: The limits of the content box's height.
+struct ContentBox {
+    position: Vec2,
+    width: f32,
+    height: Option<f32>,
+    min_height: f32,
+    max_height: f32,
+}
+
+/// The block of an item of a flex or grid container, which the container sizes and
+/// places itself.
+///
+/// # Fields
+/// - `node`: The element that generates the item, or for an anonymous item, the
+///   container.
+/// - `anonymous_content`: For an anonymous item, the run of text it wraps.
+/// - `layout`: The item's block, laid out again whenever its width changes.
+/// - `style`: The item's style; the initial values for an anonymous item.
+/// - `margin`: The margins, `auto` ones as `0` until the container shares its free
+///   space.
+/// - `auto_margin`: Which margins are `auto`.
+/// - `edges`: The borders plus the paddings.
+struct ItemBlock {
+    node: Arc<RwLock<HtmlNode>>,
+    anonymous_content: Option<Vec<Arc<RwLock<HtmlNode>>>>,
+    layout: Arc<RwLock<LayoutNode>>,
+    style: ComputedStyle,
+    margin: Edges<f32>,
+    auto_margin: Edges<bool>,
+    edges: Edges<f32>,
+}
+
+impl ItemBlock {
+    /// Resolves the margins, borders and paddings of an item, against `width`, as CSS
+    /// does for both axes: the width of the container's content box.
+    fn new(child_box: ChildBox, container: &Arc<RwLock<LayoutNode>>, context: &Context, width: f32) -> ItemBlock {
+        let (node, anonymous_content, style) = match child_box {
+            ChildBox::Block(node) => {
+                let style = node.read().unwrap().style.clone();
+                (node, None, style)
+            }
+            ChildBox::Anonymous(content) => (container.read().unwrap().node.clone(), Some(content), anonymous_style()),
+        };
+        let padding = style.padding.map(|padding| padding.resolve(width));
+        let edges = Edges {
+            top: style.border_width.top + padding.top,
+            right: style.border_width.right + padding.right,
+            bottom: style.border_width.bottom + padding.bottom,
+            left: style.border_width.left + padding.left,
+        };
+        ItemBlock {
+            layout: LayoutNode::new_block(node.clone(), Some(container.clone()), context.clone()),
+            node,
+            anonymous_content,
+            margin: style.margin.map(|margin| margin.as_ref().map_or(0.0, |margin| margin.resolve(width))),
+            auto_margin: style.margin.map(|margin| margin.is_none()),
+            edges,
+            style,
+        }
+    }
+
+    /// Lays out the item's block afresh, with its margin box at the origin.
+    ///
+    /// # Parameters
+    /// - `width`: The width of the content box, or `None` to shrink it to fit.
+    fn lay_out(&mut self, container: &Arc<RwLock<LayoutNode>>, context: &Context,
+               containing_block: ContainingBlock, width: Option<f32>) {
+        self.layout = match &self.anonymous_content {
+            Some(content) => LayoutNode::new_anonymous_block(self.node.clone(), content.clone(), container.clone(), context.clone()),
+            None => LayoutNode::new_block(self.node.clone(), Some(container.clone()), context.clone()),
+        };
+        LayoutNode::layout_block(&self.layout, context, containing_block, 0.0, CollapsedMargin::default(), BlockRole::Item { width });
+    }
+
+    /// The size of the laid out border box.
+    fn border_box(&self) -> Vec2 {
+        self.layout.read().unwrap().size.unwrap_or(Vec2::ZERO)
+    }
+
+    /// Returns how much of `edges` a specified size includes, under the item's
+    /// `box-sizing`.
+    fn sizing(&self, edges: f32) -> f32 {
+        match self.style.box_sizing {
+            BoxSizing::ContentBox => 0.0,
+            BoxSizing::BorderBox => edges,
+        }
+    }
+}
+
+#[cfg(test)]
+impl ItemBlock {
+    /// Returns the block of an item with `style` and no margins, borders or paddings,
+    /// for testing how flex and grid containers size and place their items.
+    fn with_style(style: ComputedStyle) -> ItemBlock {
+        let node = Arc::new(RwLock::new(HtmlNode::new(HtmlNodeType::Text(crate::node::Text { text: String::new() }), None)));
+        ItemBlock {
+            layout: LayoutNode::new_block(node.clone(), None, Context::default()),
+            node,
+            anonymous_content: None,
+            style,
+            margin: Edges::all(0.0),
+            auto_margin: Edges::all(false),
+            edges: Edges::all(0.0),
+        }
+    }
+}
+
+/// Returns where `justify-content` puts the first item of a flex line, or the first
+/// column of a grid, and the space it adds between them.
+///
+/// # Parameters
+/// - `justify_content`: The container's `justify-content`.
+/// - `free`: The space left along the axis; negative if the items overflow.
+/// - `count`: The number of items or columns.
+///
+/// # Notes
+/// The `space-*` values fall back to the start for `space-between`, and to the center
+/// for the others, when the items overflow or for a single item (`space-between`).
+fn distribute(justify_content: JustifyContent, free: f32, count: usize) -> (f32, f32) {
+    let count = count as f32;
+    match justify_content {
+        JustifyContent::Normal | JustifyContent::FlexStart => (0.0, 0.0),
+        JustifyContent::FlexEnd => (free, 0.0),
+        JustifyContent::Center => (free / 2.0, 0.0),
+        JustifyContent::SpaceBetween if free > 0.0 && count > 1.0 => (0.0, free / (count - 1.0)),
+        JustifyContent::SpaceBetween => (0.0, 0.0),
+        JustifyContent::SpaceAround if free > 0.0 => (free / count / 2.0, free / count),
+        JustifyContent::SpaceEvenly if free > 0.0 => (free / (count + 1.0), free / (count + 1.0)),
+        JustifyContent::SpaceAround | JustifyContent::SpaceEvenly => (free / 2.0, 0.0),
+    }
+}
+
+/// Resolves a size against `base`, or returns `None` if it is a percentage of a size
+/// that is not known.
+fn resolve_length(length: &LengthPercentage, base: Option<f32>) -> Option<f32> {
+    match length {
+        LengthPercentage::Px(px) => Some(*px),
+        length => base.map(|base| length.resolve(base)),
+    }
+}
+
+/// Adjoining vertical margins, which collapse into a single margin: the largest
+/// positive margin plus the most negative one.
+#[derive(Clone, Copy, Debug, Default)]
+struct CollapsedMargin {
+    positive: f32,
+    negative: f32,
+}
+
+impl CollapsedMargin {
+    /// Returns these margins with `margin` adjoining them.
+    fn with(self, margin: f32) -> CollapsedMargin {
+        CollapsedMargin { positive: self.positive.max(margin), negative: self.negative.min(margin) }
+    }
+
+    /// The size of the collapsed margin.
+    fn resolve(self) -> f32 {
+        self.positive + self.negative
+    }
+}
+
+/// What `LayoutNode::layout_block` tells the parent about a laid out block.
+///
+/// # Fields
+/// - `margin`: The margins at the bottom of the block, still to be collapsed with the
+///   top margin of the next sibling or the bottom margin of the parent.
+/// - `collapsed_through`: Whether the block is empty and its margins collapsed through
+///   it, so that the next sibling's margins adjoin the previous one's.
+struct BlockOutcome {
+    margin: CollapsedMargin,
+    collapsed_through: bool,
 }
 
 /// ```rust
@@ -881,6 +1565,10 @@ pub struct TextLayout {
 /// * `font_id` - A `FontId`, identifying the font used in the block layout.
 /// * `space_width` - A `f32` value representing the width of a single space character, useful
 ///   for alignment and spacing calculations.
+/// * `margin`, `border`, `padding` - The used margins, border widths and paddings of the
+///   block in pixels. The block's `position` and `size` are those of its border box.
+/// * `anonymous_content` - For an anonymous block box, the inline-level nodes it wraps;
+///   `None` for the box of an element.
 ///
 /// This struct is derived from the `Debug` trait, which allows for formatted output using the
 /// `{:?}` formatter.
@@ -895,6 +1583,10 @@ pub struct BlockLayout {
     context: Context,
     font_id: FontId,
     space_width: f32,
+    pub(crate) margin: Edges<f32>,
+    pub(crate) border: Edges<f32>,
+    pub(crate) padding: Edges<f32>,
+    anonymous_content: Option<Vec<Arc<RwLock<HtmlNode>>>>,
 }
 
 /// ```rust
@@ -918,12 +1610,10 @@ pub struct BlockLayout {
 ///   This node acts as the parent to which child nodes (e.g., lines of text) will be added.
 ///   The use of `Rc<RefCell<_>>` ensures shared ownership and interior mutability for the node.
 ///
-/// * `outer_position`: A mutable reference to an optional `Vec2` representing the outer
-///   position of the block. If set, this determines the block's absolute position in the layout
-///   space.
+/// * `content_position`: The absolute position of the block's content box, where the
+///   first line starts.
 ///
-/// * `outer_size`: A mutable reference to an optional `Vec2` representing the size of the block.
-///   If set, this specifies the block's total dimensions, including padding or margins.
+/// * `content_width`: The width of the block's content box, which lines wrap at.
 ///
 /// * `current_line_nodes`: A vector of `Rc<RefCell<LayoutNode>>` representing temporary storage
 ///   for the text nodes belonging to the current line. This buffer facilitates efficient handling
@@ -938,9 +1628,12 @@ struct BlockComposer<'a> {
     // We need the block's HTML node to create Line nodes
     block_html_node: Arc<RwLock<HtmlNode>>,
 
-    outer_position: &'a mut Option<Vec2>,
-    outer_size: &'a mut Option<Vec2>,
+    content_position: Vec2,
+    content_width: f32,
     current_line_nodes: Vec<Arc<RwLock<LayoutNode>>>,
+    // Whether collapsible whitespace was seen since the last word. It becomes a single
+    // space before the next word on the same line, even across inline elements.
+    pending_space: bool,
 }
 
 impl<'a> BlockComposer<'a> {
@@ -957,52 +1650,7 @@ impl<'a> BlockComposer<'a> {
     fn open_tag(&mut self, tag: String) {
         match tag.as_str() {
             "br" => {
-                self.flush_line();
-            }
-            _ => {}
-        }
-    }
-
-    /// Handles the closing of an HTML-like tag by updating the layout state accordingly.
-    ///
-    /// This method is responsible for reversing changes made to the layout state when
-    /// a corresponding opening tag is closed. It supports tags such as `<i>`, `<b>`,
-    /// `<big>`, `<small>`, and `<p>`. Different actions are taken depending on the tag:
-    /// - `<i>`: Resets the font style.
-    /// - `<b>`: Resets the font weight.
-    /// - `<big>`: Decreases the font size.
-    /// - `<small>`: Increases the font size.
-    /// - `<p>`: Flushes the current line and moves the cursor down by a vertical step.
-    ///
-    /// # Parameters
-    /// - `tag`: A `String` representing the closing tag (e.g., "i", "b", "big", "small", "p").
-    ///
-    /// # Behavior
-    /// - For `<i>` and `<b>`, the font style and weight are reset respectively.
-    /// - For `<big>` and `<small>`, the font size is adjusted relative to a predefined value.
-    /// - For `<p>`, the current line is finalized and the cursor is moved down to the next line.
-    /// - If the tag does not match any of the handled cases, the method does nothing.
-    ///
-    /// # Side Effects
-    /// - May modify the `font_style`, `font_weight`, and `font_size` properties of the `layout`.
-    /// - Calls `self.update_font()` to apply font-related changes.
-    /// - For the `<p>` tag, calls `self.flush_line()` and modifies `layout.cursor_y`.
-    ///
-    /// # Examples
-    /// ```rust
-    /// let mut renderer = Renderer::new();
-    /// renderer.close_tag("i".into());  // Resets italic style.
-    /// renderer.close_tag("p".into());  // Moves to a new paragraph.
-    /// renderer.close_tag("unknown".into());  // No action taken.
-    /// ```
-    ///
-    /// # Note
-    /// - `VSTEP` is assumed to be a predefined constant controlling the vertical spacing between lines.
-    fn close_tag(&mut self, tag: String) {
-        match tag.as_str() {
-            "p" => {
-                self.flush_line();
-                self.layout.cursor_y += VSTEP;
+                self.line_break();
             }
             _ => {}
         }
@@ -1012,17 +1660,21 @@ impl<'a> BlockComposer<'a> {
     ///
     /// # Parameters
     /// - `word`: A string slice (`&str`) representing the word to be added to the layout.
+    /// - `white_space`: The `white-space` style of the text, which decides whether the word may wrap.
     ///
     /// # Behavior
     /// 1. Measures the width of the `word` using the font settings stored in the layout context.
-    /// 2. Checks if adding the `word` to the current line would exceed the available width (`outer_size.x`).
-    ///    - If it would exceed, calls `flush_line()` to finalize the current line and move to the next one.
+    /// 2. Checks if adding the pending space and the `word` to the current line would exceed the available
+    ///    width (`content_width`).
+    ///    - If it would exceed and wrapping is allowed, calls `flush_line()` to finalize the current line and
+    ///      move to the next one; the pending space is dropped.
     /// 3. Creates a `DrawText` object for the `word`, initialized with:
     ///    - The current cursor position (`cursor_x`) for the x-coordinate.
     ///    - A y-coordinate of `0.0` (default alignment).
     ///    - The precomputed galley (text layout information).
     /// 4. Adds the `DrawText` object to the current layout line.
-    /// 5. Advances the `cursor_x` position by the width of the `word` (`text_width`) plus spacing between words (`space_width`).
+    /// 5. Advances the `cursor_x` position by the width of the `word` (`text_width`). Spacing between words
+    ///    comes from the whitespace in the text, see `text()`.
     ///
     /// # Notes
     /// - This function relies on the layout system's `flush_line` method to handle wrapping when a word does not fit within the available width.
@@ -1032,33 +1684,27 @@ impl<'a> BlockComposer<'a> {
     /// ```
     /// some_layout.word("example");
     /// ```
-    fn word(&mut self, word: &str, node: Arc<RwLock<HtmlNode>>) {
-        let weight = node.read().unwrap().style.get("font-weight").unwrap_or(&"normal".to_string()).clone();
-        match weight.as_str() {
-            "bold" => self.layout.font_weight = "bold".into(),
-            _ => self.layout.font_weight = "".into(),
-        }
-
-        let style = node.read().unwrap().style.get("font-style").unwrap_or(&"normal".to_string()).clone();
-        match style.as_str() {
-            "italic" => self.layout.font_style = "italic".into(),
-            _ => self.layout.font_style = "".into(),
-        };
-
-        let size = node.read().unwrap().style.get("font-size").unwrap_or(&"16px".to_string()).clone().replace("px", "").parse::<f32>().unwrap();
-        self.layout.font_size = size;
+    fn word(&mut self, word: &str, node: Arc<RwLock<HtmlNode>>, white_space: WhiteSpace) {
+        let style = node.read().unwrap().style.clone();
+        self.select_font(&style);
+        self.layout.font_size = style.font_size;
         self.update_font();
-        let color_parse = csscolorparser::parse(node.read().unwrap().style.get("color").unwrap_or(&"black".to_string()).as_str());
-        let color = Color32::from_hex(&*color_parse.unwrap().to_css_hex()).unwrap();
-
+        let color = style.color;
 
         let galley = self.layout.context.fonts_mut(|f|
             f.layout_no_wrap(word.to_string(), self.layout.font_id.clone(), color));
         let text_width = galley.size().x;
         let text_height = galley.size().y;
-        // 3. Check for wrapping
-        if self.layout.cursor_x + text_width > self.outer_size.unwrap().x {
+        // 3. Check for wrapping. The pending space, if any, goes before the word unless the
+        // word starts a new line.
+        let space = if self.pending_space { self.layout.space_width } else { 0.0 };
+        self.pending_space = false;
+        if white_space.wraps()
+            && !self.current_line_nodes.is_empty()
+            && self.layout.cursor_x + space + text_width > self.content_width {
             self.flush_line();
+        } else {
+            self.layout.cursor_x += space;
         }
 
         // 4. Create the TextLayout struct
@@ -1082,7 +1728,87 @@ impl<'a> BlockComposer<'a> {
         // 6. Add to pending buffer
         self.current_line_nodes.push(text_node);
 
-        self.layout.cursor_x += text_width + self.layout.space_width;
+        self.layout.cursor_x += text_width;
+    }
+
+    /// Lays out the text of a text node according to its `white-space` style.
+    ///
+    /// # Behavior
+    /// - `normal`, `nowrap` and `pre-line` collapse each run of whitespace into a single
+    ///   space, which is dropped at the start of a line. `pre-line` still breaks the line
+    ///   at every newline.
+    /// - `pre` and `pre-wrap` keep every space, expand tabs to the next tab stop (every
+    ///   eight spaces) and break the line at every newline.
+    /// - `normal`, `pre-wrap` and `pre-line` wrap words that do not fit on the line;
+    ///   `pre` and `nowrap` never wrap, so their lines may overflow the block.
+    fn text(&mut self, text: &str, node: Arc<RwLock<HtmlNode>>) {
+        let white_space = node.read().unwrap().style.white_space;
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                if white_space.preserves_newlines() {
+                    self.pending_space = false;
+                    self.line_break();
+                } else {
+                    self.collapsible_space();
+                }
+            }
+
+            let mut rest = line;
+            while let Some(c) = rest.chars().next() {
+                let end = rest
+                    .find(|ch: char| ch.is_ascii_whitespace() != c.is_ascii_whitespace())
+                    .unwrap_or(rest.len());
+                let (run, tail) = rest.split_at(end);
+                rest = tail;
+
+                if !c.is_ascii_whitespace() {
+                    self.word(run, node.clone(), white_space);
+                } else if white_space.collapses_spaces() {
+                    self.collapsible_space();
+                } else {
+                    self.preserved_space(run);
+                }
+            }
+        }
+    }
+
+    /// Records a collapsible space, unless the current line is still empty.
+    fn collapsible_space(&mut self) {
+        if !self.current_line_nodes.is_empty() {
+            self.pending_space = true;
+        }
+    }
+
+    /// Advances the cursor over preserved spaces and tabs. Tabs move the cursor to the
+    /// next multiple of eight space widths.
+    fn preserved_space(&mut self, spaces: &str) {
+        if self.pending_space {
+            self.layout.cursor_x += self.layout.space_width;
+            self.pending_space = false;
+        }
+        let tab_stop = self.layout.space_width * 8.0;
+        for c in spaces.chars() {
+            if c == '\t' && tab_stop > 0.0 {
+                self.layout.cursor_x = ((self.layout.cursor_x / tab_stop).floor() + 1.0) * tab_stop;
+            } else {
+                self.layout.cursor_x += self.layout.space_width;
+            }
+        }
+    }
+
+    /// Ends the current line, as `<br>` and preserved newlines do. Unlike `flush_line`,
+    /// an empty line still takes up vertical space, so consecutive breaks leave blank lines.
+    fn line_break(&mut self) {
+        if self.current_line_nodes.is_empty() {
+            self.update_font();
+            let line_height = self.layout.context.fonts_mut(|f| f.row_height(&self.layout.font_id));
+            self.layout.cursor_x = 0.0;
+            self.layout.cursor_y += line_height * 1.25;
+        } else {
+            self.flush_line();
+        }
     }
 
     /// Flushes the current line of text from the internal layout structure to the display list
@@ -1117,8 +1843,8 @@ impl<'a> BlockComposer<'a> {
     /// ### Notes:
     /// - The `1.25` multiplier applied to the ascent and descent margins provides additional vertical
     ///   spacing between lines for improved readability.
-    /// - The method relies on an `outer_position` field for determining the outer coordinates of the
-    ///   current layout block, which must be defined prior to calling `flush_line`.
+    /// - The method relies on the `content_position` field for determining the coordinates of the
+    ///   current block's content box, where lines are placed.
     ///
     /// ### Fields Used:
     /// - `self.layout.line`: Stores the text elements in the current line.
@@ -1126,13 +1852,20 @@ impl<'a> BlockComposer<'a> {
     /// - `self.layout.cursor_y`: Tracks the Y position of text addition.
     /// - `self.layout.display_list`: A collection of `DrawText` objects representing text ready for
     ///   rendering.
-    /// - `self.outer_position`: An optional field for the layout's outer positioning.
+    /// - `self.content_position`: The position of the block's content box.
     fn flush_line(&mut self) {
-        if self.current_line_nodes.is_empty() { return; }
+        self.pending_space = false;
+        if self.current_line_nodes.is_empty() {
+            self.layout.cursor_x = 0.0;
+            return;
+        }
 
         // 1. Calculate Metrics
         let mut max_ascent: f32 = 0.0;
         let mut max_descent: f32 = 0.0;
+        // Inline-blocks sit on the baseline with their bottom margin edge, and are not
+        // spaced out like text.
+        let mut max_box_height: f32 = 0.0;
 
         for node in &self.current_line_nodes {
             match &node.read().unwrap().content {
@@ -1155,16 +1888,19 @@ impl<'a> BlockComposer<'a> {
                     let descent = height - ascent;
                     if descent > max_descent { max_descent = descent; }
                 }
+                LayoutNodeType::Block(_) => {
+                    max_box_height = max_box_height.max(margin_box(node).height());
+                }
                 _ => {}
             }
         }
 
-        let line_height = (max_ascent + max_descent) * 1.25;
-        let baseline_offset = max_ascent * 1.25;
+        let baseline_offset = f32::max(max_ascent * 1.25, max_box_height);
+        let line_height = baseline_offset + max_descent * 1.25;
 
         // 2. Get the Block's Absolute Position
-        // We assume self.outer_position is already absolute (set in layout())
-        let block_pos = self.outer_position.unwrap_or(Vec2::ZERO);
+        // The content box is already absolute (set in layout_block())
+        let block_pos = self.content_position;
 
         // Calculate Line's Absolute Position
         // X = Block X
@@ -1178,7 +1914,7 @@ impl<'a> BlockComposer<'a> {
         );
 
         line_node.write().unwrap().position = Some(line_abs_pos);
-        line_node.write().unwrap().size = Some(Vec2::new(self.outer_size.unwrap().x, line_height));
+        line_node.write().unwrap().size = Some(Vec2::new(self.content_width, line_height));
 
         if let LayoutNodeType::Line(l) = &mut line_node.write().unwrap().content {
             l.max_ascent = max_ascent;
@@ -1187,6 +1923,14 @@ impl<'a> BlockComposer<'a> {
 
         // 4. Update Children (Text & Input Nodes) with Absolute Positions
         for child_node in &self.current_line_nodes {
+            if matches!(child_node.read().unwrap().content, LayoutNodeType::Block(_)) {
+                // Inline-blocks were laid out with their margin box at the top left of
+                // the line, and move with their whole subtree.
+                let height = margin_box(child_node).height();
+                child_node.write().unwrap().parent = Some(line_node.clone());
+                translate(child_node, Vec2::new(block_pos.x, line_abs_pos.y + baseline_offset - height));
+                continue;
+            }
             let mut child = child_node.write().unwrap();
 
             // Parent pointer update
@@ -1233,9 +1977,11 @@ impl<'a> BlockComposer<'a> {
     /// This function traverses an `HtmlNode` tree structure in depth-first order.
     /// Depending on the type of each node (element or text), it performs specific actions.
     ///
-    /// - For elements: It opens the tag (`open_tag` method), processes its children recursively,
-    ///   and then closes the tag (`close_tag` method).
-    /// - For text: It splits the text content into words and processes each word using the `word` method.
+    /// - For elements: It opens the tag (`open_tag` method) and processes its children recursively.
+    ///   Elements with `display: none` are skipped, and inline-blocks are laid out as a single
+    ///   box on the line (`inline_block` method).
+    /// - For text: It lays out the text with the `text` method, which handles whitespace according to
+    ///   the node's `white-space` style and processes each word using the `word` method.
     ///
     /// # Arguments
     ///
@@ -1252,10 +1998,9 @@ impl<'a> BlockComposer<'a> {
     /// # Example Workflow
     ///
     /// 1. If the node is an `Element`, extract its tag name and children and invoke
-    ///    `open_tag(tag)` followed by recursive traversal of its children. Finally,
-    ///    invoke `close_tag(tag)`.
-    /// 2. If the node is `Text`, split the text into individual words and process each
-    ///    word using the `word(word)` method.
+    ///    `open_tag(tag)` followed by recursive traversal of its children.
+    /// 2. If the node is `Text`, hand it to `text(text)`, which splits it into words and
+    ///    whitespace and processes each word using the `word(word)` method.
     ///
     /// # Implementation Notes
     ///
@@ -1269,7 +2014,6 @@ impl<'a> BlockComposer<'a> {
     /// This function assumes the presence of the following methods in its scope:
     ///
     /// * `open_tag(tag: String)`: Handles the processing of opening an HTML tag.
-    /// * `close_tag(tag: String)`: Handles the processing of closing an HTML tag.
     /// * `word(word: &str)`: Handles the processing of individual words in text nodes.
     ///
     /// # Parameters of the Enum `Action`
@@ -1301,18 +2045,48 @@ impl<'a> BlockComposer<'a> {
             Action::ProcessElement { tag, children } => {
                 if tag == "input" || tag == "button" {
                     self.input(node_ref);
-                } else {
-                    self.open_tag(tag.clone());
-                    for child in children {
-                        self.recurse(child);
+                    return;
+                }
+                match display(&node_ref) {
+                    Some(Display::None) => {}
+                    Some(Display::InlineBlock | Display::InlineFlex | Display::InlineGrid | Display::InlineTable) => self.inline_block(node_ref),
+                    _ => {
+                        self.open_tag(tag);
+                        for child in children {
+                            self.recurse(child);
+                        }
                     }
-                    self.close_tag(tag);
                 }
             }
             Action::ProcessText(text) => {
-                for word in text.split_whitespace() {
-                    self.word(word, tree.clone());
-                }
+                self.text(&text, tree.clone());
+            }
+        }
+    }
+
+    /// Picks the font for `style`: the first family of its `font-family` that has been
+    /// loaded through `@font-face`, or the built-in sans-serif font.
+    ///
+    /// A web font is used in its bold or italic variant if the page declared one, and
+    /// in its plain variant otherwise. Generic families like `serif` use the built-in font.
+    fn select_font(&mut self, style: &ComputedStyle) {
+        let bold = style.font_weight >= 600;
+        let italic = matches!(style.font_style, FontStyle::Italic | FontStyle::Oblique);
+        self.layout.font_family = "sans".into();
+        self.layout.font_weight = if bold { "bold".into() } else { "".into() };
+        self.layout.font_style = if italic { "italic".into() } else { "".into() };
+        let is_loaded = |name: &str| self.layout.context.fonts(|f|
+            f.definitions().families.contains_key(&FontFamily::Name(Arc::from(name))));
+        for family in &style.font_family {
+            if is_loaded(&web_font_name(family, bold, italic)) {
+                self.layout.font_family = web_font_name(family, false, false);
+                return;
+            }
+            if is_loaded(&web_font_name(family, false, false)) {
+                self.layout.font_family = web_font_name(family, false, false);
+                self.layout.font_weight = "".into();
+                self.layout.font_style = "".into();
+                return;
             }
         }
     }
@@ -1337,6 +2111,34 @@ impl<'a> BlockComposer<'a> {
         self.layout.space_width = space_galley.size().x;
     }
 
+    /// Lays out an inline-block or an inline flex container and places it on the current
+    /// line, like a word: a block laid out on its own, in its specified width or the
+    /// width of its content.
+    ///
+    /// # Notes
+    /// The block is laid out with its margin box at the top left of the line, and moved
+    /// into place by `flush_line` once the line's height is known.
+    fn inline_block(&mut self, node: Arc<RwLock<HtmlNode>>) {
+        let context = self.layout.context.clone();
+        let block = LayoutNode::new_block(node, Some(self.parent_ptr.clone()), context.clone());
+        let containing_block = ContainingBlock { x: 0.0, width: self.content_width, height: None };
+        LayoutNode::layout_block(&block, &context, containing_block, 0.0, CollapsedMargin::default(), BlockRole::InlineBlock);
+        let width = margin_box(&block).width();
+
+        let space = if self.pending_space { self.layout.space_width } else { 0.0 };
+        self.pending_space = false;
+        if !self.current_line_nodes.is_empty()
+            && self.layout.cursor_x + space + width > self.content_width {
+            self.flush_line();
+        } else {
+            self.layout.cursor_x += space;
+        }
+
+        translate(&block, Vec2::new(self.layout.cursor_x, 0.0));
+        self.current_line_nodes.push(block);
+        self.layout.cursor_x += width;
+    }
+
     fn input(&mut self, node: Arc<RwLock<HtmlNode>>) {
         let node_borrow = node.read().unwrap();
         let text = match &node_borrow.node_type {
@@ -1354,11 +2156,7 @@ impl<'a> BlockComposer<'a> {
             _ => String::new(),
         };
 
-        let color_str = node_borrow.style.get("color").unwrap_or(&"black".to_string()).clone();
-        let color = csscolorparser::parse(&color_str)
-            .ok()
-            .and_then(|c| Color32::from_hex(&c.to_css_hex()).ok())
-            .unwrap_or(Color32::BLACK);
+        let color = node_borrow.style.color;
 
         self.update_font();
         let galley = self.layout.context.fonts_mut(|f| {
@@ -1368,27 +2166,108 @@ impl<'a> BlockComposer<'a> {
         let input_width = INPUT_WIDTH_PX;
         let input_height = galley.size().y + 4.0; // Add some padding
 
-        if self.layout.cursor_x + input_width > self.outer_size.unwrap().x {
+        let space = if self.pending_space { self.layout.space_width } else { 0.0 };
+        self.pending_space = false;
+        if !self.current_line_nodes.is_empty()
+            && self.layout.cursor_x + space + input_width > self.content_width {
             self.flush_line();
+        } else {
+            self.layout.cursor_x += space;
         }
 
         let input_node = Arc::new(RwLock::new(LayoutNode {
             node: node.clone(),
             parent: Some(self.parent_ptr.clone()),
             children: vec![],
-            previous: None,
             content: LayoutNodeType::Input(InputLayout { galley }),
             position: Some(Vec2::new(self.layout.cursor_x, 0.0)),
             size: Some(Vec2::new(input_width, input_height)),
         }));
 
         self.current_line_nodes.push(input_node);
-        self.layout.cursor_x += input_width + self.layout.space_width;
+        self.layout.cursor_x += input_width;
     }
 }
 
+/// Paints one side of a border.
+///
+/// # Parameters
+/// - `cmds`: Where to add the draw commands.
+/// - `strip`: The area of the side, whose thickness is the border's width.
+/// - `side`: Which side it is, which decides the shading of 3D styles.
+/// - `style`: The border style; never `none` or `hidden`.
+/// - `color`: The border color.
+fn paint_border_side(cmds: &mut Vec<DrawCommand>, strip: Rect, side: Side, style: BorderStyle, color: Color32) {
+    let horizontal = matches!(side, Side::Top | Side::Bottom);
+    let thickness = if horizontal { strip.height() } else { strip.width() };
+    // The part of the strip from `from` to `to`, as fractions of its thickness from the
+    // outer edge of the border.
+    let part = |from: f32, to: f32| match side {
+        Side::Top => Rect::from_min_max(
+            Pos2::new(strip.min.x, strip.min.y + thickness * from),
+            Pos2::new(strip.max.x, strip.min.y + thickness * to)),
+        Side::Bottom => Rect::from_min_max(
+            Pos2::new(strip.min.x, strip.max.y - thickness * to),
+            Pos2::new(strip.max.x, strip.max.y - thickness * from)),
+        Side::Left => Rect::from_min_max(
+            Pos2::new(strip.min.x + thickness * from, strip.min.y),
+            Pos2::new(strip.min.x + thickness * to, strip.max.y)),
+        Side::Right => Rect::from_min_max(
+            Pos2::new(strip.max.x - thickness * to, strip.min.y),
+            Pos2::new(strip.max.x - thickness * from, strip.max.y)),
+    };
+    let mut fill = |rect: Rect, color: Color32| {
+        if rect.width() > 0.0 && rect.height() > 0.0 {
+            cmds.push(DrawCommand::DrawRect(DrawRect { rect, color, radius: 0.0 }));
+        }
+    };
+
+    let dark = shade(color, Color32::BLACK);
+    let light = shade(color, Color32::WHITE);
+    let top_left = matches!(side, Side::Top | Side::Left);
+    match style {
+        BorderStyle::None | BorderStyle::Hidden => {}
+        BorderStyle::Solid => fill(strip, color),
+        BorderStyle::Double if thickness >= 3.0 => {
+            fill(part(0.0, 1.0 / 3.0), color);
+            fill(part(2.0 / 3.0, 1.0), color);
+        }
+        BorderStyle::Double => fill(strip, color),
+        BorderStyle::Dotted | BorderStyle::Dashed => {
+            let dash = if style == BorderStyle::Dotted { thickness } else { thickness * 3.0 };
+            let (start, end) = if horizontal { (strip.min.x, strip.max.x) } else { (strip.min.y, strip.max.y) };
+            let mut at = start;
+            while at < end {
+                let to = (at + dash).min(end);
+                let rect = if horizontal {
+                    Rect::from_min_max(Pos2::new(at, strip.min.y), Pos2::new(to, strip.max.y))
+                } else {
+                    Rect::from_min_max(Pos2::new(strip.min.x, at), Pos2::new(strip.max.x, to))
+                };
+                fill(rect, color);
+                at += dash * 2.0;
+            }
+        }
+        BorderStyle::Inset => fill(strip, if top_left { dark } else { light }),
+        BorderStyle::Outset => fill(strip, if top_left { light } else { dark }),
+        BorderStyle::Groove | BorderStyle::Ridge => {
+            let carved = style == BorderStyle::Groove;
+            let (outer, inner) = if carved == top_left { (dark, light) } else { (light, dark) };
+            fill(part(0.0, 0.5), outer);
+            fill(part(0.5, 1.0), inner);
+        }
+    }
+}
+
+/// Mixes `color` halfway with `towards`, for the shaded sides of 3D border styles.
+fn shade(color: Color32, towards: Color32) -> Color32 {
+    let mix = |a: u8, b: u8| ((a as u16 + b as u16) / 2) as u8;
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    Color32::from_rgba_unmultiplied(mix(r, towards.r()), mix(g, towards.g()), mix(b, towards.b()), a)
+}
+
 #[derive(Debug)]
-pub(crate) struct InputLayout {
+pub struct InputLayout {
     pub galley: Arc<Galley>,
 }
 
@@ -0,0 +1,601 @@
+//! The flex formatting context of CSS Flexible Box Layout: how a flex container sizes
+//! its items along its main axis, breaks them into lines and aligns them. See
+//! `layout_flex`.
+//!
+//! Items are laid out as blocks by `LayoutNode::layout_block`, with the role
+//! `BlockRole::Item`, then moved into place with `translate`. They stay children of the
+//! container's layout node, so that painting and hit testing treat them as any block.
+
+use super::{anonymous_style, distribute, resolve_length, translate, ChildBox, ContainingBlock, ContentBox, ItemBlock, LayoutMode, LayoutNode, LayoutNodeType};
+use crate::node::HtmlNode;
+use crate::style::{Align, BoxSizing, ComputedStyle, Edges, FlexBasis, FlexWrap, LengthPercentage, Side};
+use egui::{Context, Vec2};
+use std::sync::{Arc, RwLock};
+
+/// The axes of a flex container, as the sides of a box that they start and end at.
+///
+/// # Fields
+/// - `column`: Whether the main axis is vertical.
+/// - `main_start`, `main_end`: Where the main axis starts and ends; from the right for
+///   `row-reverse` and from the bottom for `column-reverse`.
+/// - `cross_start`, `cross_end`: Where the cross axis starts and ends; reversed by
+///   `flex-wrap: wrap-reverse`.
+#[derive(Clone, Copy)]
+struct Axes {
+    column: bool,
+    main_start: Side,
+    main_end: Side,
+    cross_start: Side,
+    cross_end: Side,
+}
+
+impl Axes {
+    /// Returns the axes of a flex container with this style.
+    fn new(style: &ComputedStyle) -> Axes {
+        let column = style.flex_direction.is_column();
+        let (mut main_start, mut main_end) = if column { (Side::Top, Side::Bottom) } else { (Side::Left, Side::Right) };
+        if style.flex_direction.is_reverse() {
+            (main_start, main_end) = (main_end, main_start);
+        }
+        let (mut cross_start, mut cross_end) = if column { (Side::Left, Side::Right) } else { (Side::Top, Side::Bottom) };
+        if style.flex_wrap == FlexWrap::WrapReverse {
+            (cross_start, cross_end) = (cross_end, cross_start);
+        }
+        Axes { column, main_start, main_end, cross_start, cross_end }
+    }
+
+    /// Returns the size of `size` along the cross axis.
+    fn cross(&self, size: Vec2) -> f32 {
+        if self.column { size.x } else { size.y }
+    }
+
+    /// Returns the sum of the values of `edges` at both ends of the main axis.
+    fn main_sum(&self, edges: &Edges<f32>) -> f32 {
+        if self.column { edges.vertical() } else { edges.horizontal() }
+    }
+
+    /// Returns the sum of the values of `edges` at both ends of the cross axis.
+    fn cross_sum(&self, edges: &Edges<f32>) -> f32 {
+        if self.column { edges.horizontal() } else { edges.vertical() }
+    }
+
+    /// Returns the vector whose main and cross components are `main` and `cross`.
+    fn vec(&self, main: f32, cross: f32) -> Vec2 {
+        if self.column { Vec2::new(cross, main) } else { Vec2::new(main, cross) }
+    }
+}
+
+/// A flex item being laid out.
+///
+/// # Fields
+/// - `block`: The item's block, with its style, margins, borders and paddings.
+/// - `base_size`: The flex base size: the size of the content box along the main axis,
+///   before growing or shrinking.
+/// - `min_main`, `max_main`: The limits of the content box's size along the main axis.
+/// - `target`: The size of the content box along the main axis: the base size clamped
+///   by the limits at first, then as the free space is shared.
+/// - `frozen`: Whether `target` is final, while flexible lengths are resolved.
+struct FlexItem {
+    block: ItemBlock,
+    base_size: f32,
+    min_main: f32,
+    max_main: f32,
+    target: f32,
+    frozen: bool,
+}
+
+impl FlexItem {
+    /// The size of the margin box along the main axis, once `target` is final.
+    fn outer_main(&self, axes: Axes) -> f32 {
+        self.target + axes.main_sum(&self.block.edges) + axes.main_sum(&self.block.margin)
+    }
+
+    /// The size of the margin box along the cross axis, once laid out.
+    fn outer_cross(&self, axes: Axes) -> f32 {
+        axes.cross(self.block.border_box()) + axes.cross_sum(&self.block.margin)
+    }
+
+    /// The `align-self` of the item, with `auto` resolved to the container's
+    /// `align-items`.
+    fn align(&self, container_style: &ComputedStyle) -> Align {
+        self.block.style.align_self.unwrap_or(container_style.align_items)
+    }
+
+    /// Whether the item stretches to fill its line on the cross axis: it is aligned with
+    /// `stretch`, and neither its size nor its margins on the cross axis are `auto`.
+    fn stretches(&self, container_style: &ComputedStyle, axes: Axes) -> bool {
+        let cross_size = if axes.column { &self.block.style.width } else { &self.block.style.height };
+        self.align(container_style) == Align::Stretch
+            && cross_size.is_none()
+            && !*self.block.auto_margin.get(axes.cross_start)
+            && !*self.block.auto_margin.get(axes.cross_end)
+    }
+
+    /// Returns the size of the content box along the cross axis that fills `line_cross`,
+    /// clamped by the item's `min-*` and `max-*` sizes on that axis.
+    fn stretched_cross(&self, line_cross: f32, axes: Axes, container: &ContentBox) -> f32 {
+        let (min, max, base) = if axes.column {
+            (&self.block.style.min_width, &self.block.style.max_width, Some(container.width))
+        } else {
+            (&self.block.style.min_height, &self.block.style.max_height, container.height)
+        };
+        let sizing = self.block.sizing(axes.cross_sum(&self.block.edges));
+        let min = resolve_length(min, base).map_or(0.0, |min| (min - sizing).max(0.0));
+        let max = max.as_ref().and_then(|max| resolve_length(max, base)).map_or(f32::INFINITY, |max| (max - sizing).max(0.0));
+        (line_cross - axes.cross_sum(&self.block.margin) - axes.cross_sum(&self.block.edges)).min(max).max(min)
+    }
+}
+
+/// Lays out the items of a flex container, following the flex layout algorithm of CSS
+/// Flexible Box Layout.
+///
+/// # Parameters
+/// - `container`: The container's layout node. The items' blocks are added to its
+///   children, in the order they are placed.
+/// - `context`: The egui context, for measuring text.
+/// - `style`: The container's style.
+/// - `items`: The container's items, in document order; see `LayoutNode::layout_mode`.
+/// - `content_box`: The container's content box.
+///
+/// # Returns
+/// The height of the content: the lines and the gaps between them in a row container,
+/// and the size of the main axis in a column container.
+///
+/// # Behavior
+/// 1. Items are placed by increasing `order`, then in document order.
+/// 2. Each item's flex base size is its `flex-basis`, or its `width` (`height` in a
+///    column) for `flex-basis: auto`, or the size of its content if that is `auto` as
+///    well: its max-content width in a row, its height at its cross size in a column.
+///    Its hypothetical main size is the base size clamped by its `min-*` and `max-*`
+///    sizes.
+/// 3. With `flex-wrap: wrap`, items go on a new line when the next one would overflow
+///    the main axis. A column container without a height only wraps at its
+///    `max-height`.
+/// 4. On each line, the free space is shared between the items in proportion to their
+///    `flex-grow`, or if the items overflow, taken from them in proportion to their
+///    `flex-shrink` times their base size. An item that reaches its `min-*` or `max-*`
+///    size is frozen there and the rest is shared again.
+/// 5. Each line is as high (wide in a column) as its largest item, or as the container
+///    if it has a single line and a definite size on the cross axis. Lines stack from
+///    the cross start, with `row-gap` (`column-gap` in a column) between them.
+/// 6. On each line, positive free space goes first to the `auto` margins on the main
+///    axis, or else is distributed by `justify-content`. Items are separated by
+///    `column-gap` (`row-gap` in a column).
+/// 7. On the cross axis, `auto` margins take the free space of the line, or else each
+///    item is aligned by its `align-self`. Stretched items fill their line.
+///
+/// # Notes
+/// - `align-content` is not supported: extra space on the cross axis of a multi-line
+///   container stays after its last line.
+/// - Baseline alignment aligns the cross start of the items.
+/// - The automatic minimum size of items is `0`, so that items may shrink below the
+///   size of their content.
+pub(super) fn layout_flex(container: &Arc<RwLock<LayoutNode>>,
+                          context: &Context,
+                          style: &ComputedStyle,
+                          items: Vec<ChildBox>,
+                          content_box: ContentBox) -> f32 {
+    // Without items there are no lines: the container is only as high as its height,
+    // or its minimum height.
+    if items.is_empty() {
+        return content_box.height.unwrap_or(content_box.min_height);
+    }
+
+    let axes = Axes::new(style);
+    let containing_block = ContainingBlock { x: 0.0, width: content_box.width, height: content_box.height };
+    let column_gap = style.column_gap.resolve(content_box.width);
+    let row_gap = resolve_length(&style.row_gap, content_box.height).unwrap_or(0.0);
+    let (main_gap, cross_gap) = if axes.column { (row_gap, column_gap) } else { (column_gap, row_gap) };
+
+    let mut items: Vec<FlexItem> = items.into_iter()
+        .map(|child_box| FlexItem {
+            block: ItemBlock::new(child_box, container, context, content_box.width),
+            base_size: 0.0,
+            min_main: 0.0,
+            max_main: f32::INFINITY,
+            target: 0.0,
+            frozen: false,
+        })
+        .collect();
+    items.sort_by_key(|item| item.block.style.order);
+
+    // The flex base sizes and hypothetical main sizes.
+    let single_line = style.flex_wrap == FlexWrap::NoWrap;
+    let main_base = if axes.column { content_box.height } else { Some(content_box.width) };
+    for item in items.iter_mut() {
+        let (main_size, min, max) = if axes.column {
+            (&item.block.style.height, &item.block.style.min_height, &item.block.style.max_height)
+        } else {
+            (&item.block.style.width, &item.block.style.min_width, &item.block.style.max_width)
+        };
+        let sizing = item.block.sizing(axes.main_sum(&item.block.edges));
+        let basis = match &item.block.style.flex_basis {
+            FlexBasis::Length(length) => resolve_length(length, main_base),
+            FlexBasis::Auto => main_size.as_ref().and_then(|size| resolve_length(size, main_base)),
+            FlexBasis::Content => None,
+        }.map(|basis| (basis - sizing).max(0.0));
+        item.min_main = resolve_length(min, main_base).map_or(0.0, |min| (min - sizing).max(0.0));
+        item.max_main = max.as_ref().and_then(|max| resolve_length(max, main_base)).map_or(f32::INFINITY, |max| (max - sizing).max(0.0));
+
+        if axes.column {
+            // The width decides the height of the content, so the item is laid out
+            // now, stretched to the container if it will be.
+            let width = (single_line && item.stretches(style, axes))
+                .then(|| item.stretched_cross(content_box.width, axes, &content_box));
+            item.block.lay_out(container, context, containing_block, width);
+        }
+        item.base_size = match basis {
+            Some(basis) => basis,
+            None if axes.column => (item.block.border_box().y - item.block.edges.vertical()).max(0.0),
+            None => LayoutNode::max_content_width(&item.block.node, item.block.anonymous_content.clone(), context),
+        };
+        item.target = item.base_size.min(item.max_main).max(item.min_main);
+    }
+
+    // Break the items into lines, by their hypothetical main sizes.
+    let available = match (axes.column, content_box.height) {
+        (false, _) => content_box.width,
+        (true, Some(height)) => height,
+        (true, None) => content_box.max_height,
+    };
+    let mut lines: Vec<Vec<usize>> = vec![];
+    let mut line_main = 0.0;
+    for (index, item) in items.iter().enumerate() {
+        let outer = item.outer_main(axes);
+        match lines.last_mut() {
+            Some(line) if single_line || line_main + main_gap + outer <= available => {
+                line.push(index);
+                line_main += main_gap + outer;
+            }
+            _ => {
+                lines.push(vec![index]);
+                line_main = outer;
+            }
+        }
+    }
+
+    let main_size = match (axes.column, content_box.height) {
+        (false, _) => content_box.width,
+        (true, Some(height)) => height,
+        (true, None) => lines.iter()
+            .map(|line| hypothetical_line_size(&items, line, axes, main_gap))
+            .fold(0.0, f32::max)
+            .min(content_box.max_height)
+            .max(content_box.min_height),
+    };
+    for line in &lines {
+        resolve_flexible_lengths(&mut items, line, main_size - main_gap * (line.len() - 1) as f32, axes);
+    }
+
+    // The cross sizes: lay out each item at its main size.
+    for item in items.iter_mut() {
+        if axes.column {
+            let border_box = item.block.border_box();
+            item.block.layout.write().unwrap().size = Some(Vec2::new(border_box.x, item.target + item.block.edges.vertical()));
+        } else {
+            item.block.lay_out(container, context, containing_block, Some(item.target));
+        }
+    }
+    let definite_cross = if axes.column {
+        Some(content_box.width)
+    } else {
+        content_box.height
+    };
+    let mut line_crosses: Vec<f32> = lines.iter()
+        .map(|line| line.iter().map(|&index| items[index].outer_cross(axes)).fold(0.0, f32::max))
+        .collect();
+    if single_line {
+        line_crosses[0] = match definite_cross {
+            Some(cross) => cross,
+            None => line_crosses[0].min(content_box.max_height).max(content_box.min_height),
+        };
+    }
+    let cross_total = line_crosses.iter().sum::<f32>() + cross_gap * lines.len().saturating_sub(1) as f32;
+    let cross_size = definite_cross.unwrap_or(cross_total.min(content_box.max_height).max(content_box.min_height));
+
+    // Stretch the items that fill their line. In a column, the width decides the
+    // height of the content, so the item is laid out again at its new width.
+    for (line, &line_cross) in lines.iter().zip(&line_crosses) {
+        for &index in line {
+            let item = &mut items[index];
+            if !item.stretches(style, axes) {
+                continue;
+            }
+            let cross = item.stretched_cross(line_cross, axes, &content_box);
+            if axes.column {
+                if !single_line {
+                    item.block.lay_out(container, context, containing_block, Some(cross));
+                }
+                let size = Vec2::new(cross + item.block.edges.horizontal(), item.target + item.block.edges.vertical());
+                item.block.layout.write().unwrap().size = Some(size);
+            } else {
+                let width = item.block.border_box().x;
+                item.block.layout.write().unwrap().size = Some(Vec2::new(width, cross + item.block.edges.vertical()));
+            }
+        }
+    }
+
+    // Place the items in their lines, and the lines in the container.
+    let mut line_start = 0.0;
+    for (line, &line_cross) in lines.iter().zip(&line_crosses) {
+        let gaps = main_gap * (line.len() - 1) as f32;
+        let mut free = main_size - gaps - line.iter().map(|&index| items[index].outer_main(axes)).sum::<f32>();
+        let auto_margins = line.iter()
+            .map(|&index| [axes.main_start, axes.main_end].iter().filter(|&&side| *items[index].block.auto_margin.get(side)).count())
+            .sum::<usize>();
+        if free > 0.0 && auto_margins > 0 {
+            let share = free / auto_margins as f32;
+            for &index in line {
+                for side in [axes.main_start, axes.main_end] {
+                    if *items[index].block.auto_margin.get(side) {
+                        *items[index].block.margin.get_mut(side) += share;
+                    }
+                }
+            }
+            free = 0.0;
+        }
+        let (mut cursor, between) = distribute(style.justify_content, free, line.len());
+
+        // The cross start of the line, from the top or left of the content box.
+        let line_offset = if style.flex_wrap == FlexWrap::WrapReverse {
+            cross_size - line_start - line_cross
+        } else {
+            line_start
+        };
+        for &index in line {
+            let item = &mut items[index];
+            let outer_main = item.outer_main(axes);
+            let main_offset = if matches!(axes.main_start, Side::Right | Side::Bottom) {
+                main_size - cursor - outer_main
+            } else {
+                cursor
+            };
+            cursor += outer_main + main_gap + between;
+
+            let mut cross_free = line_cross - item.outer_cross(axes);
+            let auto_start = *item.block.auto_margin.get(axes.cross_start);
+            let auto_end = *item.block.auto_margin.get(axes.cross_end);
+            if auto_start || auto_end {
+                if cross_free > 0.0 {
+                    let share = cross_free / (auto_start as u8 + auto_end as u8) as f32;
+                    for (side, auto) in [(axes.cross_start, auto_start), (axes.cross_end, auto_end)] {
+                        if auto {
+                            *item.block.margin.get_mut(side) += share;
+                        }
+                    }
+                }
+                cross_free = 0.0;
+            }
+            let from_cross_start = match item.align(style) {
+                Align::Stretch | Align::Start | Align::Baseline => 0.0,
+                Align::End => cross_free,
+                Align::Center => cross_free / 2.0,
+            };
+            let cross_offset = if matches!(axes.cross_start, Side::Right | Side::Bottom) {
+                line_offset + cross_free - from_cross_start
+            } else {
+                line_offset + from_cross_start
+            };
+
+            // The item was laid out with its margin box at the origin.
+            let margin_box_origin = content_box.position + axes.vec(main_offset, cross_offset);
+            let border_box_origin = margin_box_origin + Vec2::new(item.block.margin.left, item.block.margin.top);
+            let delta = border_box_origin - item.block.layout.read().unwrap().position.unwrap_or(Vec2::ZERO);
+            translate(&item.block.layout, delta);
+            if let LayoutNodeType::Block(block_layout) = &mut item.block.layout.write().unwrap().content {
+                block_layout.margin = item.block.margin;
+            }
+        }
+        line_start += line_cross + cross_gap;
+    }
+
+    container.write().unwrap().children.extend(items.iter().map(|item| item.block.layout.clone()));
+    if axes.column { main_size } else { cross_total }
+}
+
+/// Returns the size of a line along the main axis before its items grow or shrink.
+fn hypothetical_line_size(items: &[FlexItem], line: &[usize], axes: Axes, gap: f32) -> f32 {
+    line.iter().map(|&index| items[index].outer_main(axes)).sum::<f32>() + gap * line.len().saturating_sub(1) as f32
+}
+
+/// Resolves the main sizes of the items of a line, sharing the free space between
+/// them by their `flex-grow` or `flex-shrink`, as in the "Resolving Flexible Lengths"
+/// section of CSS Flexible Box Layout.
+///
+/// # Parameters
+/// - `items`: Every item of the container; the `target` of the line's items is set.
+/// - `line`: The positions of the line's items in `items`.
+/// - `space`: The size of the line along the main axis, less the gaps.
+/// - `axes`: The container's axes.
+fn resolve_flexible_lengths(items: &mut [FlexItem], line: &[usize], space: f32, axes: Axes) {
+    // The sums are kept in `f64` so that huge base sizes do not overflow to infinity.
+    let outer = |item: &FlexItem| (axes.main_sum(&item.block.edges) + axes.main_sum(&item.block.margin)) as f64;
+    let hypothetical: f64 = line.iter().map(|&index| items[index].outer_main(axes) as f64).sum();
+    let growing = hypothetical < space as f64;
+    let factor = |item: &FlexItem| if growing { item.block.style.flex_grow } else { item.block.style.flex_shrink };
+
+    // Items that cannot flex keep their hypothetical size.
+    for &index in line {
+        let item = &mut items[index];
+        let hypothetical = item.target;
+        item.frozen = factor(item) == 0.0
+            || (growing && item.base_size > hypothetical)
+            || (!growing && item.base_size < hypothetical);
+    }
+    let free_space = |items: &[FlexItem]| space as f64 - line.iter()
+        .map(|&index| {
+            let item = &items[index];
+            outer(item) + if item.frozen { item.target } else { item.base_size } as f64
+        })
+        .sum::<f64>();
+    let initial_free = free_space(items);
+
+    while line.iter().any(|&index| !items[index].frozen) {
+        let unfrozen: Vec<usize> = line.iter().copied().filter(|&index| !items[index].frozen).collect();
+        let mut free = free_space(items);
+        let factors: f64 = unfrozen.iter().map(|&index| factor(&items[index]) as f64).sum();
+        if factors < 1.0 && (initial_free * factors).abs() < free.abs() {
+            free = initial_free * factors;
+        }
+
+        // Share the free space, then clamp each item by its limits.
+        let scaled_shrinks: f64 = unfrozen.iter()
+            .map(|&index| items[index].block.style.flex_shrink as f64 * items[index].base_size as f64)
+            .sum();
+        let mut violation = 0.0;
+        let mut clamped = vec![];
+        for &index in &unfrozen {
+            let item = &items[index];
+            let base_size = item.base_size as f64;
+            let target = if growing {
+                base_size + free * item.block.style.flex_grow as f64 / factors
+            } else if scaled_shrinks > 0.0 {
+                base_size + free * item.block.style.flex_shrink as f64 * base_size / scaled_shrinks
+            } else {
+                base_size
+            };
+            let limited = target.min(item.max_main as f64).max(item.min_main as f64);
+            violation += limited - target;
+            clamped.push((index, limited, limited - target));
+        }
+
+        // Freeze every item if the limits cancel out, and otherwise only the items
+        // clamped in the direction of the total. A total that is not a number freezes
+        // every item too, as does a pass that would freeze none, so the loop ends.
+        let freeze_all = !f64::is_finite(violation) || violation == 0.0;
+        let mut froze_any = false;
+        for &(index, limited, adjustment) in &clamped {
+            let item = &mut items[index];
+            item.target = limited as f32;
+            item.frozen = freeze_all
+                || (violation > 0.0 && adjustment > 0.0)
+                || (violation < 0.0 && adjustment < 0.0);
+            froze_any |= item.frozen;
+        }
+        if !froze_any {
+            for (index, _, _) in clamped {
+                items[index].frozen = true;
+            }
+        }
+    }
+}
+
+/// Returns the max-content width of a flex container: in a row, the sum of the widths
+/// of its items' margin boxes at their max-content width and of the gaps between them;
+/// in a column, the widest of them.
+///
+/// # Notes
+/// Items with a `flex-basis` or `width` in pixels count at that size. Percentages of
+/// the container's width, which depends on this, count as `0`.
+pub(super) fn max_content_width(node: &Arc<RwLock<HtmlNode>>, context: &Context) -> f32 {
+    let LayoutMode::Flex(items) = LayoutNode::layout_mode(node) else {
+        return 0.0;
+    };
+    let style = node.read().unwrap().style.clone();
+    let column = style.flex_direction.is_column();
+    let mut widths = vec![];
+    for child_box in items {
+        let (item_node, anonymous_content, item_style) = match child_box {
+            ChildBox::Block(item) => {
+                let item_style = item.read().unwrap().style.clone();
+                (item, None, item_style)
+            }
+            ChildBox::Anonymous(content) => (node.clone(), Some(content), anonymous_style()),
+        };
+        let padding = item_style.padding.map(|padding| resolve_length(padding, None).unwrap_or(0.0));
+        let edges = padding.horizontal() + item_style.border_width.horizontal();
+        let sizing = match item_style.box_sizing {
+            BoxSizing::ContentBox => 0.0,
+            BoxSizing::BorderBox => edges,
+        };
+        let specified = match (&item_style.flex_basis, &item_style.width) {
+            (FlexBasis::Length(LengthPercentage::Px(px)), _) if !column => Some(*px),
+            (FlexBasis::Auto, Some(LengthPercentage::Px(px))) if !column => Some(*px),
+            (_, Some(LengthPercentage::Px(px))) if column => Some(*px),
+            _ => None,
+        };
+        let content = match specified {
+            Some(specified) => (specified - sizing).max(0.0),
+            None => LayoutNode::max_content_width(&item_node, anonymous_content, context),
+        };
+        let min = resolve_length(&item_style.min_width, None).map_or(0.0, |min| (min - sizing).max(0.0));
+        let max = item_style.max_width.as_ref().and_then(|max| resolve_length(max, None)).map_or(f32::INFINITY, |max| (max - sizing).max(0.0));
+        let margin = item_style.margin.map(|margin| match margin {
+            Some(LengthPercentage::Px(px)) => *px,
+            _ => 0.0,
+        });
+        widths.push(content.min(max).max(min) + edges + margin.horizontal());
+    }
+    if column {
+        widths.into_iter().fold(0.0, f32::max)
+    } else {
+        let gap = match style.column_gap {
+            LengthPercentage::Px(px) => px,
+            _ => 0.0,
+        };
+        widths.iter().sum::<f32>() + gap * widths.len().saturating_sub(1) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the axes of a `flex-direction: row` container.
+    fn row() -> Axes {
+        Axes::new(&ComputedStyle::default())
+    }
+
+    /// Returns an item without edges or margins, with its `target` at its hypothetical
+    /// main size, as `layout_flex` leaves it before resolving flexible lengths.
+    fn item(base_size: f32, min_main: f32, max_main: f32, flex_grow: f32, flex_shrink: f32) -> FlexItem {
+        FlexItem {
+            block: ItemBlock::with_style(ComputedStyle { flex_grow, flex_shrink, ..ComputedStyle::default() }),
+            base_size,
+            min_main,
+            max_main,
+            target: base_size.min(max_main).max(min_main),
+            frozen: false,
+        }
+    }
+
+    /// Resolves the flexible lengths of `items` on a single row of size `space`, and
+    /// returns their targets.
+    fn resolved(mut items: Vec<FlexItem>, space: f32) -> Vec<f32> {
+        let line: Vec<usize> = (0..items.len()).collect();
+        resolve_flexible_lengths(&mut items, &line, space, row());
+        items.iter().map(|item| item.target).collect()
+    }
+
+    #[test]
+    fn free_space_is_shared_by_flex_grow() {
+        assert_eq!(resolved(vec![item(0.0, 0.0, f32::INFINITY, 1.0, 1.0), item(0.0, 0.0, f32::INFINITY, 3.0, 1.0)], 400.0), [100.0, 300.0]);
+        // Factors below 1 in total only take that fraction of the free space.
+        assert_eq!(resolved(vec![item(100.0, 0.0, f32::INFINITY, 0.25, 1.0), item(100.0, 0.0, f32::INFINITY, 0.25, 1.0)], 400.0), [150.0, 150.0]);
+        // An item that reaches its maximum is frozen there, and the rest is shared again.
+        assert_eq!(resolved(vec![item(0.0, 0.0, 50.0, 1.0, 1.0), item(0.0, 0.0, f32::INFINITY, 1.0, 1.0)], 300.0), [50.0, 250.0]);
+        // Items that cannot grow keep their hypothetical size.
+        assert_eq!(resolved(vec![item(80.0, 100.0, f32::INFINITY, 1.0, 1.0), item(50.0, 0.0, f32::INFINITY, 0.0, 1.0)], 400.0), [350.0, 50.0]);
+    }
+
+    #[test]
+    fn overflow_is_taken_by_flex_shrink_times_base_size() {
+        assert_eq!(resolved(vec![item(100.0, 0.0, f32::INFINITY, 0.0, 1.0), item(300.0, 0.0, f32::INFINITY, 0.0, 1.0)], 200.0), [50.0, 150.0]);
+        assert_eq!(resolved(vec![item(200.0, 150.0, f32::INFINITY, 0.0, 1.0), item(200.0, 0.0, f32::INFINITY, 0.0, 1.0)], 200.0), [150.0, 50.0]);
+        assert_eq!(resolved(vec![item(200.0, 0.0, f32::INFINITY, 0.0, 0.0), item(200.0, 0.0, f32::INFINITY, 0.0, 1.0)], 200.0), [200.0, 0.0]);
+    }
+
+    #[test]
+    fn degenerate_lines_are_resolved() {
+        assert_eq!(resolved(vec![], 100.0), Vec::<f32>::new());
+        assert_eq!(hypothetical_line_size(&[], &[], row(), 10.0), 0.0);
+        // Zero base sizes cannot shrink, and huge ones shrink to finite sizes rather than
+        // overflowing the shared space.
+        assert_eq!(resolved(vec![item(0.0, 0.0, f32::INFINITY, 0.0, 1.0), item(0.0, 0.0, f32::INFINITY, 0.0, 1.0)], -50.0), [0.0, 0.0]);
+        let huge = resolved(vec![item(f32::MAX, 0.0, f32::INFINITY, 0.0, 1.0), item(f32::MAX, 0.0, f32::INFINITY, 0.0, 1.0)], 100.0);
+        assert!(huge.iter().all(|target| (0.0..=100.0).contains(target)), "{:?}", huge);
+        assert_eq!(resolved(vec![item(0.0, 0.0, f32::INFINITY, f32::MAX, 1.0), item(0.0, 0.0, f32::INFINITY, f32::MAX, 1.0)], 100.0), [50.0, 50.0]);
+        let items = vec![item(10.0, 0.0, f32::INFINITY, 0.0, 1.0), item(20.0, 0.0, f32::INFINITY, 0.0, 1.0)];
+        assert_eq!(hypothetical_line_size(&items, &[0, 1], row(), 5.0), 35.0);
+    }
+}
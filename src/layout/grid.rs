@@ -0,0 +1,818 @@
+//! The grid formatting context of CSS Grid Layout: how a grid container places its
+//! items in rows and columns, and sizes those tracks. See `layout_grid`.
+//!
+//! As in `flex`, items are laid out as blocks by `LayoutNode::layout_block`, with the
+//! role `BlockRole::Item`, then moved into their grid areas with `translate`. They stay
+//! children of the container's layout node, so that painting and hit testing treat
+//! them as any block.
+
+use super::{distribute, resolve_length, translate, ChildBox, ContainingBlock, ContentBox, ItemBlock, LayoutMode, LayoutNode, LayoutNodeType};
+use crate::css_grid::{GridLine, TrackBreadth, TrackSize, MAX_TRACKS};
+use crate::node::HtmlNode;
+use crate::style::{Align, ComputedStyle, JustifyContent};
+use egui::{Context, Vec2};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, RwLock};
+
+/// A grid item, placed in the grid.
+///
+/// # Fields
+/// - `block`: The item's block, with its style, margins, borders and paddings.
+/// - `rows`, `columns`: The tracks of its grid area, counted from `0`.
+struct GridItem {
+    block: ItemBlock,
+    rows: Range<usize>,
+    columns: Range<usize>,
+}
+
+impl GridItem {
+    /// Returns the min-content and max-content contributions of the item to the width
+    /// of its columns: the width of its margin box at its min-content and max-content
+    /// widths, or at its `width` if that is in pixels.
+    fn width_contributions(&self, context: &Context) -> (f32, f32) {
+        let style = &self.block.style;
+        let sizing = self.block.sizing(self.block.edges.horizontal());
+        let (min_content, max_content) = match style.width.as_ref().and_then(|width| resolve_length(width, None)) {
+            Some(width) => ((width - sizing).max(0.0), (width - sizing).max(0.0)),
+            None => (
+                LayoutNode::min_content_width(&self.block.node, self.block.anonymous_content.clone(), context),
+                LayoutNode::max_content_width(&self.block.node, self.block.anonymous_content.clone(), context),
+            ),
+        };
+        let min = resolve_length(&style.min_width, None).map_or(0.0, |min| (min - sizing).max(0.0));
+        let max = style.max_width.as_ref().and_then(|max| resolve_length(max, None)).map_or(f32::INFINITY, |max| (max - sizing).max(0.0));
+        let outer = self.block.edges.horizontal() + self.block.margin.horizontal();
+        (min_content.min(max).max(min) + outer, max_content.min(max).max(min) + outer)
+    }
+}
+
+/// Where an item goes along one axis of the grid, before auto-placement.
+#[derive(Clone, Debug)]
+enum Position {
+    /// Between these lines, counted from `0`.
+    Definite(Range<usize>),
+    /// Across so many tracks, wherever auto-placement puts it.
+    Auto(usize),
+}
+
+/// What an item contributes to the size of the tracks it spans.
+///
+/// # Fields
+/// - `tracks`: The tracks it spans.
+/// - `min`, `max`: Its min-content and max-content contributions: the size of its
+///   margin box along the tracks' axis.
+struct Contribution {
+    tracks: Range<usize>,
+    min: f32,
+    max: f32,
+}
+
+/// Lays out the items of a grid container, following CSS Grid Layout.
+///
+/// # Parameters
+/// - `container`: The container's layout node. The items' blocks are added to its
+///   children, in the order they are placed.
+/// - `context`: The egui context, for measuring text.
+/// - `style`: The container's style.
+/// - `items`: The container's items, in document order; see `LayoutNode::layout_mode`.
+/// - `content_box`: The container's content box.
+///
+/// # Returns
+/// The height of the content: the rows and the gaps between them.
+///
+/// # Behavior
+/// 1. The explicit grid has the tracks of `grid-template-columns` and
+///    `grid-template-rows`, with `repeat(auto-fill, ...)` and `repeat(auto-fit, ...)`
+///    repeated as many times as fit, and at least as many as `grid-template-areas`
+///    has columns and rows.
+/// 2. Items are placed by increasing `order`, then in document order (see
+///    `place_items`), and the grid grows implicit tracks, sized by
+///    `grid-auto-columns` and `grid-auto-rows`, to hold them. The repetitions of
+///    `auto-fit` that hold no item collapse, along with their gaps.
+/// 3. Columns are sized from the min-content and max-content widths of their items
+///    (see `size_tracks`), then offset by `justify-content`.
+/// 4. Each item is laid out at the width of its grid area if it stretches, or shrinks
+///    to fit it otherwise. Rows are then sized from the heights of their items.
+/// 5. In its grid area, each item's `auto` margins take the free space, or else it is
+///    placed by `justify-self` and `align-self`. Stretched items fill their area.
+///
+/// # Notes
+/// - Lines before the explicit grid, such as `-5` in a grid of three columns, are
+///   clamped to its first line, so no implicit track is added before it.
+/// - `align-content` is not supported: rows start at the top of the content box, and
+///   `auto` rows stretch to fill a container with a definite height.
+/// - Baseline alignment aligns the start of the items.
+/// - Percentage margins and paddings refer to the width of the container's content box
+///   rather than to the grid area.
+pub(super) fn layout_grid(container: &Arc<RwLock<LayoutNode>>,
+                          context: &Context,
+                          style: &ComputedStyle,
+                          items: Vec<ChildBox>,
+                          content_box: ContentBox) -> f32 {
+    let column_gap = style.column_gap.resolve(content_box.width);
+    let row_gap = resolve_length(&style.row_gap, content_box.height).unwrap_or(0.0);
+    let (column_templates, repeated_columns) = style.grid_template_columns.expand(Some(content_box.width), column_gap);
+    let max_height = content_box.max_height.is_finite().then_some(content_box.max_height);
+    let (row_templates, repeated_rows) = style.grid_template_rows.expand(content_box.height.or(max_height), row_gap);
+
+    let blocks = items.into_iter()
+        .map(|child_box| ItemBlock::new(child_box, container, context, content_box.width))
+        .collect();
+    let mut items = place_items(style, blocks, row_templates.len(), column_templates.len());
+
+    // The columns, from the widths of their items.
+    let column_count = items.iter().map(|item| item.columns.end).fold(explicit_count(style, false, column_templates.len()), usize::max);
+    let columns = tracks(&column_templates, &style.grid_auto_columns, column_count);
+    let fit = style.grid_template_columns.auto_repeat.as_ref().is_some_and(|repeat| repeat.fit);
+    let collapsed_columns = collapsed(column_count, &repeated_columns, fit, items.iter().map(|item| &item.columns));
+    let contributions: Vec<Contribution> = items.iter()
+        .map(|item| {
+            let (min, max) = item.width_contributions(context);
+            Contribution { tracks: item.columns.clone(), min, max }
+        })
+        .collect();
+    let stretch_columns = style.justify_content == JustifyContent::Normal;
+    let column_sizes = size_tracks(&columns, &collapsed_columns, &contributions, Some(content_box.width), column_gap, stretch_columns);
+    let free = content_box.width - total_size(&column_sizes, &collapsed_columns, column_gap);
+    let (column_offset, between) = distribute(style.justify_content, free, collapsed_columns.iter().filter(|c| !**c).count());
+    let column_starts = track_starts(&column_sizes, &collapsed_columns, column_gap + between);
+
+    // The rows, from the heights of their items laid out in their columns.
+    for item in items.iter_mut() {
+        let area_width = area_size(&column_starts, &column_sizes, &item.columns);
+        let justify = item.block.style.justify_self.unwrap_or(style.justify_items);
+        let stretches = justify == Align::Stretch
+            && item.block.style.width.is_none()
+            && !item.block.auto_margin.left
+            && !item.block.auto_margin.right;
+        let width = stretches
+            .then(|| (area_width - item.block.margin.horizontal() - item.block.edges.horizontal()).max(0.0));
+        let containing_block = ContainingBlock { x: 0.0, width: area_width, height: None };
+        item.block.lay_out(container, context, containing_block, width);
+    }
+    let row_count = items.iter().map(|item| item.rows.end).fold(explicit_count(style, true, row_templates.len()), usize::max);
+    let rows = tracks(&row_templates, &style.grid_auto_rows, row_count);
+    let fit = style.grid_template_rows.auto_repeat.as_ref().is_some_and(|repeat| repeat.fit);
+    let collapsed_rows = collapsed(row_count, &repeated_rows, fit, items.iter().map(|item| &item.rows));
+    let contributions: Vec<Contribution> = items.iter()
+        .map(|item| {
+            let outer = item.block.border_box().y + item.block.margin.vertical();
+            Contribution { tracks: item.rows.clone(), min: outer, max: outer }
+        })
+        .collect();
+    let row_sizes = size_tracks(&rows, &collapsed_rows, &contributions, content_box.height, row_gap, content_box.height.is_some());
+    let row_starts = track_starts(&row_sizes, &collapsed_rows, row_gap);
+
+    // Place each item in its grid area.
+    for item in items.iter_mut() {
+        let area = Vec2::new(area_size(&column_starts, &column_sizes, &item.columns), area_size(&row_starts, &row_sizes, &item.rows));
+        let align = item.block.style.align_self.unwrap_or(style.align_items);
+        if align == Align::Stretch
+            && item.block.style.height.is_none()
+            && !item.block.auto_margin.top
+            && !item.block.auto_margin.bottom {
+            let sizing = item.block.sizing(item.block.edges.vertical());
+            let min = resolve_length(&item.block.style.min_height, content_box.height).map_or(0.0, |min| (min - sizing).max(0.0));
+            let max = item.block.style.max_height.as_ref()
+                .and_then(|max| resolve_length(max, content_box.height))
+                .map_or(f32::INFINITY, |max| (max - sizing).max(0.0));
+            let height = (area.y - item.block.margin.vertical() - item.block.edges.vertical()).min(max).max(min);
+            let width = item.block.border_box().x;
+            item.block.layout.write().unwrap().size = Some(Vec2::new(width, height + item.block.edges.vertical()));
+        }
+
+        let justify = item.block.style.justify_self.unwrap_or(style.justify_items);
+        let border_box = item.block.border_box();
+        let x = align_in_area(&mut item.block.margin.left, &mut item.block.margin.right,
+                              (item.block.auto_margin.left, item.block.auto_margin.right), justify, area.x - border_box.x);
+        let y = align_in_area(&mut item.block.margin.top, &mut item.block.margin.bottom,
+                              (item.block.auto_margin.top, item.block.auto_margin.bottom), align, area.y - border_box.y);
+
+        // The item was laid out with its margin box at the origin.
+        let area_origin = Vec2::new(column_offset + column_starts[item.columns.start], row_starts[item.rows.start]);
+        let border_box_origin = content_box.position + area_origin + Vec2::new(x, y);
+        let delta = border_box_origin - item.block.layout.read().unwrap().position.unwrap_or(Vec2::ZERO);
+        translate(&item.block.layout, delta);
+        if let LayoutNodeType::Block(block_layout) = &mut item.block.layout.write().unwrap().content {
+            block_layout.margin = item.block.margin;
+        }
+    }
+
+    container.write().unwrap().children.extend(items.iter().map(|item| item.block.layout.clone()));
+    total_size(&row_sizes, &collapsed_rows, row_gap)
+}
+
+/// Returns the max-content width of a grid container: the sum of its columns sized
+/// for the max-content widths of its items, and of the gaps between them.
+///
+/// # Notes
+/// Percentages of the container's width, which depends on this, count as `auto`, and
+/// `repeat(auto-fill, ...)` and `repeat(auto-fit, ...)` repeat their tracks once.
+pub(super) fn max_content_width(node: &Arc<RwLock<HtmlNode>>, context: &Context) -> f32 {
+    let LayoutMode::Grid(items) = LayoutNode::layout_mode(node) else {
+        return 0.0;
+    };
+    let style = node.read().unwrap().style.clone();
+    let container = LayoutNode::new_block(node.clone(), None, context.clone());
+    let column_gap = resolve_length(&style.column_gap, None).unwrap_or(0.0);
+    let (column_templates, _) = style.grid_template_columns.expand(None, column_gap);
+    let (row_templates, _) = style.grid_template_rows.expand(None, 0.0);
+
+    let blocks = items.into_iter()
+        .map(|child_box| ItemBlock::new(child_box, &container, context, 0.0))
+        .collect();
+    let items = place_items(&style, blocks, row_templates.len(), column_templates.len());
+    let column_count = items.iter().map(|item| item.columns.end).fold(explicit_count(&style, false, column_templates.len()), usize::max);
+    let columns = tracks(&column_templates, &style.grid_auto_columns, column_count);
+    let collapsed = vec![false; column_count];
+    let contributions: Vec<Contribution> = items.iter()
+        .map(|item| {
+            let (min, max) = item.width_contributions(context);
+            Contribution { tracks: item.columns.clone(), min, max }
+        })
+        .collect();
+    let sizes = size_tracks(&columns, &collapsed, &contributions, None, column_gap, false);
+    total_size(&sizes, &collapsed, column_gap)
+}
+
+/// Returns the number of tracks of the explicit grid along the rows or the columns:
+/// those of the template, or of `grid-template-areas` if it has more.
+fn explicit_count(style: &ComputedStyle, rows: bool, templates: usize) -> usize {
+    let areas = style.grid_template_areas.as_ref().map_or(0, |areas| if rows { areas.rows } else { areas.columns });
+    templates.max(areas)
+}
+
+/// Returns the sizes of `count` tracks along an axis: those of the template, then the
+/// implicit tracks, which cycle through `auto_tracks`.
+fn tracks(templates: &[TrackSize], auto_tracks: &[TrackSize], count: usize) -> Vec<TrackSize> {
+    (0..count)
+        .map(|index| match templates.get(index) {
+            Some(track) => track.clone(),
+            None => auto_tracks[(index - templates.len()) % auto_tracks.len()].clone(),
+        })
+        .collect()
+}
+
+/// Returns which of `count` tracks collapse: with `auto-fit`, the repeated ones that
+/// no item spans.
+fn collapsed<'a>(count: usize, repeated: &Range<usize>, fit: bool, spans: impl Iterator<Item = &'a Range<usize>>) -> Vec<bool> {
+    let mut collapsed: Vec<bool> = (0..count).map(|index| fit && repeated.contains(&index)).collect();
+    for span in spans {
+        for index in span.clone() {
+            collapsed[index] = false;
+        }
+    }
+    collapsed
+}
+
+/// Resolves where an item goes along one axis, from its start and end lines.
+///
+/// # Parameters
+/// - `start`, `end`: The item's `grid-*-start` and `grid-*-end`.
+/// - `explicit`: The number of tracks of the explicit grid along the axis, which
+///   negative lines count back from.
+/// - `area`: The tracks of a named area along the axis.
+///
+/// # Notes
+/// An end line before the start line swaps with it, and one on the start line is
+/// ignored. A `span` at both ends spans as the start says. A name that no area has
+/// counts as `auto`. Positions past `MAX_TRACKS` are clamped back to its last track.
+fn resolve_position(start: &GridLine, end: &GridLine, explicit: usize, area: impl Fn(&str) -> Option<Range<usize>>) -> Position {
+    let line = |line: &GridLine, is_start: bool| match line {
+        GridLine::Line(number) if *number > 0 => Some(*number as usize - 1),
+        GridLine::Line(number) => Some((explicit as i32 + 1 + number).max(0) as usize),
+        GridLine::Area(name) => area(name).map(|tracks| if is_start { tracks.start } else { tracks.end }),
+        GridLine::Auto | GridLine::Span(_) => None,
+    };
+    let span = |line: &GridLine| match line {
+        GridLine::Span(span) => *span as usize,
+        _ => 1,
+    };
+    let clamp = |tracks: Range<usize>| {
+        let end = tracks.end.min(MAX_TRACKS);
+        Position::Definite(tracks.start.min(end - 1)..end)
+    };
+    match (line(start, true), line(end, false)) {
+        (Some(start), Some(end)) if start < end => clamp(start..end),
+        (Some(start), Some(end)) if end < start => clamp(end..start),
+        (Some(start), _) => clamp(start..start + span(end)),
+        (None, Some(end)) => {
+            let end = end.max(1);
+            clamp(end.saturating_sub(span(start))..end)
+        }
+        (None, None) => Position::Auto(match start {
+            GridLine::Span(_) => span(start),
+            _ => span(end),
+        }),
+    }
+}
+
+/// Places items in the grid, following the grid item placement algorithm of CSS Grid
+/// Layout, with `grid-auto-flow: row` (`column` swaps the axes below).
+///
+/// # Parameters
+/// - `style`: The container's style.
+/// - `blocks`: The items' blocks, in document order.
+/// - `template_rows`, `template_columns`: The number of tracks of the templates.
+///
+/// # Returns
+/// The items by increasing `order`, then in document order, in their grid areas.
+///
+/// # Behavior
+/// 1. Items with a definite row and column go there, even over other items.
+/// 2. Items with only a definite row go in the first columns of that row that are
+///    free, after the last item placed so in that row.
+/// 3. The other items go, one after the other, where they first fit after the
+///    previous one, in rows as wide as the explicit grid (or the widest item, or the
+///    last column of an item placed in step 1). Items with a definite column look for
+///    the first row where it is free.
+///
+/// With `dense`, steps 2 and 3 search from the start of the grid for each item,
+/// filling holes that earlier items left.
+fn place_items(style: &ComputedStyle, blocks: Vec<ItemBlock>, template_rows: usize, template_columns: usize) -> Vec<GridItem> {
+    let flow = style.grid_auto_flow;
+    let mut blocks = blocks;
+    blocks.sort_by_key(|block| block.style.order);
+
+    let explicit_rows = explicit_count(style, true, template_rows);
+    let explicit_columns = explicit_count(style, false, template_columns);
+    let area = |name: &str, rows: bool| style.grid_template_areas.as_ref()
+        .and_then(|areas| areas.areas.get(name))
+        .map(|area| if rows { area.rows.clone() } else { area.columns.clone() });
+
+    // The positions along the major axis (rows for `row` flow) and the minor axis.
+    let positions: Vec<(Position, Position)> = blocks.iter()
+        .map(|block| {
+            let rows = resolve_position(&block.style.grid_row_start, &block.style.grid_row_end, explicit_rows, |name| area(name, true));
+            let columns = resolve_position(&block.style.grid_column_start, &block.style.grid_column_end, explicit_columns, |name| area(name, false));
+            if flow.column { (columns, rows) } else { (rows, columns) }
+        })
+        .collect();
+    let explicit_minor = if flow.column { explicit_rows } else { explicit_columns };
+    let minor_count = positions.iter()
+        .map(|(_, minor)| match minor {
+            Position::Definite(range) => range.end,
+            Position::Auto(span) => *span,
+        })
+        .fold(explicit_minor.max(1), usize::max);
+
+    let mut placed: Vec<Option<(Range<usize>, Range<usize>)>> = vec![None; positions.len()];
+    let is_free = |placed: &[Option<(Range<usize>, Range<usize>)>], major: &Range<usize>, minor: &Range<usize>| {
+        let overlap = |a: &Range<usize>, b: &Range<usize>| a.start < b.end && b.start < a.end;
+        !placed.iter().flatten().any(|(m, n)| overlap(m, major) && overlap(n, minor))
+    };
+
+    // 1. Items with a definite position on both axes.
+    for (index, position) in positions.iter().enumerate() {
+        if let (Position::Definite(major), Position::Definite(minor)) = position {
+            placed[index] = Some((major.clone(), minor.clone()));
+        }
+    }
+
+    // 2. Items locked to tracks of the major axis.
+    let mut cursors: HashMap<usize, usize> = HashMap::new();
+    for (index, position) in positions.iter().enumerate() {
+        let (Position::Definite(major), Position::Auto(span)) = position else {
+            continue;
+        };
+        let mut minor = if flow.dense { 0 } else { cursors.get(&major.start).copied().unwrap_or(0) };
+        while !is_free(&placed, major, &(minor..minor + span)) {
+            minor += 1;
+        }
+        cursors.insert(major.start, minor + span);
+        placed[index] = Some((major.clone(), minor..minor + span));
+    }
+
+    // 3. The other items, after a cursor.
+    let (mut cursor_major, mut cursor_minor) = (0, 0);
+    for (index, position) in positions.iter().enumerate() {
+        if placed[index].is_some() {
+            continue;
+        }
+        let (major_span, minor) = match position {
+            (Position::Auto(span), minor) => (*span, minor),
+            _ => continue,
+        };
+        if flow.dense {
+            (cursor_major, cursor_minor) = (0, 0);
+        }
+        let (major, minor) = match minor {
+            Position::Definite(minor) => {
+                if minor.start < cursor_minor {
+                    cursor_major += 1;
+                }
+                while !is_free(&placed, &(cursor_major..cursor_major + major_span), minor) {
+                    cursor_major += 1;
+                }
+                cursor_minor = minor.start;
+                (cursor_major..cursor_major + major_span, minor.clone())
+            }
+            Position::Auto(minor_span) => {
+                loop {
+                    if cursor_minor + minor_span > minor_count {
+                        cursor_major += 1;
+                        cursor_minor = 0;
+                    } else if is_free(&placed, &(cursor_major..cursor_major + major_span), &(cursor_minor..cursor_minor + minor_span)) {
+                        break;
+                    } else {
+                        cursor_minor += 1;
+                    }
+                }
+                cursor_minor += minor_span;
+                (cursor_major..cursor_major + major_span, cursor_minor - minor_span..cursor_minor)
+            }
+        };
+        placed[index] = Some((major, minor));
+    }
+
+    blocks.into_iter()
+        .zip(placed)
+        .map(|(block, placed)| {
+            let (major, minor) = placed.unwrap_or((0..1, 0..1));
+            let (rows, columns) = if flow.column { (minor, major) } else { (major, minor) };
+            GridItem { block, rows, columns }
+        })
+        .collect()
+}
+
+/// Sizes the tracks along an axis, following the track sizing algorithm of CSS Grid
+/// Layout.
+///
+/// # Parameters
+/// - `tracks`: The size of each track.
+/// - `collapsed`: Which tracks collapse to `0`, with their gaps.
+/// - `contributions`: What each item contributes to the tracks it spans.
+/// - `available`: The size of the container's content box along the axis, or `None`
+///   if it depends on the tracks, in which case every track takes its maximum and
+///   percentages count as `auto`.
+/// - `gap`: The gap between tracks.
+/// - `stretch`: Whether `auto` tracks share the space left at the end.
+///
+/// # Returns
+/// The size of each track.
+///
+/// # Behavior
+/// 1. Each track starts at its fixed minimum, or `0`, and may grow up to its fixed
+///    maximum, or indefinitely.
+/// 2. Intrinsic minimums (`auto`, `min-content`, `max-content`) grow to the
+///    contributions of the items, by increasing span, with an item spanning several
+///    tracks sharing what they lack equally. Intrinsic maximums grow likewise, to the
+///    max-content contributions (min-content ones for `min-content`). Items that span
+///    a flexible track only grow the minimums of the flexible tracks.
+/// 3. The tracks grow equally towards their maximums to fill the space.
+/// 4. Flexible tracks (`fr`) share the space left in proportion to their factors,
+///    without shrinking below their minimums. Without an available size, one `fr`
+///    is the largest size that an item or a track needs for it.
+/// 5. With `stretch`, `auto` tracks share the space still left.
+fn size_tracks(tracks: &[TrackSize],
+               collapsed: &[bool],
+               contributions: &[Contribution],
+               available: Option<f32>,
+               gap: f32,
+               stretch: bool) -> Vec<f32> {
+    let fixed = |breadth: &TrackBreadth| match breadth {
+        TrackBreadth::Length(length) => resolve_length(length, available),
+        _ => None,
+    };
+    let flex = |index: usize| match tracks[index].max {
+        TrackBreadth::Fr(factor) if !collapsed[index] => Some(factor),
+        _ => None,
+    };
+    let gaps = |range: &Range<usize>| gap * range.clone().filter(|&index| !collapsed[index]).count().saturating_sub(1) as f32;
+    let live = |range: Range<usize>| range.filter(|&index| !collapsed[index]);
+
+    // 1. The initial base sizes and growth limits.
+    let mut base = vec![0.0; tracks.len()];
+    let mut limit = vec![0.0; tracks.len()];
+    for index in live(0..tracks.len()) {
+        base[index] = fixed(&tracks[index].min).unwrap_or(0.0);
+        limit[index] = match &tracks[index].max {
+            TrackBreadth::Fr(_) => base[index],
+            max => fixed(max).unwrap_or(f32::INFINITY),
+        }.max(base[index]);
+    }
+
+    // 2. The intrinsic sizes, from the items that span no flexible track.
+    let crosses_flexible = |contribution: &Contribution| contribution.tracks.clone().any(|index| flex(index).is_some());
+    let mut spanning: Vec<&Contribution> = contributions.iter().filter(|c| !crosses_flexible(c)).collect();
+    spanning.sort_by_key(|contribution| contribution.tracks.len());
+    for contribution in spanning {
+        let range = &contribution.tracks;
+        let intrinsic_min: Vec<usize> = live(range.clone()).filter(|&index| fixed(&tracks[index].min).is_none()).collect();
+        let size = if intrinsic_min.iter().any(|&index| tracks[index].min == TrackBreadth::MaxContent) {
+            contribution.max
+        } else {
+            contribution.min
+        };
+        let lacking = size - range.clone().map(|index| base[index]).sum::<f32>() - gaps(range);
+        grow(&mut base, &intrinsic_min, lacking);
+
+        let intrinsic_max: Vec<usize> = live(range.clone()).filter(|&index| fixed(&tracks[index].max).is_none()).collect();
+        let size = if intrinsic_max.iter().all(|&index| tracks[index].max == TrackBreadth::MinContent) {
+            contribution.min
+        } else {
+            contribution.max
+        };
+        for index in range.clone() {
+            if limit[index] == f32::INFINITY && intrinsic_max.contains(&index) {
+                limit[index] = base[index];
+            }
+        }
+        let lacking = size - range.clone().map(|index| limit[index].max(base[index])).sum::<f32>() - gaps(range);
+        grow(&mut limit, &intrinsic_max, lacking);
+        for index in range.clone() {
+            limit[index] = limit[index].max(base[index]);
+        }
+    }
+    for contribution in contributions.iter().filter(|c| crosses_flexible(c)) {
+        let range = &contribution.tracks;
+        let flexible: Vec<usize> = live(range.clone())
+            .filter(|&index| flex(index).is_some() && fixed(&tracks[index].min).is_none())
+            .collect();
+        let lacking = contribution.min - range.clone().map(|index| base[index]).sum::<f32>() - gaps(range);
+        grow(&mut base, &flexible, lacking);
+    }
+    for index in 0..tracks.len() {
+        if limit[index] == f32::INFINITY {
+            limit[index] = base[index];
+        }
+        limit[index] = limit[index].max(base[index]);
+    }
+
+    // 3. Maximize the tracks.
+    let inflexible: Vec<usize> = live(0..tracks.len()).filter(|&index| flex(index).is_none()).collect();
+    match available {
+        Some(available) => {
+            let mut free = available - total_size(&base, collapsed, gap);
+            loop {
+                let growable: Vec<usize> = inflexible.iter().copied().filter(|&index| base[index] < limit[index]).collect();
+                if growable.is_empty() || free <= f32::EPSILON {
+                    break;
+                }
+                let share = free / growable.len() as f32;
+                for index in growable {
+                    let growth = share.min(limit[index] - base[index]);
+                    base[index] += growth;
+                    free -= growth;
+                }
+            }
+        }
+        None => {
+            for &index in &inflexible {
+                base[index] = limit[index];
+            }
+        }
+    }
+
+    // 4. Expand the flexible tracks.
+    let flexible: Vec<usize> = live(0..tracks.len()).filter(|&index| flex(index).is_some()).collect();
+    if !flexible.is_empty() {
+        let fr_size = match available {
+            Some(available) => {
+                let all: Range<usize> = 0..tracks.len();
+                let space = available - gaps(&all) - inflexible.iter().map(|&index| base[index]).sum::<f32>();
+                fr_size(&flexible.iter().map(|&index| (base[index], flex(index).unwrap_or(0.0))).collect::<Vec<_>>(), space)
+            }
+            None => {
+                let per_track = flexible.iter()
+                    .map(|&index| {
+                        let factor = flex(index).unwrap_or(0.0);
+                        if factor > 1.0 { base[index] / factor } else { base[index] }
+                    })
+                    .fold(0.0, f32::max);
+                contributions.iter()
+                    .filter(|c| crosses_flexible(c))
+                    .map(|contribution| {
+                        let range = &contribution.tracks;
+                        let factors: f32 = range.clone().filter_map(flex).sum();
+                        let space = contribution.max - gaps(range)
+                            - range.clone().filter(|&index| flex(index).is_none()).map(|index| base[index]).sum::<f32>();
+                        space / factors.max(1.0)
+                    })
+                    .fold(per_track, f32::max)
+            }
+        };
+        for &index in &flexible {
+            base[index] = base[index].max(fr_size * flex(index).unwrap_or(0.0));
+        }
+    }
+
+    // 5. Stretch the `auto` tracks.
+    if let Some(available) = available && stretch {
+        let free = available - total_size(&base, collapsed, gap);
+        let auto: Vec<usize> = live(0..tracks.len()).filter(|&index| tracks[index].max == TrackBreadth::Auto).collect();
+        if free > 0.0 && !auto.is_empty() {
+            for &index in &auto {
+                base[index] += free / auto.len() as f32;
+            }
+        }
+    }
+    base
+}
+
+/// Grows `sizes` at `indices` by equal shares of `lacking`, if it is positive.
+fn grow(sizes: &mut [f32], indices: &[usize], lacking: f32) {
+    if lacking > 0.0 && !indices.is_empty() {
+        for &index in indices {
+            sizes[index] += lacking / indices.len() as f32;
+        }
+    }
+}
+
+/// Returns the size of one `fr`, so that flexible tracks fill `space`.
+///
+/// # Parameters
+/// - `flexible`: The base size and the factor of each flexible track.
+/// - `space`: The space left by the other tracks and the gaps.
+///
+/// # Notes
+/// A track whose share would be smaller than its base size keeps its base size
+/// instead, and the rest is shared again. Factors that add up to less than `1` share
+/// only part of the space.
+fn fr_size(flexible: &[(f32, f32)], space: f32) -> f32 {
+    let mut inflexible = vec![false; flexible.len()];
+    loop {
+        let left = space - flexible.iter().zip(&inflexible).filter(|(_, fixed)| **fixed).map(|((base, _), _)| base).sum::<f32>();
+        let factors: f32 = flexible.iter().zip(&inflexible).filter(|(_, fixed)| !**fixed).map(|((_, factor), _)| factor).sum();
+        let size = left.max(0.0) / factors.max(1.0);
+        let mut changed = false;
+        for ((base, factor), fixed) in flexible.iter().zip(inflexible.iter_mut()) {
+            if !*fixed && size * factor < *base {
+                *fixed = true;
+                changed = true;
+            }
+        }
+        if !changed {
+            return size;
+        }
+    }
+}
+
+/// Returns the size of the tracks and of the gaps between those that do not collapse.
+fn total_size(sizes: &[f32], collapsed: &[bool], gap: f32) -> f32 {
+    let live = collapsed.iter().filter(|collapsed| !**collapsed).count();
+    sizes.iter().sum::<f32>() + gap * live.saturating_sub(1) as f32
+}
+
+/// Returns where each track starts, from the start of the first one, with `gap`
+/// between the tracks that do not collapse.
+fn track_starts(sizes: &[f32], collapsed: &[bool], gap: f32) -> Vec<f32> {
+    let mut starts = Vec::with_capacity(sizes.len());
+    let mut position = 0.0;
+    let mut first = true;
+    for (size, collapsed) in sizes.iter().zip(collapsed) {
+        if !collapsed && !first {
+            position += gap;
+        }
+        starts.push(position);
+        position += size;
+        first &= *collapsed;
+    }
+    starts
+}
+
+/// Returns the size of the grid area of an item along an axis: from the start of its
+/// first track to the end of its last one.
+fn area_size(starts: &[f32], sizes: &[f32], tracks: &Range<usize>) -> f32 {
+    let last = tracks.end - 1;
+    starts[last] + sizes[last] - starts[tracks.start]
+}
+
+/// Returns where an item's border box goes in its grid area along an axis, from the
+/// start of the area.
+///
+/// # Parameters
+/// - `margin_start`, `margin_end`: The item's margins along the axis; `auto` ones get
+///   equal shares of the free space.
+/// - `auto`: Which of the margins are `auto`.
+/// - `align`: The item's `justify-self` or `align-self`.
+/// - `free`: The size of the area less that of the item's border box.
+fn align_in_area(margin_start: &mut f32, margin_end: &mut f32, auto: (bool, bool), align: Align, free: f32) -> f32 {
+    let mut free = free - *margin_start - *margin_end;
+    if auto.0 || auto.1 {
+        if free > 0.0 {
+            let share = free / (auto.0 as u8 + auto.1 as u8) as f32;
+            if auto.0 {
+                *margin_start += share;
+            }
+            if auto.1 {
+                *margin_end += share;
+            }
+        }
+        free = 0.0;
+    }
+    *margin_start + match align {
+        Align::Stretch | Align::Start | Align::Baseline => 0.0,
+        Align::End => free,
+        Align::Center => free / 2.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css_grid::{parse_auto_tracks, GridAutoFlow};
+    use crate::style::LengthContext;
+
+    /// Parses a list of track sizes, such as `100px 1fr auto`.
+    fn track_sizes(value: &str) -> Vec<TrackSize> {
+        let context = LengthContext { font_size: 16.0, root_font_size: 16.0, viewport: Vec2::ZERO };
+        parse_auto_tracks(value, &context).unwrap()
+    }
+
+    /// Sizes the tracks of `value`, none of them collapsed, from the min-content and
+    /// max-content contributions of items spanning the given tracks.
+    fn sized(value: &str, contributions: &[(Range<usize>, f32, f32)], available: Option<f32>, gap: f32, stretch: bool) -> Vec<f32> {
+        let tracks = track_sizes(value);
+        let contributions: Vec<Contribution> = contributions.iter()
+            .map(|(tracks, min, max)| Contribution { tracks: tracks.clone(), min: *min, max: *max })
+            .collect();
+        size_tracks(&tracks, &vec![false; tracks.len()], &contributions, available, gap, stretch)
+    }
+
+    /// Places items with these styles in a container with `container`'s style and
+    /// `columns` template columns, and returns their rows and columns in placement order.
+    fn placed(container: ComputedStyle, items: Vec<ComputedStyle>, columns: usize) -> Vec<(Range<usize>, Range<usize>)> {
+        let blocks = items.into_iter().map(ItemBlock::with_style).collect();
+        place_items(&container, blocks, 0, columns).into_iter().map(|item| (item.rows, item.columns)).collect()
+    }
+
+    /// Returns the style of an item between these row and column lines.
+    fn at(grid_row_start: GridLine, grid_column_start: GridLine, grid_column_end: GridLine) -> ComputedStyle {
+        ComputedStyle { grid_row_start, grid_column_start, grid_column_end, ..ComputedStyle::default() }
+    }
+
+    #[test]
+    fn flexible_tracks_share_the_space_left_by_the_others() {
+        assert_eq!(sized("100px 1fr 3fr", &[], Some(500.0), 0.0, false), [100.0, 100.0, 300.0]);
+        assert_eq!(sized("100px 1fr 3fr", &[], Some(500.0), 10.0, false), [100.0, 95.0, 285.0]);
+        // Without an available size, one `fr` is the largest that an item needs.
+        assert_eq!(sized("1fr 2fr", &[(0..1, 10.0, 40.0), (1..2, 10.0, 60.0)], None, 0.0, false), [40.0, 80.0]);
+        assert_eq!(fr_size(&[(0.0, 1.0), (0.0, 1.0)], 100.0), 50.0);
+        // A track whose share is below its base size keeps it, and the rest is shared.
+        assert_eq!(fr_size(&[(80.0, 1.0), (0.0, 1.0)], 100.0), 20.0);
+        // Factors below 1 in total share only part of the space.
+        assert_eq!(fr_size(&[(0.0, 0.5)], 100.0), 100.0);
+    }
+
+    #[test]
+    fn intrinsic_tracks_grow_to_their_items() {
+        let contributions = [(0..1, 30.0, 60.0), (2..3, 40.0, 90.0)];
+        assert_eq!(sized("auto 50px min-content", &contributions, None, 0.0, false), [60.0, 50.0, 40.0]);
+        assert_eq!(sized("auto 50px min-content", &contributions, Some(200.0), 0.0, false), [60.0, 50.0, 40.0]);
+        // Stretching gives the space still left to the `auto` tracks.
+        assert_eq!(sized("auto 50px min-content", &contributions, Some(200.0), 0.0, true), [110.0, 50.0, 40.0]);
+        // Tracks grow towards their items' maximums only as far as the space allows.
+        assert_eq!(sized("auto auto", &[(0..1, 30.0, 500.0)], Some(100.0), 0.0, false), [100.0, 0.0]);
+    }
+
+    #[test]
+    fn degenerate_tracks_are_sized() {
+        assert_eq!(sized("1fr", &[], Some(-50.0), 0.0, true), [0.0]);
+        assert_eq!(size_tracks(&[], &[], &[], Some(100.0), 10.0, true), Vec::<f32>::new());
+        assert_eq!(size_tracks(&[], &[], &[], None, 10.0, true), Vec::<f32>::new());
+        assert_eq!(fr_size(&[], 100.0), 100.0);
+        assert_eq!(fr_size(&[(0.0, 1.0)], -100.0), 0.0);
+        // Collapsed tracks take no space, nor do their gaps.
+        let tracks = track_sizes("1fr 1fr 1fr");
+        assert_eq!(size_tracks(&tracks, &[true, false, true], &[], Some(100.0), 10.0, false), [0.0, 100.0, 0.0]);
+    }
+
+    #[test]
+    fn items_are_placed_in_the_first_free_cells() {
+        let auto = ComputedStyle::default;
+        assert_eq!(placed(auto(), vec![auto(), auto(), auto(), auto()], 3),
+                   [(0..1, 0..1), (0..1, 1..2), (0..1, 2..3), (1..2, 0..1)]);
+        // Items with a definite position go first; the others flow around them.
+        let fixed = at(GridLine::Line(1), GridLine::Line(2), GridLine::Auto);
+        assert_eq!(placed(auto(), vec![fixed, auto(), auto(), auto()], 3),
+                   [(0..1, 1..2), (0..1, 0..1), (0..1, 2..3), (1..2, 0..1)]);
+        // Items placed earlier come first, in placement order.
+        let first = ComputedStyle { order: -1, ..ComputedStyle::default() };
+        assert_eq!(placed(auto(), vec![auto(), first], 2), [(0..1, 0..1), (0..1, 1..2)]);
+    }
+
+    #[test]
+    fn dense_placement_fills_holes() {
+        let span = || at(GridLine::Auto, GridLine::Span(2), GridLine::Auto);
+        let items = || vec![span(), span(), ComputedStyle::default()];
+        assert_eq!(placed(ComputedStyle::default(), items(), 3), [(0..1, 0..2), (1..2, 0..2), (1..2, 2..3)]);
+        let dense = ComputedStyle { grid_auto_flow: GridAutoFlow { column: false, dense: true }, ..ComputedStyle::default() };
+        assert_eq!(placed(dense, items(), 3), [(0..1, 0..2), (1..2, 0..2), (0..1, 2..3)]);
+    }
+
+    #[test]
+    fn degenerate_grids_are_placed() {
+        let auto = ComputedStyle::default;
+        assert_eq!(placed(auto(), vec![], 0), []);
+        // Without explicit columns, auto-placed items stack in a single column.
+        assert_eq!(placed(auto(), vec![auto(), auto()], 0), [(0..1, 0..1), (1..2, 0..1)]);
+        // Lines past `MAX_TRACKS` are clamped back to its last track.
+        let far = at(GridLine::Line(1), GridLine::Line(MAX_TRACKS as i32 + 5), GridLine::Auto);
+        assert_eq!(placed(auto(), vec![far], 0), [(0..1, MAX_TRACKS - 1..MAX_TRACKS)]);
+        // An end line on the start line is ignored, and one before it swaps with it.
+        assert_eq!(placed(auto(), vec![at(GridLine::Line(1), GridLine::Line(2), GridLine::Line(2))], 3), [(0..1, 1..2)]);
+        assert_eq!(placed(auto(), vec![at(GridLine::Line(1), GridLine::Line(3), GridLine::Line(1))], 3), [(0..1, 0..2)]);
+    }
+}
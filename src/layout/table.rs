@@ -0,0 +1,753 @@
+//! The table formatting context of CSS 2: how a table places its rows and cells in a
+//! grid of columns, and sizes the columns from the content of the cells. See
+//! `layout_table`.
+//!
+//! Cells are laid out as blocks by `LayoutNode::layout_block`, with the role
+//! `BlockRole::Cell`, then moved into place with `translate`. Rows and row groups that
+//! are elements get blocks of their own around their cells, so that their backgrounds
+//! are painted under the cells and hit testing finds them.
+
+use super::{anonymous_style, box_children, display, first_line, flush_text_run, resolve_length, translate, BlockRole, ChildBox,
+            CollapsedMargin, ContainingBlock, ContentBox, ItemBlock, LayoutMode, LayoutNode};
+use crate::node::{HtmlNode, HtmlNodeType};
+use crate::style::{BorderCollapse, BoxSizing, CaptionSide, ComputedStyle, Display, Edges, VerticalAlign};
+use egui::{Context, Vec2};
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::{Arc, RwLock};
+
+/// The largest `colspan` of a cell, as in HTML.
+const MAX_COLSPAN: usize = 1000;
+
+/// The largest `rowspan` of a cell, as in HTML.
+const MAX_ROWSPAN: usize = 65534;
+
+/// The boxes of a table, sorted out of its children.
+///
+/// # Fields
+/// - `captions`: The captions, in document order.
+/// - `groups`: The row groups, in the order they are laid out: the first header
+///   group, then the body groups, then the first footer group. Rows outside a group
+///   are wrapped in anonymous ones.
+/// - `rows`: The rows of every group, in the order they are laid out.
+/// - `cells`: The cells, row after row.
+/// - `columns`: The number of columns.
+struct TableStructure {
+    captions: Vec<Arc<RwLock<HtmlNode>>>,
+    groups: Vec<RowGroup>,
+    rows: Vec<Option<Arc<RwLock<HtmlNode>>>>,
+    cells: Vec<TableCell>,
+    columns: usize,
+}
+
+/// A group of rows, such as `<tbody>`.
+///
+/// # Fields
+/// - `node`: The element, or `None` for an anonymous group.
+/// - `rows`: Its rows among those of the table.
+struct RowGroup {
+    node: Option<Arc<RwLock<HtmlNode>>>,
+    rows: Range<usize>,
+}
+
+/// The rows of a group, before their cells are placed in columns: the element of
+/// each row (`None` for an anonymous row) and its cells.
+type GroupRows = Vec<(Option<Arc<RwLock<HtmlNode>>>, Vec<ChildBox>)>;
+
+/// A cell of a table, placed in its grid.
+///
+/// # Fields
+/// - `node`: The element, or for an anonymous cell, the table.
+/// - `anonymous_content`: For an anonymous cell, the run of text it wraps.
+/// - `style`: The cell's style; the initial values for an anonymous cell.
+/// - `row`, `column`: The first row and column it occupies, counted from `0`.
+/// - `rows`, `columns`: How many rows and columns it spans.
+struct TableCell {
+    node: Arc<RwLock<HtmlNode>>,
+    anonymous_content: Option<Vec<Arc<RwLock<HtmlNode>>>>,
+    style: ComputedStyle,
+    row: usize,
+    column: usize,
+    rows: usize,
+    columns: usize,
+}
+
+impl TableCell {
+    /// Returns the min-content and max-content widths of the cell's border box.
+    ///
+    /// # Parameters
+    /// - `edges`: The sum of its horizontal borders and paddings.
+    ///
+    /// # Notes
+    /// A `width` in pixels larger than the content is both widths, as in CSS 2.
+    fn widths(&self, context: &Context, edges: f32) -> (f32, f32) {
+        let min = LayoutNode::min_content_width(&self.node, self.anonymous_content.clone(), context) + edges;
+        let max = LayoutNode::max_content_width(&self.node, self.anonymous_content.clone(), context) + edges;
+        let sizing = match self.style.box_sizing {
+            BoxSizing::ContentBox => edges,
+            BoxSizing::BorderBox => 0.0,
+        };
+        match self.style.width.as_ref().and_then(|width| resolve_length(width, None)) {
+            Some(width) => {
+                let width = (width + sizing).max(min);
+                (width, width)
+            }
+            None => (min, max.max(min)),
+        }
+    }
+}
+
+impl TableStructure {
+    /// Sorts the children of a table into captions, row groups, rows and cells, and
+    /// places the cells in columns.
+    ///
+    /// # Parameters
+    /// - `table`: The table element.
+    /// - `children`: Its children that generate boxes, see `box_children`.
+    ///
+    /// # Notes
+    /// - Columns (`<col>`, `<colgroup>`) generate nothing.
+    /// - Rows outside a group, and other content outside rows, are wrapped in
+    ///   anonymous groups and rows, as CSS does. In a row, every element is a cell,
+    ///   and every run of text that is not only white space an anonymous cell.
+    /// - Each cell goes in the first column of its row that no cell above spans, and
+    ///   spans its `colspan` columns and `rowspan` rows, the latter no further than its
+    ///   group. `rowspan="0"` spans the rest of the group.
+    fn new(table: &Arc<RwLock<HtmlNode>>, children: Vec<Arc<RwLock<HtmlNode>>>) -> TableStructure {
+        let mut captions = vec![];
+        let (mut header, mut footer) = (None, None);
+        let mut bodies: Vec<(Option<Arc<RwLock<HtmlNode>>>, GroupRows)> = vec![];
+        let mut loose = vec![];
+        for child in children {
+            let kind = display(&child);
+            match kind {
+                Some(Display::TableCaption) => captions.push(child),
+                Some(Display::TableColumn | Display::TableColumnGroup) => {}
+                Some(Display::TableRowGroup | Display::TableHeaderGroup | Display::TableFooterGroup) => {
+                    if !loose.is_empty() {
+                        bodies.push((None, group_rows(std::mem::take(&mut loose))));
+                    }
+                    let group = (Some(child.clone()), group_rows(box_children(&child)));
+                    match kind {
+                        Some(Display::TableHeaderGroup) if header.is_none() => header = Some(group),
+                        Some(Display::TableFooterGroup) if footer.is_none() => footer = Some(group),
+                        _ => bodies.push(group),
+                    }
+                }
+                _ => loose.push(child),
+            }
+        }
+        if !loose.is_empty() {
+            bodies.push((None, group_rows(loose)));
+        }
+
+        let mut structure = TableStructure { captions, groups: vec![], rows: vec![], cells: vec![], columns: 0 };
+        for (node, rows) in header.into_iter().chain(bodies).chain(footer) {
+            let first_row = structure.rows.len();
+            let group_end = first_row + rows.len();
+            let mut occupied = HashSet::new();
+            for (row, (row_node, cells)) in rows.into_iter().enumerate() {
+                let row = first_row + row;
+                structure.rows.push(row_node);
+                let mut column = 0;
+                for child_box in cells {
+                    while occupied.contains(&(row, column)) {
+                        column += 1;
+                    }
+                    let (node, anonymous_content, style) = match child_box {
+                        ChildBox::Block(node) => {
+                            let style = node.read().unwrap().style.clone();
+                            (node, None, style)
+                        }
+                        ChildBox::Anonymous(content) => (table.clone(), Some(content), anonymous_style()),
+                    };
+                    let (columns, rows) = match anonymous_content {
+                        Some(_) => (1, 1),
+                        None => (
+                            span_attribute(&node, "colspan").clamp(1, MAX_COLSPAN),
+                            match span_attribute(&node, "rowspan").min(MAX_ROWSPAN) {
+                                0 => group_end - row,
+                                rows => rows.min(group_end - row),
+                            },
+                        ),
+                    };
+                    for spanned_row in row..row + rows {
+                        for spanned_column in column..column + columns {
+                            occupied.insert((spanned_row, spanned_column));
+                        }
+                    }
+                    structure.cells.push(TableCell { node, anonymous_content, style, row, column, rows, columns });
+                    column += columns;
+                    structure.columns = structure.columns.max(column);
+                }
+            }
+            structure.groups.push(RowGroup { node, rows: first_row..group_end });
+        }
+        structure
+    }
+
+    /// Returns the widths of the borders of each cell.
+    ///
+    /// # Notes
+    /// With `border-collapse: collapse`, neighbouring cells share the border between
+    /// them, as wide as the wider of their two borders: the cell above or on the left
+    /// draws it in its own style, and the other cell has none there. On a side where
+    /// the table has a border, the table's border replaces those of the cells.
+    fn borders(&self, table_style: &ComputedStyle) -> Vec<Edges<f32>> {
+        if table_style.border_collapse == BorderCollapse::Separate {
+            return self.cells.iter().map(|cell| cell.style.border_width).collect();
+        }
+        let mut grid = vec![vec![None; self.columns]; self.rows.len()];
+        for (index, cell) in self.cells.iter().enumerate() {
+            for row in grid.iter_mut().skip(cell.row).take(cell.rows) {
+                for slot in row.iter_mut().skip(cell.column).take(cell.columns) {
+                    *slot = Some(index);
+                }
+            }
+        }
+        let outer = |own: f32, table: f32| if table > 0.0 { 0.0 } else { own };
+        self.cells.iter()
+            .map(|cell| {
+                let own = cell.style.border_width;
+                let row_end = cell.row + cell.rows;
+                let column_end = cell.column + cell.columns;
+                let below = grid.get(row_end).map(|row| {
+                    row[cell.column..column_end].iter().flatten().map(|&index| self.cells[index].style.border_width.top).fold(own.bottom, f32::max)
+                });
+                let right = (column_end < self.columns).then(|| {
+                    grid[cell.row..row_end].iter().filter_map(|row| row[column_end]).map(|index| self.cells[index].style.border_width.left).fold(own.right, f32::max)
+                });
+                Edges {
+                    top: if cell.row == 0 { outer(own.top, table_style.border_width.top) } else { 0.0 },
+                    left: if cell.column == 0 { outer(own.left, table_style.border_width.left) } else { 0.0 },
+                    bottom: below.unwrap_or_else(|| outer(own.bottom, table_style.border_width.bottom)),
+                    right: right.unwrap_or_else(|| outer(own.right, table_style.border_width.right)),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the minimum and maximum width of each column: the largest min-content
+    /// and max-content widths of the cells in it.
+    ///
+    /// # Parameters
+    /// - `edges`: The sum of the horizontal borders and paddings of each cell.
+    /// - `spacing`: The horizontal space between columns.
+    ///
+    /// # Notes
+    /// Cells spanning several columns are counted last, by increasing span: the width
+    /// that the columns lack is shared between them in proportion to their maximum
+    /// widths, or equally if those are all `0`.
+    fn column_widths(&self, context: &Context, edges: &[f32], spacing: f32) -> (Vec<f32>, Vec<f32>) {
+        let widths: Vec<(f32, f32)> = self.cells.iter().zip(edges)
+            .map(|(cell, edges)| cell.widths(context, *edges))
+            .collect();
+        let mut min = vec![0.0; self.columns];
+        let mut max = vec![0.0; self.columns];
+        for (cell, (cell_min, cell_max)) in self.cells.iter().zip(&widths) {
+            if cell.columns == 1 {
+                min[cell.column] = f32::max(min[cell.column], *cell_min);
+                max[cell.column] = f32::max(max[cell.column], *cell_max);
+            }
+        }
+        let mut spanning: Vec<usize> = (0..self.cells.len()).filter(|&index| self.cells[index].columns > 1).collect();
+        spanning.sort_by_key(|&index| self.cells[index].columns);
+        for index in spanning {
+            let cell = &self.cells[index];
+            let columns = cell.column..cell.column + cell.columns;
+            let weights = max.clone();
+            spread(&mut min, &columns, widths[index].0, spacing, &weights);
+            spread(&mut max, &columns, widths[index].1, spacing, &weights);
+        }
+        for (max, min) in max.iter_mut().zip(&min) {
+            *max = f32::max(*max, *min);
+        }
+        (min, max)
+    }
+}
+
+/// Returns the rows of a row group, or of the loose content of a table: its rows, with
+/// every run of other content between them wrapped in an anonymous row.
+fn group_rows(children: Vec<Arc<RwLock<HtmlNode>>>) -> GroupRows {
+    let mut rows = vec![];
+    let mut loose = vec![];
+    let flush = |loose: &mut Vec<Arc<RwLock<HtmlNode>>>, rows: &mut GroupRows| {
+        let cells = row_cells(std::mem::take(loose));
+        if !cells.is_empty() {
+            rows.push((None, cells));
+        }
+    };
+    for child in children {
+        match display(&child) {
+            Some(Display::TableRow) => {
+                flush(&mut loose, &mut rows);
+                let cells = row_cells(box_children(&child));
+                rows.push((Some(child), cells));
+            }
+            Some(Display::TableColumn | Display::TableColumnGroup) => {}
+            _ => loose.push(child),
+        }
+    }
+    flush(&mut loose, &mut rows);
+    rows
+}
+
+/// Returns the cells of a row: each element, and each run of text that is not only
+/// white space.
+fn row_cells(children: Vec<Arc<RwLock<HtmlNode>>>) -> Vec<ChildBox> {
+    let mut cells = vec![];
+    let mut run = vec![];
+    for child in children {
+        if display(&child).is_some() {
+            flush_text_run(&mut run, &mut cells);
+            cells.push(ChildBox::Block(child));
+        } else {
+            run.push(child);
+        }
+    }
+    flush_text_run(&mut run, &mut cells);
+    cells
+}
+
+/// Returns the `colspan` or `rowspan` attribute of a cell, `1` if it has none or it
+/// is not a number.
+fn span_attribute(node: &Arc<RwLock<HtmlNode>>, name: &str) -> usize {
+    match &node.read().unwrap().node_type {
+        HtmlNodeType::Element(element) => element.attributes.get(name)
+            .and_then(|span| span.trim().parse::<usize>().ok())
+            .unwrap_or(1),
+        HtmlNodeType::Text(_) => 1,
+    }
+}
+
+/// Grows `sizes` over `tracks` so that, with `spacing` between them, they add up to at
+/// least `size`, sharing what they lack in proportion to `weights`.
+fn spread(sizes: &mut [f32], tracks: &Range<usize>, size: f32, spacing: f32, weights: &[f32]) {
+    let current = sizes[tracks.clone()].iter().sum::<f32>() + spacing * tracks.len().saturating_sub(1) as f32;
+    let lacking = size - current;
+    if lacking <= 0.0 {
+        return;
+    }
+    let total: f32 = weights[tracks.clone()].iter().sum();
+    for index in tracks.clone() {
+        sizes[index] += if total > 0.0 {
+            lacking * weights[index] / total
+        } else {
+            lacking / tracks.len() as f32
+        };
+    }
+}
+
+/// Returns the width of each column of a table whose columns have `available` width
+/// between them.
+///
+/// # Notes
+/// As in the automatic table layout of CSS 2: columns get their maximum widths and
+/// share the rest in proportion to them if there is enough space, or else get their
+/// minimum widths plus the same fraction of the difference to their maximums.
+fn distribute_width(min: &[f32], max: &[f32], available: f32) -> Vec<f32> {
+    let min_total: f32 = min.iter().sum();
+    let max_total: f32 = max.iter().sum();
+    if available >= max_total {
+        let extra = available - max_total;
+        max.iter()
+            .map(|max| max + if max_total > 0.0 { extra * max / max_total } else { extra / min.len() as f32 })
+            .collect()
+    } else if available > min_total {
+        let fraction = (available - min_total) / (max_total - min_total);
+        min.iter().zip(max).map(|(min, max)| min + (max - min) * fraction).collect()
+    } else {
+        min.to_vec()
+    }
+}
+
+/// Returns the border spacing of a table: none if its borders collapse.
+fn spacing(style: &ComputedStyle) -> Vec2 {
+    match style.border_collapse {
+        BorderCollapse::Separate => style.border_spacing,
+        BorderCollapse::Collapse => Vec2::ZERO,
+    }
+}
+
+/// Returns the sum of the spacings around `count` tracks: one between each two, and
+/// one at each end.
+fn spacings(spacing: f32, count: usize) -> f32 {
+    if count == 0 { 0.0 } else { spacing * (count + 1) as f32 }
+}
+
+/// Returns the horizontal borders plus paddings of each cell, with percentage paddings
+/// resolved against `width`, or `0` if it is not known.
+fn cell_edges(structure: &TableStructure, borders: &[Edges<f32>], width: Option<f32>) -> Vec<Edges<f32>> {
+    structure.cells.iter().zip(borders)
+        .map(|(cell, border)| {
+            let padding = cell.style.padding.map(|padding| resolve_length(padding, width).unwrap_or(0.0));
+            Edges {
+                top: border.top + padding.top,
+                right: border.right + padding.right,
+                bottom: border.bottom + padding.bottom,
+                left: border.left + padding.left,
+            }
+        })
+        .collect()
+}
+
+/// Returns the minimum and maximum widths of the content box of a table: those of its
+/// columns, and of the spacing around them, or the min-content width of its widest
+/// caption if that is wider.
+pub(super) fn content_widths(node: &Arc<RwLock<HtmlNode>>, context: &Context) -> (f32, f32) {
+    let LayoutMode::Table(children) = LayoutNode::layout_mode(node) else {
+        return (0.0, 0.0);
+    };
+    let style = node.read().unwrap().style.clone();
+    let structure = TableStructure::new(node, children);
+    let spacing = spacing(&style);
+    let borders = structure.borders(&style);
+    let edges: Vec<f32> = cell_edges(&structure, &borders, None).iter().map(Edges::horizontal).collect();
+    let (min, max) = structure.column_widths(context, &edges, spacing.x);
+    let caption = structure.captions.iter()
+        .map(|caption| {
+            let caption_style = caption.read().unwrap().style.clone();
+            let padding = caption_style.padding.map(|padding| resolve_length(padding, None).unwrap_or(0.0));
+            LayoutNode::min_content_width(caption, None, context) + padding.horizontal() + caption_style.border_width.horizontal()
+        })
+        .fold(0.0, f32::max);
+    let outer = spacings(spacing.x, structure.columns);
+    ((min.iter().sum::<f32>() + outer).max(caption), (max.iter().sum::<f32>() + outer).max(caption))
+}
+
+/// Lays out the captions, rows and cells of a table, following the automatic table
+/// layout of CSS 2.
+///
+/// # Parameters
+/// - `container`: The table's layout node. The blocks of its captions, row groups,
+///   rows and cells are added under it.
+/// - `context`: The egui context, for measuring text.
+/// - `style`: The table's style.
+/// - `children`: The table's children, see `LayoutNode::layout_mode`.
+/// - `content_box`: The table's content box, as wide as its columns need (see
+///   `content_widths`).
+///
+/// # Returns
+/// The height of the content: the captions, and the rows with the spacing around them.
+///
+/// # Behavior
+/// 1. The children are sorted into captions, row groups, rows and cells; the header
+///    group goes first and the footer group last (see `TableStructure::new`).
+/// 2. Each column is as wide as its widest cell at its max-content width, if they fit;
+///    the width of the table is then shared in proportion to those widths. Otherwise,
+///    columns shrink towards the min-content widths of their cells.
+/// 3. With `border-collapse: separate`, `border-spacing` separates the cells, and the
+///    cells from the table's padding. With `collapse`, neighbouring cells share their
+///    borders (see `TableStructure::borders`).
+/// 4. Each row is as high as its tallest cell and its own `height`. Cells spanning
+///    several rows share what those rows lack equally between them.
+/// 5. Every cell is stretched to the height of its rows, and its content placed by
+///    `vertical-align`: at the top, middle or bottom, or for `baseline`, with the
+///    bottoms of the first lines of the row's cells aligned.
+/// 6. Captions are as wide as the table, above the rows or below them per
+///    `caption-side`.
+///
+/// # Notes
+/// - The table's borders and paddings go around its captions too.
+/// - Percentage widths of cells count as `auto`, and an extra `height` of the table
+///   stays below its last row.
+pub(super) fn layout_table(container: &Arc<RwLock<LayoutNode>>,
+                           context: &Context,
+                           style: &ComputedStyle,
+                           children: Vec<Arc<RwLock<HtmlNode>>>,
+                           content_box: ContentBox) -> f32 {
+    let table = container.read().unwrap().node.clone();
+    let structure = TableStructure::new(&table, children);
+    let spacing = spacing(style);
+    let borders = structure.borders(style);
+    let edges = cell_edges(&structure, &borders, Some(content_box.width));
+    let horizontal_edges: Vec<f32> = edges.iter().map(Edges::horizontal).collect();
+    let (min, max) = structure.column_widths(context, &horizontal_edges, spacing.x);
+    let widths = distribute_width(&min, &max, content_box.width - spacings(spacing.x, structure.columns));
+    let mut column_starts = Vec::with_capacity(widths.len());
+    let mut x = spacing.x;
+    for width in &widths {
+        column_starts.push(x);
+        x += width + spacing.x;
+    }
+    let span_width = |columns: Range<usize>| widths[columns.clone()].iter().sum::<f32>() + spacing.x * (columns.len() - 1) as f32;
+
+    let mut cursor = 0.0;
+    let mut children = vec![];
+    let place_captions = |side: CaptionSide, cursor: &mut f32, children: &mut Vec<Arc<RwLock<LayoutNode>>>| {
+        for caption in structure.captions.iter().filter(|caption| caption.read().unwrap().style.caption_side == side) {
+            let mut block = ItemBlock::new(ChildBox::Block(caption.clone()), container, context, content_box.width);
+            let width = (content_box.width - block.margin.horizontal() - block.edges.horizontal()).max(0.0);
+            let containing_block = ContainingBlock { x: 0.0, width: content_box.width, height: None };
+            block.lay_out(container, context, containing_block, Some(width));
+            let origin = content_box.position + Vec2::new(block.margin.left, *cursor + block.margin.top);
+            let delta = origin - block.layout.read().unwrap().position.unwrap_or(Vec2::ZERO);
+            translate(&block.layout, delta);
+            *cursor += block.border_box().y + block.margin.vertical();
+            children.push(block.layout.clone());
+        }
+    };
+    place_captions(CaptionSide::Top, &mut cursor, &mut children);
+
+    // The blocks of the row groups and rows that are elements.
+    let new_block = |node: &Option<Arc<RwLock<HtmlNode>>>, parent: &Arc<RwLock<LayoutNode>>| {
+        node.as_ref().map(|node| LayoutNode::new_block(node.clone(), Some(parent.clone()), context.clone()))
+    };
+    let group_blocks: Vec<Option<Arc<RwLock<LayoutNode>>>> = structure.groups.iter()
+        .map(|group| new_block(&group.node, container))
+        .collect();
+    let mut row_parents = vec![container.clone(); structure.rows.len()];
+    let mut row_blocks = vec![None; structure.rows.len()];
+    for (group, group_block) in structure.groups.iter().zip(&group_blocks) {
+        let parent = group_block.clone().unwrap_or_else(|| container.clone());
+        for row in group.rows.clone() {
+            row_blocks[row] = new_block(&structure.rows[row], &parent);
+            row_parents[row] = row_blocks[row].clone().unwrap_or_else(|| parent.clone());
+        }
+    }
+
+    // Lay out each cell at the width of its columns, and size the rows.
+    let mut cell_blocks = vec![];
+    for ((cell, border), edges) in structure.cells.iter().zip(&borders).zip(&edges) {
+        let parent = &row_parents[cell.row];
+        let block = match &cell.anonymous_content {
+            Some(content) => LayoutNode::new_anonymous_block(cell.node.clone(), content.clone(), parent.clone(), context.clone()),
+            None => LayoutNode::new_block(cell.node.clone(), Some(parent.clone()), context.clone()),
+        };
+        let width = span_width(cell.column..cell.column + cell.columns);
+        let containing_block = ContainingBlock { x: 0.0, width: content_box.width, height: None };
+        let role = BlockRole::Cell { width: (width - edges.horizontal()).max(0.0), border: *border };
+        LayoutNode::layout_block(&block, context, containing_block, 0.0, CollapsedMargin::default(), role);
+        cell_blocks.push(block);
+    }
+    let heights: Vec<f32> = cell_blocks.iter().map(|block| block.read().unwrap().size.unwrap_or(Vec2::ZERO).y).collect();
+    // The distance from the top of each cell to the bottom of its first line, or of
+    // its content box if it has none.
+    let baselines: Vec<f32> = cell_blocks.iter().zip(&edges).zip(&heights)
+        .map(|((block, edges), height)| {
+            let block = block.read().unwrap();
+            let top = block.position.unwrap_or(Vec2::ZERO).y;
+            first_line(&block).map_or(height - edges.bottom, |line| line.bottom() - top)
+        })
+        .collect();
+
+    let mut row_heights: Vec<f32> = structure.rows.iter()
+        .map(|row| {
+            row.as_ref()
+                .and_then(|row| row.read().unwrap().style.height.as_ref().and_then(|height| resolve_length(height, None)))
+                .unwrap_or(0.0)
+        })
+        .collect();
+    let mut row_baselines = vec![0.0f32; structure.rows.len()];
+    let mut below_baselines = vec![0.0f32; structure.rows.len()];
+    for (index, cell) in structure.cells.iter().enumerate() {
+        if cell.rows != 1 {
+            continue;
+        }
+        row_heights[cell.row] = row_heights[cell.row].max(heights[index]);
+        if cell.style.vertical_align == VerticalAlign::Baseline {
+            row_baselines[cell.row] = row_baselines[cell.row].max(baselines[index]);
+            below_baselines[cell.row] = below_baselines[cell.row].max(heights[index] - baselines[index]);
+        }
+    }
+    for (height, (baseline, below)) in row_heights.iter_mut().zip(row_baselines.iter().zip(&below_baselines)) {
+        *height = height.max(baseline + below);
+    }
+    let mut spanning: Vec<usize> = (0..structure.cells.len()).filter(|&index| structure.cells[index].rows > 1).collect();
+    spanning.sort_by_key(|&index| structure.cells[index].rows);
+    for index in spanning {
+        let cell = &structure.cells[index];
+        let weights = vec![0.0; row_heights.len()];
+        spread(&mut row_heights, &(cell.row..cell.row + cell.rows), heights[index], spacing.y, &weights);
+    }
+
+    // Place the rows, then the cells in them.
+    let grid_top = cursor;
+    let mut row_tops = Vec::with_capacity(row_heights.len());
+    let mut y = grid_top + spacing.y;
+    for height in &row_heights {
+        row_tops.push(y);
+        y += height + spacing.y;
+    }
+    let grid_width = if structure.columns == 0 { 0.0 } else { span_width(0..structure.columns).max(0.0) };
+    for (row, block) in row_blocks.iter().enumerate() {
+        if let Some(block) = block {
+            let mut block = block.write().unwrap();
+            block.position = Some(content_box.position + Vec2::new(spacing.x, row_tops[row]));
+            block.size = Some(Vec2::new(grid_width, row_heights[row]));
+        }
+    }
+    for (group, block) in structure.groups.iter().zip(&group_blocks) {
+        if let Some(block) = block {
+            let (top, bottom) = match (group.rows.start, group.rows.end) {
+                (start, end) if start < end => (row_tops[start], row_tops[end - 1] + row_heights[end - 1]),
+                (start, _) => {
+                    let top = row_tops.get(start).copied().unwrap_or(y);
+                    (top, top)
+                }
+            };
+            let mut block = block.write().unwrap();
+            block.position = Some(content_box.position + Vec2::new(spacing.x, top));
+            block.size = Some(Vec2::new(grid_width, bottom - top));
+        }
+    }
+    for (index, (cell, block)) in structure.cells.iter().zip(&cell_blocks).enumerate() {
+        let height = row_heights[cell.row..cell.row + cell.rows].iter().sum::<f32>() + spacing.y * (cell.rows - 1) as f32;
+        let free = height - heights[index];
+        let offset = match cell.style.vertical_align {
+            VerticalAlign::Top => 0.0,
+            VerticalAlign::Middle => free / 2.0,
+            VerticalAlign::Bottom => free,
+            VerticalAlign::Baseline if cell.rows == 1 => row_baselines[cell.row] - baselines[index],
+            VerticalAlign::Baseline => 0.0,
+        };
+        let origin = content_box.position + Vec2::new(column_starts[cell.column], row_tops[cell.row]);
+        let delta = origin - block.read().unwrap().position.unwrap_or(Vec2::ZERO);
+        translate(block, delta);
+        let mut block = block.write().unwrap();
+        let width = block.size.unwrap_or(Vec2::ZERO).x;
+        block.size = Some(Vec2::new(width, height));
+        for child in &block.children {
+            translate(child, Vec2::new(0.0, offset));
+        }
+    }
+    cursor = if structure.rows.is_empty() { grid_top } else { y };
+
+    // Attach the cells to their rows, the rows to their groups and the groups to the
+    // table, in order.
+    let mut cells = structure.cells.iter().zip(cell_blocks).peekable();
+    for (group, group_block) in structure.groups.iter().zip(group_blocks) {
+        let mut group_children = vec![];
+        for row in group.rows.clone() {
+            let mut row_children = vec![];
+            while let Some((_, block)) = cells.next_if(|(cell, _)| cell.row == row) {
+                row_children.push(block);
+            }
+            match &row_blocks[row] {
+                Some(row_block) => {
+                    row_block.write().unwrap().children = row_children;
+                    group_children.push(row_block.clone());
+                }
+                None => group_children.extend(row_children),
+            }
+        }
+        match group_block {
+            Some(group_block) => {
+                group_block.write().unwrap().children = group_children;
+                children.push(group_block);
+            }
+            None => children.extend(group_children),
+        }
+    }
+    place_captions(CaptionSide::Bottom, &mut cursor, &mut children);
+
+    container.write().unwrap().children.extend(children);
+    cursor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{Element, Text};
+
+    /// Returns an element with `display` and these attributes and children.
+    fn element(display: Display, attributes: &[(&str, &str)], children: Vec<Arc<RwLock<HtmlNode>>>) -> Arc<RwLock<HtmlNode>> {
+        let attributes = attributes.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        let mut node = HtmlNode::new(HtmlNodeType::Element(Element { tag: String::new(), attributes }), None);
+        node.style.display = display;
+        node.children = children;
+        Arc::new(RwLock::new(node))
+    }
+
+    /// Returns a row with these cells.
+    fn row(cells: Vec<Arc<RwLock<HtmlNode>>>) -> Arc<RwLock<HtmlNode>> {
+        element(Display::TableRow, &[], cells)
+    }
+
+    /// Returns a cell with these attributes, such as `colspan`.
+    fn cell(attributes: &[(&str, &str)]) -> Arc<RwLock<HtmlNode>> {
+        element(Display::TableCell, attributes, vec![])
+    }
+
+    /// Sorts out the structure of a table with these children, and returns where its
+    /// cells go, as `(row, column, rows, columns)`, and its number of columns.
+    fn placed(children: Vec<Arc<RwLock<HtmlNode>>>) -> (Vec<(usize, usize, usize, usize)>, usize) {
+        let structure = TableStructure::new(&element(Display::Table, &[], vec![]), children);
+        let cells = structure.cells.iter().map(|cell| (cell.row, cell.column, cell.rows, cell.columns)).collect();
+        (cells, structure.columns)
+    }
+
+    #[test]
+    fn cells_go_in_the_first_column_that_no_cell_above_spans() {
+        let rows = vec![
+            row(vec![cell(&[("colspan", "2")]), cell(&[("rowspan", "2")])]),
+            row(vec![cell(&[]), cell(&[]), cell(&[])]),
+        ];
+        assert_eq!(placed(rows), (vec![(0, 0, 1, 2), (0, 2, 2, 1), (1, 0, 1, 1), (1, 1, 1, 1), (1, 3, 1, 1)], 4));
+    }
+
+    #[test]
+    fn row_spans_stop_at_the_end_of_their_group() {
+        let body = |rows| element(Display::TableRowGroup, &[], rows);
+        let children = vec![
+            body(vec![row(vec![cell(&[("rowspan", "0")])]), row(vec![]), row(vec![])]),
+            body(vec![row(vec![cell(&[("rowspan", "5")])]), row(vec![])]),
+        ];
+        assert_eq!(placed(children), (vec![(0, 0, 3, 1), (3, 0, 2, 1)], 1));
+        // Spans that are zero, not numbers or too large fall back to one or the maximum.
+        let rows = vec![row(vec![cell(&[("colspan", "0")]), cell(&[("colspan", "x")]), cell(&[("colspan", "99999")])])];
+        assert_eq!(placed(rows), (vec![(0, 0, 1, 1), (0, 1, 1, 1), (0, 2, 1, MAX_COLSPAN)], MAX_COLSPAN + 2));
+    }
+
+    #[test]
+    fn headers_and_footers_are_laid_out_around_the_bodies() {
+        let group = |display, cells| element(display, &[], vec![row(cells)]);
+        let children = vec![
+            group(Display::TableFooterGroup, vec![cell(&[])]),
+            row(vec![cell(&[]), cell(&[])]),
+            group(Display::TableHeaderGroup, vec![cell(&[]), cell(&[]), cell(&[])]),
+        ];
+        let structure = TableStructure::new(&element(Display::Table, &[], vec![]), children);
+        let groups: Vec<(Option<Display>, Range<usize>)> = structure.groups.iter()
+            .map(|group| (group.node.as_ref().and_then(display), group.rows.clone()))
+            .collect();
+        assert_eq!(groups, [(Some(Display::TableHeaderGroup), 0..1), (None, 1..2), (Some(Display::TableFooterGroup), 2..3)]);
+        assert_eq!(structure.columns, 3);
+    }
+
+    #[test]
+    fn degenerate_tables_have_no_columns() {
+        assert_eq!(placed(vec![]), (vec![], 0));
+        assert_eq!(placed(vec![element(Display::TableCaption, &[], vec![])]), (vec![], 0));
+        assert_eq!(placed(vec![row(vec![]), row(vec![])]), (vec![], 0));
+        // Text between rows is wrapped in an anonymous cell, but white space is not.
+        let text = |text: &str| Arc::new(RwLock::new(HtmlNode::new(HtmlNodeType::Text(Text { text: text.to_string() }), None)));
+        assert_eq!(placed(vec![text("a"), row(vec![cell(&[])])]), (vec![(0, 0, 1, 1), (1, 0, 1, 1)], 1));
+        assert_eq!(spacings(10.0, 0), 0.0);
+        assert_eq!(spacings(10.0, 2), 30.0);
+    }
+
+    #[test]
+    fn columns_share_the_available_width_by_their_content() {
+        // With room for every maximum, the rest is shared in proportion to them.
+        assert_eq!(distribute_width(&[10.0, 10.0], &[100.0, 300.0], 800.0), [200.0, 600.0]);
+        // Otherwise each column gets the same fraction of its range.
+        assert_eq!(distribute_width(&[10.0, 20.0], &[110.0, 220.0], 180.0), [60.0, 120.0]);
+        assert_eq!(distribute_width(&[10.0, 20.0], &[110.0, 220.0], 10.0), [10.0, 20.0]);
+        assert_eq!(distribute_width(&[0.0, 0.0], &[0.0, 0.0], 100.0), [50.0, 50.0]);
+        assert_eq!(distribute_width(&[], &[], 100.0), Vec::<f32>::new());
+    }
+
+    #[test]
+    fn spanning_cells_widen_their_columns_by_weight() {
+        let mut sizes = vec![10.0, 20.0, 30.0];
+        spread(&mut sizes, &(0..2), 50.0, 5.0, &[1.0, 3.0, 0.0]);
+        assert_eq!(sizes, [13.75, 31.25, 30.0]);
+        spread(&mut sizes, &(1..3), 60.0, 0.0, &[0.0, 0.0, 0.0]);
+        assert_eq!(sizes, [13.75, 31.25, 30.0]);
+        spread(&mut sizes, &(1..3), 81.25, 0.0, &[0.0, 0.0, 0.0]);
+        assert_eq!(sizes, [13.75, 41.25, 40.0]);
+        spread(&mut sizes, &(0..0), 100.0, 5.0, &[1.0, 1.0, 1.0]);
+        assert_eq!(sizes, [13.75, 41.25, 40.0]);
+    }
+}
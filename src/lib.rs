@@ -0,0 +1,26 @@
+//! The ZipSurf browser engine: networking, HTML and CSS parsing, layout, scripting and
+//! the browser chrome. The `ZipSurf` binary only opens a window around it.
+
+pub mod url;
+pub mod tab;
+pub mod node;
+pub mod layout;
+pub mod html_parser;
+pub mod html_serializer;
+pub mod entities;
+pub mod css_tokenizer;
+pub mod css_parser;
+pub mod css_shorthand;
+pub mod css_calc;
+pub mod css_grid;
+pub mod selector;
+pub mod rule_index;
+pub mod style;
+pub mod media;
+pub mod browser;
+pub mod chrome;
+pub mod rect;
+pub mod js_context;
+pub mod task;
+pub mod task_runner;
+pub mod measure_time;
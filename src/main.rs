@@ -2,23 +2,8 @@ use std::env::args;
 use std::sync::{Arc, RwLock};
 use eframe::Frame;
 use egui::Ui;
-use crate::{url::Url};
-use crate::browser::Browser;
-
-mod url;
-mod tab;
-mod node;
-mod layout;
-mod html_parser;
-mod css_parser;
-mod selector;
-mod browser;
-mod chrome;
-mod rect;
-mod js_context;
-mod task;
-mod task_runner;
-mod measure_time;
+use zipsurf::url::Url;
+use zipsurf::browser::Browser;
 
 fn main() -> eframe::Result<(), eframe::Error> {
 
@@ -7,7 +7,7 @@ pub struct MeasureTime {
 }
 
 impl MeasureTime {
-    pub fn new() -> Self {
+    pub(crate) fn new() -> Self {
         let mut file = File::create("browser.trace").unwrap();
         write!(file, "{{\"traceEvents\": [").unwrap();
         let ts = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros();
@@ -0,0 +1,305 @@
+//! Media queries, for `@media` rules and the media list of `@import`.
+//!
+//! A query list such as `screen and (min-width: 600px), print` is parsed once by
+//! `CssParser` and evaluated against a [`MediaContext`] every time the page is styled,
+//! so that the rules that apply change when the viewport is resized.
+
+use crate::css_tokenizer::matching_paren;
+use crate::style::{DEFAULT_FONT_SIZE, Length, LengthContext};
+use egui::Vec2;
+
+/// The media type a page is shown on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MediaType {
+    All,
+    Screen,
+    Print,
+    /// A media type this engine does not know, such as `tv`. It never matches.
+    Unknown,
+}
+
+/// The color scheme the user prefers, for `prefers-color-scheme`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ColorScheme {
+    Light,
+    Dark,
+}
+
+/// What media queries are evaluated against.
+///
+/// # Fields
+/// - `media_type`: What the page is shown on; `Screen` in a tab.
+/// - `viewport`: The size of the viewport, for `width` and `height`.
+/// - `color_scheme`: The user's preferred color scheme.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct MediaContext {
+    pub(crate) media_type: MediaType,
+    pub(crate) viewport: Vec2,
+    pub(crate) color_scheme: ColorScheme,
+}
+
+/// How a range feature compares the viewport size with a length.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    /// Returns whether `actual <comparison> expected`.
+    fn holds(self, actual: f32, expected: f32) -> bool {
+        match self {
+            Comparison::Less => actual < expected,
+            Comparison::LessOrEqual => actual <= expected,
+            Comparison::Equal => actual == expected,
+            Comparison::GreaterOrEqual => actual >= expected,
+            Comparison::Greater => actual > expected,
+        }
+    }
+
+    /// Returns the comparison with its operands swapped, so that `600px < width`
+    /// becomes `width > 600px`.
+    fn flipped(self) -> Comparison {
+        match self {
+            Comparison::Less => Comparison::Greater,
+            Comparison::LessOrEqual => Comparison::GreaterOrEqual,
+            Comparison::Equal => Comparison::Equal,
+            Comparison::GreaterOrEqual => Comparison::LessOrEqual,
+            Comparison::Greater => Comparison::Less,
+        }
+    }
+}
+
+/// A media feature test, the part of a query in parentheses.
+///
+/// # Variants
+/// - `Width`, `Height`: Compare the viewport size with a length, from `(min-width: 600px)`,
+///   `(width: 600px)` or the range syntax `(width >= 600px)`.
+/// - `PrefersColorScheme`: `(prefers-color-scheme: dark)` or `light`.
+/// - `Unknown`: A feature this engine does not support. It never matches.
+#[derive(Clone, Debug, PartialEq)]
+enum MediaFeature {
+    Width(Comparison, Length),
+    Height(Comparison, Length),
+    PrefersColorScheme(ColorScheme),
+    Unknown,
+}
+
+impl MediaFeature {
+    /// Parses the text between the parentheses of a feature test.
+    fn parse(text: &str) -> MediaFeature {
+        let text = text.trim().to_ascii_lowercase();
+        if let Some((name, value)) = text.split_once(':') {
+            let value = value.trim();
+            let (comparison, name) = match name.trim() {
+                name if name.starts_with("min-") => (Comparison::GreaterOrEqual, &name[4..]),
+                name if name.starts_with("max-") => (Comparison::LessOrEqual, &name[4..]),
+                name => (Comparison::Equal, name),
+            };
+            return match (name, comparison) {
+                ("prefers-color-scheme", Comparison::Equal) => match value {
+                    "dark" => MediaFeature::PrefersColorScheme(ColorScheme::Dark),
+                    "light" => MediaFeature::PrefersColorScheme(ColorScheme::Light),
+                    _ => MediaFeature::Unknown,
+                },
+                ("width" | "height", comparison) => Self::range(name, comparison, value),
+                _ => MediaFeature::Unknown,
+            };
+        }
+        Self::parse_range(&text).unwrap_or(MediaFeature::Unknown)
+    }
+
+    /// Parses the range syntax of Media Queries Level 4: `width >= 600px` or
+    /// `600px < width`. Double ranges like `400px <= width <= 700px` are split into two
+    /// features by `MediaQuery::try_parse` first.
+    fn parse_range(text: &str) -> Option<MediaFeature> {
+        let mut parts = Vec::new();
+        let mut rest = text;
+        while let Some(index) = rest.find(['<', '>', '=']) {
+            parts.push(rest[..index].trim());
+            let length = if rest[index + 1..].starts_with('=') { 2 } else { 1 };
+            parts.push(&rest[index..index + length]);
+            rest = &rest[index + length..];
+        }
+        parts.push(rest.trim());
+
+        let comparison = |op: &str| match op {
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            "=" => Some(Comparison::Equal),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            ">" => Some(Comparison::Greater),
+            _ => None,
+        };
+        match parts.as_slice() {
+            [name @ ("width" | "height"), op, value] => Some(Self::range(name, comparison(op)?, value)),
+            [value, op, name @ ("width" | "height")] => Some(Self::range(name, comparison(op)?.flipped(), value)),
+            _ => None,
+        }
+    }
+
+    /// Builds a `width` or `height` feature, or `Unknown` if `value` is not a length.
+    fn range(name: &str, comparison: Comparison, value: &str) -> MediaFeature {
+        match (name, Length::parse(value)) {
+            (_, None | Some(Length::Percent(_))) => MediaFeature::Unknown,
+            ("width", Some(length)) => MediaFeature::Width(comparison, length),
+            (_, Some(length)) => MediaFeature::Height(comparison, length),
+        }
+    }
+
+    /// Evaluates the feature. Font-relative lengths are relative to the initial font
+    /// size, as in every browser.
+    fn matches(&self, context: &MediaContext) -> bool {
+        let length_context = LengthContext {
+            font_size: DEFAULT_FONT_SIZE,
+            root_font_size: DEFAULT_FONT_SIZE,
+            viewport: context.viewport,
+        };
+        match self {
+            MediaFeature::Width(comparison, length) => {
+                comparison.holds(context.viewport.x, length.to_px(&length_context, 0.0))
+            }
+            MediaFeature::Height(comparison, length) => {
+                comparison.holds(context.viewport.y, length.to_px(&length_context, 0.0))
+            }
+            MediaFeature::PrefersColorScheme(scheme) => *scheme == context.color_scheme,
+            MediaFeature::Unknown => false,
+        }
+    }
+}
+
+/// A single media query: `[not | only] <type> [and (<feature>)]*` or
+/// `(<feature>) [and (<feature>)]*`.
+#[derive(Clone, Debug, PartialEq)]
+struct MediaQuery {
+    negated: bool,
+    media_type: MediaType,
+    features: Vec<MediaFeature>,
+}
+
+impl MediaQuery {
+    /// Parses a query. Malformed queries become `not all`, which never matches, as in CSS.
+    fn parse(text: &str) -> MediaQuery {
+        Self::try_parse(text).unwrap_or(MediaQuery {
+            negated: true,
+            media_type: MediaType::All,
+            features: vec![],
+        })
+    }
+
+    /// Parses a query, or returns `None` if it is malformed.
+    fn try_parse(text: &str) -> Option<MediaQuery> {
+        let mut negated = false;
+        let mut media_type = None;
+        let mut features = Vec::new();
+        let mut expects_and = false;
+        let mut rest = text.trim();
+        while !rest.is_empty() {
+            if let Some(inner) = rest.strip_prefix('(') {
+                if expects_and {
+                    return None;
+                }
+                let end = matching_paren(inner)?;
+                let feature_text = &inner[..end];
+                // `400px <= width <= 700px` is split into its two comparisons.
+                let operators = feature_text.matches(['<', '>']).count();
+                if operators == 2 && !feature_text.contains(':') {
+                    let (low, high) = split_double_range(feature_text)?;
+                    features.push(MediaFeature::parse(&low));
+                    features.push(MediaFeature::parse(&high));
+                } else {
+                    features.push(MediaFeature::parse(feature_text));
+                }
+                rest = inner[end + 1..].trim_start();
+                expects_and = true;
+                continue;
+            }
+
+            let word_end = rest.find(|c: char| c.is_whitespace() || c == '(').unwrap_or(rest.len());
+            let word = rest[..word_end].to_ascii_lowercase();
+            rest = rest[word_end..].trim_start();
+            match word.as_str() {
+                "and" if expects_and => expects_and = false,
+                "not" if media_type.is_none() && features.is_empty() && !negated => negated = true,
+                "only" if media_type.is_none() && features.is_empty() => {}
+                _ if media_type.is_none() && features.is_empty() && !expects_and => {
+                    media_type = Some(match word.as_str() {
+                        "all" => MediaType::All,
+                        "screen" => MediaType::Screen,
+                        "print" => MediaType::Print,
+                        _ => MediaType::Unknown,
+                    });
+                    expects_and = true;
+                }
+                _ => return None,
+            }
+        }
+        if !expects_and || (negated && media_type.is_none()) {
+            return None;
+        }
+        Some(MediaQuery { negated, media_type: media_type.unwrap_or(MediaType::All), features })
+    }
+
+    /// Returns whether the query matches `context`.
+    fn matches(&self, context: &MediaContext) -> bool {
+        let type_matches = match self.media_type {
+            MediaType::All => true,
+            MediaType::Unknown => false,
+            media_type => media_type == context.media_type,
+        };
+        let matches = type_matches && self.features.iter().all(|f| f.matches(context));
+        matches != self.negated
+    }
+}
+
+/// A comma-separated list of media queries, which matches if any of its queries does.
+/// An empty list, as in a plain `@import url(a.css);`, always matches.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MediaQueryList {
+    queries: Vec<MediaQuery>,
+}
+
+impl MediaQueryList {
+    /// Parses a media query list, such as the prelude of `@media screen, (width < 40em)`.
+    pub(crate) fn parse(text: &str) -> MediaQueryList {
+        let mut queries = Vec::new();
+        let mut depth = 0usize;
+        let mut start = 0;
+        for (i, c) in text.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth = depth.saturating_sub(1),
+                ',' if depth == 0 => {
+                    queries.push(MediaQuery::parse(&text[start..i]));
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        if !text[start..].trim().is_empty() || !queries.is_empty() {
+            queries.push(MediaQuery::parse(&text[start..]));
+        }
+        MediaQueryList { queries }
+    }
+
+    /// Returns whether the list matches `context`.
+    pub(crate) fn matches(&self, context: &MediaContext) -> bool {
+        self.queries.is_empty() || self.queries.iter().any(|q| q.matches(context))
+    }
+}
+
+/// Splits `400px <= width < 700px` into `400px <= width` and `width < 700px`.
+fn split_double_range(text: &str) -> Option<(String, String)> {
+    let first = text.find(['<', '>'])?;
+    let first_length = if text[first + 1..].starts_with('=') { 2 } else { 1 };
+    let after_first = &text[first + first_length..];
+    let second = after_first.find(['<', '>'])?;
+    let name = after_first[..second].trim();
+    Some((
+        format!("{}{}", &text[..first + first_length], name),
+        format!("{}{}", name, &after_first[second..]),
+    ))
+}
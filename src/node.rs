@@ -2,6 +2,7 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::{Arc, RwLock};
+use crate::style::ComputedStyle;
 
 
 /// Represents a node in a document structure, which can either be a tag or plain text.
@@ -41,19 +42,16 @@ pub enum HtmlNodeType {
 ///   as an [`Rc`] wrapped in [`RefCell`]. If the node is the root, this
 ///   field will be `None`.
 ///
-/// * `style` - A [`HashMap`] storing the inline CSS styles associated
-///   with the node. The keys are CSS property names (as strings), and
-///   the values are the corresponding property values.
+/// * `style` - The node's [`ComputedStyle`], set by `Tab::style` from the
+///   style sheets, the `style` attribute and the parent's style.
 ///
 /// # Example
 ///
 /// ```rust
-/// use std::collections::HashMap;
 /// use std::cell::RefCell;
 /// use std::rc::Rc;
 ///
-/// let mut style = HashMap::new();
-/// style.insert(String::from("color"), String::from("red"));
+/// let style = ComputedStyle::default();
 ///
 /// let root = Rc::new(RefCell::new(HtmlNode {
 ///     node_type: HtmlNodeType::Element(String::from("div")),
@@ -72,8 +70,19 @@ pub struct HtmlNode {
     pub(crate) node_type: HtmlNodeType,
     pub(crate) children: Vec<Arc<RwLock<HtmlNode>>>,
     pub(crate) parent: Option<Arc<RwLock<HtmlNode>>>,
-    pub(crate) style: HashMap<String, String>,
+    pub(crate) style: ComputedStyle,
     pub(crate) is_focused: bool,
+    /// Whether the pointer is over this element or one of its descendants (`:hover`).
+    pub(crate) is_hovered: bool,
+    /// Whether this element or one of its descendants is being pressed (`:active`).
+    pub(crate) is_active: bool,
+    /// Whether this is a link to a URL in the global history (`:visited`).
+    pub(crate) is_visited: bool,
+    /// Whether `style` is out of date and must be recomputed on the next render. New
+    /// nodes start out dirty.
+    pub(crate) needs_style: bool,
+    /// Whether some descendant `needs_style`, so that restyling can skip clean subtrees.
+    pub(crate) child_needs_style: bool,
 }
 
 impl std::fmt::Debug for HtmlNode {
@@ -98,7 +107,7 @@ impl HtmlNode {
     /// - The specified `node_type`.
     /// - An empty list of children (`children`).
     /// - The provided parent node reference (`parent`).
-    /// - An empty style map (`style`), which can later be used to store CSS-like properties.
+    /// - The initial computed style (`style`), until `Tab::style` computes the real one.
     ///
     /// # Example
     /// ```
@@ -114,8 +123,13 @@ impl HtmlNode {
             node_type,
             children: vec![],
             parent,
-            style: HashMap::new(),
+            style: ComputedStyle::default(),
             is_focused: false,
+            is_hovered: false,
+            is_active: false,
+            is_visited: false,
+            needs_style: true,
+            child_needs_style: false,
         }
     }
 
@@ -163,6 +177,21 @@ impl HtmlNode {
         return vec;
 
     }
+
+    /// Returns the concatenated text of all descendant text nodes, in document order.
+    ///
+    /// This is the DOM `textContent` of the node, and is used to read the contents of
+    /// elements such as `<script>`, `<style>` and `<title>`.
+    pub(crate) fn text_content(&self) -> String {
+        match &self.node_type {
+            HtmlNodeType::Text(t) => t.text.clone(),
+            HtmlNodeType::Element(_) => self
+                .children
+                .iter()
+                .map(|child| child.read().unwrap().text_content())
+                .collect(),
+        }
+    }
 }
 
     /// A struct representing a tag.
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, RwLock};
+
+use crate::css_parser::StyleRule;
+use crate::node::{HtmlNode, HtmlNodeType};
+use crate::selector::{Selector, SelectorType};
+
+/// The number of counters in an `AncestorFilter`. A power of two, so that a hash can be
+/// reduced to an index with a mask.
+const FILTER_SIZE: usize = 4096;
+
+/// A list of style rules, indexed so that the rules that may match an element can be
+/// found without trying every selector against it.
+///
+/// Each rule is filed in one bucket, by the rightmost compound selector of its selector
+/// (the part that must match the element itself):
+/// - by its ID if it has one, as for `ul #main`,
+/// - otherwise by one of its classes, as for `div.note > .title`,
+/// - otherwise by its tag, as for `nav a`,
+/// - otherwise in the universal bucket, as for `*`, `[href]` or `:hover`.
+///
+/// An element then only needs to be matched against the rules of the buckets of its ID,
+/// its classes and its tag, and those of the universal bucket.
+///
+/// # Fields
+/// - `rules`: The rules, in source order. Rules are referred to by their position here.
+/// - `by_id`, `by_class`, `by_tag`: The buckets, by the ID, class or tag of the rules'
+///   rightmost compound selector.
+/// - `universal`: The rules that must be tried against every element.
+/// - `ancestor_hashes`: For each rule, the hashes of the IDs, classes and tags that its
+///   selector requires of the element's ancestors, for rejecting it early with an
+///   `AncestorFilter`.
+pub(crate) struct RuleIndex {
+    rules: Vec<StyleRule>,
+    by_id: HashMap<String, Vec<usize>>,
+    by_class: HashMap<String, Vec<usize>>,
+    by_tag: HashMap<String, Vec<usize>>,
+    universal: Vec<usize>,
+    ancestor_hashes: Vec<Vec<u64>>,
+}
+
+/// What a rule is bucketed by in a `RuleIndex`.
+enum RuleKey<'a> {
+    Id(&'a str),
+    Class(&'a str),
+    Tag(&'a str),
+}
+
+impl RuleIndex {
+    /// Indexes `rules`, which keep their order.
+    pub(crate) fn new(rules: Vec<StyleRule>) -> Self {
+        let mut index = RuleIndex {
+            rules: vec![],
+            by_id: HashMap::new(),
+            by_class: HashMap::new(),
+            by_tag: HashMap::new(),
+            universal: vec![],
+            ancestor_hashes: vec![],
+        };
+        for (position, (selector, _)) in rules.iter().enumerate() {
+            match rule_key(selector) {
+                Some(RuleKey::Id(id)) => index.by_id.entry(id.to_string()).or_default().push(position),
+                Some(RuleKey::Class(class)) => index.by_class.entry(class.to_string()).or_default().push(position),
+                Some(RuleKey::Tag(tag)) => index.by_tag.entry(tag.to_string()).or_default().push(position),
+                None => index.universal.push(position),
+            }
+            let mut hashes = vec![];
+            add_ancestor_hashes(selector, false, &mut hashes);
+            index.ancestor_hashes.push(hashes);
+        }
+        index.rules = rules;
+        index
+    }
+
+    /// Returns the rules, in source order.
+    pub(crate) fn rules(&self) -> &[StyleRule] {
+        &self.rules
+    }
+
+    /// Returns the positions in `rules()` of the rules whose selector matches `node`, in
+    /// source order.
+    ///
+    /// # Parameters
+    /// - `node`: The node to match. Text nodes match no rule.
+    /// - `filter`: The IDs, classes and tags of the ancestors of `node`. Rules that need
+    ///   an ancestor that is not in it are rejected without walking the ancestors.
+    pub(crate) fn matching(&self, node: &Arc<RwLock<HtmlNode>>, filter: &AncestorFilter) -> Vec<usize> {
+        let mut candidates = {
+            let node_ref = node.read().unwrap();
+            let HtmlNodeType::Element(element) = &node_ref.node_type else {
+                return vec![];
+            };
+            let mut candidates = self.universal.clone();
+            if let Some(rules) = self.by_tag.get(&element.tag) {
+                candidates.extend(rules);
+            }
+            if let Some(rules) = element.attributes.get("id").and_then(|id| self.by_id.get(id)) {
+                candidates.extend(rules);
+            }
+            for class in element.attributes.get("class").into_iter().flat_map(|class| class.split_ascii_whitespace()) {
+                if let Some(rules) = self.by_class.get(class) {
+                    candidates.extend(rules);
+                }
+            }
+            candidates
+        };
+        // Restore the source order, which the cascade relies on. A class listed twice
+        // would otherwise return its rules twice.
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates.retain(|&position| {
+            self.ancestor_hashes[position].iter().all(|&hash| filter.may_contain(hash))
+                && self.rules[position].0.matches(node.clone())
+        });
+        candidates
+    }
+}
+
+/// Returns what a selector is bucketed by: the most selective of the ID, class and tag
+/// selectors of its rightmost compound, or `None` for the universal bucket.
+fn rule_key(selector: &Selector) -> Option<RuleKey<'_>> {
+    match &selector.selector {
+        SelectorType::Id { id } => Some(RuleKey::Id(id)),
+        SelectorType::Class { class } => Some(RuleKey::Class(class)),
+        SelectorType::Tag { tag } => Some(RuleKey::Tag(tag)),
+        SelectorType::Compound { selectors } => selectors.iter()
+            .filter_map(rule_key)
+            .min_by_key(|key| match key {
+                RuleKey::Id(_) => 0,
+                RuleKey::Class(_) => 1,
+                RuleKey::Tag(_) => 2,
+            }),
+        SelectorType::Descendant { descendant: subject, .. }
+        | SelectorType::Child { child: subject, .. }
+        | SelectorType::NextSibling { next: subject, .. }
+        | SelectorType::SubsequentSibling { next: subject, .. } => rule_key(subject),
+        SelectorType::Is { selectors } if selectors.len() == 1 => rule_key(&selectors[0]),
+        _ => None,
+    }
+}
+
+/// Adds to `hashes` the hashes of the IDs, classes and tags that `selector` requires of
+/// the ancestors of the element it matches. `is_ancestor` tells whether the compound
+/// selectors reached so far must match an ancestor.
+///
+/// The left side of `div p` or `div > p` is an ancestor. The left side of `h1 + p` is
+/// not, but its own ancestors are those of the element, so that in `div h1 + p`, `div`
+/// is. Selectors inside `:not()` and `:is()` are not required, and are left out.
+fn add_ancestor_hashes(selector: &Selector, is_ancestor: bool, hashes: &mut Vec<u64>) {
+    match &selector.selector {
+        SelectorType::Id { id } if is_ancestor => hashes.push(key_hash('#', id)),
+        SelectorType::Class { class } if is_ancestor => hashes.push(key_hash('.', class)),
+        SelectorType::Tag { tag } if is_ancestor => hashes.push(key_hash(' ', tag)),
+        SelectorType::Compound { selectors } => {
+            for selector in selectors {
+                add_ancestor_hashes(selector, is_ancestor, hashes);
+            }
+        }
+        SelectorType::Descendant { ancestor: left, descendant: right }
+        | SelectorType::Child { parent: left, child: right } => {
+            add_ancestor_hashes(right, is_ancestor, hashes);
+            add_ancestor_hashes(left, true, hashes);
+        }
+        SelectorType::NextSibling { previous: left, next: right }
+        | SelectorType::SubsequentSibling { previous: left, next: right } => {
+            add_ancestor_hashes(right, is_ancestor, hashes);
+            add_ancestor_hashes(left, false, hashes);
+        }
+        _ => {}
+    }
+}
+
+/// Hashes an ID (`kind` `#`), a class (`.`) or a tag (` `) for an `AncestorFilter`.
+fn key_hash(kind: char, value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    kind.hash(&mut hasher);
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A counting Bloom filter of the IDs, classes and tags of the ancestors of the node
+/// being styled.
+///
+/// Styling walks the tree depth first, pushing each element before its children and
+/// popping it after them. A selector like `.sidebar a` can then be rejected for a link
+/// outside of any `.sidebar` without walking up to the root: if the filter does not
+/// contain `.sidebar`, no ancestor has that class.
+///
+/// # Notes
+/// - Like any Bloom filter, it may report a key that is not there, in which case the
+///   selector is matched normally. It never misses a key that is there.
+/// - Counters, rather than bits, allow an element to be removed.
+pub(crate) struct AncestorFilter {
+    counters: Vec<u16>,
+}
+
+impl AncestorFilter {
+    /// Returns a filter containing the ancestors of `node`, for styling `node` and its
+    /// subtree.
+    pub(crate) fn for_node(node: &Arc<RwLock<HtmlNode>>) -> Self {
+        let mut filter = AncestorFilter { counters: vec![0; FILTER_SIZE] };
+        let mut current = node.read().unwrap().parent.clone();
+        while let Some(ancestor) = current {
+            filter.push(&ancestor);
+            current = ancestor.read().unwrap().parent.clone();
+        }
+        filter
+    }
+
+    /// Adds the ID, classes and tag of `node`, before styling its children.
+    pub(crate) fn push(&mut self, node: &Arc<RwLock<HtmlNode>>) {
+        for_each_key_hash(node, |hash| {
+            for index in counter_indices(hash) {
+                self.counters[index] = self.counters[index].saturating_add(1);
+            }
+        });
+    }
+
+    /// Removes the ID, classes and tag of `node`, after styling its children.
+    pub(crate) fn pop(&mut self, node: &Arc<RwLock<HtmlNode>>) {
+        for_each_key_hash(node, |hash| {
+            for index in counter_indices(hash) {
+                self.counters[index] = self.counters[index].saturating_sub(1);
+            }
+        });
+    }
+
+    /// Returns `false` if no ancestor has the ID, class or tag with this hash, and `true`
+    /// if one probably does.
+    fn may_contain(&self, hash: u64) -> bool {
+        counter_indices(hash).into_iter().all(|index| self.counters[index] > 0)
+    }
+}
+
+/// Calls `f` with the hash of the ID, of each class and of the tag of `node`, if it is an
+/// element.
+fn for_each_key_hash(node: &Arc<RwLock<HtmlNode>>, mut f: impl FnMut(u64)) {
+    let node = node.read().unwrap();
+    let HtmlNodeType::Element(element) = &node.node_type else {
+        return;
+    };
+    f(key_hash(' ', &element.tag));
+    if let Some(id) = element.attributes.get("id") {
+        f(key_hash('#', id));
+    }
+    for class in element.attributes.get("class").into_iter().flat_map(|class| class.split_ascii_whitespace()) {
+        f(key_hash('.', class));
+    }
+}
+
+/// Returns the two counters of the filter that a hash sets, from two independent parts
+/// of it.
+fn counter_indices(hash: u64) -> [usize; 2] {
+    [hash as usize & (FILTER_SIZE - 1), (hash >> 32) as usize & (FILTER_SIZE - 1)]
+}
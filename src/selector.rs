@@ -1,7 +1,8 @@
 use std::sync::RwLock;
 use std::{cell::RefCell, sync::Arc};
 use std::rc::Rc;
-use crate::node::{HtmlNode, HtmlNodeType};
+use crate::css_parser::StyleRule;
+use crate::node::{Element, HtmlNode, HtmlNodeType};
 
 /// A struct representing a generic selector used to encapsulate some selection mechanism.
 ///
@@ -37,12 +38,36 @@ pub struct Selector {
 ///
 /// # Variants
 ///
-/// - `Tag`:
-///   Represents a selector based on a tag name. This is typically used
-///   to match elements by their tag, such as `<div>` or `<span>`.
-///
-///   - `tag`:
-///     A `String` representing the name of the tag to be matched.
+/// - `Tag`: Matches elements by their tag name, such as `div` or `span`.
+/// - `Universal`: The `*` selector, which matches every element.
+/// - `Class`: Matches elements whose whitespace-separated `class` attribute contains
+///   `class`, as in `.note`.
+/// - `Id`: Matches the element whose `id` attribute equals `id`, as in `#main`.
+/// - `Attribute`: Matches elements by an attribute, as in `[href]` or `[data-x="1"]`.
+///   See [`AttributeMatcher`] for the supported operators.
+/// - `Compound`: A sequence of simple selectors that must all match the same element,
+///   as in `div.note#main`.
+/// - `Descendant`: Matches elements matched by `descendant` that have an ancestor
+///   matched by `ancestor`, as in `div span`.
+/// - `Child`: Matches elements matched by `child` whose parent is matched by `parent`,
+///   as in `ul > li`.
+/// - `NextSibling`: Matches elements matched by `next` that immediately follow an
+///   element matched by `previous`, as in `h1 + p`.
+/// - `SubsequentSibling`: Matches elements matched by `next` that follow an element
+///   matched by `previous` among the children of the same parent, as in `h1 ~ p`.
+/// - `FirstChild`, `LastChild`: The `:first-child` and `:last-child` pseudo-classes.
+/// - `NthChild`: The `:nth-child(an+b)` pseudo-class, which matches the elements whose
+///   1-based position among their element siblings is `a*n + b` for some `n >= 0`.
+/// - `Root`: The `:root` pseudo-class, which matches the document element.
+/// - `Empty`: The `:empty` pseudo-class, which matches elements without children
+///   other than empty text.
+/// - `Not`, `Is`: The `:not()` and `:is()` pseudo-classes, which match the elements
+///   matched by none, respectively any, of `selectors`.
+/// - `Hover`, `Active`, `Focus`, `FocusVisible`, `Visited`: The dynamic pseudo-classes,
+///   which read the state the tab keeps on each `HtmlNode` (`is_hovered`, `is_active`,
+///   `is_focused` and `is_visited`).
+/// - `Checked`: The `:checked` pseudo-class, which matches checked checkboxes and radio
+///   buttons and selected `<option>` elements.
 ///
 /// # Example
 ///
@@ -57,7 +82,93 @@ pub struct Selector {
 #[derive(Clone)]
 pub enum SelectorType {
     Tag { tag: String },
-    Descendant { ancestor: Box<Selector>, descendant: Box<Selector>}
+    Universal,
+    Class { class: String },
+    Id { id: String },
+    Attribute { name: String, matcher: AttributeMatcher, case_insensitive: bool },
+    Compound { selectors: Vec<Selector> },
+    Descendant { ancestor: Box<Selector>, descendant: Box<Selector>},
+    Child { parent: Box<Selector>, child: Box<Selector> },
+    NextSibling { previous: Box<Selector>, next: Box<Selector> },
+    SubsequentSibling { previous: Box<Selector>, next: Box<Selector> },
+    FirstChild,
+    LastChild,
+    NthChild { a: i32, b: i32 },
+    Root,
+    Empty,
+    Not { selectors: Vec<Selector> },
+    Is { selectors: Vec<Selector> },
+    Hover,
+    Active,
+    Focus,
+    FocusVisible,
+    Visited,
+    Checked,
+}
+
+/// How an attribute selector compares the attribute's value with the selector's value.
+///
+/// # Variants
+///
+/// - `Exists`: `[name]`, the attribute is present, whatever its value.
+/// - `Equals`: `[name=value]`, the value is exactly `value`.
+/// - `Includes`: `[name~=value]`, the value is a whitespace-separated list containing `value`.
+/// - `DashMatch`: `[name|=value]`, the value is `value` or starts with `value` followed by `-`,
+///   as used for language codes like `en-US`.
+/// - `Prefix`: `[name^=value]`, the value starts with `value`.
+/// - `Suffix`: `[name$=value]`, the value ends with `value`.
+/// - `Substring`: `[name*=value]`, the value contains `value`.
+///
+/// As in CSS, `Includes`, `Prefix`, `Suffix` and `Substring` never match when `value`
+/// is empty, and `Includes` never matches when `value` contains whitespace.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttributeMatcher {
+    Exists,
+    Equals(String),
+    Includes(String),
+    DashMatch(String),
+    Prefix(String),
+    Suffix(String),
+    Substring(String),
+}
+
+impl AttributeMatcher {
+    /// Returns `true` if the attribute value `actual` satisfies this matcher. With
+    /// `case_insensitive` (the `i` flag, as in `[type="a" i]`), ASCII letters are
+    /// compared case-insensitively.
+    pub(crate) fn matches(&self, actual: &str, case_insensitive: bool) -> bool {
+        let (actual, expected) = match self {
+            AttributeMatcher::Exists => return true,
+            AttributeMatcher::Equals(v)
+            | AttributeMatcher::Includes(v)
+            | AttributeMatcher::DashMatch(v)
+            | AttributeMatcher::Prefix(v)
+            | AttributeMatcher::Suffix(v)
+            | AttributeMatcher::Substring(v) => {
+                if case_insensitive {
+                    (actual.to_ascii_lowercase(), v.to_ascii_lowercase())
+                } else {
+                    (actual.to_string(), v.clone())
+                }
+            }
+        };
+
+        match self {
+            AttributeMatcher::Exists => true,
+            AttributeMatcher::Equals(_) => actual == expected,
+            AttributeMatcher::Includes(_) => {
+                !expected.is_empty()
+                    && !expected.contains(|c: char| c.is_ascii_whitespace())
+                    && actual.split_ascii_whitespace().any(|word| word == expected)
+            }
+            AttributeMatcher::DashMatch(_) => {
+                actual == expected || actual.starts_with(&format!("{}-", expected))
+            }
+            AttributeMatcher::Prefix(_) => !expected.is_empty() && actual.starts_with(&expected),
+            AttributeMatcher::Suffix(_) => !expected.is_empty() && actual.ends_with(&expected),
+            AttributeMatcher::Substring(_) => !expected.is_empty() && actual.contains(&expected),
+        }
+    }
 }
 
 impl Selector {
@@ -82,6 +193,12 @@ impl Selector {
     ///     the current node and there exists an ancestor node in the DOM tree that
     ///     matches the `ancestor` selector. This works by traversing the parent chain
     ///     of the current node.
+    ///   - `SelectorType::Child`, `NextSibling` and `SubsequentSibling` work the same
+    ///     way, but only look at the parent, the previous element sibling or all the
+    ///     previous element siblings, respectively.
+    ///   - The structural pseudo-classes look at the node's position among its element
+    ///     siblings (text nodes do not count), or at its children for `:empty`.
+    /// - Text nodes never match.
     ///
     /// # Note
     /// - For `SelectorType::Descendant`, if the `descendant` selector does not match
@@ -99,15 +216,10 @@ impl Selector {
     /// }
     /// ```
     pub(crate) fn matches(&self, mut node: Arc<RwLock<HtmlNode>>) -> bool {
+        if !matches!(node.read().unwrap().node_type, HtmlNodeType::Element(_)) {
+            return false;
+        }
         match &self.selector {
-            SelectorType::Tag { tag } => {
-                match &node.read().unwrap().node_type {
-                    HtmlNodeType::Element(e) => {
-                        e.tag == *tag
-                    }
-                    HtmlNodeType::Text(_) => {false}
-                }
-            }
             SelectorType::Descendant { descendant, ancestor } => {
                 if !descendant.matches(node.clone()) {return false}
                 while node.read().unwrap().parent.is_some() {
@@ -119,40 +231,411 @@ impl Selector {
                 }
                 false
             }
+            SelectorType::Child { parent, child } => {
+                if !child.matches(node.clone()) {return false}
+                let parent_node = node.read().unwrap().parent.clone();
+                parent_node.is_some_and(|p| parent.matches(p))
+            }
+            SelectorType::NextSibling { previous, next } => {
+                if !next.matches(node.clone()) {return false}
+                let (siblings, index) = element_siblings(&node);
+                index > 0 && previous.matches(siblings[index - 1].clone())
+            }
+            SelectorType::SubsequentSibling { previous, next } => {
+                if !next.matches(node.clone()) {return false}
+                let (siblings, index) = element_siblings(&node);
+                siblings[..index].iter().any(|sibling| previous.matches(sibling.clone()))
+            }
+            SelectorType::Compound { selectors } => {
+                selectors.iter().all(|selector| selector.matches(node.clone()))
+            }
+            SelectorType::Not { selectors } => {
+                !selectors.iter().any(|selector| selector.matches(node.clone()))
+            }
+            SelectorType::Is { selectors } => {
+                selectors.iter().any(|selector| selector.matches(node.clone()))
+            }
+            SelectorType::FirstChild => element_siblings(&node).1 == 0,
+            SelectorType::LastChild => {
+                let (siblings, index) = element_siblings(&node);
+                index + 1 == siblings.len()
+            }
+            SelectorType::NthChild { a, b } => {
+                let position = element_siblings(&node).1 as i64 + 1;
+                let (a, b) = (*a as i64, *b as i64);
+                match a {
+                    0 => position == b,
+                    _ => (position - b) % a == 0 && (position - b) / a >= 0,
+                }
+            }
+            SelectorType::Root => node.read().unwrap().parent.is_none(),
+            SelectorType::Empty => node.read().unwrap().children.iter().all(|child| {
+                matches!(&child.read().unwrap().node_type, HtmlNodeType::Text(t) if t.text.is_empty())
+            }),
+            SelectorType::Hover => node.read().unwrap().is_hovered,
+            SelectorType::Active => node.read().unwrap().is_active,
+            SelectorType::Focus => node.read().unwrap().is_focused,
+            // Only text inputs can take focus, and browsers always show focus on those.
+            SelectorType::FocusVisible => node.read().unwrap().is_focused,
+            SelectorType::Visited => node.read().unwrap().is_visited,
+            simple => {
+                match &node.read().unwrap().node_type {
+                    HtmlNodeType::Element(e) => Self::matches_element(simple, e),
+                    HtmlNodeType::Text(_) => {false}
+                }
+            }
+        }
+    }
+
+    /// Matches a simple selector (a tag, `*`, class, ID or attribute selector) against
+    /// a single element, without looking at the rest of the tree.
+    fn matches_element(selector: &SelectorType, element: &Element) -> bool {
+        match selector {
+            SelectorType::Tag { tag } => element.tag == *tag,
+            SelectorType::Universal => true,
+            SelectorType::Class { class } => element.attributes.get("class")
+                .is_some_and(|classes| classes.split_ascii_whitespace().any(|c| c == class)),
+            SelectorType::Id { id } => element.attributes.get("id").is_some_and(|value| value == id),
+            SelectorType::Attribute { name, matcher, case_insensitive } => element.attributes.get(name)
+                .is_some_and(|value| matcher.matches(value, *case_insensitive)),
+            SelectorType::Checked => match element.tag.as_str() {
+                "input" => element.attributes.contains_key("checked")
+                    && element.attributes.get("type")
+                        .is_some_and(|t| t.eq_ignore_ascii_case("checkbox") || t.eq_ignore_ascii_case("radio")),
+                "option" => element.attributes.contains_key("selected"),
+                _ => false,
+            },
+            _ => false,
         }
     }
-    
-    /// Computes the priority of the current `SelectorType`.
+
+    /// Computes the specificity of the selector as an `(id, class, type)` triple, as
+    /// defined by CSS Selectors Level 3.
     ///
     /// # Returns
-    /// * An `i32` representing the priority of the selector:
-    ///   - If the selector is of type `Tag`, it returns a fixed priority of `1`.
-    ///   - If the selector is of type `Descendant`, it recursively computes the priority
-    ///     by summing the priorities of its `ancestor` and `descendant` selectors.
+    /// * A tuple counting, in order:
+    ///   - the ID selectors (`#main`),
+    ///   - the class and attribute selectors (`.note`, `[href]`),
+    ///   - the type selectors (`div`).
     ///
-    /// # Behavior
-    /// This function matches the `SelectorType` of the current instance:
-    /// - For `SelectorType::Tag`, a fixed priority of `1` is assigned.
-    /// - For `SelectorType::Descendant`, the priority is calculated based on the priorities
-    ///   of the `ancestor` and `descendant` selectors.
+    ///   The universal selector `*` counts for nothing and other pseudo-classes count
+    ///   as classes. Compound selectors and combinators add up the specificities of
+    ///   their parts, while `:not()` and `:is()` take the specificity of their most
+    ///   specific argument.
+    ///
+    /// # Notes
+    /// Tuples compare lexicographically, so one ID selector outweighs any number of
+    /// class selectors, which is the order `Tab::load` sorts style rules in.
     ///
     /// # Examples
     /// ```rust
-    /// let tag_selector = Selector::new(SelectorType::Tag { name: "div".to_string() });
-    /// assert_eq!(tag_selector.priority(), 1);
-    ///
-    /// let descendant_selector = Selector::new(SelectorType::Descendant {
-    ///     ancestor: Box::new(tag_selector),
-    ///     descendant: Box::new(Selector::new(SelectorType::Tag { name: "span".to_string() })),
-    /// });
-    /// assert_eq!(descendant_selector.priority(), 2); // 1 (ancestor) + 1 (descendant)
+    /// let selector = CssParser::new("div.note#main p").selector().unwrap();
+    /// assert_eq!(selector.specificity(), (1, 1, 2));
     /// ```
-    pub(crate) fn priority(&self) -> i32 {
+    pub(crate) fn specificity(&self) -> (u32, u32, u32) {
         match &self.selector {
-            SelectorType::Tag { .. } => {1}
-            SelectorType::Descendant { ancestor, descendant } => {
-                ancestor.priority() + descendant.priority()
+            SelectorType::Tag { .. } => (0, 0, 1),
+            SelectorType::Universal => (0, 0, 0),
+            SelectorType::Class { .. } | SelectorType::Attribute { .. } => (0, 1, 0),
+            SelectorType::FirstChild | SelectorType::LastChild | SelectorType::NthChild { .. }
+            | SelectorType::Root | SelectorType::Empty => (0, 1, 0),
+            SelectorType::Hover | SelectorType::Active | SelectorType::Focus | SelectorType::FocusVisible
+            | SelectorType::Visited | SelectorType::Checked => (0, 1, 0),
+            SelectorType::Not { selectors } | SelectorType::Is { selectors } => selectors.iter()
+                .map(|selector| selector.specificity())
+                .max()
+                .unwrap_or((0, 0, 0)),
+            SelectorType::Id { .. } => (1, 0, 0),
+            SelectorType::Compound { selectors } => selectors.iter()
+                .map(|selector| selector.specificity())
+                .fold((0, 0, 0), add_specificity),
+            SelectorType::Descendant { ancestor: first, descendant: second }
+            | SelectorType::Child { parent: first, child: second }
+            | SelectorType::NextSibling { previous: first, next: second }
+            | SelectorType::SubsequentSibling { previous: first, next: second } => {
+                add_specificity(first.specificity(), second.specificity())
+            }
+        }
+    }
+}
+
+/// What a change to an element, such as a new attribute or `:hover` state, can affect
+/// besides its own style, given the selectors of the style sheets in use.
+///
+/// Used to invalidate as few styles as possible after a change (see
+/// `Tab::invalidate_style`).
+///
+/// # Fields
+/// - `descendants`: Some selector has a descendant or child combinator (`div p`,
+///   `ul > li`), so the change can make the element's descendants match other rules.
+/// - `siblings`: Some selector has a sibling combinator (`h1 + p`, `h1 ~ p`), so the
+///   change can make the following siblings of the element match other rules.
+/// - `hover`, `active`, `focus`, `visited`: Some selector uses the corresponding
+///   dynamic pseudo-class (`:focus-visible` counts as `:focus`). If none does, a change
+///   of that state restyles nothing.
+/// - `state_descendants`, `state_siblings`: Some selector has a dynamic pseudo-class
+///   left of a descendant or child combinator (`div:hover p`), respectively of a sibling
+///   combinator (`a:focus + span`). If none does, a change of state only affects the
+///   element's own style.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct SelectorDependencies {
+    pub(crate) descendants: bool,
+    pub(crate) siblings: bool,
+    pub(crate) state_descendants: bool,
+    pub(crate) state_siblings: bool,
+    pub(crate) hover: bool,
+    pub(crate) active: bool,
+    pub(crate) focus: bool,
+    pub(crate) visited: bool,
+}
+
+impl SelectorDependencies {
+    /// Returns the dependencies of every selector in `rules`.
+    pub(crate) fn of<'a>(rules: impl IntoIterator<Item = &'a StyleRule>) -> Self {
+        let mut dependencies = Self::default();
+        for (selector, _) in rules {
+            dependencies.add(selector);
+        }
+        dependencies
+    }
+
+    /// Adds the dependencies of `selector`, including those of the selectors nested in
+    /// its combinators, `:not()` and `:is()`.
+    fn add(&mut self, selector: &Selector) {
+        match &selector.selector {
+            SelectorType::Descendant { ancestor: first, descendant: second }
+            | SelectorType::Child { parent: first, child: second } => {
+                self.descendants = true;
+                self.state_descendants |= has_dynamic_pseudo_class(first);
+                self.add(first);
+                self.add(second);
             }
+            SelectorType::NextSibling { previous: first, next: second }
+            | SelectorType::SubsequentSibling { previous: first, next: second } => {
+                self.siblings = true;
+                self.state_siblings |= has_dynamic_pseudo_class(first);
+                self.add(first);
+                self.add(second);
+            }
+            SelectorType::Compound { selectors } | SelectorType::Not { selectors } | SelectorType::Is { selectors } => {
+                for selector in selectors {
+                    self.add(selector);
+                }
+            }
+            SelectorType::Hover => self.hover = true,
+            SelectorType::Active => self.active = true,
+            SelectorType::Focus | SelectorType::FocusVisible => self.focus = true,
+            SelectorType::Visited => self.visited = true,
+            _ => {}
+        }
+    }
+}
+
+/// Returns `true` if `selector` or a selector nested in it uses a dynamic pseudo-class.
+fn has_dynamic_pseudo_class(selector: &Selector) -> bool {
+    let mut dependencies = SelectorDependencies::default();
+    dependencies.add(selector);
+    dependencies.hover || dependencies.active || dependencies.focus || dependencies.visited
+}
+
+/// Adds two specificity triples component by component.
+fn add_specificity(a: (u32, u32, u32), b: (u32, u32, u32)) -> (u32, u32, u32) {
+    (a.0 + b.0, a.1 + b.1, a.2 + b.2)
+}
+
+/// Returns the element children of `node`'s parent, and the index of `node` among them.
+/// The root element is treated as the only child of the document.
+fn element_siblings(node: &Arc<RwLock<HtmlNode>>) -> (Vec<Arc<RwLock<HtmlNode>>>, usize) {
+    let Some(parent) = node.read().unwrap().parent.clone() else {
+        return (vec![node.clone()], 0);
+    };
+    let siblings = parent.read().unwrap().children.iter()
+        .filter(|child| matches!(child.read().unwrap().node_type, HtmlNodeType::Element(_)))
+        .cloned()
+        .collect::<Vec<_>>();
+    match siblings.iter().position(|sibling| Arc::ptr_eq(sibling, node)) {
+        Some(index) => (siblings, index),
+        None => (vec![node.clone()], 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css_parser::CssParser;
+    use crate::html_parser::HtmlParser;
+
+    fn selector(text: &str) -> Selector {
+        CssParser::new(text).selector().unwrap_or_else(|e| panic!("{} does not parse: {}", text, e))
+    }
+
+    /// Returns the element with `id="target"` in the document parsed from `html`.
+    fn target(html: &str) -> Arc<RwLock<HtmlNode>> {
+        let root = HtmlParser::new(html.to_string()).parse();
+        HtmlNode::tree_to_vec(root, &mut vec![]).iter()
+            .find(|node| matches!(&node.read().unwrap().node_type,
+                HtmlNodeType::Element(e) if e.attributes.get("id").is_some_and(|id| id == "target")))
+            .cloned()
+            .expect("no element with id=target")
+    }
+
+    #[test]
+    fn attribute_operators_compare_values() {
+        let cases = [
+            (AttributeMatcher::Exists, "", true),
+            (AttributeMatcher::Equals("a b".to_string()), "a b", true),
+            (AttributeMatcher::Equals("a".to_string()), "a b", false),
+            (AttributeMatcher::Includes("b".to_string()), "a  b\tc", true),
+            (AttributeMatcher::Includes("b".to_string()), "ab bc", false),
+            (AttributeMatcher::DashMatch("en".to_string()), "en", true),
+            (AttributeMatcher::DashMatch("en".to_string()), "en-US", true),
+            (AttributeMatcher::DashMatch("en".to_string()), "english", false),
+            (AttributeMatcher::Prefix("http".to_string()), "https://a", true),
+            (AttributeMatcher::Prefix("https".to_string()), "http://a", false),
+            (AttributeMatcher::Suffix(".pdf".to_string()), "a.pdf", true),
+            (AttributeMatcher::Suffix(".pdf".to_string()), "a.pdf.html", false),
+            (AttributeMatcher::Substring("mid".to_string()), "amidst", true),
+            (AttributeMatcher::Substring("mad".to_string()), "amidst", false),
+        ];
+        for (matcher, actual, expected) in cases {
+            assert_eq!(matcher.matches(actual, false), expected, "{:?} against {:?}", matcher, actual);
+        }
+    }
+
+    #[test]
+    fn empty_attribute_values_match_only_equals_and_dash_match() {
+        for matcher in [AttributeMatcher::Equals(String::new()), AttributeMatcher::DashMatch(String::new())] {
+            assert!(matcher.matches("", false), "{:?}", matcher);
+        }
+        for matcher in [AttributeMatcher::Includes(String::new()), AttributeMatcher::Prefix(String::new()),
+            AttributeMatcher::Suffix(String::new()), AttributeMatcher::Substring(String::new())] {
+            assert!(!matcher.matches("", false), "{:?}", matcher);
+            assert!(!matcher.matches("a", false), "{:?}", matcher);
+        }
+        // A value with white space can never be one of the words of a list.
+        assert!(!AttributeMatcher::Includes("a b".to_string()).matches("a b", false));
+    }
+
+    #[test]
+    fn the_i_flag_ignores_ascii_case() {
+        let node = target("<input id=target type=CheckBox lang=EN-gb data-x=\"É\">");
+        for text in ["[type=checkbox i]", "[type='CHECKBOX' I]", "[lang|=en i]", "[TYPE^=check i]", "[type$=BOX i]",
+            "[type=CheckBox]", "[type=CheckBox s]", "[data-x=É i]"] {
+            assert!(selector(text).matches(node.clone()), "{} should match", text);
+        }
+        for text in ["[type=checkbox]", "[type=checkbox s]", "[lang|=en]", "[data-x=é i]"] {
+            assert!(!selector(text).matches(node.clone()), "{} should not match", text);
+        }
+    }
+
+    #[test]
+    fn class_id_and_attribute_selectors_match_elements() {
+        let node = target("<a id=target class=\"btn  primary\" href=\"/docs/a.pdf\" rel=\"noopener external\">x</a>");
+        for text in ["#target", ".btn", ".primary.btn", "a.btn#target", "[href]", "[HREF]", "[rel~=external]",
+            "[href^='/docs/'][href$=\".pdf\"]", "[href*=cs]", "a[class=\"btn  primary\"]"] {
+            assert!(selector(text).matches(node.clone()), "{} should match", text);
+        }
+        for text in ["#Target", ".btn-primary", ".prim", "[title]", "[rel~=noopener\\ external]", "[href$=PDF]", "p.btn"] {
+            assert!(!selector(text).matches(node.clone()), "{} should not match", text);
+        }
+    }
+
+    #[test]
+    fn specificity_counts_ids_classes_and_types() {
+        let cases = [
+            ("*", (0, 0, 0)),
+            ("li", (0, 0, 1)),
+            ("ul li", (0, 0, 2)),
+            ("ul ol + li", (0, 0, 3)),
+            ("h1 + *[rel=up]", (0, 1, 1)),
+            ("ul ol li.red", (0, 1, 3)),
+            ("li.red.level", (0, 2, 1)),
+            ("#x34y", (1, 0, 0)),
+            ("div.note#main > p:first-child", (1, 2, 2)),
+            ("a:hover[href]", (0, 2, 1)),
+            ("#s12:not(foo)", (1, 0, 1)),
+            (":is(#a, .b) span", (1, 0, 1)),
+            (":not(.a, p.b) > *", (0, 1, 1)),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(selector(text).specificity(), expected, "{}", text);
+        }
+        assert!(selector("#a").specificity() > selector(".a.b.c.d.e.f.g.h.i.j.k").specificity());
+    }
+
+    /// Returns the flag of a node that a dynamic pseudo-class reads.
+    type StateAccessor = fn(&mut HtmlNode) -> &mut bool;
+
+    #[test]
+    fn dynamic_pseudo_classes_read_the_node_state() {
+        let node = target("<a id=target href=/>x</a>");
+        let states: [(&str, StateAccessor); 5] = [
+            (":hover", |node| &mut node.is_hovered),
+            (":active", |node| &mut node.is_active),
+            (":focus", |node| &mut node.is_focused),
+            (":focus-visible", |node| &mut node.is_focused),
+            (":visited", |node| &mut node.is_visited),
+        ];
+        for (text, state) in states {
+            let selector = selector(&format!("a{}", text));
+            assert!(!selector.matches(node.clone()), "{} should not match yet", text);
+            *state(&mut node.write().unwrap()) = true;
+            assert!(selector.matches(node.clone()), "{} should match", text);
+            *state(&mut node.write().unwrap()) = false;
+        }
+        node.write().unwrap().is_hovered = true;
+        assert!(!selector("a:active").matches(node.clone()));
+        assert!(!selector("a:hover:focus").matches(node.clone()));
+    }
+
+    #[test]
+    fn checked_matches_checked_inputs_and_selected_options() {
+        let checked = |html: &str| selector(":checked").matches(target(html));
+        assert!(checked("<input id=target type=checkbox checked>"));
+        assert!(checked("<input id=target type=RADIO checked=''>"));
+        assert!(checked("<select><option>a<option id=target selected>b</select>"));
+        assert!(!checked("<input id=target type=checkbox>"));
+        assert!(!checked("<input id=target type=text checked>"));
+        assert!(!checked("<input id=target checked>"));
+        assert!(!checked("<select><option id=target>a<option selected>b</select>"));
+        assert!(!checked("<p id=target checked>x</p>"));
+    }
+
+    /// Returns whether the selector list `text` is valid and matches the element with
+    /// `id="target"` in `html`.
+    fn list_matches(html: &str, text: &str) -> bool {
+        let node = target(html);
+        CssParser::new(text).selector_list_only()
+            .is_ok_and(|list| list.iter().any(|selector| selector.matches(node.clone())))
+    }
+
+    #[test]
+    fn combinators_and_selector_lists_match() {
+        let html = "<div id=d><h1>a</h1><p class=x>b</p><em>c</em><span id=target>d</span></div>";
+        for selector in ["div span", "body #target", "#d > span", "html > body > div > span", "em + span", "h1 ~ span",
+            ".x ~ #target", "h1 ~ em + span", "h2, .x ~ span", "span:last-child", "span:not(p, em)", "div :is(h2, span)"] {
+            assert!(list_matches(html, selector), "{} should match", selector);
+        }
+        for selector in ["body > span", "p + span", "span ~ em", "h1 + span", "em > span", "h2, p + span", "span:first-child"] {
+            assert!(!list_matches(html, selector), "{} should not match", selector);
+        }
+        // One invalid selector drops the whole list.
+        assert!(!list_matches(html, "span, p:bogus"));
+        assert!(!list_matches(html, "span, > p"));
+    }
+
+    #[test]
+    fn nth_child_accepts_every_an_plus_b_form() {
+        // The target is the fourth child.
+        let html = "<div><h1>a</h1><p>b</p><em>c</em><span id=target>d</span></div>";
+        for argument in ["4", "even", "EVEN", "2n", "n", "+n", "-n+4", "+4n", "3n+1", "3n-2", "4n+0", " 2n + 0 ", "-2n+8", "0n+4"] {
+            assert!(list_matches(html, &format!("span:nth-child({})", argument)), "{} should match", argument);
+        }
+        for argument in ["odd", "2n+1", "-n+3", "5", "3n", "-n"] {
+            assert!(!list_matches(html, &format!("span:nth-child({})", argument)), "{} should not match", argument);
+        }
+        for invalid in ["", "2n+", "n-", "2 n", "+ 4", "1.5n", "n+-1", "n++1", "4x", "--n", "2nü", "nü"] {
+            assert!(!list_matches(html, &format!("span:nth-child({}), span", invalid)), "{} should be invalid", invalid);
         }
     }
 }
@@ -0,0 +1,1498 @@
+//! Computed styles.
+//!
+//! The cascade in `Tab::style` picks one declared value per property for each node.
+//! `ComputedStyle::compute` then turns those strings into typed values once, resolving
+//! relative lengths (`em`, `rem`, `vw`, ...), keywords and inheritance, so that layout
+//! and paint never parse CSS text themselves.
+//!
+//! Only percentages that depend on the layout (such as the `50%` of `border-radius: 50%`)
+//! are left unresolved, as a [`LengthPercentage`].
+//!
+//! Custom properties (`--brand: #3366ff`) are computed here too. They inherit, and
+//! `var()` references to them are substituted into the other declarations before those
+//! are parsed, as CSS does at computed-value time.
+
+use crate::css_calc::CalcExpr;
+use crate::css_grid::{self, GridAutoFlow, GridLine, GridTemplateAreas, TrackList, TrackSize};
+use crate::css_shorthand;
+use crate::css_tokenizer::{Token, matching_paren, tokenize};
+use eframe::epaint::Color32;
+use egui::Vec2;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// The properties that inherit by default: an element that has no declaration for one of
+/// them takes its parent's computed value instead of the initial value.
+pub(crate) const INHERITED_PROPERTIES: &[&str] = &[
+    "color", "font-size", "font-family", "font-weight", "font-style", "white-space", "list-style-type",
+    "border-collapse", "border-spacing", "caption-side",
+];
+
+/// The initial value of `font-size` in pixels, which is also the size `rem` is relative
+/// to when the root element has no font size of its own.
+pub(crate) const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+/// The width of a `medium` border in pixels, which is the initial `border-width`.
+const BORDER_WIDTH_MEDIUM: f32 = 3.0;
+
+/// How many custom properties a `var()` chain may go through, and how deeply `var()`
+/// fallbacks may nest. A value past it is invalid at computed-value time.
+const MAX_VAR_DEPTH: usize = 32;
+
+/// The longest value, in bytes, that `var()` substitution may produce. Each reference
+/// may repeat another, so without a limit a short chain could grow exponentially.
+const MAX_SUBSTITUTED_LENGTH: usize = 64 * 1024;
+
+/// A CSS length as written in a style sheet, before it is resolved to pixels.
+///
+/// # Variants
+/// - `Px`: Absolute pixels.
+/// - `Pt`: Points; one point is 4/3 pixels.
+/// - `Em`: Multiples of the element's font size. In `font-size` itself, multiples of the
+///   parent's font size.
+/// - `Rem`: Multiples of the root element's font size.
+/// - `Ch`: Multiples of the width of `0`, taken to be half the font size.
+/// - `Vw`, `Vh`: Percentages of the viewport width and height.
+/// - `Percent`: A percentage of a property-specific base.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Length {
+    Px(f32),
+    Pt(f32),
+    Em(f32),
+    Rem(f32),
+    Ch(f32),
+    Vw(f32),
+    Vh(f32),
+    Percent(f32),
+}
+
+impl Length {
+    /// Parses a length such as `12px`, `1.5em` or `50%`. A unitless `0` is `0px`.
+    ///
+    /// # Returns
+    /// - `Some(Length)` for a finite number followed by a supported unit.
+    /// - `None` otherwise, including for other unitless numbers.
+    pub(crate) fn parse(value: &str) -> Option<Length> {
+        let value = value.trim().to_ascii_lowercase();
+        let number_end = value
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+' || c == 'e'))
+            .unwrap_or(value.len());
+        // An `e` is an exponent only if a digit follows; otherwise it starts `em` or `ex`.
+        let number_end = match value[..number_end].find('e') {
+            Some(e) if !value[e + 1..].starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+') => e,
+            _ => number_end,
+        };
+        let (number, unit) = value.split_at(number_end);
+        let number = number.parse::<f32>().ok()?;
+        if unit.is_empty() {
+            return (number == 0.0).then_some(Length::Px(0.0));
+        }
+        Length::with_unit(number, unit)
+    }
+
+    /// Returns the length of `number` in `unit`, such as `px` or `%`, matched
+    /// case-insensitively.
+    ///
+    /// # Returns
+    /// `None` if `number` is not finite or the unit is not supported.
+    pub(crate) fn with_unit(number: f32, unit: &str) -> Option<Length> {
+        if !number.is_finite() {
+            return None;
+        }
+        Some(match unit.to_ascii_lowercase().as_str() {
+            "px" => Length::Px(number),
+            "pt" => Length::Pt(number),
+            "em" => Length::Em(number),
+            "rem" => Length::Rem(number),
+            "ch" => Length::Ch(number),
+            "vw" => Length::Vw(number),
+            "vh" => Length::Vh(number),
+            "%" => Length::Percent(number),
+            _ => return None,
+        })
+    }
+
+    /// Resolves the length to pixels.
+    ///
+    /// # Parameters
+    /// - `context`: The font sizes and viewport that relative units refer to.
+    /// - `percent_base`: What `100%` is, in pixels.
+    pub(crate) fn to_px(self, context: &LengthContext, percent_base: f32) -> f32 {
+        match self {
+            Length::Px(px) => px,
+            Length::Pt(pt) => pt * 4.0 / 3.0,
+            Length::Em(em) => em * context.font_size,
+            Length::Rem(rem) => rem * context.root_font_size,
+            Length::Ch(ch) => ch * context.font_size / 2.0,
+            Length::Vw(vw) => vw * context.viewport.x / 100.0,
+            Length::Vh(vh) => vh * context.viewport.y / 100.0,
+            Length::Percent(percent) => percent * percent_base / 100.0,
+        }
+    }
+}
+
+/// What relative lengths are resolved against while computing a style.
+///
+/// # Fields
+/// - `font_size`: The size `em` and `ch` refer to, in pixels.
+/// - `root_font_size`: The root element's font size, which `rem` refers to.
+/// - `viewport`: The size of the viewport, which `vw` and `vh` refer to.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LengthContext {
+    pub(crate) font_size: f32,
+    pub(crate) root_font_size: f32,
+    pub(crate) viewport: Vec2,
+}
+
+/// A computed length that may still be a percentage, because the size it is a
+/// percentage of is only known during layout.
+///
+/// # Variants
+/// - `Px`: An absolute length.
+/// - `Percent`: A percentage of the size the property refers to.
+/// - `Calc`: A math function that mixes the two, as in `calc(100% - 20px)`. Every length
+///   in it other than a percentage has already been computed to pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum LengthPercentage {
+    Px(f32),
+    Percent(f32),
+    Calc(Box<CalcExpr>),
+}
+
+impl LengthPercentage {
+    /// Parses and computes a length, a percentage or a math function.
+    ///
+    /// # Parameters
+    /// - `value`: The declared value.
+    /// - `context`: What relative lengths such as `em` refer to.
+    ///
+    /// # Returns
+    /// The computed value, or `None` if `value` is invalid. A math function without
+    /// percentages is computed to `Px` straight away.
+    pub(crate) fn parse(value: &str, context: &LengthContext) -> Option<LengthPercentage> {
+        if CalcExpr::is_math_function(value) {
+            let expr = CalcExpr::parse(value)?;
+            if !expr.has_percentage() {
+                let px = expr.evaluate(&|length| length.to_px(context, 0.0));
+                return px.is_finite().then_some(LengthPercentage::Px(px));
+            }
+            let computed = expr.map_lengths(&|length| match length {
+                Length::Percent(_) => length,
+                length => Length::Px(length.to_px(context, 0.0)),
+            });
+            return Some(LengthPercentage::Calc(Box::new(computed)));
+        }
+        match Length::parse(value)? {
+            Length::Percent(percent) => Some(LengthPercentage::Percent(percent)),
+            length => Some(LengthPercentage::Px(length.to_px(context, 0.0))),
+        }
+    }
+
+    /// Resolves the value to pixels, with `base` as `100%`. A math function that divides
+    /// by zero resolves to `0`.
+    pub(crate) fn resolve(&self, base: f32) -> f32 {
+        match self {
+            LengthPercentage::Px(px) => *px,
+            LengthPercentage::Percent(percent) => percent * base / 100.0,
+            LengthPercentage::Calc(expr) => {
+                let px = expr.evaluate(&|length| match length {
+                    Length::Px(px) => px,
+                    Length::Percent(percent) => percent * base / 100.0,
+                    // Computed expressions only hold pixels and percentages.
+                    _ => 0.0,
+                });
+                if px.is_finite() { px } else { 0.0 }
+            }
+        }
+    }
+
+    /// Returns whether the value is known to be negative. Percentages of a positive size
+    /// never are; a math function is only known once it is resolved.
+    fn is_negative(&self) -> bool {
+        match self {
+            LengthPercentage::Px(px) => *px < 0.0,
+            LengthPercentage::Percent(percent) => *percent < 0.0,
+            LengthPercentage::Calc(_) => false,
+        }
+    }
+}
+
+/// The value of the CSS `font-style` property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum FontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+/// The value of the CSS `white-space` property, which decides how the spaces and
+/// newlines in text are laid out. See `BlockComposer::text`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum WhiteSpace {
+    Normal,
+    Pre,
+    PreWrap,
+    PreLine,
+    NoWrap,
+}
+
+impl WhiteSpace {
+    /// Whether runs of spaces and tabs collapse into a single space.
+    pub(crate) fn collapses_spaces(self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::PreLine | WhiteSpace::NoWrap)
+    }
+
+    /// Whether newlines in the text break the line.
+    pub(crate) fn preserves_newlines(self) -> bool {
+        matches!(self, WhiteSpace::Pre | WhiteSpace::PreWrap | WhiteSpace::PreLine)
+    }
+
+    /// Whether lines may wrap at spaces to fit in the block.
+    pub(crate) fn wraps(self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::PreWrap | WhiteSpace::PreLine)
+    }
+}
+
+/// One side of a box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Side {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+impl Side {
+    /// Parses `top`, `right`, `bottom` or `left`.
+    fn parse(side: &str) -> Option<Side> {
+        match side {
+            "top" => Some(Side::Top),
+            "right" => Some(Side::Right),
+            "bottom" => Some(Side::Bottom),
+            "left" => Some(Side::Left),
+            _ => None,
+        }
+    }
+}
+
+/// A value for each side of a box, as for `margin`, `padding` and the `border-*`
+/// properties.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Edges<T> {
+    pub(crate) top: T,
+    pub(crate) right: T,
+    pub(crate) bottom: T,
+    pub(crate) left: T,
+}
+
+impl<T: Clone> Edges<T> {
+    /// Returns edges with `value` on every side.
+    pub(crate) fn all(value: T) -> Self {
+        Edges { top: value.clone(), right: value.clone(), bottom: value.clone(), left: value }
+    }
+
+    /// Returns the value of `side`.
+    pub(crate) fn get(&self, side: Side) -> &T {
+        match side {
+            Side::Top => &self.top,
+            Side::Right => &self.right,
+            Side::Bottom => &self.bottom,
+            Side::Left => &self.left,
+        }
+    }
+
+    /// Returns the value of `side`, for changing it.
+    pub(crate) fn get_mut(&mut self, side: Side) -> &mut T {
+        match side {
+            Side::Top => &mut self.top,
+            Side::Right => &mut self.right,
+            Side::Bottom => &mut self.bottom,
+            Side::Left => &mut self.left,
+        }
+    }
+
+    /// Applies `f` to the value of every side.
+    pub(crate) fn map<U>(&self, f: impl Fn(&T) -> U) -> Edges<U> {
+        Edges { top: f(&self.top), right: f(&self.right), bottom: f(&self.bottom), left: f(&self.left) }
+    }
+}
+
+impl Edges<f32> {
+    /// The sum of the left and right values.
+    pub(crate) fn horizontal(&self) -> f32 {
+        self.left + self.right
+    }
+
+    /// The sum of the top and bottom values.
+    pub(crate) fn vertical(&self) -> f32 {
+        self.top + self.bottom
+    }
+}
+
+/// The value of a `border-*-style` property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BorderStyle {
+    None,
+    Hidden,
+    Dotted,
+    Dashed,
+    Solid,
+    Double,
+    Groove,
+    Ridge,
+    Inset,
+    Outset,
+}
+
+impl BorderStyle {
+    /// Parses a border style keyword, already lowercased.
+    fn parse(keyword: &str) -> Option<BorderStyle> {
+        match keyword {
+            "none" => Some(BorderStyle::None),
+            "hidden" => Some(BorderStyle::Hidden),
+            "dotted" => Some(BorderStyle::Dotted),
+            "dashed" => Some(BorderStyle::Dashed),
+            "solid" => Some(BorderStyle::Solid),
+            "double" => Some(BorderStyle::Double),
+            "groove" => Some(BorderStyle::Groove),
+            "ridge" => Some(BorderStyle::Ridge),
+            "inset" => Some(BorderStyle::Inset),
+            "outset" => Some(BorderStyle::Outset),
+            _ => None,
+        }
+    }
+
+    /// Whether the style draws no border, in which case the border's width is `0`.
+    pub(crate) fn is_none(self) -> bool {
+        matches!(self, BorderStyle::None | BorderStyle::Hidden)
+    }
+}
+
+/// The value of the `display` property, which decides the box an element generates.
+/// See `LayoutNode::layout_block`.
+///
+/// # Variants
+/// - `None`: No box for the element or its subtree.
+/// - `Block`: A block-level box, stacked vertically with its siblings.
+/// - `Inline`: Inline content, which flows into lines with the text around it.
+/// - `InlineBlock`: A block laid out on a line as a single unit, like a word.
+/// - `ListItem`: A block-level box with a list marker.
+/// - `Contents`: No box for the element itself; its children take its place.
+/// - `Flex`: A block-level flex container, whose children are laid out as flex items.
+/// - `InlineFlex`: A flex container laid out on a line, like an inline-block.
+/// - `Grid`: A block-level grid container, whose children are placed in its grid.
+/// - `InlineGrid`: A grid container laid out on a line, like an inline-block.
+/// - `Table`: A block-level table, whose rows and cells are laid out in a grid.
+/// - `InlineTable`: A table laid out on a line, like an inline-block.
+/// - `TableRowGroup`, `TableHeaderGroup`, `TableFooterGroup`: A group of rows of a
+///   table, such as `<tbody>`, `<thead>` and `<tfoot>`.
+/// - `TableRow`: A row of cells.
+/// - `TableCell`: A cell, which may span several rows and columns.
+/// - `TableCaption`: A caption, above or below the table.
+/// - `TableColumnGroup`, `TableColumn`: Columns, which generate no box.
+///
+/// Outside a table, the parts of a table are laid out as blocks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Display {
+    None,
+    Block,
+    Inline,
+    InlineBlock,
+    ListItem,
+    Contents,
+    Flex,
+    InlineFlex,
+    Grid,
+    InlineGrid,
+    Table,
+    InlineTable,
+    TableRowGroup,
+    TableHeaderGroup,
+    TableFooterGroup,
+    TableRow,
+    TableCell,
+    TableCaption,
+    TableColumnGroup,
+    TableColumn,
+}
+
+/// The value of the `list-style-type` property: the marker of a list item. Numbered
+/// markers are not supported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ListStyleType {
+    None,
+    Disc,
+    Circle,
+    Square,
+}
+
+/// The value of the `border-collapse` property: whether the cells of a table have
+/// borders of their own, apart by `border-spacing`, or share them with their neighbours.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BorderCollapse {
+    Separate,
+    Collapse,
+}
+
+/// The value of the `caption-side` property: whether a table's captions go above or
+/// below it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CaptionSide {
+    Top,
+    Bottom,
+}
+
+/// The value of the `vertical-align` property, for table cells: where their content
+/// goes in the height of their row. `text-top` is `Top` and `text-bottom` is `Bottom`;
+/// `sub`, `super` and lengths are not supported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum VerticalAlign {
+    Baseline,
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// The value of the `box-sizing` property: whether `width` and `height` size the
+/// content box, or the border box including padding and borders.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BoxSizing {
+    ContentBox,
+    BorderBox,
+}
+
+/// The value of the `flex-direction` property: the main axis of a flex container, along
+/// which its items are placed one after the other.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum FlexDirection {
+    Row,
+    RowReverse,
+    Column,
+    ColumnReverse,
+}
+
+impl FlexDirection {
+    /// Whether the main axis is vertical.
+    pub(crate) fn is_column(self) -> bool {
+        matches!(self, FlexDirection::Column | FlexDirection::ColumnReverse)
+    }
+
+    /// Whether items are placed from the end of the main axis.
+    pub(crate) fn is_reverse(self) -> bool {
+        matches!(self, FlexDirection::RowReverse | FlexDirection::ColumnReverse)
+    }
+}
+
+/// The value of the `flex-wrap` property: whether a flex container breaks its items
+/// into several lines, and whether the lines stack from the end of the cross axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum FlexWrap {
+    NoWrap,
+    Wrap,
+    WrapReverse,
+}
+
+/// The value of the `justify-content` property: where the space left on the main axis
+/// of a flex container, or along the columns of a grid, goes. `start` and `left` are
+/// `FlexStart`; `end` and `right` are `FlexEnd`.
+///
+/// `Normal` (also for `stretch`) packs flex items at the start, and stretches the `auto`
+/// columns of a grid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum JustifyContent {
+    Normal,
+    FlexStart,
+    FlexEnd,
+    Center,
+    SpaceBetween,
+    SpaceAround,
+    SpaceEvenly,
+}
+
+/// The value of the `align-items`, `align-self`, `justify-items` and `justify-self`
+/// properties: where an item is placed on the cross axis of its flex line, or in its
+/// grid area. `normal` is `Stretch`; `flex-start`, `self-start`, `start` and `left` are
+/// `Start`, and likewise for the ends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Align {
+    Stretch,
+    Start,
+    End,
+    Center,
+    Baseline,
+}
+
+impl Align {
+    /// Parses an alignment keyword, already lowercased.
+    fn parse(keyword: &str) -> Option<Align> {
+        match keyword {
+            "normal" | "stretch" => Some(Align::Stretch),
+            "flex-start" | "self-start" | "start" | "left" => Some(Align::Start),
+            "flex-end" | "self-end" | "end" | "right" => Some(Align::End),
+            "center" => Some(Align::Center),
+            "baseline" | "first baseline" => Some(Align::Baseline),
+            _ => None,
+        }
+    }
+}
+
+/// The value of the `flex-basis` property: the size of a flex item on the main axis
+/// before it grows or shrinks.
+///
+/// # Variants
+/// - `Auto`: The item's `width` or `height`, or `Content` if that is `auto`.
+/// - `Content`: The size of the item's content.
+/// - `Length`: A size, sizing the box that `box-sizing` says.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum FlexBasis {
+    Auto,
+    Content,
+    Length(LengthPercentage),
+}
+
+/// The properties that have a value per side, whose longhands are named like
+/// `margin-left` or `border-left-width`.
+#[derive(Clone, Copy)]
+enum BoxProperty {
+    Margin,
+    Padding,
+    BorderWidth,
+    BorderStyle,
+    BorderColor,
+}
+
+impl BoxProperty {
+    /// Splits a per-side longhand into its property and side.
+    fn parse(property: &str) -> Option<(BoxProperty, Side)> {
+        if let Some(side) = property.strip_prefix("margin-") {
+            return Some((BoxProperty::Margin, Side::parse(side)?));
+        }
+        if let Some(side) = property.strip_prefix("padding-") {
+            return Some((BoxProperty::Padding, Side::parse(side)?));
+        }
+        let (side, part) = property.strip_prefix("border-")?.split_once('-')?;
+        let kind = match part {
+            "width" => BoxProperty::BorderWidth,
+            "style" => BoxProperty::BorderStyle,
+            "color" => BoxProperty::BorderColor,
+            _ => return None,
+        };
+        Some((kind, Side::parse(side)?))
+    }
+}
+
+/// The computed values of the CSS properties the engine supports, for one node.
+///
+/// # Fields
+/// - `color`: The text color.
+/// - `background_color`: The background color; transparent by default.
+/// - `font_size`: The font size in pixels.
+/// - `font_family`: The font families to try in order, lowercased and without quotes,
+///   such as `["open sans", "sans-serif"]`.
+/// - `font_weight`: The numeric font weight, from 1 to 1000; `normal` is 400 and `bold` 700.
+/// - `font_style`: Whether the text is italic.
+/// - `white_space`: How spaces and newlines in the text are laid out.
+/// - `border_radius`: The radius of the background's corners.
+/// - `margin`: The margins; `None` for `auto`.
+/// - `padding`: The paddings, never negative.
+/// - `border_width`: The border widths in pixels; `0` on sides whose style is `none` or
+///   `hidden`.
+/// - `border_style`: The border styles; `none` by default.
+/// - `border_color`: The border colors; `None` for `currentcolor`, which is the text
+///   color.
+/// - `width`, `height`: The size of the content box, or of the border box with
+///   `box-sizing: border-box`; `None` for `auto`.
+/// - `min_width`, `min_height`: The minimum sizes; `auto` computes to `0`.
+/// - `max_width`, `max_height`: The maximum sizes; `None` for `none`.
+/// - `box_sizing`: Which box `width`, `height` and their limits size.
+/// - `display`: The kind of box the element generates; `inline` by default.
+/// - `list_style_type`: The marker of list items.
+/// - `border_collapse`: Whether the borders of a table's cells collapse.
+/// - `border_spacing`: The horizontal and vertical space between the cells of a table
+///   with separate borders.
+/// - `caption_side`: Where a table's captions go.
+/// - `vertical_align`: Where the content of a table cell goes in its row.
+/// - `flex_direction`, `flex_wrap`, `justify_content`, `align_items`: How a flex
+///   container places its items.
+/// - `align_self`: How a flex item is placed on the cross axis; `None` for `auto`, which
+///   is the container's `align_items`.
+/// - `flex_grow`, `flex_shrink`: How much of the free space a flex item takes, or gives
+///   up when there is too little, relative to its siblings.
+/// - `flex_basis`: The size of a flex item before it grows or shrinks.
+/// - `row_gap`, `column_gap`: The space between the lines and between the items of a
+///   flex container, or between the rows and columns of a grid; `normal` computes to
+///   `0`.
+/// - `order`: The position of a flex or grid item among its siblings, which are placed
+///   by increasing `order` and then in document order.
+/// - `grid_template_columns`, `grid_template_rows`: The tracks of the explicit grid.
+/// - `grid_template_areas`: The named areas of the grid; `None` for `none`.
+/// - `grid_auto_columns`, `grid_auto_rows`: The sizes that implicit tracks cycle through.
+/// - `grid_auto_flow`: How items without a position are placed.
+/// - `grid_row_start`, `grid_row_end`, `grid_column_start`, `grid_column_end`: Where a
+///   grid item is placed.
+/// - `justify_items`: How the items of a grid are placed horizontally in their areas.
+/// - `justify_self`: How a grid item is placed horizontally in its area; `None` for
+///   `auto`, which is the container's `justify_items`.
+/// - `custom_properties`: The custom properties (`--name`) and their values, with every
+///   `var()` already substituted. Shared with the parent when the node declares none.
+/// - `root_font_size`: Not a CSS property: the root element's font size, which `rem`
+///   refers to. Every node carries it so that styling a subtree does not need the root.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ComputedStyle {
+    pub(crate) color: Color32,
+    pub(crate) background_color: Color32,
+    pub(crate) font_size: f32,
+    pub(crate) font_family: Vec<String>,
+    pub(crate) font_weight: u16,
+    pub(crate) font_style: FontStyle,
+    pub(crate) white_space: WhiteSpace,
+    pub(crate) border_radius: LengthPercentage,
+    pub(crate) margin: Edges<Option<LengthPercentage>>,
+    pub(crate) padding: Edges<LengthPercentage>,
+    pub(crate) border_width: Edges<f32>,
+    pub(crate) border_style: Edges<BorderStyle>,
+    pub(crate) border_color: Edges<Option<Color32>>,
+    pub(crate) width: Option<LengthPercentage>,
+    pub(crate) height: Option<LengthPercentage>,
+    pub(crate) min_width: LengthPercentage,
+    pub(crate) min_height: LengthPercentage,
+    pub(crate) max_width: Option<LengthPercentage>,
+    pub(crate) max_height: Option<LengthPercentage>,
+    pub(crate) box_sizing: BoxSizing,
+    pub(crate) display: Display,
+    pub(crate) list_style_type: ListStyleType,
+    pub(crate) border_collapse: BorderCollapse,
+    pub(crate) border_spacing: Vec2,
+    pub(crate) caption_side: CaptionSide,
+    pub(crate) vertical_align: VerticalAlign,
+    pub(crate) flex_direction: FlexDirection,
+    pub(crate) flex_wrap: FlexWrap,
+    pub(crate) justify_content: JustifyContent,
+    pub(crate) align_items: Align,
+    pub(crate) align_self: Option<Align>,
+    pub(crate) flex_grow: f32,
+    pub(crate) flex_shrink: f32,
+    pub(crate) flex_basis: FlexBasis,
+    pub(crate) row_gap: LengthPercentage,
+    pub(crate) column_gap: LengthPercentage,
+    pub(crate) order: i32,
+    pub(crate) grid_template_columns: TrackList,
+    pub(crate) grid_template_rows: TrackList,
+    pub(crate) grid_template_areas: Option<GridTemplateAreas>,
+    pub(crate) grid_auto_columns: Vec<TrackSize>,
+    pub(crate) grid_auto_rows: Vec<TrackSize>,
+    pub(crate) grid_auto_flow: GridAutoFlow,
+    pub(crate) grid_row_start: GridLine,
+    pub(crate) grid_row_end: GridLine,
+    pub(crate) grid_column_start: GridLine,
+    pub(crate) grid_column_end: GridLine,
+    pub(crate) justify_items: Align,
+    pub(crate) justify_self: Option<Align>,
+    pub(crate) custom_properties: Arc<HashMap<String, String>>,
+    pub(crate) root_font_size: f32,
+}
+
+impl Default for ComputedStyle {
+    /// Returns the initial value of every property.
+    fn default() -> Self {
+        ComputedStyle {
+            color: Color32::BLACK,
+            background_color: Color32::TRANSPARENT,
+            font_size: DEFAULT_FONT_SIZE,
+            font_family: vec!["sans-serif".to_string()],
+            font_weight: 400,
+            font_style: FontStyle::Normal,
+            white_space: WhiteSpace::Normal,
+            border_radius: LengthPercentage::Px(0.0),
+            margin: Edges::all(Some(LengthPercentage::Px(0.0))),
+            padding: Edges::all(LengthPercentage::Px(0.0)),
+            border_width: Edges::all(BORDER_WIDTH_MEDIUM),
+            border_style: Edges::all(BorderStyle::None),
+            border_color: Edges::all(None),
+            width: None,
+            height: None,
+            min_width: LengthPercentage::Px(0.0),
+            min_height: LengthPercentage::Px(0.0),
+            max_width: None,
+            max_height: None,
+            box_sizing: BoxSizing::ContentBox,
+            display: Display::Inline,
+            list_style_type: ListStyleType::Disc,
+            border_collapse: BorderCollapse::Separate,
+            border_spacing: Vec2::ZERO,
+            caption_side: CaptionSide::Top,
+            vertical_align: VerticalAlign::Baseline,
+            flex_direction: FlexDirection::Row,
+            flex_wrap: FlexWrap::NoWrap,
+            justify_content: JustifyContent::Normal,
+            align_items: Align::Stretch,
+            align_self: None,
+            flex_grow: 0.0,
+            flex_shrink: 1.0,
+            flex_basis: FlexBasis::Auto,
+            row_gap: LengthPercentage::Px(0.0),
+            column_gap: LengthPercentage::Px(0.0),
+            order: 0,
+            grid_template_columns: TrackList::default(),
+            grid_template_rows: TrackList::default(),
+            grid_template_areas: None,
+            grid_auto_columns: vec![TrackSize::AUTO],
+            grid_auto_rows: vec![TrackSize::AUTO],
+            grid_auto_flow: GridAutoFlow::default(),
+            grid_row_start: GridLine::Auto,
+            grid_row_end: GridLine::Auto,
+            grid_column_start: GridLine::Auto,
+            grid_column_end: GridLine::Auto,
+            justify_items: Align::Stretch,
+            justify_self: None,
+            custom_properties: Arc::new(HashMap::new()),
+            root_font_size: DEFAULT_FONT_SIZE,
+        }
+    }
+}
+
+impl ComputedStyle {
+    /// Computes the style of a node from the declarations the cascade picked for it.
+    ///
+    /// # Parameters
+    /// - `declared`: The declarations that apply to the node, from weakest to strongest,
+    ///   so that a later declaration of a property overrides an earlier one.
+    /// - `parent`: The parent's computed style, or `None` for the root of the document.
+    /// - `viewport`: The size of the viewport, for `vw` and `vh`.
+    ///
+    /// # Returns
+    /// The computed style. Properties without a declaration inherit the parent's value if
+    /// they are in `INHERITED_PROPERTIES` and take their initial value otherwise. The
+    /// CSS-wide keywords `inherit`, `initial`, `unset` and `revert` work on every property.
+    /// Invalid or unsupported values are ignored, as if they had not been declared.
+    ///
+    /// # Notes
+    /// - Custom properties are computed first, then `var()` is substituted into the other
+    ///   declarations. A declaration whose `var()` cannot be resolved is invalid at
+    ///   computed-value time and acts as `unset`. Shorthands containing `var()` are kept
+    ///   whole by `CssParser` and expanded here, after substitution, in their place
+    ///   among the other declarations.
+    /// - `font-size` is computed before the other properties, since `em` refers to it,
+    ///   then `color`, which `currentcolor` refers to.
+    /// - The width of a border whose style is `none` or `hidden` computes to `0`.
+    pub(crate) fn compute(declared: &[(String, String)], parent: Option<&ComputedStyle>, viewport: Vec2) -> ComputedStyle {
+        let initial = ComputedStyle::default();
+        let parent_style = parent.unwrap_or(&initial);
+        let mut style = initial.clone();
+        for property in INHERITED_PROPERTIES {
+            style.copy_property(property, parent_style);
+        }
+        style.root_font_size = parent_style.root_font_size;
+        style.custom_properties = compute_custom_properties(declared, &parent_style.custom_properties);
+
+        let mut properties = HashMap::<String, String>::new();
+        for (property, value) in declared.iter().filter(|(property, _)| !property.starts_with("--")) {
+            let value = if value.to_ascii_lowercase().contains("var(") {
+                substitute_var(value, &mut |name| style.custom_properties.get(name).cloned())
+                    .unwrap_or("unset".to_string())
+            } else {
+                value.clone()
+            };
+            match css_shorthand::expand(property, &value) {
+                Some(Ok(longhands)) => properties.extend(longhands),
+                Some(Err(_)) => properties.extend(css_shorthand::expand(property, "unset").into_iter().flatten().flatten()),
+                None => {
+                    properties.insert(property.clone(), value);
+                }
+            }
+        }
+
+        let mut properties: Vec<(String, String)> = properties.into_iter().collect();
+        properties.sort_by_key(|(property, _)| match property.as_str() {
+            "font-size" => 0,
+            "color" => 1,
+            _ => 2,
+        });
+        for (property, value) in properties {
+            let value = value.trim();
+            let source = match value.to_ascii_lowercase().as_str() {
+                "inherit" => Some(parent_style),
+                "initial" => Some(&initial),
+                "unset" | "revert" if INHERITED_PROPERTIES.contains(&property.as_str()) => Some(parent_style),
+                "unset" | "revert" => Some(&initial),
+                _ => None,
+            };
+            match source {
+                Some(source) => style.copy_property(&property, source),
+                None => style.set_property(&property, value, parent_style, viewport),
+            };
+        }
+
+        // A border without a style has no width, whatever `border-width` says.
+        for side in [Side::Top, Side::Right, Side::Bottom, Side::Left] {
+            if style.border_style.get(side).is_none() {
+                *style.border_width.get_mut(side) = 0.0;
+            }
+        }
+
+        // The root element's font size is what `rem` means for the whole document.
+        if parent.is_none() {
+            style.root_font_size = style.font_size;
+        }
+        style
+    }
+
+    /// Copies the computed value of `property` from `source`.
+    ///
+    /// # Returns
+    /// `true` if `property` is supported.
+    fn copy_property(&mut self, property: &str, source: &ComputedStyle) -> bool {
+        if let Some((kind, side)) = BoxProperty::parse(property) {
+            match kind {
+                BoxProperty::Margin => *self.margin.get_mut(side) = source.margin.get(side).clone(),
+                BoxProperty::Padding => *self.padding.get_mut(side) = source.padding.get(side).clone(),
+                BoxProperty::BorderWidth => *self.border_width.get_mut(side) = *source.border_width.get(side),
+                BoxProperty::BorderStyle => *self.border_style.get_mut(side) = *source.border_style.get(side),
+                BoxProperty::BorderColor => *self.border_color.get_mut(side) = *source.border_color.get(side),
+            }
+            return true;
+        }
+        match property {
+            "color" => self.color = source.color,
+            "background-color" => self.background_color = source.background_color,
+            "font-size" => self.font_size = source.font_size,
+            "font-family" => self.font_family = source.font_family.clone(),
+            "font-weight" => self.font_weight = source.font_weight,
+            "font-style" => self.font_style = source.font_style,
+            "white-space" => self.white_space = source.white_space,
+            "border-radius" => self.border_radius = source.border_radius.clone(),
+            "width" => self.width = source.width.clone(),
+            "height" => self.height = source.height.clone(),
+            "min-width" => self.min_width = source.min_width.clone(),
+            "min-height" => self.min_height = source.min_height.clone(),
+            "max-width" => self.max_width = source.max_width.clone(),
+            "max-height" => self.max_height = source.max_height.clone(),
+            "box-sizing" => self.box_sizing = source.box_sizing,
+            "display" => self.display = source.display,
+            "list-style-type" => self.list_style_type = source.list_style_type,
+            "border-collapse" => self.border_collapse = source.border_collapse,
+            "border-spacing" => self.border_spacing = source.border_spacing,
+            "caption-side" => self.caption_side = source.caption_side,
+            "vertical-align" => self.vertical_align = source.vertical_align,
+            "flex-direction" => self.flex_direction = source.flex_direction,
+            "flex-wrap" => self.flex_wrap = source.flex_wrap,
+            "justify-content" => self.justify_content = source.justify_content,
+            "align-items" => self.align_items = source.align_items,
+            "align-self" => self.align_self = source.align_self,
+            "flex-grow" => self.flex_grow = source.flex_grow,
+            "flex-shrink" => self.flex_shrink = source.flex_shrink,
+            "flex-basis" => self.flex_basis = source.flex_basis.clone(),
+            "row-gap" => self.row_gap = source.row_gap.clone(),
+            "column-gap" => self.column_gap = source.column_gap.clone(),
+            "order" => self.order = source.order,
+            "grid-template-columns" => self.grid_template_columns = source.grid_template_columns.clone(),
+            "grid-template-rows" => self.grid_template_rows = source.grid_template_rows.clone(),
+            "grid-template-areas" => self.grid_template_areas = source.grid_template_areas.clone(),
+            "grid-auto-columns" => self.grid_auto_columns = source.grid_auto_columns.clone(),
+            "grid-auto-rows" => self.grid_auto_rows = source.grid_auto_rows.clone(),
+            "grid-auto-flow" => self.grid_auto_flow = source.grid_auto_flow,
+            "grid-row-start" => self.grid_row_start = source.grid_row_start.clone(),
+            "grid-row-end" => self.grid_row_end = source.grid_row_end.clone(),
+            "grid-column-start" => self.grid_column_start = source.grid_column_start.clone(),
+            "grid-column-end" => self.grid_column_end = source.grid_column_end.clone(),
+            "justify-items" => self.justify_items = source.justify_items,
+            "justify-self" => self.justify_self = source.justify_self,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Parses `value` and stores it as the computed value of `property`, leaving the
+    /// property unchanged if the value is invalid.
+    ///
+    /// # Returns
+    /// `true` if `property` is supported and `value` is valid for it.
+    fn set_property(&mut self, property: &str, value: &str, parent: &ComputedStyle, viewport: Vec2) -> bool {
+        let context = LengthContext {
+            font_size: self.font_size,
+            root_font_size: self.root_font_size,
+            viewport,
+        };
+        let keyword = value.to_ascii_lowercase();
+        // A length or percentage that may not be negative, as sizes and paddings.
+        let size = || LengthPercentage::parse(value, &context).filter(|size| !size.is_negative());
+        if let Some((kind, side)) = BoxProperty::parse(property) {
+            return match kind {
+                BoxProperty::Margin => {
+                    let margin = match keyword.as_str() {
+                        "auto" => Some(None),
+                        _ => LengthPercentage::parse(value, &context).map(Some),
+                    };
+                    margin.map(|margin| *self.margin.get_mut(side) = margin).is_some()
+                }
+                BoxProperty::Padding => size().map(|padding| *self.padding.get_mut(side) = padding).is_some(),
+                BoxProperty::BorderWidth => {
+                    let width = match keyword.as_str() {
+                        "thin" => Some(1.0),
+                        "medium" => Some(BORDER_WIDTH_MEDIUM),
+                        "thick" => Some(5.0),
+                        // Border widths cannot be percentages.
+                        _ => match LengthPercentage::parse(value, &context) {
+                            Some(LengthPercentage::Px(px)) if px >= 0.0 => Some(px),
+                            _ => None,
+                        },
+                    };
+                    width.map(|width| *self.border_width.get_mut(side) = width).is_some()
+                }
+                BoxProperty::BorderStyle => BorderStyle::parse(&keyword)
+                    .map(|style| *self.border_style.get_mut(side) = style)
+                    .is_some(),
+                BoxProperty::BorderColor => {
+                    let color = match keyword.as_str() {
+                        "currentcolor" => Some(None),
+                        _ => parse_color(value, self.color).map(Some),
+                    };
+                    color.map(|color| *self.border_color.get_mut(side) = color).is_some()
+                }
+            };
+        }
+        match property {
+            // `color: currentcolor` is the inherited color.
+            "color" => parse_color(value, parent.color).map(|color| self.color = color).is_some(),
+            "background-color" => parse_color(value, self.color).map(|color| self.background_color = color).is_some(),
+            "font-size" => parse_font_size(value, parent, viewport).map(|size| self.font_size = size).is_some(),
+            "font-family" => parse_font_family(value).map(|family| self.font_family = family).is_some(),
+            "font-weight" => {
+                let weight = match keyword.as_str() {
+                    "normal" => Some(400),
+                    "bold" => Some(700),
+                    "bolder" => Some(bolder(parent.font_weight)),
+                    "lighter" => Some(lighter(parent.font_weight)),
+                    weight => weight.parse::<f32>().ok()
+                        .filter(|w| (1.0..=1000.0).contains(w))
+                        .map(|w| w.round() as u16),
+                };
+                weight.map(|weight| self.font_weight = weight).is_some()
+            }
+            "font-style" => {
+                let font_style = match keyword.as_str() {
+                    "normal" => Some(FontStyle::Normal),
+                    "italic" => Some(FontStyle::Italic),
+                    k if k == "oblique" || k.starts_with("oblique ") => Some(FontStyle::Oblique),
+                    _ => None,
+                };
+                font_style.map(|font_style| self.font_style = font_style).is_some()
+            }
+            "white-space" => {
+                let white_space = match keyword.as_str() {
+                    "normal" => Some(WhiteSpace::Normal),
+                    "pre" => Some(WhiteSpace::Pre),
+                    "pre-wrap" => Some(WhiteSpace::PreWrap),
+                    "pre-line" => Some(WhiteSpace::PreLine),
+                    "nowrap" => Some(WhiteSpace::NoWrap),
+                    _ => None,
+                };
+                white_space.map(|white_space| self.white_space = white_space).is_some()
+            }
+            "border-radius" => {
+                // Only one radius for all corners is supported, so `10px 5px` uses `10px`.
+                let radius = first_component(value).and_then(|v| LengthPercentage::parse(v, &context));
+                radius.filter(|r| !r.is_negative()).map(|radius| self.border_radius = radius).is_some()
+            }
+            "width" | "height" => {
+                let length = match keyword.as_str() {
+                    "auto" => Some(None),
+                    _ => size().map(Some),
+                };
+                let Some(length) = length else {
+                    return false;
+                };
+                if property == "width" { self.width = length } else { self.height = length }
+                true
+            }
+            "min-width" | "min-height" => {
+                let length = match keyword.as_str() {
+                    "auto" => Some(LengthPercentage::Px(0.0)),
+                    _ => size(),
+                };
+                let Some(length) = length else {
+                    return false;
+                };
+                if property == "min-width" { self.min_width = length } else { self.min_height = length }
+                true
+            }
+            "max-width" | "max-height" => {
+                let length = match keyword.as_str() {
+                    "none" => Some(None),
+                    _ => size().map(Some),
+                };
+                let Some(length) = length else {
+                    return false;
+                };
+                if property == "max-width" { self.max_width = length } else { self.max_height = length }
+                true
+            }
+            "box-sizing" => {
+                let box_sizing = match keyword.as_str() {
+                    "content-box" => Some(BoxSizing::ContentBox),
+                    "border-box" => Some(BoxSizing::BorderBox),
+                    _ => None,
+                };
+                box_sizing.map(|box_sizing| self.box_sizing = box_sizing).is_some()
+            }
+            "display" => {
+                let display = match keyword.as_str() {
+                    "none" => Some(Display::None),
+                    "block" => Some(Display::Block),
+                    "inline" => Some(Display::Inline),
+                    "inline-block" => Some(Display::InlineBlock),
+                    "list-item" => Some(Display::ListItem),
+                    "contents" => Some(Display::Contents),
+                    "flex" => Some(Display::Flex),
+                    "inline-flex" => Some(Display::InlineFlex),
+                    "grid" => Some(Display::Grid),
+                    "inline-grid" => Some(Display::InlineGrid),
+                    "table" => Some(Display::Table),
+                    "inline-table" => Some(Display::InlineTable),
+                    "table-row-group" => Some(Display::TableRowGroup),
+                    "table-header-group" => Some(Display::TableHeaderGroup),
+                    "table-footer-group" => Some(Display::TableFooterGroup),
+                    "table-row" => Some(Display::TableRow),
+                    "table-cell" => Some(Display::TableCell),
+                    "table-caption" => Some(Display::TableCaption),
+                    "table-column-group" => Some(Display::TableColumnGroup),
+                    "table-column" => Some(Display::TableColumn),
+                    _ => None,
+                };
+                display.map(|display| self.display = display).is_some()
+            }
+            "list-style-type" => {
+                let list_style_type = match keyword.as_str() {
+                    "none" => Some(ListStyleType::None),
+                    "disc" => Some(ListStyleType::Disc),
+                    "circle" => Some(ListStyleType::Circle),
+                    "square" => Some(ListStyleType::Square),
+                    _ => None,
+                };
+                list_style_type.map(|list_style_type| self.list_style_type = list_style_type).is_some()
+            }
+            "border-collapse" => {
+                let border_collapse = match keyword.as_str() {
+                    "separate" => Some(BorderCollapse::Separate),
+                    "collapse" => Some(BorderCollapse::Collapse),
+                    _ => None,
+                };
+                border_collapse.map(|border_collapse| self.border_collapse = border_collapse).is_some()
+            }
+            "border-spacing" => {
+                // One length for both directions, or the horizontal then the vertical
+                // one. Percentages are not allowed.
+                let lengths: Option<Vec<f32>> = value.split_ascii_whitespace()
+                    .map(|length| match LengthPercentage::parse(length, &context) {
+                        Some(LengthPercentage::Px(px)) if px >= 0.0 => Some(px),
+                        _ => None,
+                    })
+                    .collect();
+                let spacing = match lengths.as_deref() {
+                    Some([both]) => Some(Vec2::splat(*both)),
+                    Some([horizontal, vertical]) => Some(Vec2::new(*horizontal, *vertical)),
+                    _ => None,
+                };
+                spacing.map(|spacing| self.border_spacing = spacing).is_some()
+            }
+            "caption-side" => {
+                let caption_side = match keyword.as_str() {
+                    "top" => Some(CaptionSide::Top),
+                    "bottom" => Some(CaptionSide::Bottom),
+                    _ => None,
+                };
+                caption_side.map(|caption_side| self.caption_side = caption_side).is_some()
+            }
+            "vertical-align" => {
+                let vertical_align = match keyword.as_str() {
+                    "baseline" => Some(VerticalAlign::Baseline),
+                    "top" | "text-top" => Some(VerticalAlign::Top),
+                    "middle" => Some(VerticalAlign::Middle),
+                    "bottom" | "text-bottom" => Some(VerticalAlign::Bottom),
+                    _ => None,
+                };
+                vertical_align.map(|vertical_align| self.vertical_align = vertical_align).is_some()
+            }
+            "flex-direction" => {
+                let flex_direction = match keyword.as_str() {
+                    "row" => Some(FlexDirection::Row),
+                    "row-reverse" => Some(FlexDirection::RowReverse),
+                    "column" => Some(FlexDirection::Column),
+                    "column-reverse" => Some(FlexDirection::ColumnReverse),
+                    _ => None,
+                };
+                flex_direction.map(|flex_direction| self.flex_direction = flex_direction).is_some()
+            }
+            "flex-wrap" => {
+                let flex_wrap = match keyword.as_str() {
+                    "nowrap" => Some(FlexWrap::NoWrap),
+                    "wrap" => Some(FlexWrap::Wrap),
+                    "wrap-reverse" => Some(FlexWrap::WrapReverse),
+                    _ => None,
+                };
+                flex_wrap.map(|flex_wrap| self.flex_wrap = flex_wrap).is_some()
+            }
+            "justify-content" => {
+                let justify_content = match keyword.as_str() {
+                    "normal" | "stretch" => Some(JustifyContent::Normal),
+                    "flex-start" | "start" | "left" => Some(JustifyContent::FlexStart),
+                    "flex-end" | "end" | "right" => Some(JustifyContent::FlexEnd),
+                    "center" => Some(JustifyContent::Center),
+                    "space-between" => Some(JustifyContent::SpaceBetween),
+                    "space-around" => Some(JustifyContent::SpaceAround),
+                    "space-evenly" => Some(JustifyContent::SpaceEvenly),
+                    _ => None,
+                };
+                justify_content.map(|justify_content| self.justify_content = justify_content).is_some()
+            }
+            "align-items" => Align::parse(&keyword).map(|align| self.align_items = align).is_some(),
+            "justify-items" => Align::parse(&keyword).map(|align| self.justify_items = align).is_some(),
+            "align-self" | "justify-self" => {
+                let Some(align) = (match keyword.as_str() {
+                    "auto" => Some(None),
+                    keyword => Align::parse(keyword).map(Some),
+                }) else {
+                    return false;
+                };
+                if property == "align-self" { self.align_self = align } else { self.justify_self = align }
+                true
+            }
+            "flex-grow" | "flex-shrink" => {
+                let Some(factor) = value.parse::<f32>().ok().filter(|f| f.is_finite() && *f >= 0.0) else {
+                    return false;
+                };
+                if property == "flex-grow" { self.flex_grow = factor } else { self.flex_shrink = factor }
+                true
+            }
+            "flex-basis" => {
+                let flex_basis = match keyword.as_str() {
+                    "auto" => Some(FlexBasis::Auto),
+                    "content" => Some(FlexBasis::Content),
+                    _ => size().map(FlexBasis::Length),
+                };
+                flex_basis.map(|flex_basis| self.flex_basis = flex_basis).is_some()
+            }
+            "row-gap" | "column-gap" => {
+                let gap = match keyword.as_str() {
+                    "normal" => Some(LengthPercentage::Px(0.0)),
+                    _ => size(),
+                };
+                let Some(gap) = gap else {
+                    return false;
+                };
+                if property == "row-gap" { self.row_gap = gap } else { self.column_gap = gap }
+                true
+            }
+            "order" => value.parse::<i32>().map(|order| self.order = order).is_ok(),
+            "grid-template-columns" | "grid-template-rows" => {
+                let Some(tracks) = TrackList::parse(value, &context) else {
+                    return false;
+                };
+                if property == "grid-template-columns" { self.grid_template_columns = tracks } else { self.grid_template_rows = tracks }
+                true
+            }
+            "grid-template-areas" => GridTemplateAreas::parse(value).map(|areas| self.grid_template_areas = areas).is_some(),
+            "grid-auto-columns" | "grid-auto-rows" => {
+                let Some(tracks) = css_grid::parse_auto_tracks(value, &context) else {
+                    return false;
+                };
+                if property == "grid-auto-columns" { self.grid_auto_columns = tracks } else { self.grid_auto_rows = tracks }
+                true
+            }
+            "grid-auto-flow" => GridAutoFlow::parse(value).map(|flow| self.grid_auto_flow = flow).is_some(),
+            "grid-row-start" | "grid-row-end" | "grid-column-start" | "grid-column-end" => {
+                let Some(line) = GridLine::parse(value) else {
+                    return false;
+                };
+                match property {
+                    "grid-row-start" => self.grid_row_start = line,
+                    "grid-row-end" => self.grid_row_end = line,
+                    "grid-column-start" => self.grid_column_start = line,
+                    _ => self.grid_column_end = line,
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns whether the engine supports `property: value`, for `@supports`.
+    ///
+    /// # Notes
+    /// Custom properties accept any value. Shorthands are supported if all of their
+    /// longhands are. The CSS-wide keywords are valid for every supported property.
+    pub(crate) fn supports(property: &str, value: &str) -> bool {
+        let value = value.trim();
+        if property.starts_with("--") {
+            return true;
+        }
+        if let Some(longhands) = css_shorthand::expand(property, value) {
+            return longhands.is_ok_and(|longhands| longhands.iter().all(|(p, v)| Self::supports(p, v)));
+        }
+        let initial = ComputedStyle::default();
+        let is_supported_property = initial.clone().set_property(property, "initial", &initial, Vec2::ZERO)
+            || ComputedStyle::default().copy_property(property, &initial);
+        if ["inherit", "initial", "unset", "revert"].iter().any(|k| value.eq_ignore_ascii_case(k)) {
+            return is_supported_property;
+        }
+        initial.clone().set_property(property, value, &initial, Vec2::ZERO)
+    }
+}
+
+/// Parses a color, resolving `currentcolor` to `current_color`.
+fn parse_color(value: &str, current_color: Color32) -> Option<Color32> {
+    if value.eq_ignore_ascii_case("currentcolor") {
+        return Some(current_color);
+    }
+    let [r, g, b, a] = csscolorparser::parse(value).ok()?.to_rgba8();
+    Some(Color32::from_rgba_unmultiplied(r, g, b, a))
+}
+
+/// Parses a `font-size` into pixels. Keywords follow the usual browser scale around the
+/// 16px `medium`; `em` and percentages are relative to the parent's font size.
+fn parse_font_size(value: &str, parent: &ComputedStyle, viewport: Vec2) -> Option<f32> {
+    let size = match value.to_ascii_lowercase().as_str() {
+        "xx-small" => 9.0,
+        "x-small" => 10.0,
+        "small" => 13.0,
+        "medium" => DEFAULT_FONT_SIZE,
+        "large" => 18.0,
+        "x-large" => 24.0,
+        "xx-large" => 32.0,
+        "xxx-large" => 48.0,
+        "smaller" => parent.font_size / 1.2,
+        "larger" => parent.font_size * 1.2,
+        _ => {
+            let context = LengthContext {
+                font_size: parent.font_size,
+                root_font_size: parent.root_font_size,
+                viewport,
+            };
+            if CalcExpr::is_math_function(value) {
+                CalcExpr::parse(value)?.evaluate(&|length| length.to_px(&context, parent.font_size))
+            } else {
+                Length::parse(value)?.to_px(&context, parent.font_size)
+            }
+        }
+    };
+    (size.is_finite() && size >= 0.0).then_some(size)
+}
+
+/// Parses a comma-separated `font-family` list. Family names may be quoted, or written
+/// as several identifiers separated by spaces.
+pub(crate) fn parse_font_family(value: &str) -> Option<Vec<String>> {
+    let tokens = tokenize(value);
+    let families: Vec<String> = tokens.split(|token| *token == Token::Comma)
+        .map(|family| {
+            let words: Vec<&Token> = family.iter().filter(|token| **token != Token::Whitespace).collect();
+            match words.as_slice() {
+                [Token::String(name)] => Some(name.to_lowercase()),
+                words => words.iter()
+                    .map(|word| match word {
+                        Token::Ident(word) => Some(word.to_ascii_lowercase()),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .map(|words| words.join(" ")),
+            }
+        })
+        .collect::<Option<_>>()?;
+    families.iter().all(|family| !family.is_empty()).then_some(families)
+}
+
+/// The weight `bolder` gives relative to the parent's weight.
+fn bolder(weight: u16) -> u16 {
+    match weight {
+        0..350 => 400,
+        350..550 => 700,
+        _ => 900,
+    }
+}
+
+/// The weight `lighter` gives relative to the parent's weight.
+fn lighter(weight: u16) -> u16 {
+    match weight {
+        0..550 => 100,
+        550..750 => 400,
+        _ => 700,
+    }
+}
+
+/// Computes the custom properties of a node: the inherited ones, overridden by the
+/// node's own `--name` declarations with `var()` substituted.
+///
+/// # Parameters
+/// - `declared`: The node's declarations, from weakest to strongest.
+/// - `inherited`: The parent's computed custom properties.
+///
+/// # Returns
+/// The custom properties, sharing `inherited` if the node declares none.
+///
+/// # Notes
+/// - `initial` removes a custom property; `inherit`, `unset` and `revert` keep the
+///   inherited value.
+/// - Custom properties that reference each other in a cycle, like `--a: var(--b)` and
+///   `--b: var(--a)`, are all invalid, even if their `var()` has a fallback. So is a
+///   custom property whose `var()` references a missing property without a fallback.
+///   Invalid custom properties are removed, so `var()` falls back as if they were unset.
+/// - A custom property whose `var()` chain goes through more than `MAX_VAR_DEPTH`
+///   custom properties of the node is invalid too, as is one whose value grows past
+///   `MAX_SUBSTITUTED_LENGTH`.
+fn compute_custom_properties(declared: &[(String, String)], inherited: &Arc<HashMap<String, String>>) -> Arc<HashMap<String, String>> {
+    let mut own = HashMap::<&str, &str>::new();
+    for (property, value) in declared.iter().filter(|(property, _)| property.starts_with("--")) {
+        own.insert(property, value.trim());
+    }
+    if own.is_empty() {
+        return inherited.clone();
+    }
+
+    /// A resolved custom property: its value and how many custom properties of the node
+    /// its `var()` chain goes through, itself included; `None` if it is invalid.
+    type Resolved = Option<(String, usize)>;
+
+    /// Resolves the custom property `name` depth-first, recording cycles in `in_cycle`.
+    ///
+    /// `stack` never grows past `MAX_VAR_DEPTH`: a chain that would go deeper sets
+    /// `truncated`, which makes the property at the bottom of the stack invalid. The
+    /// others are left unresolved, to be resolved again from a stack of their own.
+    fn resolve(
+        name: &str,
+        own: &HashMap<&str, &str>,
+        inherited: &HashMap<String, String>,
+        stack: &mut Vec<String>,
+        resolved: &mut HashMap<String, Resolved>,
+        in_cycle: &mut HashSet<String>,
+        truncated: &mut bool,
+    ) -> Resolved {
+        if let Some(value) = resolved.get(name) {
+            return value.clone();
+        }
+        let Some(&value) = own.get(name) else {
+            return inherited.get(name).map(|value| (value.clone(), 0));
+        };
+        if let Some(start) = stack.iter().position(|n| n == name) {
+            in_cycle.extend(stack[start..].iter().cloned());
+            return None;
+        }
+        if stack.len() == MAX_VAR_DEPTH {
+            *truncated = true;
+            return None;
+        }
+
+        let value = match value.to_ascii_lowercase().as_str() {
+            "initial" => None,
+            "inherit" | "unset" | "revert" => inherited.get(name).map(|value| (value.clone(), 1)),
+            _ => {
+                stack.push(name.to_string());
+                let mut depth = 1;
+                let substituted = substitute_var(value, &mut |reference| {
+                    let (value, reference_depth) = resolve(reference, own, inherited, stack, resolved, in_cycle, truncated)?;
+                    depth = depth.max(reference_depth + 1);
+                    Some(value)
+                });
+                stack.pop();
+                substituted.filter(|_| depth <= MAX_VAR_DEPTH).map(|value| (value, depth))
+            }
+        };
+        let value = value.filter(|_| !in_cycle.contains(name));
+        if !*truncated {
+            resolved.insert(name.to_string(), value.clone());
+        } else if stack.is_empty() {
+            resolved.insert(name.to_string(), None);
+        }
+        value
+    }
+
+    let mut resolved = HashMap::new();
+    let mut in_cycle = HashSet::new();
+    for name in own.keys() {
+        resolve(name, &own, inherited, &mut vec![], &mut resolved, &mut in_cycle, &mut false);
+    }
+
+    let mut custom_properties = (**inherited).clone();
+    for (name, value) in resolved {
+        match value.map(|(value, _)| value).filter(|_| !in_cycle.contains(&name)) {
+            Some(value) => custom_properties.insert(name, value),
+            None => custom_properties.remove(&name),
+        };
+    }
+    Arc::new(custom_properties)
+}
+
+/// Replaces every `var(--name)` or `var(--name, fallback)` in `value`.
+///
+/// # Parameters
+/// - `value`: The declared value.
+/// - `lookup`: Returns the value of a custom property, or `None` if it is not set.
+///
+/// # Returns
+/// The value with every `var()` substituted, or `None` if a `var()` references a custom
+/// property that is not set and has no fallback, or is malformed. Fallbacks may contain
+/// `var()` themselves, up to `MAX_VAR_DEPTH` deep. A result longer than
+/// `MAX_SUBSTITUTED_LENGTH` is `None` too.
+fn substitute_var(value: &str, lookup: &mut dyn FnMut(&str) -> Option<String>) -> Option<String> {
+    substitute_var_in(value, lookup, 0)
+}
+
+/// Does the work of `substitute_var` for a value nested in `depth` fallbacks.
+fn substitute_var_in(value: &str, lookup: &mut dyn FnMut(&str) -> Option<String>, depth: usize) -> Option<String> {
+    if depth > MAX_VAR_DEPTH {
+        return None;
+    }
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.to_ascii_lowercase().find("var(") {
+        result.push_str(&rest[..start]);
+        let arguments_start = start + "var(".len();
+        let arguments_end = matching_paren(&rest[arguments_start..])? + arguments_start;
+        let arguments = &rest[arguments_start..arguments_end];
+        let (name, fallback) = match split_top_level_comma(arguments) {
+            Some(comma) => (arguments[..comma].trim(), Some(&arguments[comma + 1..])),
+            None => (arguments.trim(), None),
+        };
+        if !name.starts_with("--") {
+            return None;
+        }
+        let substituted = match lookup(name) {
+            Some(value) => value,
+            None => substitute_var_in(fallback?.trim(), lookup, depth + 1)?,
+        };
+        result.push_str(&substituted);
+        if result.len() > MAX_SUBSTITUTED_LENGTH {
+            return None;
+        }
+        rest = &rest[arguments_end + 1..];
+    }
+    result.push_str(rest);
+    (result.len() <= MAX_SUBSTITUTED_LENGTH).then_some(result)
+}
+
+/// Returns the byte index of the first comma outside of parentheses.
+fn split_top_level_comma(value: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the first space-separated component of a value, keeping parenthesized
+/// groups such as `calc(50% - 1px)` whole.
+fn first_component(value: &str) -> Option<&str> {
+    let mut depth = 0usize;
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            c if c.is_whitespace() && depth == 0 => return Some(&value[..i]),
+            _ => {}
+        }
+    }
+    (!value.is_empty()).then_some(value)
+}
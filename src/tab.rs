@@ -15,9 +15,11 @@
 //!   - A `HashMap` containing CSS property-value key-pairs to apply.
 //!
 //! # Behavior
-//! - The function uses the cascade principle to resolve conflicting styles based on selector specificity.
-//! - Inline styles (from the HTML element's `style` attribute) take the highest precedence.
-//! - Inherited properties (defined in the `INHERITED_PROPERTIES` constant) are passed down to descendant elements.
+//! - The function uses the cascade principle to resolve conflicting styles based on origin,
+//!   `!important`, selector specificity and source order.
+//! - Inline styles (from the HTML element's `style` attribute) beat any selector of the same
+//!   importance.
+//! - Inherited properties (defined in `style::INHERITED_PROPERTIES`) are passed down to descendant elements.
 //!
 //! # Panics
 //! - The function panics if `node` is `None` when styling is attempted.
@@ -34,12 +36,16 @@
 //! Browser::style(Some(Rc::new(RefCell::new(html_tree))), &rules);
 //! ```
 //! fn
-use crate::css_parser::CssParser;
+use crate::css_parser::{CssParser, CssRule, Declarations, StyleRule};
 use crate::html_parser::HtmlParser;
+use crate::html_serializer::document_html;
 use crate::js_context::JsContext;
-use crate::layout::{LayoutNode, VSTEP};
+use crate::media::{ColorScheme, MediaContext, MediaType};
+use crate::layout::{LayoutNode, VSTEP, WIDTH, web_font_name};
 use crate::node::{HtmlNode, HtmlNodeType};
-use crate::selector::Selector;
+use crate::rule_index::{AncestorFilter, RuleIndex};
+use crate::selector::SelectorDependencies;
+use crate::style::{ComputedStyle, parse_font_family};
 use crate::task::Task;
 use crate::task_runner::TaskRunner;
 use crate::url::Url;
@@ -47,22 +53,21 @@ use eframe::egui;
 use egui::{Color32, Context, Galley, Pos2, Rect, Vec2};
 use lazy_static::lazy_static;
 use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
-use std::collections::HashMap;
-use std::str::FromStr;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::{Arc, RwLock};
 use std::thread;
+use std::time::{Duration, Instant};
 
 lazy_static! {
-    static ref DEFAULT_STYLE_SHEET: Vec<(Selector, HashMap<String, String>)> =
+    static ref DEFAULT_STYLE_SHEET: RuleIndex = RuleIndex::new(
         CssParser::new(include_str!("../assets/browser.css"))
             .parse()
-            .unwrap();
-    static ref INHERITED_PROPERTIES: HashMap<&'static str, &'static str> = HashMap::from([
-        ("color", "black"),
-        ("font-size", "16px"),
-        ("font-weight", "normal"),
-        ("font-style", "normal"),
-    ]);
+            .unwrap());
+    /// Every URL loaded in any tab during this session, for `:visited`.
+    static ref VISITED_URLS: RwLock<HashSet<String>> = RwLock::new(HashSet::new());
 }
 
 /// Represents a browser tab or a parsing/processing context.
@@ -164,8 +169,19 @@ pub enum TabMessage {
     KeyPress { text: String },
     ScrollDown,
     GoBack,
+    /// The pointer moved to `position`, or left the page if `None`.
+    Hover { position: Option<Pos2> },
+    /// The primary pointer button was pressed at `position`.
+    PointerDown { position: Pos2 },
+    /// The primary pointer button was released.
+    PointerUp,
     RunTask(Task),
     AnimationFrame,
+    SavePage,
+    /// The page area was resized to `viewport`, so media queries may match differently.
+    Resize { viewport: Vec2 },
+    /// A web font from `@font-face` has been registered, so text can be laid out with it.
+    FontLoaded,
 }
 
 pub struct Tab {
@@ -195,22 +211,78 @@ pub struct Tab {
     pub(crate) url: Option<Url>,
     pub(crate) tab_height: f32,
     history: Vec<Url>,
-    rules: Vec<(Selector, HashMap<String, String>)>,
+    /// The author style sheets of the page, in document order, with their `@import`s
+    /// already fetched and spliced in as `@media` blocks. The user-agent style sheet
+    /// (`DEFAULT_STYLE_SHEET`) is kept separately, since it has its own origin.
+    style_sheets: Vec<CssRule>,
+    /// The style rules of `style_sheets` whose media queries match `rules_context`, in
+    /// source order and indexed for matching. Recomputed when the media context changes,
+    /// such as on a resize.
+    rules: RuleIndex,
+    rules_context: Option<MediaContext>,
+    /// What a change to an element can restyle besides the element itself, given the
+    /// user-agent rules and `rules`.
+    dependencies: SelectorDependencies,
+    /// Whether the next render must restyle every node, such as after the rules or the
+    /// document changed, instead of only the nodes marked by `invalidate_style`.
+    restyle_all: bool,
+    /// The size of the page area, against which media queries and viewport units are
+    /// evaluated.
+    pub(crate) viewport: Vec2,
     focus: Option<Arc<RwLock<HtmlNode>>>,
+    /// The element under the pointer, whose ancestors are all `:hover`.
+    hovered: Option<Arc<RwLock<HtmlNode>>>,
+    /// The element being pressed, whose ancestors are all `:active`.
+    active: Option<Arc<RwLock<HtmlNode>>>,
+    /// Whether the pointer is over a link, so the browser can show a pointing hand.
+    pub(crate) hovering_link: bool,
     pub(crate) needs_redraw: bool,
     pub(crate) js: Option<Arc<JsContext>>,
     pub(crate) cookie_jar: Arc<RwLock<HashMap<String, (String, HashMap<String, String>)>>>,
     allowed_origins: Option<Vec<String>>,
+    /// Whether inline `<script>` and `<style>` contents may be used under the page's CSP.
+    allow_inline: bool,
     pub(crate) task_runner: Option<TaskRunner>,
     pub(crate) task_tx: Option<std::sync::mpsc::Sender<TabMessage>>,
     pub(crate) ctx: Option<Context>,
     pub(crate) measure: Option<Arc<std::sync::Mutex<crate::measure_time::MeasureTime>>>,
     pub(crate) has_raf_request: bool,
     pub(crate) scroll_sync_needed: bool,
+    /// The text of the document's `<title>`, with whitespace collapsed.
+    pub(crate) title: Option<String>,
+    /// The document's `<base href>`, against which its relative URLs are resolved.
+    base_url: Option<Url>,
+    /// The page's icon, from `<link rel=icon>` or `/favicon.ico`.
+    pub(crate) favicon: Option<egui::TextureHandle>,
+    /// The outcome of the last action on the page, such as saving it, which the chrome
+    /// shows next to the URL until the tab navigates.
+    pub(crate) status: Option<String>,
+    /// Incremented on every load, so that a pending `<meta http-equiv=refresh>` or
+    /// favicon fetch can tell whether the tab has navigated away in the meantime.
+    load_count: u64,
 }
 
 pub(crate) const SCROLL_STEP: f32 = 100.0;
 
+/// The size, in pixels, that favicons are scaled to for the tab strip.
+pub(crate) const FAVICON_SIZE: u32 = 16;
+
+/// How many characters of the `<title>` are kept. The tab strip shows far fewer, and the
+/// window title gains nothing from a longer one.
+const MAX_TITLE_CHARS: usize = 1024;
+
+/// How many numbered names `save_new_file` tries before giving up.
+const MAX_SAVE_ATTEMPTS: usize = 1000;
+
+/// How deeply `@import`s may nest, counting the sheet that starts the chain.
+const MAX_IMPORT_DEPTH: usize = 16;
+
+/// How many times, 50ms apart, a font loader checks that egui has added its font.
+const FONT_LOAD_POLLS: usize = 100;
+
+/// How often a page that is still loading is re-rendered to show the content parsed so far.
+const PROGRESSIVE_RENDER_INTERVAL: Duration = Duration::from_millis(100);
+
 impl Default for Tab {
     /// Returns a `Browser` instance with empty buffers and default scroll position.
     ///
@@ -225,18 +297,32 @@ impl Default for Tab {
             url: None,
             tab_height: 0.0,
             history: vec![],
-            rules: vec![],
+            style_sheets: vec![],
+            rules: RuleIndex::new(vec![]),
+            rules_context: None,
+            dependencies: SelectorDependencies::default(),
+            restyle_all: true,
+            viewport: Vec2::new(WIDTH, 0.0),
             focus: None,
+            hovered: None,
+            active: None,
+            hovering_link: false,
             needs_redraw: true,
             js: None,
             cookie_jar: Arc::new(RwLock::new(HashMap::new())),
             allowed_origins: None,
+            allow_inline: true,
             task_runner: None,
             task_tx: None,
             ctx: None,
             measure: None,
             has_raf_request: false,
             scroll_sync_needed: true,
+            title: None,
+            base_url: None,
+            favicon: None,
+            status: None,
+            load_count: 0,
         }
     }
 }
@@ -255,6 +341,7 @@ impl Tab {
 
         let tab = Self {
             tab_height: height,
+            viewport: Vec2::new(WIDTH, height),
             cookie_jar: cookie_jar.clone(),
             task_tx: Some(tx.clone()),
             ctx: Some(cc.clone()),
@@ -285,12 +372,36 @@ impl Tab {
                     TabMessage::GoBack => {
                         Tab::go_back(tab_clone.clone());
                     }
+                    TabMessage::Hover { position } => {
+                        Tab::hover(tab_clone.clone(), position);
+                    }
+                    TabMessage::PointerDown { position } => {
+                        Tab::pointer_down(tab_clone.clone(), position);
+                    }
+                    TabMessage::PointerUp => {
+                        Tab::set_active(tab_clone.clone(), None);
+                    }
                     TabMessage::RunTask(mut task) => {
                         let js = tab_clone.read().unwrap().js.clone();
                         if let Some(js) = js {
                             task.run(js);
                         }
                     }
+                    TabMessage::SavePage => {
+                        Tab::save_page(tab_clone.clone());
+                    }
+                    TabMessage::Resize { viewport } => {
+                        let mut tab = tab_clone.write().unwrap();
+                        tab.viewport = viewport;
+                        if tab.nodes.is_some() {
+                            tab.render();
+                        }
+                    }
+                    TabMessage::FontLoaded => {
+                        if tab_clone.read().unwrap().nodes.is_some() {
+                            tab_clone.write().unwrap().render();
+                        }
+                    }
                     TabMessage::AnimationFrame => {
                         let js = tab_clone.read().unwrap().js.clone();
                         if let Some(js) = js {
@@ -372,30 +483,108 @@ impl Tab {
         }
     }
 
-    pub(crate) fn click(this: Arc<RwLock<Tab>>, position: Pos2) {
-        if this.read().unwrap().focus.is_some() {
-            this.read().unwrap().focus.clone().unwrap().write().unwrap().is_focused = false;
-        }
+    /// Returns the innermost node laid out at `position`, in tab coordinates (before
+    /// scrolling), or `None` if there is nothing there.
+    fn hit_test(this: Arc<RwLock<Tab>>, position: Pos2) -> Option<Arc<RwLock<HtmlNode>>> {
         let mut new_pos = position.clone();
         new_pos.y += this.read().unwrap().scroll_y;
 
+        let document = this.read().unwrap().document.clone()?;
         let mut vec: Vec<Arc<RwLock<LayoutNode>>> = vec![];
-        let objs = LayoutNode::tree_to_vec(this.read().unwrap().document.clone().unwrap(), &mut vec)
+        let objs = LayoutNode::tree_to_vec(document, &mut vec)
             .iter()
             .filter(|l| {
-                Rect::from_two_pos(
-                    l.read().unwrap().position.unwrap().to_pos2(),
-                    (l.read().unwrap().position.unwrap() + l.read().unwrap().size.unwrap()).to_pos2(),
-                )
-                .contains(new_pos)
+                let (Some(position), Some(size)) = (l.read().unwrap().position, l.read().unwrap().size) else {
+                    return false;
+                };
+                Rect::from_two_pos(position.to_pos2(), (position + size).to_pos2()).contains(new_pos)
             })
             .collect::<Vec<&Arc<RwLock<LayoutNode>>>>();
-        if objs.len() == 0 {
+
+        objs.last().map(|&e| e.read().unwrap().node.clone())
+    }
+
+    /// Updates the `:hover` state for the pointer at `position`, and restyles the page if
+    /// the hovered element changed and a selector uses `:hover`. Text nodes are not
+    /// elements, so hovering text hovers its parent.
+    pub(crate) fn hover(this: Arc<RwLock<Tab>>, position: Option<Pos2>) {
+        let mut element = position.and_then(|position| Tab::hit_test(this.clone(), position));
+        if let Some(node) = element.clone() && matches!(node.read().unwrap().node_type, HtmlNodeType::Text(_)) {
+            element = node.read().unwrap().parent.clone();
+        }
+
+        let previous = this.read().unwrap().hovered.clone();
+        if previous.as_ref().map(Arc::as_ptr) == element.as_ref().map(Arc::as_ptr) {
+            return;
+        }
+        let changed = move_state_to_ancestors(previous.as_ref(), element.as_ref(), |node, value| node.is_hovered = value);
+
+        let mut tab = this.write().unwrap();
+        let restyle = tab.dependencies.hover && !changed.is_empty();
+        if restyle {
+            for node in &changed {
+                tab.invalidate_state(node);
+            }
+        }
+        // The pointer cursor over links needs no restyle.
+        tab.hovering_link = element.as_ref().is_some_and(is_in_link);
+        tab.hovered = element;
+        if restyle && tab.nodes.is_some() {
+            tab.render();
+        }
+    }
+
+    /// Makes the element under the pointer at `position` the `:active` element.
+    pub(crate) fn pointer_down(this: Arc<RwLock<Tab>>, position: Pos2) {
+        let element = Tab::hit_test(this.clone(), position);
+        Tab::set_active(this, element);
+    }
+
+    /// Makes `element` (or its parent, for a text node) the `:active` element, or clears
+    /// the active element if `None`, and restyles the page if a selector uses `:active`.
+    pub(crate) fn set_active(this: Arc<RwLock<Tab>>, mut element: Option<Arc<RwLock<HtmlNode>>>) {
+        if let Some(node) = element.clone() && matches!(node.read().unwrap().node_type, HtmlNodeType::Text(_)) {
+            element = node.read().unwrap().parent.clone();
+        }
+
+        let previous = this.read().unwrap().active.clone();
+        if previous.as_ref().map(Arc::as_ptr) == element.as_ref().map(Arc::as_ptr) {
             return;
         }
+        let changed = move_state_to_ancestors(previous.as_ref(), element.as_ref(), |node, value| node.is_active = value);
+
+        let mut tab = this.write().unwrap();
+        let restyle = tab.dependencies.active && !changed.is_empty();
+        if restyle {
+            for node in &changed {
+                tab.invalidate_state(node);
+            }
+        }
+        tab.active = element;
+        if restyle && tab.nodes.is_some() {
+            tab.render();
+        }
+    }
+
+    pub(crate) fn click(this: Arc<RwLock<Tab>>, position: Pos2) {
+        let mut should_render = false;
+        if let Some(focus) = this.write().unwrap().focus.take() {
+            focus.write().unwrap().is_focused = false;
+            let tab = this.read().unwrap();
+            if tab.dependencies.focus {
+                tab.invalidate_state(&focus);
+                should_render = true;
+            }
+        }
+
+        let Some(node) = Tab::hit_test(this.clone(), position) else {
+            if should_render {
+                this.write().unwrap().render();
+            }
+            return;
+        };
 
-        let mut element = objs.last().map(|&e| e.read().unwrap().node.clone());
-        let should_render = false;
+        let mut element = Some(node);
         let mut url_to_load = None;
 
         if let Some(ref el) = element {
@@ -412,15 +601,9 @@ impl Tab {
                 match node.node_type {
                     HtmlNodeType::Element(ref mut ele) => {
                         if ele.tag == "a" && ele.attributes.contains_key("href") {
-                            let url = this
-                                .read()
-                                .unwrap()
-                                .url
-                                .as_ref()
-                                .unwrap()
-                                .resolve(ele.attributes.get("href").unwrap().clone().as_mut_str())
-                                .unwrap();
-                            url_to_load = Some(url);
+                            url_to_load = this.read().unwrap()
+                                .resolve_url(ele.attributes.get("href").unwrap())
+                                .ok();
                             break;
                         } else if ele.tag == "input" {
                             ele.attributes.insert("value".to_string(), "".to_string());
@@ -437,8 +620,10 @@ impl Tab {
             // Handle actions after the 'node' borrow is dropped
             match action_to_take {
                 Some("input") => {
-                    this.write().unwrap().focus = Some(current_element.clone());
-                    this.write().unwrap().render();
+                    let mut tab = this.write().unwrap();
+                    tab.invalidate_style(&current_element);
+                    tab.focus = Some(current_element.clone());
+                    tab.render();
                     break;
                 }
                 Some("button") => {
@@ -475,11 +660,18 @@ impl Tab {
         }
     }
 
-    /// Fetches a web page, strips HTML tags, and stores the raw content.
+    /// Fetches a web page, parses it as it arrives and renders it.
+    ///
+    /// The response body is streamed into an `HtmlParser` chunk by chunk. While the
+    /// document is still loading, the partial tree is styled and rendered every
+    /// `PROGRESSIVE_RENDER_INTERVAL`, so long pages on slow connections show their
+    /// content as soon as it is available. The body is read on a separate thread, so
+    /// that the render happens on time even when the connection stalls, and text that
+    /// the parser is holding back is flushed into the tree first.
     ///
-    /// This triggers a blocking network request. Upon success, the response
-    /// body is passed through a lexer to remove markup before being cached
-    /// in `self.body`.
+    /// Classic `<script>` elements are parser-blocking: tree construction pauses after
+    /// each one until it has been fetched (if external) and run. Scripts marked `defer`
+    /// or `async` are run after parsing instead.
     ///
     /// # Errors
     /// Network failures or request timeouts are logged to `stderr`.
@@ -489,128 +681,140 @@ impl Tab {
         this.write().unwrap().draw_commands.clear();
         this.write().unwrap().scroll_y = 0.0;
         this.write().unwrap().scroll_sync_needed = true;
-        match url.request(body, cookie_jar) {
-            Ok(body) => {
+        match url.request_stream(body, cookie_jar) {
+            Ok(mut response) => {
 
                 this.write().unwrap().allowed_origins = None;
+                this.write().unwrap().allow_inline = true;
 
-                if body.headers.contains_key("content-security-policy") {
-                    let csp: Vec<_> = body.headers["content-security-policy"].split_ascii_whitespace().collect();
+                if response.headers.contains_key("content-security-policy") {
+                    let csp: Vec<_> = response.headers["content-security-policy"].split_ascii_whitespace().collect();
                     if csp.len() > 0 && csp.first().unwrap().starts_with("default-src") {
                         let mut allowed_origins = Vec::<String>::new();
+                        let mut allow_inline = false;
                         for origin in csp.iter().skip(1) {
-                            allowed_origins.push(Url::new(origin).unwrap().origin())
+                            match origin.trim_end_matches(';') {
+                                "'self'" => allowed_origins.push(url.origin()),
+                                "'unsafe-inline'" => allow_inline = true,
+                                source => {
+                                    if let Ok(source_url) = Url::new(source) {
+                                        allowed_origins.push(source_url.origin())
+                                    }
+                                }
+                            }
                         }
                         this.write().unwrap().allowed_origins = Some(allowed_origins);
+                        this.write().unwrap().allow_inline = allow_inline;
                     }
                 }
 
-                let mut parser = HtmlParser {
-                    body: body.content.clone(),
-                    unfinished: vec![],
-                };
-
-                this.write().unwrap().nodes = Some(parser.parse());
-                this.write().unwrap().rules = DEFAULT_STYLE_SHEET.clone();
-
-                let links =
-                    HtmlNode::tree_to_vec(this.read().unwrap().nodes.clone().unwrap(), &mut vec![])
-                        .iter()
-                        .filter_map(|p| match &p.read().unwrap().node_type {
-                            HtmlNodeType::Element(e) => {
-                                if e.tag == "link"
-                                    && e.attributes.contains_key("rel")
-                                    && e.attributes.get("rel").unwrap() == "stylesheet"
-                                    && e.attributes.contains_key("href")
-                                {
-                                    return Some(e.attributes.get("href").unwrap().to_string());
-                                }
-                                None
-                            }
-                            HtmlNodeType::Text(_) => None,
-                        })
-                        .collect::<Vec<String>>();
-
-                let scripts =
-                    HtmlNode::tree_to_vec(this.read().unwrap().nodes.clone().unwrap(), &mut vec![])
-                        .iter()
-                        .filter_map(|p| match &p.read().unwrap().node_type {
-                            HtmlNodeType::Element(e) => {
-                                if e.tag == "script" && e.attributes.contains_key("src") {
-                                    return Some(e.attributes.get("src").unwrap().to_string());
-                                }
-                                None
-                            }
-                            HtmlNodeType::Text(_) => None,
-                        })
-                        .collect::<Vec<String>>();
-
                 if this.read().unwrap().js.is_some() {
                     this.write().unwrap().js.as_mut().unwrap().discarded.write().unwrap().clone_from(&true);
                 }
 
+                // The script context exists before parsing starts, so that parser-blocking
+                // scripts can run while the rest of the document is still arriving.
                 let context = JsContext::new(this.clone());
                 this.write().unwrap().js = Some(Arc::new(context));
+                {
+                    let mut tab = this.write().unwrap();
+                    tab.style_sheets = vec![];
+                    tab.rules = RuleIndex::new(vec![]);
+                    tab.rules_context = None;
+                }
+                this.write().unwrap().nodes = None;
+                VISITED_URLS.write().unwrap().insert(url.to_string());
+                {
+                    let mut tab = this.write().unwrap();
+                    tab.focus = None;
+                    tab.hovered = None;
+                    tab.active = None;
+                    tab.hovering_link = false;
+                    tab.title = None;
+                    tab.base_url = None;
+                    tab.favicon = None;
+                    tab.status = None;
+                    tab.load_count += 1;
+                }
 
-                for script in scripts {
-                    let script_url = url.resolve(script.clone().as_mut_str());
-
-                    match script_url {
-                        Ok(st) => {
-                            if !Self::allowed_request(this.clone(), st.clone()) {
-                                println!("Blocked script");
-                                continue
-                            }
-                            let body = st.request(None, this.read().unwrap().cookie_jar.clone());
-                            match body {
-                                Ok(bd) => {
-                                    let task = Task::new({
-                                        let script_content = bd.content.clone();
-
-                                        move |js: Arc<JsContext>| {
-                                            js.context.read().unwrap().with(|ctx| {
-                                                let res = js.eval_with_measure(|| ctx.eval::<(), _>(script_content.as_str()));
-                                                if let Err(e) = res {
-                                                    if let rquickjs::Error::Exception = e {
-                                                        let exception = ctx.catch();
-                                                        println!("JS Exception in script eval: {:?}", exception);
-                                                    } else {
-                                                        println!("Failed to eval script: {e}");
-                                                    }
-                                                }
-                                            });
-                                        }
-                                    });
-                                    print!("Scheduling script task");
-                                    this.write().unwrap().task_runner.as_mut().unwrap().schedule_task(task);
-                                }
-                                Err(_) => {}
+                let mut parser = HtmlParser::new(String::new());
+                let mut loaded_styles = Vec::<Arc<RwLock<HtmlNode>>>::new();
+                let mut deferred_scripts = Vec::<SourceText>::new();
+                let mut last_render = Instant::now();
+                let mut changed = false;
+                // The body is read on its own thread, so that a connection that stalls
+                // does not hold back the render of what has arrived so far.
+                let (chunk_tx, chunk_rx) = mpsc::channel();
+                thread::spawn(move || loop {
+                    let chunk = response.next_chunk();
+                    let last = !matches!(chunk, Ok(Some(_)));
+                    if chunk_tx.send(chunk).is_err() || last {
+                        break;
+                    }
+                });
+                loop {
+                    // Wait for the next render only while there is something to render;
+                    // otherwise block until the next chunk, so a stalled connection does
+                    // not keep the thread busy.
+                    let received = if changed {
+                        chunk_rx.recv_timeout(PROGRESSIVE_RENDER_INTERVAL.saturating_sub(last_render.elapsed()))
+                    } else {
+                        chunk_rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+                    };
+                    match received {
+                        Ok(Ok(Some(chunk))) => {
+                            let mut script = parser.feed(&chunk);
+                            while let Some(node) = script {
+                                Self::run_parser_script(this.clone(), &parser, node, &mut loaded_styles, &mut deferred_scripts);
+                                script = parser.feed("");
                             }
+                            changed = true;
+                        }
+                        Ok(Ok(None)) | Err(RecvTimeoutError::Disconnected) => break,
+                        Ok(Err(e)) => {
+                            eprintln!("Error reading response body: {}", e);
+                            break;
                         }
-                        Err(_) => {}
+                        Err(RecvTimeoutError::Timeout) => {}
                     }
-                }
-                for link in links {
-                    let style_url = url.resolve(link.clone().as_mut_str());
-                    match style_url {
-                        Ok(st) => {
-                            let body = st.request(None, this.read().unwrap().cookie_jar.clone());
-                            match body {
-                                Ok(bd) => {
-                                    this.write().unwrap().rules.append(
-                                        &mut CssParser::new(&*bd.content).parse().unwrap_or(vec![]),
-                                    );
-                                }
-                                Err(_) => {}
-                            }
+
+                    // Show what has been parsed so far, but not after every chunk: styling
+                    // and layout are done from scratch on each render.
+                    if changed && last_render.elapsed() >= PROGRESSIVE_RENDER_INTERVAL {
+                        parser.flush_pending_text();
+                        if parser.document().is_some() {
+                            this.write().unwrap().set_document(parser.document());
+                            Self::update_metadata(this.clone());
+                            Self::load_style_sheets(this.clone(), Some(&parser), &mut loaded_styles);
+                            Self::render_partial(this.clone());
                         }
-                        Err(_) => {}
+                        last_render = Instant::now();
+                        changed = false;
                     }
                 }
-                this.write().unwrap()
-                    .rules
-                    .sort_by(|a, b| Self::cascade_priority(a).cmp(&Self::cascade_priority(b)));
+                while let Some(node) = parser.end() {
+                    Self::run_parser_script(this.clone(), &parser, node, &mut loaded_styles, &mut deferred_scripts);
+                }
+                this.write().unwrap().set_document(parser.document());
+                Self::update_metadata(this.clone());
+                Self::load_style_sheets(this.clone(), None, &mut loaded_styles);
+
+                // `defer` and `async` scripts run once the document has been parsed, in
+                // document order, as tasks on the tab's event loop.
+                for script in deferred_scripts {
+                    let Some(script_content) = Self::source_text(this.clone(), script) else {
+                        println!("Blocked script");
+                        continue
+                    };
+                    let task = Task::new(move |js: Arc<JsContext>| {
+                        run_script(&js, &script_content);
+                    });
+                    this.write().unwrap().task_runner.as_mut().unwrap().schedule_task(task);
+                }
+
                 this.write().unwrap().render();
+                Self::load_favicon(this.clone());
+                Self::schedule_refresh(this.clone());
             }
             Err(e) => {
                 eprintln!("Error loading URL: {}", e);
@@ -619,11 +823,488 @@ impl Tab {
         this.write().unwrap().history.push(url);
     }
 
+    /// Runs a `<script>` element that the parser has paused on.
+    ///
+    /// Inline scripts and classic external scripts run immediately, before parsing
+    /// resumes, so they see the document only up to themselves. Style sheets that have
+    /// been parsed so far are applied first, as a browser would block the script on them.
+    /// External scripts with `defer` or `async` are added to `deferred_scripts` instead.
+    fn run_parser_script(
+        this: Arc<RwLock<Self>>,
+        parser: &HtmlParser,
+        node: Arc<RwLock<HtmlNode>>,
+        loaded_styles: &mut Vec<Arc<RwLock<HtmlNode>>>,
+        deferred_scripts: &mut Vec<SourceText>,
+    ) {
+        let source = {
+            let node_ref = node.read().unwrap();
+            let HtmlNodeType::Element(e) = &node_ref.node_type else {
+                return;
+            };
+            if !e.attributes.get("type").is_none_or(|t| is_javascript_type(t)) {
+                return;
+            }
+            match e.attributes.get("src") {
+                Some(src) if e.attributes.contains_key("defer") || e.attributes.contains_key("async") => {
+                    deferred_scripts.push(SourceText::External(src.to_string()));
+                    return;
+                }
+                Some(src) => SourceText::External(src.to_string()),
+                None => SourceText::Inline(node_ref.text_content()),
+            }
+        };
+
+        this.write().unwrap().set_document(parser.document());
+        Self::update_metadata(this.clone());
+        Self::load_style_sheets(this.clone(), Some(parser), loaded_styles);
+
+        let Some(script_content) = Self::source_text(this.clone(), source) else {
+            println!("Blocked script");
+            return;
+        };
+        let js = this.read().unwrap().js.clone();
+        if let Some(js) = js {
+            run_script(&js, &script_content);
+        }
+    }
+
+    /// Applies the style sheets in the document that have not been applied yet, in
+    /// document order: `<link rel=stylesheet>` elements and `<style>` elements.
+    ///
+    /// While the document is being parsed (`parser` is `Some`), a `<style>` element whose
+    /// end tag has not been parsed yet is skipped until a later call. Applied elements are
+    /// remembered in `loaded_styles`.
+    fn load_style_sheets(
+        this: Arc<RwLock<Self>>,
+        parser: Option<&HtmlParser>,
+        loaded_styles: &mut Vec<Arc<RwLock<HtmlNode>>>,
+    ) {
+        let Some(root) = this.read().unwrap().nodes.clone() else {
+            return;
+        };
+
+        let mut styles = Vec::<SourceText>::new();
+        for node in HtmlNode::tree_to_vec(root, &mut vec![]) {
+            if loaded_styles.iter().any(|n| Arc::ptr_eq(n, node))
+                || parser.is_some_and(|p| p.is_open(node)) {
+                continue;
+            }
+            let node_ref = node.read().unwrap();
+            let HtmlNodeType::Element(e) = &node_ref.node_type else {
+                continue;
+            };
+            match e.tag.as_str() {
+                "link" if e.attributes.get("rel").is_some_and(|r| r == "stylesheet") => {
+                    if let Some(href) = e.attributes.get("href") {
+                        styles.push(SourceText::External(href.to_string()));
+                    }
+                }
+                "style" if e.attributes.get("type").is_none_or(|t| t.is_empty() || t.eq_ignore_ascii_case("text/css")) => {
+                    styles.push(SourceText::Inline(node_ref.text_content()));
+                }
+                _ => continue,
+            }
+            loaded_styles.push(node.clone());
+        }
+        if styles.is_empty() {
+            return;
+        }
+
+        // Style sheets are appended in document order, so that linked and inline
+        // sheets keep their relative source order in the cascade.
+        for style in styles {
+            // Relative URLs in a sheet, such as those of `@import`, are relative to the
+            // sheet itself: its own URL, or the document's for a `<style>` element.
+            let (style, sheet_url) = match style {
+                SourceText::External(href) => {
+                    let Ok(url) = this.read().unwrap().resolve_url(&href) else {
+                        continue
+                    };
+                    (SourceText::External(url.to_string()), Some(url))
+                }
+                SourceText::Inline(text) => {
+                    let tab = this.read().unwrap();
+                    (SourceText::Inline(text), tab.base_url.clone().or(tab.url.clone()))
+                }
+            };
+            let Some(style_content) = Self::source_text(this.clone(), style) else {
+                println!("Blocked style sheet");
+                continue
+            };
+            let mut chain = sheet_url.iter().map(|url| url.to_string()).collect();
+            let mut rules = Self::prepare_style_sheet(
+                this.clone(), CssParser::new(&style_content).parse_rules(), sheet_url.as_ref(), &mut chain);
+            this.write().unwrap().style_sheets.append(&mut rules);
+        }
+        this.write().unwrap().update_rules();
+    }
+
+    /// Prepares the rules of a style sheet for the cascade: fetches its `@import`s, which
+    /// are parsed in turn and spliced in as `@media` blocks with the import's media
+    /// queries, and starts loading the fonts of its `@font-face` rules.
+    ///
+    /// # Parameters
+    /// - `sheet_url`: The URL that relative URLs in the sheet are resolved against.
+    /// - `chain`: The URLs of the sheets that led to this one. An import of a sheet in the
+    ///   chain is dropped, so that sheets that import each other do not loop, as is any
+    ///   import nested deeper than `MAX_IMPORT_DEPTH`.
+    fn prepare_style_sheet(
+        this: Arc<RwLock<Self>>,
+        rules: Vec<CssRule>,
+        sheet_url: Option<&Url>,
+        chain: &mut Vec<String>,
+    ) -> Vec<CssRule> {
+        let mut prepared = Vec::new();
+        for rule in rules {
+            match rule {
+                CssRule::Import { url, queries } => {
+                    let Some(url) = sheet_url.and_then(|base| base.resolve(url.clone().as_mut_str()).ok()) else {
+                        continue
+                    };
+                    let key = url.to_string();
+                    if chain.contains(&key) || chain.len() > MAX_IMPORT_DEPTH {
+                        println!("Skipped recursive import of {key}");
+                        continue
+                    }
+                    let Some(content) = Self::source_text(this.clone(), SourceText::External(key.clone())) else {
+                        println!("Blocked style sheet");
+                        continue
+                    };
+                    chain.push(key);
+                    let rules = Self::prepare_style_sheet(
+                        this.clone(), CssParser::new(&content).parse_rules(), Some(&url), chain);
+                    chain.pop();
+                    prepared.push(CssRule::Media { queries, rules });
+                }
+                CssRule::FontFace(descriptors) => {
+                    Self::load_font_face(this.clone(), &descriptors, sheet_url);
+                    prepared.push(CssRule::FontFace(descriptors));
+                }
+                CssRule::Media { queries, rules } => {
+                    let rules = Self::prepare_style_sheet(this.clone(), rules, sheet_url, chain);
+                    prepared.push(CssRule::Media { queries, rules });
+                }
+                rule => prepared.push(rule),
+            }
+        }
+        prepared
+    }
+
+    /// Fetches the font of an `@font-face` rule in the background and registers it with
+    /// egui under `web_font_name`, so that layout can use it for its `font-family`.
+    ///
+    /// # Notes
+    /// - The first `src` that is a TrueType or OpenType `url()` is used; `local()` fonts
+    ///   and WOFF files are skipped, since egui cannot read them.
+    /// - `font-weight` and `font-style` descriptors choose between the bold and italic
+    ///   variants, as with the built-in fonts.
+    /// - Once egui has the font, a `FontLoaded` message restyles the page with it.
+    fn load_font_face(this: Arc<RwLock<Self>>, descriptors: &HashMap<String, String>, sheet_url: Option<&Url>) {
+        let Some(family) = descriptors.get("font-family")
+            .and_then(|family| parse_font_family(family))
+            .and_then(|families| families.into_iter().next()) else {
+            return;
+        };
+        let Some(src) = descriptors.get("src").and_then(|src| font_source(src)) else {
+            return;
+        };
+        let Some(url) = sheet_url.and_then(|base| base.resolve(src.clone().as_mut_str()).ok()) else {
+            return;
+        };
+        if !Self::allowed_request(this.clone(), url.clone()) {
+            return;
+        }
+        let bold = descriptors.get("font-weight").is_some_and(|weight| {
+            let weight = weight.split_whitespace().next().unwrap_or("").to_ascii_lowercase();
+            weight == "bold" || weight == "bolder" || weight.parse::<f32>().is_ok_and(|w| w >= 600.0)
+        });
+        let italic = descriptors.get("font-style").is_some_and(|style| {
+            let style = style.to_ascii_lowercase();
+            style.starts_with("italic") || style.starts_with("oblique")
+        });
+        let name = web_font_name(&family, bold, italic);
+
+        let (cookie_jar, ctx, load_count) = {
+            let tab = this.read().unwrap();
+            (tab.cookie_jar.clone(), tab.ctx.clone(), tab.load_count)
+        };
+        let Some(ctx) = ctx else {
+            return;
+        };
+        std::thread::spawn(move || {
+            let Ok(bytes) = url.request_stream(None, cookie_jar).and_then(|response| response.into_bytes()) else {
+                return;
+            };
+            // egui panics on font data it cannot parse, so the data is checked first.
+            if skrifa::FontRef::new(&bytes).is_err() {
+                println!("Unsupported font {}", url.to_string());
+                return;
+            }
+            let family = egui::FontFamily::Name(Arc::from(name.as_str()));
+            ctx.add_font(egui::epaint::text::FontInsert::new(
+                &name,
+                egui::FontData::from_owned(bytes),
+                vec![egui::epaint::text::InsertFontFamily {
+                    family: family.clone(),
+                    priority: egui::epaint::text::FontPriority::Highest,
+                }],
+            ));
+            // The font is only added at the start of the next frame.
+            for _ in 0..FONT_LOAD_POLLS {
+                ctx.request_repaint();
+                thread::sleep(Duration::from_millis(50));
+                if ctx.fonts(|f| f.definitions().families.contains_key(&family)) {
+                    if this.read().unwrap().load_count == load_count {
+                        Tab::send_message(this, TabMessage::FontLoaded);
+                    }
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Returns what media queries are evaluated against in this tab.
+    fn media_context(&self) -> MediaContext {
+        let dark = self.ctx.as_ref().and_then(|ctx| ctx.system_theme()) == Some(egui::Theme::Dark);
+        MediaContext {
+            media_type: MediaType::Screen,
+            viewport: self.viewport,
+            color_scheme: if dark { ColorScheme::Dark } else { ColorScheme::Light },
+        }
+    }
+
+    /// Recomputes `rules` from `style_sheets` for the current media context.
+    fn update_rules(&mut self) {
+        let context = self.media_context();
+        let mut rules = vec![];
+        flatten_rules(&self.style_sheets, &context, &mut rules);
+        self.rules = RuleIndex::new(rules);
+        self.rules_context = Some(context);
+        self.dependencies = SelectorDependencies::of(DEFAULT_STYLE_SHEET.rules().iter().chain(self.rules.rules()));
+        self.restyle_all = true;
+    }
+
+    /// Replaces the document with `nodes`, or with a newer version of it while it is
+    /// being parsed, whose new nodes have never been styled. The next render restyles
+    /// every node.
+    fn set_document(&mut self, nodes: Option<Arc<RwLock<HtmlNode>>>) {
+        self.nodes = nodes;
+        self.restyle_all = true;
+    }
+
+    /// Marks the style of `node` out of date after a change to it, such as a new
+    /// attribute, new children or a new `:focus` state, so that the next render restyles
+    /// it.
+    ///
+    /// The selectors in use decide what else is marked (see `SelectorDependencies`):
+    /// - With descendant or child combinators, all the descendants of `node`, since they
+    ///   may now match rules like `.open li`.
+    /// - With sibling combinators, the following siblings of `node` (and their
+    ///   descendants, as above), since they may now match rules like `.error + p`.
+    ///
+    /// Descendants that are not marked are still restyled if the style of their parent
+    /// changes, since they may inherit from it.
+    ///
+    /// # Notes
+    /// The ancestors of the marked nodes get `child_needs_style`, so that restyling can
+    /// go straight to the marked nodes from the root.
+    pub(crate) fn invalidate_style(&self, node: &Arc<RwLock<HtmlNode>>) {
+        self.invalidate(node, self.dependencies.descendants, self.dependencies.siblings);
+    }
+
+    /// Marks the style of `node` out of date after a change of its `:hover`, `:active`
+    /// or `:focus` state.
+    ///
+    /// Unlike `invalidate_style`, the descendants and following siblings of `node` are
+    /// only marked if a selector has a dynamic pseudo-class left of a combinator, as in
+    /// `div:hover p`, since the state cannot change what other selectors match.
+    pub(crate) fn invalidate_state(&self, node: &Arc<RwLock<HtmlNode>>) {
+        self.invalidate(node, self.dependencies.state_descendants, self.dependencies.state_siblings);
+    }
+
+    /// Marks `node`, and its descendants and following siblings as asked, as needing
+    /// style, and its ancestors as having a descendant that does.
+    fn invalidate(&self, node: &Arc<RwLock<HtmlNode>>, descendants: bool, siblings: bool) {
+        mark_needs_style(node, descendants);
+        let parent = node.read().unwrap().parent.clone();
+        if siblings && let Some(ref parent) = parent {
+            let siblings = parent.read().unwrap().children.clone();
+            if let Some(index) = siblings.iter().position(|sibling| Arc::ptr_eq(sibling, node)) {
+                for sibling in &siblings[index + 1..] {
+                    mark_needs_style(sibling, descendants);
+                }
+            }
+        }
+
+        let mut current = parent;
+        while let Some(ancestor) = current {
+            let mut ancestor = ancestor.write().unwrap();
+            if ancestor.child_needs_style {
+                // Its ancestors are already marked.
+                break;
+            }
+            ancestor.child_needs_style = true;
+            current = ancestor.parent.clone();
+        }
+    }
+
+    /// Reads the document metadata that the tab itself uses: the `<title>`, shown in the
+    /// tab strip and the window title, and the `<base href>`, used by `resolve_url`.
+    ///
+    /// Only the first `<title>` and the first `<base>` with an `href` count. The title is
+    /// cut short at `MAX_TITLE_CHARS`.
+    fn update_metadata(this: Arc<RwLock<Self>>) {
+        let (Some(root), Some(url)) = (this.read().unwrap().nodes.clone(), this.read().unwrap().url.clone()) else {
+            return;
+        };
+
+        let mut title = None;
+        let mut base_url = None;
+        for node in HtmlNode::tree_to_vec(root, &mut vec![]) {
+            let node_ref = node.read().unwrap();
+            let HtmlNodeType::Element(e) = &node_ref.node_type else {
+                continue;
+            };
+            match e.tag.as_str() {
+                "title" if title.is_none() => {
+                    let text = node_ref.text_content();
+                    let words = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                    title = Some(words.chars().take(MAX_TITLE_CHARS).collect());
+                }
+                "base" if base_url.is_none() => {
+                    if let Some(href) = e.attributes.get("href") {
+                        base_url = url.resolve(href.clone().as_mut_str()).ok();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut tab = this.write().unwrap();
+        tab.title = title;
+        tab.base_url = base_url;
+    }
+
+    /// Resolves a URL found in the document, such as a link's `href`, against the
+    /// document's base URL: its `<base href>` if it has one, and its own URL otherwise.
+    pub(crate) fn resolve_url(&self, href: &str) -> Result<Url, String> {
+        let base = self.base_url.as_ref().or(self.url.as_ref())
+            .ok_or("No document to resolve the URL against".to_string())?;
+        base.resolve(href.to_string().as_mut_str())
+    }
+
+    /// Returns the URL of the page's icon. A `<link rel=icon>` is resolved like any other
+    /// link, but the `/favicon.ico` fallback belongs to the page's own origin, so it is
+    /// resolved against the document URL rather than `<base href>`.
+    fn favicon_url(&self) -> Result<Url, String> {
+        let root = self.nodes.clone().ok_or("No document to find the icon in".to_string())?;
+        match favicon_href(root) {
+            Some(href) => self.resolve_url(&href),
+            None => self.url.as_ref().ok_or("No document to resolve the URL against".to_string())?
+                .resolve("/favicon.ico".to_string().as_mut_str()),
+        }
+    }
+
+    /// Handles `<meta http-equiv=refresh content="seconds; url=...">` once the page has
+    /// loaded: after the delay, the tab loads the given URL, or reloads the page if there
+    /// is none. The refresh is dropped if the tab navigates elsewhere first.
+    fn schedule_refresh(this: Arc<RwLock<Self>>) {
+        let Some(root) = this.read().unwrap().nodes.clone() else {
+            return;
+        };
+        let refresh = HtmlNode::tree_to_vec(root, &mut vec![]).iter().find_map(|node| {
+            let node_ref = node.read().unwrap();
+            let HtmlNodeType::Element(e) = &node_ref.node_type else {
+                return None;
+            };
+            let is_refresh = e.tag == "meta"
+                && e.attributes.get("http-equiv").is_some_and(|h| h.eq_ignore_ascii_case("refresh"));
+            if is_refresh { e.attributes.get("content").cloned() } else { None }
+        });
+        let Some((delay, target)) = refresh.as_deref().and_then(parse_refresh) else {
+            return;
+        };
+
+        let url = {
+            let tab = this.read().unwrap();
+            match target {
+                Some(target) => tab.resolve_url(&target),
+                None => tab.url.clone().ok_or("No document URL".to_string()),
+            }
+        };
+        let Ok(url) = url else {
+            return;
+        };
+        let load_count = this.read().unwrap().load_count;
+        std::thread::spawn(move || {
+            thread::sleep(delay);
+            if this.read().unwrap().load_count == load_count {
+                Tab::send_message(this, TabMessage::Load { url, body: None });
+            }
+        });
+    }
+
+    /// Fetches and decodes the page's icon in the background: the first
+    /// `<link rel=icon href>` (including `rel="shortcut icon"`), or `/favicon.ico`.
+    ///
+    /// The icon is scaled to `FAVICON_SIZE` pixels and uploaded as a texture for the
+    /// tab strip. Formats the `image` crate cannot decode are ignored.
+    fn load_favicon(this: Arc<RwLock<Self>>) {
+        let Ok(url) = this.read().unwrap().favicon_url() else {
+            return;
+        };
+        if !Self::allowed_request(this.clone(), url.clone()) {
+            return;
+        }
+        let (cookie_jar, ctx, load_count) = {
+            let tab = this.read().unwrap();
+            (tab.cookie_jar.clone(), tab.ctx.clone(), tab.load_count)
+        };
+        let Some(ctx) = ctx else {
+            return;
+        };
+        std::thread::spawn(move || {
+            let Ok(bytes) = url.request_stream(None, cookie_jar).and_then(|response| response.into_bytes()) else {
+                return;
+            };
+            let Some(image) = decode_favicon(&bytes) else {
+                return;
+            };
+            let texture = ctx.load_texture(format!("favicon {}", url.to_string()), image, egui::TextureOptions::LINEAR);
+            let mut tab = this.write().unwrap();
+            if tab.load_count == load_count {
+                tab.favicon = Some(texture);
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    /// Renders the partially parsed document and lays it out, so that it is shown while
+    /// the rest of the page is loading.
+    fn render_partial(this: Arc<RwLock<Self>>) {
+        this.write().unwrap().render();
+        let ctx = this.read().unwrap().ctx.clone();
+        if let Some(ctx) = ctx {
+            this.write().unwrap().update_layout(&ctx);
+            ctx.request_repaint();
+        }
+    }
+
     pub(crate) fn render(&mut self) {
         if let Some(ref measure) = self.measure {
             measure.lock().unwrap().time("render", thread::current().id());
         }
-        Self::style(Some(self.nodes.clone().unwrap()), &self.rules);
+        if self.rules_context != Some(self.media_context()) {
+            self.update_rules();
+        }
+        if self.restyle_all {
+            // The global history only changes on navigation, which restyles everything.
+            self.mark_visited_links();
+            Self::style(self.nodes.clone(), &DEFAULT_STYLE_SHEET, &self.rules, self.viewport);
+            self.restyle_all = false;
+        } else {
+            Self::restyle(self.nodes.as_ref().unwrap(), &DEFAULT_STYLE_SHEET, &self.rules, self.viewport, false);
+        }
         self.document = Some(LayoutNode::new_document(self.nodes.clone().unwrap()));
         self.needs_redraw = true;
         if let Some(ref measure) = self.measure {
@@ -631,6 +1312,25 @@ impl Tab {
         }
     }
 
+    /// Sets `is_visited` on every link (`<a href>` or `<area href>`) whose URL is in the
+    /// global history, for `:visited`.
+    fn mark_visited_links(&self) {
+        let Some(root) = self.nodes.clone() else {
+            return;
+        };
+        let visited_urls = VISITED_URLS.read().unwrap();
+        for node in HtmlNode::tree_to_vec(root, &mut vec![]) {
+            let href = match &node.read().unwrap().node_type {
+                HtmlNodeType::Element(e) if e.tag == "a" || e.tag == "area" => e.attributes.get("href").cloned(),
+                _ => None,
+            };
+            let is_visited = href
+                .and_then(|href| self.resolve_url(&href).ok())
+                .is_some_and(|url| visited_urls.contains(&url.to_string()));
+            node.write().unwrap().is_visited = is_visited;
+        }
+    }
+
     pub fn go_back(this: Arc<RwLock<Self>>) {
         if this.write().unwrap().history.len() > 1 {
             this.write().unwrap().history.pop();
@@ -642,157 +1342,207 @@ impl Tab {
         }
     }
 
-    fn cascade_priority(rule: &(Selector, HashMap<String, String>)) -> i32 {
-        return rule.0.priority();
+    /// Writes the current DOM of the tab, as serialized HTML, to a new file in the
+    /// downloads directory (see `downloads_dir`), and reports the outcome in `status`.
+    ///
+    /// Because the tab thread handles messages in order, the saved document reflects
+    /// every script that has run so far, including changes made through `innerHTML`.
+    /// The file is named after the last segment of the URL path (e.g. `index.html`), or
+    /// after the host for the root page, and numbered rather than overwriting an
+    /// existing file.
+    pub fn save_page(this: Arc<RwLock<Self>>) {
+        let (nodes, url) = {
+            let tab = this.read().unwrap();
+            (tab.nodes.clone(), tab.url.clone())
+        };
+        let (Some(nodes), Some(url)) = (nodes, url) else {
+            return;
+        };
+
+        let path = url.path.split(['?', '#']).next().unwrap_or_default();
+        let mut file_name = path.rsplit('/').find(|segment| !segment.is_empty())
+            .unwrap_or(url.host.as_str())
+            .to_string();
+        if !file_name.ends_with(".html") && !file_name.ends_with(".htm") {
+            file_name.push_str(".html");
+        }
+
+        let saved = downloads_dir()
+            .ok_or_else(|| "no downloads directory".to_string())
+            .and_then(|dir| save_new_file(&dir, &file_name, document_html(&nodes).as_bytes()).map_err(|e| e.to_string()));
+        let mut tab = this.write().unwrap();
+        tab.status = Some(match saved {
+            Ok(path) => format!("Saved to {}", path.display()),
+            Err(e) => format!("Could not save {}: {}", file_name, e),
+        });
+        if let Some(ctx) = &tab.ctx {
+            ctx.request_repaint();
+        }
     }
 
     /// Applies CSS styling rules to an HTML node and its descendants.
     ///
     /// This function traverses the structure of an HTML document, starting from the given node.
-    /// It matches CSS selectors with elements, applies the corresponding styles, and resolves
-    /// inline styles if defined in the `style` attribute of elements. The styles are stored in
-    /// the `style` attribute of each `HtmlNode` object.
+    /// For each element, it collects the declarations of every matching rule and of the inline
+    /// `style` attribute, and resolves conflicts between them with the CSS Cascade Level 4
+    /// ordering. The winning values are computed into the typed `style` of each `HtmlNode`
+    /// (see `ComputedStyle::compute`), so that layout never parses CSS values.
     ///
     /// # Parameters
-    /// - `node`: An optional reference-counted pointer (`Rc`) to a mutable `HtmlNode`, which is
-    ///           the starting point of the HTML tree where the styles will be applied. If `None`,
-    ///           the function panics because the browser document is not initialized.
-    /// - `rules`: A vector of CSS rules, where each rule is represented as a tuple containing:
-    ///   - `Selector`: A CSS selector that determines which elements the rule applies to.
-    ///   - `HashMap<String, String>`: A map of CSS property names and their associated values.
+    /// - `node`: An optional reference-counted pointer to the `HtmlNode` where styling starts.
+    ///   If `None`, the function panics because the browser document is not initialized.
+    /// - `user_agent_rules`: The browser's own style sheet (`DEFAULT_STYLE_SHEET`).
+    /// - `author_rules`: The rules of the page's style sheets, in document order.
+    /// - `viewport`: The size of the viewport, which `vw` and `vh` lengths refer to.
     ///
     /// # Behavior
-    /// 1. Checks if the `node` is `None`. If it is `None`, the function panics with the message:
-    ///    "Browser document not initialized."
-    /// 2. If the `node` is present:
-    ///    - If the node is an element (`HtmlNodeType::Element`):
-    ///      - Iterates over the provided `rules`.
-    ///      - Matches the `Selector` against the current `node`. If the selector doesn't match,
-    ///        the rule is skipped.
-    ///      - For matching selectors, updates the node's `style` by adding CSS properties and
-    ///        their values from the rule to the `style` attribute of the node.
-    ///      - Resolves and parses any inline styles defined in the `style` attribute of the
-    ///        element, applying them to the node's `style`.
-    ///    - If the node is a text node (`HtmlNodeType::Text`), it skips processing as styles
-    ///      are not applicable to text nodes.
-    /// 3. Recursively applies styles to all child nodes of the current node.
+    /// Declarations for the same property are ordered by, from weakest to strongest:
+    /// 1. Origin and importance: user-agent normal, author normal, author `!important`,
+    ///    user-agent `!important`. Important declarations reverse the origin order.
+    /// 2. Whether the declaration comes from the `style` attribute, which beats any
+    ///    selector of the same origin and importance.
+    /// 3. Selector specificity.
+    /// 4. Source order: later rules win.
+    ///
+    /// The strongest declaration wins. Inherited properties (`style::INHERITED_PROPERTIES`)
+    /// take the parent's computed value when no declaration applies. The node's previous
+    /// `style` is recomputed from scratch, so that styles from rules that stopped matching (e.g. `:hover`)
+    /// do not linger.
+    ///
+    /// Every node of the subtree is restyled; `restyle` only restyles the nodes whose
+    /// style is out of date. Rules are looked up in the `RuleIndex` buckets of each
+    /// element, and those that need an ancestor that the element does not have are
+    /// rejected with an `AncestorFilter`, kept up to date along the walk.
     ///
     /// # Panics
     /// - If the `node` is `None`, indicating an uninitialized document.
-    /// - If the inline `style` attribute of an element fails to parse using the `CssParser`,
-    ///   the function will panic without a specific error message.
     ///
     /// # Examples
     /// ```
-    /// // Example usage:
-    /// let root_node = Some(Rc::new(RefCell::new(HtmlNode::new(HtmlNodeType::Element(
-    ///     ElementData::new("div".to_string(), HashMap::new())
-    /// )))));
-    /// let rules = vec![
-    ///     (Selector::new("div".to_string()), HashMap::from([("color".to_string(), "red".to_string())]))
-    /// ];
-    /// style(root_node, rules);
+    /// let rules = RuleIndex::new(CssParser::new("div { color: red !important } #x { color: blue }").parse().unwrap());
+    /// style(root_node, &DEFAULT_STYLE_SHEET, &rules, Vec2::new(WIDTH, HEIGHT));
     /// ```
     ///
-    /// In this example, the `div` element will have its `color` property set to "red".
-    ///
-    /// # Notes
-    /// - This function assumes that `Selector::matches` is defined and correctly matches selectors
-    ///   to elements.
-    /// - The expected behavior of `CssParser::new` and its `body` method is to parse inline styles and
-    ///   resolve them into a `HashMap<String, String>`. Any deviations from this expected behavior may
-    ///   cause a panic.
+    /// In this example, every `div` gets the color red, even one with `id="x"`.
     fn style(
         node: Option<Arc<RwLock<HtmlNode>>>,
-        rules: &Vec<(Selector, HashMap<String, String>)>,
+        user_agent_rules: &RuleIndex,
+        author_rules: &RuleIndex,
+        viewport: Vec2,
     ) {
         let nd = node.expect("Browser document not initialized.");
+        Self::restyle(&nd, user_agent_rules, author_rules, viewport, true);
+    }
 
-        let mut css_style_maps = Vec::new();
-        let mut inherited_style_map = HashMap::<String, String>::new();
-        for item in INHERITED_PROPERTIES.iter() {
-            match nd.read().unwrap().parent {
-                None => {
-                    inherited_style_map.insert(item.0.to_string(), item.1.to_string());
-                }
-                Some(ref pt) => {
-                    inherited_style_map.insert(
-                        item.0.parse().unwrap(),
-                        pt.read().unwrap()
-                            .style
-                            .get(&item.0.to_string())
-                            .unwrap()
-                            .to_string(),
-                    );
-                }
-            }
-        }
-        css_style_maps.push(inherited_style_map);
+    /// Restyles the nodes of a subtree whose style is out of date, and skips the rest.
+    ///
+    /// # Parameters
+    /// - `node`: The root of the subtree.
+    /// - `user_agent_rules`, `author_rules`, `viewport`: As for `style`.
+    /// - `all`: Whether to restyle every node, whatever its flags.
+    fn restyle(
+        nd: &Arc<RwLock<HtmlNode>>,
+        user_agent_rules: &RuleIndex,
+        author_rules: &RuleIndex,
+        viewport: Vec2,
+        all: bool,
+    ) {
+        let mut filter = AncestorFilter::for_node(nd);
+        Self::restyle_subtree(nd, user_agent_rules, author_rules, viewport, all, false, &mut filter);
+    }
 
-        for (selector, style_map) in rules {
-            if selector.matches(nd.clone()) {
-                css_style_maps.push(style_map.clone());
-            }
+    /// Restyles the nodes of a subtree whose style is out of date (see `restyle`).
+    ///
+    /// # Parameters
+    /// - `parent_changed`: Whether the style of the parent of `node` has just changed,
+    ///   so that `node` must be restyled for what it inherits.
+    /// - `filter`: The ancestors of `node`.
+    ///
+    /// # Behavior
+    /// A node is restyled if `all` or `parent_changed` is set, or if it `needs_style`
+    /// (see `Tab::invalidate_style`). The children of a restyled node are visited in
+    /// turn, with `parent_changed` set if its computed style is different from before.
+    /// Subtrees without `needs_style` or `child_needs_style` are skipped entirely. The
+    /// flags of every visited node are cleared.
+    fn restyle_subtree(
+        nd: &Arc<RwLock<HtmlNode>>,
+        user_agent_rules: &RuleIndex,
+        author_rules: &RuleIndex,
+        viewport: Vec2,
+        all: bool,
+        parent_changed: bool,
+        filter: &mut AncestorFilter,
+    ) {
+        let (needs_style, child_needs_style) = {
+            let node = nd.read().unwrap();
+            (all || parent_changed || node.needs_style, node.child_needs_style)
+        };
+        if !needs_style && !child_needs_style {
+            return;
         }
+        let style = needs_style.then(|| Self::compute_style(nd, user_agent_rules, author_rules, viewport, filter));
 
-        // Encapsulate the mutation in a block to drop the borrow_mut() before recursion
-        let children = {
+        // Encapsulate the mutation in a block to drop the write lock before recursion
+        let (changed, children) = {
             let mut node_ref = nd.write().unwrap();
+            let changed = style.is_some_and(|style| {
+                let changed = node_ref.style != style;
+                node_ref.style = style;
+                changed
+            });
+            node_ref.needs_style = false;
+            node_ref.child_needs_style = false;
+            (changed, node_ref.children.clone())
+        };
 
-            let inline_style_attr = if let HtmlNodeType::Element(el) = &node_ref.node_type {
-                el.attributes.get("style").cloned()
-            } else {
-                None
-            };
-
-            for style_map in css_style_maps {
-                for (property, value) in style_map {
-                    node_ref.style.insert(property.clone(), value.clone());
-                }
-            }
-
-            if let Some(style_str) = inline_style_attr {
-                println!("LOCAL STYLES{:?}", style_str);
-
-                let mut parser = CssParser::new(&style_str);
-                if let Ok(pairs_map) = parser.body() {
-                    println!("LOCAL STYLES{:?}", pairs_map);
-                    for (key, value) in pairs_map {
-                        node_ref.style.insert(key, value);
-                    }
-                }
-            }
+        filter.push(nd);
+        for child in &children {
+            Self::restyle_subtree(child, user_agent_rules, author_rules, viewport, all, changed, filter);
+        }
+        filter.pop(nd);
+    }
 
-            node_ref.children.clone()
+    /// Runs the cascade for a single node and returns its computed style, from the
+    /// rules that match it, its `style` attribute and its parent's computed style (see
+    /// `style`).
+    fn compute_style(
+        nd: &Arc<RwLock<HtmlNode>>,
+        user_agent_rules: &RuleIndex,
+        author_rules: &RuleIndex,
+        viewport: Vec2,
+        filter: &AncestorFilter,
+    ) -> ComputedStyle {
+        let inline_style = match &nd.read().unwrap().node_type {
+            HtmlNodeType::Element(el) => el.attributes.get("style")
+                .and_then(|style| CssParser::new(style).body().ok()),
+            HtmlNodeType::Text(_) => None,
         };
 
-        if nd
-            .read().unwrap()
-            .style
-            .get("font-size")
-            .unwrap()
-            .ends_with("%")
-        {
-            let mut node_ref = nd.write().unwrap();
-            let parent_font_size = match &node_ref.parent {
-                None => INHERITED_PROPERTIES.get("font-size").unwrap().to_string(),
-                Some(pt) => pt.read().unwrap().style.get("font-size").unwrap().to_string(),
-            };
-
-            let node_pct =
-                f32::from_str(&node_ref.style.get("font-size").unwrap().replace("%", "")).unwrap()
-                    / 100.0;
-            let parent_x = parent_font_size.replace("px", "").parse::<f32>().unwrap();
-            node_ref.style.insert(
-                "font-size".to_string(),
-                format!("{}px", parent_x * node_pct),
-            );
+        // Collect every applicable declaration block with its cascade precedence.
+        let mut declarations = Vec::<(CascadeOrigin, (u32, u32, u32), usize, &Declarations)>::new();
+        for (origin, rules) in [(CascadeOrigin::UserAgent, user_agent_rules), (CascadeOrigin::Author, author_rules)] {
+            for order in rules.matching(nd, filter) {
+                let (selector, style_map) = &rules.rules()[order];
+                declarations.push((origin, selector.specificity(), order, style_map));
+            }
+        }
+        if let Some(inline_style) = &inline_style {
+            declarations.push((CascadeOrigin::Inline, (0, 0, 0), 0, inline_style));
         }
 
-        // 3. Recursive phase - the borrow on 'nd' is now released
-        for child in children {
-            Self::style(Some(child), rules);
+        let mut cascaded = Vec::<((u8, (u32, u32, u32), usize), &String, &String)>::new();
+        for (origin, specificity, order, style_map) in declarations {
+            for (property, (value, important)) in style_map {
+                cascaded.push(((origin.precedence(*important), specificity, order), property, value));
+            }
         }
+        cascaded.sort_by_key(|(precedence, _, _)| *precedence);
+        let declared: Vec<(String, String)> = cascaded.into_iter()
+            .map(|(_, property, value)| (property.clone(), value.clone()))
+            .collect();
+
+        let parent_style = nd.read().unwrap().parent.as_ref().map(|pt| pt.read().unwrap().style.clone());
+        ComputedStyle::compute(&declared, parent_style.as_ref(), viewport)
     }
 
     pub fn keypress(this: Arc<RwLock<Tab>>, keypress: &String) {
@@ -820,8 +1570,11 @@ impl Tab {
 
             // 3. Render
             // This will now succeed because the JS execution above has finished
-            // and it didn't find Tab already borrowed.
-            this.write().unwrap().render();
+            // and it didn't find Tab already borrowed. Only the input, and whatever
+            // its new value can affect, is restyled.
+            let mut tab = this.write().unwrap();
+            tab.invalidate_style(&node);
+            tab.render();
         }
     }
 
@@ -869,14 +1622,9 @@ impl Tab {
         }
         body.remove(0);
 
-        let url = this
-            .read()
-            .unwrap()
-            .url
-            .clone()
-            .unwrap()
-            .resolve(&mut *action)
-            .unwrap();
+        let Ok(url) = this.read().unwrap().resolve_url(&action) else {
+            return;
+        };
         Tab::load(this, url, Some(body));
     }
 
@@ -884,6 +1632,25 @@ impl Tab {
         this.read().unwrap().allowed_origins.is_none() || this.read().unwrap().allowed_origins.as_ref().unwrap().contains(&url.origin())
     }
 
+    /// Returns the text of a script or style sheet, fetching it if it is external.
+    ///
+    /// Returns `None` if the page's content security policy blocks it (an external URL
+    /// from a disallowed origin, or inline content without `'unsafe-inline'`), or if the
+    /// URL cannot be resolved or fetched.
+    fn source_text(this: Arc<RwLock<Tab>>, source: SourceText) -> Option<String> {
+        match source {
+            SourceText::Inline(text) => this.read().unwrap().allow_inline.then_some(text),
+            SourceText::External(href) => {
+                let resolved = this.read().unwrap().resolve_url(&href).ok()?;
+                if !Self::allowed_request(this.clone(), resolved.clone()) {
+                    return None;
+                }
+                let cookie_jar = this.read().unwrap().cookie_jar.clone();
+                resolved.request(None, cookie_jar).ok().map(|response| response.content)
+            }
+        }
+    }
+
     pub fn send_message(this: Arc<RwLock<Tab>>, msg: TabMessage) {
         let tx = {
             let tab = this.read().unwrap();
@@ -895,6 +1662,245 @@ impl Tab {
     }
 }
 
+/// Moves a piece of dynamic state (such as `is_hovered`) from `previous` and all of its
+/// ancestors to `next` and all of its ancestors, since `:hover` and `:active` also apply
+/// to the ancestors of the element under the pointer.
+///
+/// # Returns
+/// The nodes whose state changed: those that are an ancestor of only one of the two.
+fn move_state_to_ancestors(
+    previous: Option<&Arc<RwLock<HtmlNode>>>,
+    next: Option<&Arc<RwLock<HtmlNode>>>,
+    set: fn(&mut HtmlNode, bool),
+) -> Vec<Arc<RwLock<HtmlNode>>> {
+    let ancestors = |node: Option<&Arc<RwLock<HtmlNode>>>| {
+        let mut ancestors = vec![];
+        let mut current = node.cloned();
+        while let Some(node) = current {
+            current = node.read().unwrap().parent.clone();
+            ancestors.push(node);
+        }
+        ancestors
+    };
+    let (previous, next) = (ancestors(previous), ancestors(next));
+    for node in &previous {
+        set(&mut node.write().unwrap(), false);
+    }
+    for node in &next {
+        set(&mut node.write().unwrap(), true);
+    }
+
+    let only_in = |nodes: &[Arc<RwLock<HtmlNode>>], others: &[Arc<RwLock<HtmlNode>>]| nodes.iter()
+        .filter(|node| !others.iter().any(|other| Arc::ptr_eq(node, other)))
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut changed = only_in(&previous, &next);
+    changed.extend(only_in(&next, &previous));
+    changed
+}
+
+/// Sets `needs_style` on `node` and, if `subtree` is set, on all of its descendants.
+fn mark_needs_style(node: &Arc<RwLock<HtmlNode>>, subtree: bool) {
+    let children = {
+        let mut node = node.write().unwrap();
+        node.needs_style = true;
+        if !subtree {
+            return;
+        }
+        node.children.clone()
+    };
+    for child in &children {
+        mark_needs_style(child, true);
+    }
+}
+
+/// Returns `true` if `node` is a link (`<a href>`) or inside one.
+fn is_in_link(node: &Arc<RwLock<HtmlNode>>) -> bool {
+    let mut current = Some(node.clone());
+    while let Some(node) = current {
+        if matches!(&node.read().unwrap().node_type,
+            HtmlNodeType::Element(e) if e.tag == "a" && e.attributes.contains_key("href")) {
+            return true;
+        }
+        current = node.read().unwrap().parent.clone();
+    }
+    false
+}
+
+/// Where a declaration comes from, for the cascade in `Tab::style`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CascadeOrigin {
+    /// The browser's default style sheet.
+    UserAgent,
+    /// A style sheet of the page.
+    Author,
+    /// The `style` attribute of the element, which is an author declaration that beats
+    /// any selector.
+    Inline,
+}
+
+impl CascadeOrigin {
+    /// Ranks a declaration from this origin by origin and importance, from weakest to
+    /// strongest: user-agent, author and inline normal declarations, then author, inline
+    /// and user-agent important declarations.
+    fn precedence(self, important: bool) -> u8 {
+        match (self, important) {
+            (CascadeOrigin::UserAgent, false) => 0,
+            (CascadeOrigin::Author, false) => 1,
+            (CascadeOrigin::Inline, false) => 2,
+            (CascadeOrigin::Author, true) => 3,
+            (CascadeOrigin::Inline, true) => 4,
+            (CascadeOrigin::UserAgent, true) => 5,
+        }
+    }
+}
+
+/// Appends the style rules in `rules` that apply in `context` to `out`, in source order:
+/// those at the top level and those in `@media` blocks whose queries match.
+fn flatten_rules(rules: &[CssRule], context: &MediaContext, out: &mut Vec<StyleRule>) {
+    for rule in rules {
+        match rule {
+            CssRule::Style(rule) => out.push(rule.clone()),
+            CssRule::Media { queries, rules } if queries.matches(context) => flatten_rules(rules, context, out),
+            _ => {}
+        }
+    }
+}
+
+/// Picks the URL to load from the `src` descriptor of an `@font-face` rule: the first
+/// `url()` that is not a WOFF file, by its `format()` hint or its extension.
+fn font_source(src: &str) -> Option<String> {
+    src.split(',').find_map(|source| {
+        let source = source.trim();
+        let lowercase = source.to_ascii_lowercase();
+        let url = lowercase.strip_prefix("url(")?;
+        let end = url.find(')')?;
+        let url = source[4..4 + end].trim().trim_matches(['"', '\'']);
+        let path = url.split(['?', '#']).next().unwrap_or("").to_ascii_lowercase();
+        let is_woff = lowercase[4 + end..].contains("woff") || path.ends_with(".woff") || path.ends_with(".woff2");
+        (!is_woff && !url.is_empty()).then(|| url.to_string())
+    })
+}
+
+/// The source of a script or style sheet referenced by the document.
+enum SourceText {
+    /// Referenced by URL, through `<script src>` or `<link rel=stylesheet href>`.
+    External(String),
+    /// Written directly inside a `<script>` or `<style>` element.
+    Inline(String),
+}
+
+/// Parses the `content` of a `<meta http-equiv=refresh>`, such as `5; url=/next`, into
+/// the delay and the optional URL to load.
+///
+/// Returns `None` if the delay is not a non-negative number of seconds that fits in a
+/// `Duration`.
+fn parse_refresh(content: &str) -> Option<(Duration, Option<String>)> {
+    let content = content.trim();
+    let (delay, rest) = match content.find([';', ',']) {
+        Some(index) => (&content[..index], &content[index + 1..]),
+        None => (content, ""),
+    };
+    let delay = delay.trim().parse::<f64>().ok().filter(|d| d.is_finite() && *d >= 0.0)?;
+
+    let mut target = rest.trim();
+    if target.get(..3).is_some_and(|prefix| prefix.eq_ignore_ascii_case("url")) {
+        let after_url = target[3..].trim_start();
+        if let Some(value) = after_url.strip_prefix('=') {
+            target = value.trim_start();
+        }
+    }
+    let target = target.trim_matches(|c| c == '"' || c == '\'').trim();
+    let target = (!target.is_empty()).then(|| target.to_string());
+    Some((Duration::try_from_secs_f64(delay).ok()?, target))
+}
+
+/// Returns the `href` of the page's icon: that of the first `<link rel=icon>`
+/// (including `rel="shortcut icon"`), if there is one.
+fn favicon_href(root: Arc<RwLock<HtmlNode>>) -> Option<String> {
+    HtmlNode::tree_to_vec(root, &mut vec![]).iter().find_map(|node| {
+        let node_ref = node.read().unwrap();
+        let HtmlNodeType::Element(e) = &node_ref.node_type else {
+            return None;
+        };
+        let is_icon = e.tag == "link" && e.attributes.get("rel")
+            .is_some_and(|rel| rel.split_ascii_whitespace().any(|r| r.eq_ignore_ascii_case("icon")));
+        if is_icon { e.attributes.get("href").cloned() } else { None }
+    })
+}
+
+/// Returns the directory that saved pages go to: `$XDG_DOWNLOAD_DIR`, or `Downloads` in
+/// the user's home directory.
+fn downloads_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_DOWNLOAD_DIR").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(Path::new(&home).join("Downloads"))
+}
+
+/// Writes `contents` to a new file named `file_name` in `dir`, creating `dir` if needed,
+/// and returns its path. An existing file is never overwritten: `page.html` becomes
+/// `page (1).html`, `page (2).html` and so on.
+fn save_new_file(dir: &Path, file_name: &str, contents: &[u8]) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (file_name, String::new()),
+    };
+    for n in 0..MAX_SAVE_ATTEMPTS {
+        let path = match n {
+            0 => dir.join(file_name),
+            n => dir.join(format!("{} ({}){}", stem, n, extension)),
+        };
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => return file.write_all(contents).map(|_| path),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, "too many files with that name"))
+}
+
+/// Decodes a favicon (ICO, PNG, BMP or JPEG) and scales it to `FAVICON_SIZE` pixels.
+fn decode_favicon(bytes: &[u8]) -> Option<egui::ColorImage> {
+    let image = image::load_from_memory(bytes).ok()?
+        .resize(FAVICON_SIZE, FAVICON_SIZE, image::imageops::FilterType::Triangle)
+        .to_rgba8();
+    Some(egui::ColorImage::from_rgba_unmultiplied(
+        [image.width() as usize, image.height() as usize],
+        image.as_raw(),
+    ))
+}
+
+/// Evaluates `script_content` in the page's script context, logging any exception.
+fn run_script(js: &JsContext, script_content: &str) {
+    js.context.read().unwrap().with(|ctx| {
+        let res = js.eval_with_measure(|| ctx.eval::<(), _>(script_content));
+        if let Err(e) = res {
+            if let rquickjs::Error::Exception = e {
+                let exception = ctx.catch();
+                println!("JS Exception in script eval: {:?}", exception);
+            } else {
+                println!("Failed to eval script: {e}");
+            }
+        }
+    });
+}
+
+/// Returns `true` if a `<script type>` value denotes a classic JavaScript script.
+///
+/// Scripts with other types, such as `application/ld+json` or `text/template`, are data
+/// blocks and must not be executed.
+fn is_javascript_type(script_type: &str) -> bool {
+    let script_type = script_type.trim().to_ascii_lowercase();
+    script_type.is_empty()
+        || script_type.ends_with("/javascript")
+        || script_type.ends_with("/ecmascript")
+        || script_type.ends_with("/jscript")
+        || script_type.ends_with("/livescript")
+}
+
 /// A structure that represents text rendering properties, including its content,
 /// position, and font details.
 ///
@@ -908,7 +1914,7 @@ impl Tab {
 /// - `font_name`:
 ///   The name of the font family to be used for rendering the text.
 #[derive(Clone, Debug)]
-pub(crate) struct DrawText {
+pub struct DrawText {
     /// Absolute horizontal position in points.
     pub(crate) x: f32,
     /// Absolute vertical position in points.
@@ -917,7 +1923,7 @@ pub(crate) struct DrawText {
     pub(crate) galley: Arc<Galley>,
 }
 
-pub(crate) struct DrawRect {
+pub struct DrawRect {
     pub rect: Rect,
     /// A public field representing the color of the object.
     ///
@@ -943,7 +1949,7 @@ pub(crate) struct DrawRect {
     pub radius: f32
 }
 
-pub(crate) struct DrawOutline {
+pub struct DrawOutline {
     pub(crate) rect: Rect,
     pub(crate) color: Color32,
     pub(crate) thickness: f32,
@@ -955,11 +1961,18 @@ pub struct DrawLine {
     pub(crate) color: Color32,
     pub(crate) thickness: f32,
 }
+/// Draws a texture, such as a favicon, stretched to `rect`.
+pub struct DrawImage {
+    pub(crate) rect: Rect,
+    pub(crate) texture: egui::TextureHandle,
+}
+
 pub enum DrawCommand {
     DrawText(DrawText),
     DrawRect(DrawRect),
     DrawOutline(DrawOutline),
     DrawLine(DrawLine),
+    DrawImage(DrawImage),
 }
 
 impl DrawCommand {
@@ -1000,6 +2013,7 @@ impl DrawCommand {
             DrawCommand::DrawRect(rct) => rct.rect.bottom(),
             DrawCommand::DrawOutline(out) => out.rect.top(),
             DrawCommand::DrawLine(line) => f32::min(line.from.y, line.to.y),
+            DrawCommand::DrawImage(image) => image.rect.bottom(),
         }
     }
 
@@ -1030,6 +2044,1235 @@ impl DrawCommand {
             DrawCommand::DrawRect(rct) => rct.rect.top(),
             DrawCommand::DrawOutline(out) => out.rect.top(),
             DrawCommand::DrawLine(line) => f32::max(line.from.y, line.to.y),
+            DrawCommand::DrawImage(image) => image.rect.top(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::HSTEP;
+
+    const VIEWPORT: Vec2 = Vec2::new(800.0, 600.0);
+
+    /// Styles `html` with the given user-agent and author style sheets and returns the
+    /// computed style of the element with `id="target"`.
+    fn computed_style(html: &str, user_agent_css: &str, author_css: &str) -> ComputedStyle {
+        let root = HtmlParser::new(html.to_string()).parse();
+        let user_agent_rules = RuleIndex::new(CssParser::new(user_agent_css).parse().unwrap());
+        let author_rules = RuleIndex::new(CssParser::new(author_css).parse().unwrap());
+        Tab::style(Some(root.clone()), &user_agent_rules, &author_rules, VIEWPORT);
+
+        let nodes = HtmlNode::tree_to_vec(root, &mut vec![]).clone();
+        let target = nodes.iter().find(|node| matches!(&node.read().unwrap().node_type,
+            HtmlNodeType::Element(e) if e.attributes.get("id").is_some_and(|id| id == "target")))
+            .expect("no element with id=target");
+        target.read().unwrap().style.clone()
+    }
+
+    /// Returns the computed `color` of the element with `id="target"`.
+    fn cascaded_color(html: &str, user_agent_css: &str, author_css: &str) -> Color32 {
+        computed_style(html, user_agent_css, author_css).color
+    }
+
+    /// Parses a CSS color name, for comparing with computed colors.
+    fn color(name: &str) -> Color32 {
+        let [r, g, b, a] = csscolorparser::parse(name).unwrap().to_rgba8();
+        Color32::from_rgba_unmultiplied(r, g, b, a)
+    }
+
+    #[test]
+    fn author_rules_beat_user_agent_rules_of_any_specificity() {
+        let value = cascaded_color("<p id=target class=c>x</p>", "p#target.c { color: red; }", "* { color: blue; }");
+        assert_eq!(value, color("blue"));
+    }
+
+    #[test]
+    fn equal_specificity_is_decided_by_source_order() {
+        let html = "<p id=target class=a>x</p>";
+        assert_eq!(cascaded_color(html, "", ".a { color: red; } p.a { color: green; } .a { color: blue; }"), color("green"));
+        assert_eq!(cascaded_color(html, "", "p, .a { color: red; } .a { color: blue; }"), color("blue"));
+        assert_eq!(cascaded_color(html, "", ".a { color: blue; } p, .a { color: red; }"), color("red"));
+    }
+
+    #[test]
+    fn inline_style_beats_id_selectors() {
+        let value = cascaded_color("<p id=target style=\"color: green\">x</p>", "", "#target#target { color: red; }");
+        assert_eq!(value, color("green"));
+    }
+
+    #[test]
+    fn important_beats_higher_specificity_and_inline_style() {
+        let html = "<p id=target style=\"color: green\">x</p>";
+        let value = cascaded_color(html, "", "p { color: red !important; } #target { color: blue; }");
+        assert_eq!(value, color("red"));
+    }
+
+    #[test]
+    fn important_inline_style_beats_important_rules() {
+        let html = "<p id=target style=\"color: green !important\">x</p>";
+        let value = cascaded_color(html, "", "#target { color: red !important; }");
+        assert_eq!(value, color("green"));
+    }
+
+    #[test]
+    fn important_user_agent_rules_beat_important_author_rules() {
+        let html = "<p id=target style=\"color: green !important\">x</p>";
+        let value = cascaded_color(html, "p { color: black !important; }", "#target { color: red !important; }");
+        assert_eq!(value, color("black"));
+    }
+
+    #[test]
+    fn important_flag_allows_whitespace_and_any_case() {
+        let value = cascaded_color("<p id=target>x</p>", "", "p { color: red ! IMPORTANT; } #target { color: blue; }");
+        assert_eq!(value, color("red"));
+    }
+
+    #[test]
+    fn important_declarations_beat_later_ones_in_the_same_block() {
+        let html = "<p id=target>x</p>";
+        assert_eq!(cascaded_color(html, "", "p { color: red !important; color: blue; }"), color("red"));
+        assert_eq!(cascaded_color(html, "", "p { color: red !important; color: blue !important; }"), color("blue"));
+        let style = computed_style(html, "", "p { margin-left: 3px !important; margin: 5px; }");
+        let px = |px| Some(crate::style::LengthPercentage::Px(px));
+        assert_eq!((style.margin.left, style.margin.right), (px(3.0), px(5.0)));
+    }
+
+    #[test]
+    fn declarations_beat_inherited_values() {
+        let html = "<div style=\"color: red\"><p id=target>x</p></div>";
+        assert_eq!(cascaded_color(html, "", ""), color("red"));
+        assert_eq!(cascaded_color(html, "p { color: gray; }", ""), color("gray"));
+    }
+
+    #[test]
+    fn restyling_drops_values_from_rules_that_no_longer_match() {
+        let root = HtmlParser::new("<p id=target>x</p>".to_string()).parse();
+        let rules = RuleIndex::new(CssParser::new("p:hover { background-color: yellow; }").parse().unwrap());
+        let target = HtmlNode::tree_to_vec(root.clone(), &mut vec![]).clone().into_iter()
+            .find(|node| matches!(&node.read().unwrap().node_type, HtmlNodeType::Element(e) if e.tag == "p"))
+            .unwrap();
+
+        target.write().unwrap().is_hovered = true;
+        Tab::style(Some(root.clone()), &RuleIndex::new(vec![]), &rules, VIEWPORT);
+        assert_eq!(target.read().unwrap().style.background_color, color("yellow"));
+
+        target.write().unwrap().is_hovered = false;
+        Tab::style(Some(root.clone()), &RuleIndex::new(vec![]), &rules, VIEWPORT);
+        assert_eq!(target.read().unwrap().style.background_color, Color32::TRANSPARENT);
+    }
+
+    #[test]
+    fn relative_font_sizes_are_resolved_to_pixels() {
+        let html = "<html style=\"font-size: 20px\"><div style=\"font-size: 1.5em\"><p id=target>x</p></div></html>";
+        assert_eq!(computed_style(html, "", "p { font-size: 50%; }").font_size, 15.0);
+        assert_eq!(computed_style(html, "", "p { font-size: 2rem; }").font_size, 40.0);
+        assert_eq!(computed_style(html, "", "p { font-size: 12pt; }").font_size, 16.0);
+        assert_eq!(computed_style(html, "", "p { font-size: 10vw; }").font_size, 80.0);
+        assert_eq!(computed_style(html, "", "p { font-size: large; }").font_size, 18.0);
+        assert_eq!(computed_style(html, "", "p { font-size: nonsense; }").font_size, 30.0);
+    }
+
+    #[test]
+    fn keywords_are_computed_with_inheritance() {
+        let html = "<div style=\"font-weight: bold; background-color: red\"><p id=target>x</p></div>";
+        let style = computed_style(html, "", "");
+        assert_eq!(style.font_weight, 700);
+        assert_eq!(style.background_color, Color32::TRANSPARENT);
+
+        let style = computed_style(html, "", "p { font-weight: bolder; background-color: inherit; border-radius: 1em; }");
+        assert_eq!(style.font_weight, 900);
+        assert_eq!(style.background_color, color("red"));
+        assert_eq!(style.border_radius, crate::style::LengthPercentage::Px(16.0));
+
+        let style = computed_style(html, "", "p { font-weight: initial; color: currentcolor; }");
+        assert_eq!(style.font_weight, 400);
+        assert_eq!(style.color, Color32::BLACK);
+    }
+
+    #[test]
+    fn currentcolor_refers_to_the_color_of_the_same_rule() {
+        let declared: Vec<(String, String)> = [("background-color", "currentcolor"), ("font-weight", "bold"),
+            ("color", "red"), ("width", "10px"), ("display", "block")]
+            .iter()
+            .map(|(property, value)| (property.to_string(), value.to_string()))
+            .collect();
+        let style = ComputedStyle::compute(&declared, None, VIEWPORT);
+        assert_eq!(style.background_color, color("red"));
+        let style = computed_style("<p id=target>x</p>", "", "p { color: red; background-color: currentcolor; }");
+        assert_eq!(style.background_color, color("red"));
+    }
+
+    #[test]
+    fn custom_properties_inherit_and_substitute_with_fallbacks() {
+        let html = "<div style=\"--brand: #3366ff; --Size: 2em\"><p id=target>x</p></div>";
+        let style = computed_style(html, "", "p { color: var(--brand, black); font-size: var(--Size); }");
+        assert_eq!(style.color, color("#3366ff"));
+        assert_eq!(style.font_size, 32.0);
+        assert_eq!(style.custom_properties.get("--brand").map(String::as_str), Some("#3366ff"));
+
+        let style = computed_style(html, "", "p { color: var(--missing, var(--also-missing, green)); }");
+        assert_eq!(style.color, color("green"));
+
+        // An unresolvable var() makes the declaration act as `unset`, not fall back to
+        // an earlier declaration.
+        let style = computed_style(html, "", "p { background-color: red; background-color: var(--missing); }");
+        assert_eq!(style.background_color, Color32::TRANSPARENT);
+    }
+
+    #[test]
+    fn custom_property_cycles_are_invalid() {
+        let html = "<p id=target style=\"--a: var(--b, red); --b: var(--a); --c: var(--c); --d: var(--e); --e: blue\">x</p>";
+        let style = computed_style(html, "", "p { color: var(--a, green); background-color: var(--d); }");
+        assert_eq!(style.color, color("green"));
+        assert_eq!(style.background_color, color("blue"));
+        assert!(!style.custom_properties.contains_key("--b"));
+        assert!(!style.custom_properties.contains_key("--c"));
+    }
+
+    #[test]
+    fn long_var_chains_and_huge_substitutions_are_invalid() {
+        let html = "<p id=target>x</p>";
+        let chain = |name: &str, length: usize| (1..length)
+            .map(|i| format!("--{name}{}: var(--{name}{});", i, i - 1))
+            .collect::<String>();
+        let css = format!("p {{ --short0: green; {} color: var(--short31, red); }}", chain("short", 32));
+        assert_eq!(computed_style(html, "", &css).color, color("green"));
+        let css = format!("p {{ --long0: red; {} color: var(--long3000, green); }}", chain("long", 3001));
+        let style = computed_style(html, "", &css);
+        assert_eq!(style.color, color("green"));
+        assert!(!style.custom_properties.contains_key("--long3000"));
+        assert_eq!(style.custom_properties.get("--long31").map(String::as_str), Some("red"));
+
+        // Each level doubles the value, until it is too long.
+        let doubling: String = (1..40).map(|i| format!("--d{}: var(--d{}) var(--d{});", i, i - 1, i - 1)).collect();
+        let style = computed_style(html, "", &format!("p {{ --d0: x; {} color: var(--d39, green); }}", doubling));
+        assert_eq!(style.color, color("green"));
+        assert!(!style.custom_properties.contains_key("--d39"));
+
+        let fallbacks = |depth: usize| format!("p {{ color: {}green{}; }}", "var(--missing, ".repeat(depth), ")".repeat(depth));
+        assert_eq!(computed_style(html, "", &fallbacks(32)).color, color("green"));
+        assert_eq!(computed_style(html, "", &fallbacks(10_000)).color, Color32::BLACK);
+    }
+
+    #[test]
+    fn shorthands_with_var_are_expanded_after_substitution() {
+        let html = "<p id=target>x</p>";
+        let style = computed_style(html, "", ":root { --bg: yellow no-repeat; } p { background: var(--bg); }");
+        assert_eq!(style.background_color, color("yellow"));
+    }
+
+    #[test]
+    fn shorthands_with_var_keep_their_place_among_the_longhands() {
+        let html = "<p id=target>x</p>";
+        let px = |px| Some(crate::style::LengthPercentage::Px(px));
+        let style = computed_style(html, "", "p { --x: 7px; margin: var(--x); margin-left: 5px; }");
+        assert_eq!((style.margin.left, style.margin.right), (px(5.0), px(7.0)));
+        let style = computed_style(html, "", "p { --x: 7px; margin-left: 5px; margin: var(--x); }");
+        assert_eq!((style.margin.left, style.margin.right), (px(7.0), px(7.0)));
+        let style = computed_style("<p id=target style=\"margin: var(--x); margin-top: 2px; --x: 1px\">x</p>", "", "");
+        assert_eq!((style.margin.top, style.margin.bottom), (px(2.0), px(1.0)));
+    }
+
+    #[test]
+    fn math_functions_mix_units() {
+        let html = "<p id=target>x</p>";
+        let style = computed_style(html, "", "p { font-size: calc(1rem + 2 * 2px); border-radius: calc(50% - 0.5em); }");
+        assert_eq!(style.font_size, 20.0);
+        assert_eq!(style.border_radius.resolve(100.0), 40.0);
+
+        let style = computed_style(html, "", "p { font-size: clamp(10px, 5vw, 30px); border-radius: max(1em, 10%); }");
+        assert_eq!(style.font_size, 30.0);
+        assert_eq!(style.border_radius.resolve(100.0), 30.0);
+        assert_eq!(style.border_radius.resolve(400.0), 40.0);
+
+        let style = computed_style(html, "", "p { font-size: min(2em, 3px * 3); border-radius: calc(1px * 2px); }");
+        assert_eq!(style.font_size, 9.0);
+        assert_eq!(style.border_radius, crate::style::LengthPercentage::Px(0.0));
+    }
+
+    /// Returns the computed `color` of the element with `id="target"` when `author_css`,
+    /// at-rules included, is evaluated in a tab whose page area is `viewport`.
+    fn color_in_viewport(html: &str, author_css: &str, viewport: Vec2) -> Color32 {
+        let mut tab = Tab { viewport, ..Default::default() };
+        tab.style_sheets = CssParser::new(author_css).parse_rules();
+        tab.update_rules();
+        let root = HtmlParser::new(html.to_string()).parse();
+        Tab::style(Some(root.clone()), &RuleIndex::new(vec![]), &tab.rules, viewport);
+        let nodes = HtmlNode::tree_to_vec(root, &mut vec![]).clone();
+        let target = nodes.iter().find(|node| matches!(&node.read().unwrap().node_type,
+            HtmlNodeType::Element(e) if e.attributes.get("id").is_some_and(|id| id == "target")))
+            .expect("no element with id=target");
+        target.read().unwrap().style.color
+    }
+
+    #[test]
+    fn media_rules_follow_the_viewport() {
+        let html = "<p id=target>x</p>";
+        let css = "p { color: red; }
+            @media screen and (min-width: 600px) { p { color: blue; } }
+            @media (400px <= width < 600px) { p { color: green; } }
+            @media print, (prefers-color-scheme: dark) { p { color: yellow; } }";
+        assert_eq!(color_in_viewport(html, css, Vec2::new(800.0, 600.0)), color("blue"));
+        assert_eq!(color_in_viewport(html, css, Vec2::new(500.0, 600.0)), color("green"));
+        assert_eq!(color_in_viewport(html, css, Vec2::new(300.0, 600.0)), color("red"));
+
+        let css = "@media (max-height: 30em) { p { color: blue; } } @media tv, (min-width: 20em) and (hover-style) { p { color: red; } }";
+        assert_eq!(color_in_viewport(html, css, Vec2::new(800.0, 480.0)), color("blue"));
+    }
+
+    fn element_by_id(root: &Arc<RwLock<HtmlNode>>, id: &str) -> Arc<RwLock<HtmlNode>> {
+        HtmlNode::tree_to_vec(root.clone(), &mut vec![]).iter()
+            .find(|node| matches!(&node.read().unwrap().node_type,
+                HtmlNodeType::Element(e) if e.attributes.get("id").is_some_and(|value| value == id)))
+            .cloned()
+            .unwrap_or_else(|| panic!("no element with id={}", id))
+    }
+
+    /// Returns a tab showing `html` with `author_css`, rendered once.
+    fn rendered_tab(html: &str, author_css: &str) -> Tab {
+        let mut tab = Tab { viewport: VIEWPORT, ..Default::default() };
+        tab.style_sheets = CssParser::new(author_css).parse_rules();
+        tab.set_document(Some(HtmlParser::new(html.to_string()).parse()));
+        tab.render();
+        tab
+    }
+
+    #[test]
+    fn a_stalled_page_is_rendered_with_its_pending_text() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (resume_tx, resume_rx) = mpsc::channel::<()>();
+        let server = thread::spawn(move || {
+            use std::io::Write;
+            let (mut stream, _) = listener.accept().unwrap();
+            drop(listener);
+            stream.write_all(b"HTTP/1.0 200 OK\r\nContent-Type: text/html\r\n\r\n<p>Hello wor").unwrap();
+            let _ = resume_rx.recv_timeout(Duration::from_secs(10));
+            stream.write_all(b"ld</p>").unwrap();
+        });
+
+        let tab = Arc::new(RwLock::new(Tab { viewport: VIEWPORT, ..Default::default() }));
+        let loader = {
+            let tab = tab.clone();
+            thread::spawn(move || Tab::load(tab, Url::new(&format!("http://127.0.0.1:{}/", port)).unwrap(), None))
+        };
+        let markup = |tab: &Arc<RwLock<Tab>>| tab.read().unwrap().nodes.as_ref().map(crate::html_serializer::document_html);
+
+        // The connection stalls, yet the text that has arrived is shown.
+        let started = Instant::now();
+        while !markup(&tab).is_some_and(|markup| markup.contains("<p>Hello wor")) {
+            assert!(started.elapsed() < Duration::from_secs(5), "the partial page was never rendered");
+            thread::sleep(Duration::from_millis(10));
+        }
+        resume_tx.send(()).unwrap();
+        server.join().unwrap();
+        loader.join().unwrap();
+        assert!(markup(&tab).unwrap().contains("<p>Hello world</p>"));
+    }
+
+    /// Serves each `(path, headers, body)` of `routes` over HTTP on a local port, on a
+    /// thread that lives as long as the test process, and returns the port.
+    fn serve(routes: Vec<(&'static str, &'static str, &'static str)>) -> u16 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            use std::io::{BufRead, BufReader, Write};
+            for mut stream in listener.incoming().flatten() {
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let _ = reader.read_line(&mut request_line);
+                let mut header = String::new();
+                while reader.read_line(&mut header).is_ok_and(|read| read > 2) {
+                    header.clear();
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or("");
+                let response = match routes.iter().find(|(route, _, _)| *route == path) {
+                    Some((_, headers, body)) => format!("HTTP/1.0 200 OK\r\n{}Content-Length: {}\r\n\r\n{}", headers, body.len(), body),
+                    None => "HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string(),
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        port
+    }
+
+    /// Loads the page at `/` from a server of `routes` in a new tab and returns the tab
+    /// and the list that the page's scripts appended to `globalThis.log`.
+    fn loaded_tab(routes: Vec<(&'static str, &'static str, &'static str)>) -> (Arc<RwLock<Tab>>, String) {
+        let port = serve(routes);
+        let tab = Arc::new(RwLock::new(Tab { viewport: VIEWPORT, ..Default::default() }));
+        Tab::load(tab.clone(), Url::new(&format!("http://127.0.0.1:{}/", port)).unwrap(), None);
+        let js = tab.read().unwrap().js.clone().unwrap();
+        let log = js.context.read().unwrap()
+            .with(|ctx| ctx.eval::<String, _>("String(globalThis.log || [])").unwrap());
+        (tab, log)
+    }
+
+    /// The page and resources for the script and style sheet ordering tests: each
+    /// script appends its name to `globalThis.log`, and the style sheets set the
+    /// `color` and `background-color` of `#target`.
+    fn script_and_style_routes(headers: &'static str) -> Vec<(&'static str, &'static str, &'static str)> {
+        vec![
+            ("/", headers, "<link rel=stylesheet href=red.css><style>#target { color: green; }</style>
+                <script>globalThis.log = (globalThis.log || []).concat('inline 1')</script>
+                <script src=a.js></script>
+                <script>globalThis.log = (globalThis.log || []).concat('inline 2')</script>
+                <style>#target { background-color: blue; }</style><link rel=stylesheet href=yellow.css>
+                <script src=b.js></script><p id=target>x</p>"),
+            ("/red.css", "", "#target { color: red; background-color: red; }"),
+            ("/yellow.css", "", "#target { background-color: yellow; }"),
+            ("/a.js", "", "globalThis.log = (globalThis.log || []).concat('external a')"),
+            ("/b.js", "", "globalThis.log = (globalThis.log || []).concat('external b')"),
+        ]
+    }
+
+    #[test]
+    fn inline_and_external_scripts_and_styles_apply_in_document_order() {
+        let (tab, log) = loaded_tab(script_and_style_routes(""));
+        assert_eq!(log, "inline 1,external a,inline 2,external b");
+        let style = element_by_id(tab.read().unwrap().nodes.as_ref().unwrap(), "target").read().unwrap().style.clone();
+        assert_eq!(style.color, color("green"));
+        assert_eq!(style.background_color, color("yellow"));
+    }
+
+    #[test]
+    fn content_security_policy_blocks_inline_scripts_and_styles() {
+        let (tab, log) = loaded_tab(script_and_style_routes("Content-Security-Policy: default-src 'self'\r\n"));
+        assert_eq!(log, "external a,external b");
+        let style = element_by_id(tab.read().unwrap().nodes.as_ref().unwrap(), "target").read().unwrap().style.clone();
+        assert_eq!(style.color, color("red"));
+        assert_eq!(style.background_color, color("yellow"));
+
+        let (_, log) = loaded_tab(script_and_style_routes("Content-Security-Policy: default-src 'self' 'unsafe-inline'\r\n"));
+        assert_eq!(log, "inline 1,external a,inline 2,external b");
+    }
+
+    /// Returns a tab that has loaded `html` from `url`, with its metadata read.
+    fn tab_with_metadata(html: &str, url: &str) -> Arc<RwLock<Tab>> {
+        let tab = Arc::new(RwLock::new(Tab { url: Some(Url::new(url).unwrap()), ..Default::default() }));
+        tab.write().unwrap().set_document(Some(HtmlParser::new(html.to_string()).parse()));
+        Tab::update_metadata(tab.clone());
+        tab
+    }
+
+    #[test]
+    fn the_first_title_is_read_with_collapsed_whitespace() {
+        let tab = tab_with_metadata("<title>\n  A   &amp;\tB </title><title>second</title>", "http://example.com/");
+        assert_eq!(tab.read().unwrap().title.as_deref(), Some("A & B"));
+        let tab = tab_with_metadata("<p>no title</p>", "http://example.com/");
+        assert_eq!(tab.read().unwrap().title, None);
+        let tab = tab_with_metadata(&format!("<title>{}</title>", "é".repeat(5000)), "http://example.com/");
+        assert_eq!(tab.read().unwrap().title.as_ref().unwrap().chars().count(), MAX_TITLE_CHARS);
+    }
+
+    #[test]
+    fn urls_are_resolved_against_the_first_base_href() {
+        let resolved = |html: &str, href: &str| tab_with_metadata(html, "http://example.com/dir/page.html")
+            .read().unwrap().resolve_url(href).unwrap().to_string();
+        assert_eq!(resolved("<p>x</p>", "next.html"), "http://example.com/dir/next.html");
+        let html = "<base target=_blank><base href=\"http://other.org/docs/\"><base href=/ignored/>";
+        assert_eq!(resolved(html, "next.html"), "http://other.org/docs/next.html");
+        assert_eq!(resolved(html, "/root.html"), "http://other.org/root.html");
+        // A relative base href is itself resolved against the document's URL.
+        assert_eq!(resolved("<base href=../up/index.html>", "a.html"), "http://example.com/up/a.html");
+    }
+
+    #[test]
+    fn refresh_content_gives_a_delay_and_an_optional_url() {
+        let seconds = |secs: f64| Duration::from_secs_f64(secs);
+        assert_eq!(parse_refresh("5"), Some((seconds(5.0), None)));
+        assert_eq!(parse_refresh(" 0.5 ; URL = '/next?a=1' "), Some((seconds(0.5), Some("/next?a=1".to_string()))));
+        assert_eq!(parse_refresh("3,url=\"http://example.com/\""), Some((seconds(3.0), Some("http://example.com/".to_string()))));
+        assert_eq!(parse_refresh("1; other.html"), Some((seconds(1.0), Some("other.html".to_string()))));
+        for invalid in ["", "-1", "soon; url=/", "NaN", "inf", "1e300"] {
+            assert_eq!(parse_refresh(invalid), None, "{:?} should be invalid", invalid);
+        }
+    }
+
+    #[test]
+    fn the_first_icon_link_is_the_favicon() {
+        let href = |html: &str| favicon_href(HtmlParser::new(html.to_string()).parse());
+        assert_eq!(href("<link rel=stylesheet href=a.css><link rel=\"Shortcut ICON\" href=a.ico><link rel=icon href=b.png>").as_deref(), Some("a.ico"));
+        assert_eq!(href("<link rel=apple-touch-icon href=a.png><link rel=icons href=b.png>"), None);
+    }
+
+    #[test]
+    fn only_icon_links_are_resolved_against_the_base_url() {
+        let icon = |html: &str| tab_with_metadata(html, "http://example.com/dir/page.html")
+            .read().unwrap().favicon_url().unwrap().to_string();
+        let base = "<base href=\"http://cdn.example.org/assets/\">";
+        assert_eq!(icon(&format!("{}<link rel=icon href=i.png>", base)), "http://cdn.example.org/assets/i.png");
+        assert_eq!(icon(base), "http://example.com/favicon.ico");
+        assert_eq!(icon("<p>no icon</p>"), "http://example.com/favicon.ico");
+    }
+
+    #[test]
+    fn saved_pages_never_overwrite_existing_files() {
+        let dir = std::env::temp_dir().join(format!("zipsurf-save-{}", std::process::id())).join("Downloads");
+        let save = |name: &str, contents: &str| save_new_file(&dir, name, contents.as_bytes()).unwrap();
+        assert_eq!(save("page.html", "first"), dir.join("page.html"));
+        assert_eq!(save("page.html", "second"), dir.join("page (1).html"));
+        assert_eq!(save("page.html", "third"), dir.join("page (2).html"));
+        assert_eq!(save(".html", "hidden"), dir.join(".html"));
+        assert_eq!(save(".html", "hidden"), dir.join(".html (1)"));
+        assert_eq!(std::fs::read_to_string(dir.join("page.html")).unwrap(), "first");
+        assert_eq!(std::fs::read_to_string(dir.join("page (1).html")).unwrap(), "second");
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn font_faces_load_the_first_source_that_is_not_woff() {
+        assert_eq!(font_source("local(My), url(\"my.woff2\") format(\"woff2\"), url(\"my.ttf\")").as_deref(), Some("my.ttf"));
+        assert_eq!(font_source("url(a.otf?v=2) format(\"woff\"), url('b.TTF#x')").as_deref(), Some("b.TTF#x"));
+        assert_eq!(font_source("url(a.woff), local(A)"), None);
+    }
+
+    #[test]
+    fn incremental_restyle_matches_a_full_restyle() {
+        let html = "<div id=a><p id=b>x <span id=c>y</span></p></div><p id=d>z</p><p id=e>w</p><p id=f>v</p>";
+        let css = ".on p { color: red; } .on + p { color: blue; } .on ~ p ~ p { color: green; }
+            div:hover { font-size: 30px; } #b:focus { background-color: yellow; } div:hover + p { border-radius: 3px; }";
+        let mut tab = rendered_tab(html, css);
+        let root = tab.nodes.clone().unwrap();
+        let a = element_by_id(&root, "a");
+
+        if let HtmlNodeType::Element(e) = &mut a.write().unwrap().node_type {
+            e.attributes.insert("class".to_string(), "on".to_string());
+        }
+        tab.invalidate_style(&a);
+        tab.render();
+        let b = element_by_id(&root, "b");
+        b.write().unwrap().is_focused = true;
+        tab.invalidate_state(&b);
+        for node in move_state_to_ancestors(None, Some(&element_by_id(&root, "c")), |node, value| node.is_hovered = value) {
+            tab.invalidate_state(&node);
+        }
+        tab.render();
+
+        let style_of = |id: &str| element_by_id(&root, id).read().unwrap().style.clone();
+        assert_eq!(style_of("c").color, color("red"));
+        assert_eq!(style_of("c").font_size, 30.0);
+        assert_eq!(style_of("d").border_radius, crate::style::LengthPercentage::Px(3.0));
+        assert_eq!(style_of("b").background_color, color("yellow"));
+        assert_eq!(style_of("d").color, color("blue"));
+        assert_eq!(style_of("f").color, color("green"));
+
+        let incremental = HtmlNode::tree_to_vec(root.clone(), &mut vec![]).iter()
+            .map(|node| node.read().unwrap().style.clone())
+            .collect::<Vec<_>>();
+        Tab::style(Some(root.clone()), &DEFAULT_STYLE_SHEET, &tab.rules, VIEWPORT);
+        let full = HtmlNode::tree_to_vec(root, &mut vec![]).iter()
+            .map(|node| node.read().unwrap().style.clone())
+            .collect::<Vec<_>>();
+        assert!(incremental == full, "incremental restyle differs from a full restyle");
+    }
+
+    #[test]
+    fn restyle_skips_nodes_that_were_not_invalidated() {
+        let mut tab = rendered_tab("<p id=a>x</p><p id=b>y</p>", "p { color: red; }");
+        let root = tab.nodes.clone().unwrap();
+        let (a, b) = (element_by_id(&root, "a"), element_by_id(&root, "b"));
+        a.write().unwrap().style.color = color("blue");
+        b.write().unwrap().style.color = color("blue");
+
+        tab.invalidate_style(&a);
+        tab.render();
+        assert_eq!(a.read().unwrap().style.color, color("red"));
+        assert_eq!(b.read().unwrap().style.color, color("blue"));
+    }
+
+    #[test]
+    fn pointer_state_restyles_only_the_nodes_that_depend_on_it() {
+        let html = "<div id=a><p id=b>press me</p></div><p id=c>other</p>";
+        let tab = Arc::new(RwLock::new(laid_out_tab(html, "#b:hover { color: red; } #b:active { background-color: yellow; }")));
+        let root = tab.read().unwrap().nodes.clone().unwrap();
+        let (a, b, c) = (element_by_id(&root, "a"), element_by_id(&root, "b"), element_by_id(&root, "c"));
+        let position = border_box(&tab.read().unwrap(), "b").center();
+        // Nodes that are restyled lose this font size.
+        c.write().unwrap().style.font_size = 99.0;
+        // The tab thread lays the page out again after each message.
+        let ctx = layout_context();
+        let relayout = |tab: &Arc<RwLock<Tab>>| tab.write().unwrap().update_layout(&ctx);
+
+        Tab::hover(tab.clone(), Some(position));
+        relayout(&tab);
+        assert!(a.read().unwrap().is_hovered && b.read().unwrap().is_hovered);
+        assert_eq!(b.read().unwrap().style.color, color("red"));
+        Tab::pointer_down(tab.clone(), position);
+        relayout(&tab);
+        assert!(a.read().unwrap().is_active && b.read().unwrap().is_active);
+        assert_eq!(b.read().unwrap().style.background_color, color("yellow"));
+        assert_eq!(c.read().unwrap().style.font_size, 99.0);
+
+        Tab::set_active(tab.clone(), None);
+        relayout(&tab);
+        Tab::hover(tab.clone(), None);
+        assert!(!b.read().unwrap().is_hovered && !b.read().unwrap().is_active);
+        assert_eq!(b.read().unwrap().style.color, Color32::BLACK);
+        assert_eq!(b.read().unwrap().style.background_color, Color32::TRANSPARENT);
+        assert_eq!(c.read().unwrap().style.font_size, 99.0);
+
+        // Without :hover or :active rules, the pointer restyles nothing, and the layout
+        // tree is not rebuilt.
+        let tab = Arc::new(RwLock::new(laid_out_tab(html, "#b { color: red; }")));
+        let b = element_by_id(tab.read().unwrap().nodes.as_ref().unwrap(), "b");
+        let position = border_box(&tab.read().unwrap(), "b").center();
+        let document = tab.read().unwrap().document.clone().unwrap();
+        b.write().unwrap().style.font_size = 99.0;
+        Tab::hover(tab.clone(), Some(position));
+        relayout(&tab);
+        Tab::pointer_down(tab.clone(), position);
+        Tab::set_active(tab.clone(), None);
+        Tab::hover(tab.clone(), None);
+        assert!(!b.read().unwrap().is_hovered && !b.read().unwrap().is_active);
+        assert_eq!(b.read().unwrap().style.font_size, 99.0);
+        assert!(Arc::ptr_eq(tab.read().unwrap().document.as_ref().unwrap(), &document));
+        assert!(!tab.read().unwrap().needs_redraw);
+    }
+
+    /// Returns a page with an input and `sections` cards of a heading, a paragraph with a
+    /// link and a list of five items, about 20 nodes each.
+    fn large_page(sections: usize) -> String {
+        let mut html = String::from("<html><body><form><input id=target></form>");
+        for i in 0..sections {
+            html.push_str(&format!("<div class=\"card c{}\"><h2>Title {}</h2><p>Some <b>text</b> and <a href=#{}>a link</a></p><ul>", i % 50, i, i));
+            for j in 0..5 {
+                html.push_str(&format!("<li class=item{}>Item {}</li>", j, j));
+            }
+            html.push_str("</ul></div>");
+        }
+        html.push_str("</body></html>");
+        html
+    }
+
+    /// Compares the latency of a keypress in an input on a large page when every node is
+    /// restyled, as before incremental restyling, and when only the invalidated nodes
+    /// are. Run it with `cargo test --release keypress_latency -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn keypress_latency() {
+        const KEYPRESSES: u32 = 10;
+        let html = large_page(500);
+        let mut css = String::new();
+        for i in 0..50 {
+            css.push_str(&format!(".c{} p {{ color: #{:06x}; }} .c{} > ul li.item{} {{ font-size: {}px; }} ", i, i * 1000, i, i % 5, 10 + i % 10));
+            css.push_str(&format!("div.card h2 + p a:hover {{ color: red; }} .c{} ~ .card ul {{ padding-left: {}px; }} ", i, i));
+        }
+
+        let this = Arc::new(RwLock::new(rendered_tab(&html, &css)));
+        let input = element_by_id(this.read().unwrap().nodes.as_ref().unwrap(), "target");
+        if let HtmlNodeType::Element(e) = &mut input.write().unwrap().node_type {
+            e.attributes.insert("value".to_string(), String::new());
+        }
+        input.write().unwrap().is_focused = true;
+        this.write().unwrap().focus = Some(input);
+
+        let measure = |restyle_all: bool| {
+            let start = Instant::now();
+            for _ in 0..KEYPRESSES {
+                this.write().unwrap().restyle_all = restyle_all;
+                Tab::keypress(this.clone(), &"a".to_string());
+            }
+            start.elapsed() / KEYPRESSES
+        };
+        let full = measure(true);
+        let incremental = measure(false);
+        println!("keypress latency on {} nodes, {} rules:", HtmlNode::tree_to_vec(this.read().unwrap().nodes.clone().unwrap(), &mut vec![]).len(), this.read().unwrap().rules.rules().len());
+        println!("  full restyle:        {:?}", full);
+        println!("  incremental restyle: {:?}", incremental);
+    }
+
+    /// Returns, for each node of the tree under `root` in document order, the positions of
+    /// the rules of `rules` that match it, by trying every rule against every node.
+    fn matches_by_linear_scan(root: &Arc<RwLock<HtmlNode>>, rules: &RuleIndex) -> Vec<Vec<usize>> {
+        HtmlNode::tree_to_vec(root.clone(), &mut vec![]).iter()
+            .map(|node| (0..rules.rules().len()).filter(|&i| rules.rules()[i].0.matches(node.clone())).collect())
+            .collect()
+    }
+
+    /// Returns the same as `matches_by_linear_scan`, with the rule index and an ancestor
+    /// filter kept up to date along the walk, as `Tab::style` does.
+    fn matches_by_index(root: &Arc<RwLock<HtmlNode>>, rules: &RuleIndex) -> Vec<Vec<usize>> {
+        fn walk(node: &Arc<RwLock<HtmlNode>>, rules: &RuleIndex, filter: &mut AncestorFilter, out: &mut Vec<Vec<usize>>) {
+            out.push(rules.matching(node, filter));
+            filter.push(node);
+            for child in node.read().unwrap().children.clone() {
+                walk(&child, rules, filter, out);
+            }
+            filter.pop(node);
+        }
+        let mut out = vec![];
+        walk(root, rules, &mut AncestorFilter::for_node(root), &mut out);
+        out
+    }
+
+    #[test]
+    fn rule_index_finds_the_same_rules_as_a_linear_scan() {
+        let html = "<div id=main class=\"a  b\"><section class=b><p class=\"c a\">x <a href=#>y</a></p><p id=p2>z</p>
+            <ul><li class=c>1</li><li>2</li></ul></section></div><p class=a>w</p>";
+        let css = "* { } p { } .a { } #main { } .missing p { } div p { } div > p { } section > .c { }
+            .a .b p { } p + p { } .c ~ p { } div h1 + p { } .b :is(.c) { } :not(.a) { } p:hover { }
+            #main a { } #other a { } [href] { } ul li:first-child { } div.a.b#main p.c a { }
+            :is(.c a) { } .a.a { } section p.c:not(#p2) { } li.c + li { }";
+        let rules = RuleIndex::new(CssParser::new(css).parse().unwrap());
+        let root = HtmlParser::new(html.to_string()).parse();
+        assert_eq!(matches_by_index(&root, &rules), matches_by_linear_scan(&root, &rules));
+
+        // Styling a subtree starts with the ancestors of its root in the filter.
+        let p2 = element_by_id(&root, "p2");
+        let matched = rules.matching(&p2, &AncestorFilter::for_node(&p2));
+        assert_eq!(matched, (0..rules.rules().len()).filter(|&i| rules.rules()[i].0.matches(p2.clone())).collect::<Vec<_>>());
+        assert!(matched.len() > 3);
+    }
+
+    /// Compares selector matching by trying every rule against every node with matching
+    /// through the rule index and the ancestor filter, on a large page with a 3,000-rule
+    /// style sheet. Run it with
+    /// `cargo test --release rule_index_matching -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn rule_index_matching() {
+        let root = HtmlParser::new(large_page(500)).parse();
+        let mut css = String::new();
+        for i in 0..500 {
+            css.push_str(&format!(".btn-{i} {{ }} .nav-{i} a {{ }} #section-{i} p {{ }} .c{} > ul li.item{} {{ }} ", i % 60, i % 7));
+            css.push_str(&format!("div.col-{i} .row {{ }} .modal-{i} .modal-body p:first-child {{ }} "));
+        }
+        let rules = RuleIndex::new(CssParser::new(&css).parse().unwrap());
+
+        let start = Instant::now();
+        let linear = matches_by_linear_scan(&root, &rules);
+        let linear_time = start.elapsed();
+        let start = Instant::now();
+        let indexed = matches_by_index(&root, &rules);
+        let indexed_time = start.elapsed();
+        assert_eq!(linear, indexed);
+
+        println!("matching {} rules against {} nodes:", rules.rules().len(), linear.len());
+        println!("  every rule against every node: {:?}", linear_time);
+        println!("  rule index and ancestor filter: {:?}", indexed_time);
+    }
+
+    /// Returns a tab showing `html` with `author_css`, rendered and laid out with the
+    /// browser's fonts.
+    fn laid_out_tab(html: &str, author_css: &str) -> Tab {
+        let mut tab = rendered_tab(html, author_css);
+        tab.update_layout(&layout_context());
+        tab
+    }
+
+    /// Returns an egui context with the browser's fonts, for laying out tabs.
+    fn layout_context() -> Context {
+        let ctx = Context::default();
+        crate::browser::Browser::setup_custom_fonts(&ctx);
+        let _ = ctx.run_ui(egui::RawInput::default(), |_| {});
+        ctx
+    }
+
+    /// Returns the border box of the block laid out for the element whose `id` is `id`,
+    /// or `None` if it generates no block.
+    fn block_box(tab: &Tab, id: &str) -> Option<Rect> {
+        let element = element_by_id(tab.nodes.as_ref().unwrap(), id);
+        LayoutNode::tree_to_vec(tab.document.clone().unwrap(), &mut vec![]).iter()
+            .map(|layout| layout.read().unwrap())
+            .find(|layout| matches!(layout.content, crate::layout::LayoutNodeType::Block(_))
+                && Arc::ptr_eq(&layout.node, &element))
+            .map(|layout| Rect::from_min_size(layout.position.unwrap().to_pos2(), layout.size.unwrap()))
+    }
+
+    /// Returns the border box of the block laid out for the element whose `id` is `id`.
+    fn border_box(tab: &Tab, id: &str) -> Rect {
+        block_box(tab, id).unwrap_or_else(|| panic!("no block for id={}", id))
+    }
+
+    /// Returns the words laid out for the element whose `id` is `id`, with their boxes.
+    fn words(tab: &Tab, id: &str) -> Vec<(String, Rect)> {
+        let element = element_by_id(tab.nodes.as_ref().unwrap(), id);
+        let in_element = |node: &Arc<RwLock<HtmlNode>>| {
+            let mut node = Some(node.clone());
+            while let Some(current) = node {
+                if Arc::ptr_eq(&current, &element) {
+                    return true;
+                }
+                node = current.read().unwrap().parent.clone();
+            }
+            false
+        };
+        LayoutNode::tree_to_vec(tab.document.clone().unwrap(), &mut vec![]).iter()
+            .map(|layout| layout.read().unwrap())
+            .filter(|layout| in_element(&layout.node))
+            .filter_map(|layout| match &layout.content {
+                crate::layout::LayoutNodeType::Text(text) => Some((text.galley.text().to_string(),
+                    Rect::from_min_size(layout.position.unwrap().to_pos2(), layout.size.unwrap()))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn preformatted_text_keeps_its_line_breaks_and_spaces() {
+        let tab = laid_out_tab("<pre id=pre>\none\n    two  three\n\nfour</pre>", "");
+        let words = words(&tab, "pre");
+        let texts: Vec<&str> = words.iter().map(|(text, _)| text.as_str()).collect();
+        assert_eq!(texts, ["one", "two", "three", "four"]);
+        let [(_, one), (_, two), (_, three), (_, four)] = words.as_slice() else { unreachable!() };
+        // Each newline starts a line, and an empty line is kept.
+        assert!(two.top() > one.top() && four.top() > two.top() + two.height());
+        assert_eq!((two.top(), four.left()), (three.top(), one.left()));
+        // Four spaces of indentation are twice as wide as the two between the words.
+        assert!(three.left() > two.right());
+        assert!(((two.left() - one.left()) - 2.0 * (three.left() - two.right())).abs() < 0.5);
+    }
+
+    #[test]
+    fn white_space_decides_where_lines_wrap() {
+        let html = "<p id=normal>many words that will not fit on one line</p>
+            <p id=nowrap>many words that will not fit on one line</p>
+            <p id=inline><b>bold</b> <i>italic</i><span> </span><u>underlined</u></p>";
+        let css = "#normal, #nowrap { width: 60px; } #nowrap { white-space: nowrap; }";
+        let tab = laid_out_tab(html, css);
+        let tops = |id: &str| words(&tab, id).iter().map(|(_, rect)| rect.top().to_bits()).collect::<HashSet<_>>().len();
+        assert!(tops("normal") > 1);
+        assert_eq!(tops("nowrap"), 1);
+
+        // White space between inline elements separates their words.
+        let inline = words(&tab, "inline");
+        let [(_, bold), (_, italic), (_, underlined)] = inline.as_slice() else { panic!("{:?}", inline) };
+        assert!(italic.left() > bold.right() && underlined.left() > italic.right());
+    }
+
+    #[test]
+    fn blocks_are_sized_by_margins_borders_and_paddings() {
+        let html = "<div id=a></div><div id=b><div id=c></div></div><div id=d></div>";
+        let css = "#a { margin: 20px 0 30px; height: 10px; }
+            #b { width: 200px; margin: 50px auto 0; padding: 10px; border: 5px solid black; }
+            #c { height: 20px; margin-left: 10%; }
+            #d { box-sizing: border-box; width: 100px; min-width: 120px; padding: 0 30px; margin-left: auto; }";
+        let tab = laid_out_tab(html, css);
+        let page_width = WIDTH - 2.0 * HSTEP;
+
+        // Margins that separate nothing from the top of the page still push #a down.
+        let a = border_box(&tab, "a");
+        assert_eq!((a.left(), a.top(), a.width(), a.height()), (HSTEP, VSTEP + 20.0, page_width, 10.0));
+
+        // Sibling margins collapse to the larger one, and auto margins center #b.
+        let b = border_box(&tab, "b");
+        assert_eq!(b.top(), a.bottom() + 50.0);
+        assert_eq!(b.width(), 230.0);
+        assert_eq!(b.left(), HSTEP + (page_width - 230.0) / 2.0);
+        assert_eq!(b.height(), 20.0 + 2.0 * 15.0);
+
+        // #c is in #b's content box, and its percentage margin refers to that width.
+        let c = border_box(&tab, "c");
+        assert_eq!((c.left(), c.top(), c.width()), (b.left() + 15.0 + 20.0, b.top() + 15.0, 180.0));
+
+        // min-width wins over width, both sizing the border box, and a single auto margin
+        // takes all the space left.
+        let d = border_box(&tab, "d");
+        assert_eq!((d.width(), d.right()), (120.0, HSTEP + page_width));
+    }
+
+    #[test]
+    fn parent_and_child_margins_collapse_unless_separated() {
+        let html = "<div id=a><p id=p1>x</p></div><div id=b><p id=p2>x</p></div><div id=empty></div><div id=c>y</div>";
+        let css = "div { margin: 40px 0; } p { margin: 25px 0; } #b { padding-top: 1px; } #empty { margin: 60px 0; }";
+        let tab = laid_out_tab(html, css);
+
+        // #a has no top border or padding, so its margin and #p1's become one.
+        let (a, p1) = (border_box(&tab, "a"), border_box(&tab, "p1"));
+        assert_eq!(a.top(), VSTEP + 40.0);
+        assert_eq!(p1.top(), a.top());
+        assert_eq!(a.bottom(), p1.bottom());
+
+        // #b's padding separates them; #a's and #p1's bottom margins collapse with #b's top.
+        let (b, p2) = (border_box(&tab, "b"), border_box(&tab, "p2"));
+        assert_eq!(b.top(), a.bottom() + 40.0);
+        assert_eq!(p2.top(), b.top() + 1.0 + 25.0);
+
+        // The empty div's margins collapse through it with its neighbours'.
+        let c = border_box(&tab, "c");
+        assert_eq!(c.top(), b.bottom() + 60.0);
+    }
+
+    #[test]
+    fn borders_are_painted_with_their_styles_and_colors() {
+        let html = "<div id=a>x</div>";
+        let css = "#a { border: 4px solid red; border-left: 6px double blue; border-bottom-style: dashed; color: green; }";
+        let tab = laid_out_tab(html, css);
+        let a = border_box(&tab, "a");
+        let rects: Vec<(Rect, Color32)> = tab.draw_commands.iter()
+            .filter_map(|cmd| match cmd {
+                DrawCommand::DrawRect(rect) => Some((rect.rect, rect.color)),
+                _ => None,
+            })
+            .collect();
+
+        let top = Rect::from_min_max(a.min, Pos2::new(a.right(), a.top() + 4.0));
+        assert!(rects.contains(&(top, color("red"))));
+        // A double border is two lines, each a third of its width.
+        let left = rects.iter().filter(|(_, c)| *c == color("blue")).map(|(r, _)| *r).collect::<Vec<_>>();
+        assert_eq!(left.len(), 2);
+        assert!(left.iter().all(|r| r.width() == 2.0 && r.top() == a.top() + 4.0 && r.bottom() == a.bottom() - 4.0));
+        // A dashed border is several dashes along the side.
+        let dashes = rects.iter().filter(|(r, c)| *c == color("red") && r.bottom() == a.bottom()).count();
+        assert!(dashes > 10);
+    }
+
+    #[test]
+    fn display_decides_which_boxes_are_generated() {
+        let html = "<div id=gone>hidden</div><span id=span>block</span><div id=inline>inline</div>
+            <div id=mixed>before<p id=p>paragraph</p>after</div>
+            <div id=contents><p id=inner>x</p></div>";
+        let css = "#gone { display: none; } #span { display: block; } #inline { display: inline; }
+            #contents { display: contents; } p { margin: 0; }";
+        let tab = laid_out_tab(html, css);
+
+        assert_eq!(block_box(&tab, "gone"), None);
+        assert_eq!(block_box(&tab, "inline"), None);
+        assert_eq!(block_box(&tab, "contents"), None);
+        assert!(block_box(&tab, "inner").is_some());
+        let span = border_box(&tab, "span");
+        assert_eq!((span.top(), span.width()), (VSTEP, WIDTH - 2.0 * HSTEP));
+
+        // The text around the paragraph is wrapped in anonymous blocks above and below it.
+        let (mixed, p) = (border_box(&tab, "mixed"), border_box(&tab, "p"));
+        assert!(p.top() > mixed.top() && p.bottom() < mixed.bottom());
+
+        // Nothing is hit where the hidden element would be: the span took its place.
+        let tab = Arc::new(RwLock::new(tab));
+        let hit = Tab::hit_test(tab.clone(), span.center()).unwrap();
+        let span_element = element_by_id(tab.read().unwrap().nodes.as_ref().unwrap(), "span");
+        assert!(Arc::ptr_eq(&hit, &span_element) || Arc::ptr_eq(hit.read().unwrap().parent.as_ref().unwrap(), &span_element));
+    }
+
+    #[test]
+    fn inline_blocks_flow_in_lines_and_shrink_to_fit() {
+        let html = "<div id=line>a <span id=fit>inline block</span> <span id=fixed>x</span> b</div>";
+        let css = "#fit, #fixed { display: inline-block; border: 1px solid; } #fit { padding: 5px; } #fixed { width: 100px; }";
+        let tab = laid_out_tab(html, css);
+        let (line, fit, fixed) = (border_box(&tab, "line"), border_box(&tab, "fit"), border_box(&tab, "fixed"));
+
+        assert!(fit.left() > line.left() && fit.width() < 200.0);
+        assert_eq!(fixed.width(), 102.0);
+        assert!(fixed.left() > fit.right());
+        // Both sit on the first line, which grows to fit them.
+        assert!(fit.top() >= line.top() && fit.bottom() <= line.bottom());
+        assert_eq!(fit.bottom(), fixed.bottom());
+    }
+
+    #[test]
+    fn list_items_paint_their_markers() {
+        let markers = |css: &str| {
+            let tab = laid_out_tab("<ul><li id=item>x</li></ul>", css);
+            let item = border_box(&tab, "item");
+            tab.draw_commands.iter()
+                .filter(|cmd| matches!(cmd, DrawCommand::DrawRect(rect) if rect.rect.right() < item.left()))
+                .count()
+        };
+        assert_eq!(markers(""), 1);
+        assert_eq!(markers("ul { list-style: none; }"), 0);
+    }
+
+    #[test]
+    fn flex_items_grow_shrink_and_wrap() {
+        let html = "<div id=row><div id=a>a</div><div id=b>b</div><div id=c>c</div></div>
+            <div id=shrink><div id=d></div><div id=e></div></div>
+            <div id=wrap><div id=f></div><div id=g></div><div id=h></div></div>";
+        let css = "#row { display: flex; width: 400px; gap: 10px; }
+            #a { flex: 0 0 100px; } #b { flex: 1; } #c { flex: 3 1 0; order: -1; }
+            #shrink { display: flex; width: 300px; } #shrink > div { flex-basis: 200px; height: 10px; }
+            #e { flex-shrink: 3; }
+            #wrap { display: flex; flex-wrap: wrap; width: 250px; row-gap: 5px; }
+            #wrap > div { width: 100px; height: 20px; }";
+        let tab = laid_out_tab(html, css);
+        let row = border_box(&tab, "row");
+        let (a, b, c) = (border_box(&tab, "a"), border_box(&tab, "b"), border_box(&tab, "c"));
+
+        // `order` moves #c first; the 280px left over go 1:3 to #b and #c.
+        assert_eq!(c.left(), row.left());
+        assert_eq!(c.width(), 210.0);
+        assert_eq!(a.left(), c.right() + 10.0);
+        assert_eq!(a.width(), 100.0);
+        assert_eq!(b.left(), a.right() + 10.0);
+        assert_eq!(b.width(), 70.0);
+        // The items stretch to the line, which is as high as the text.
+        assert!(a.height() > 0.0 && a.top() == row.top() && a.bottom() == row.bottom());
+        assert_eq!(b.height(), a.height());
+
+        // The 100px of overflow are taken 1:3 from items of the same base size.
+        assert_eq!(border_box(&tab, "d").width(), 175.0);
+        assert_eq!(border_box(&tab, "e").width(), 125.0);
+
+        let (f, g, h) = (border_box(&tab, "f"), border_box(&tab, "g"), border_box(&tab, "h"));
+        assert_eq!(g.left(), f.right());
+        assert_eq!(h.left(), f.left());
+        assert_eq!(h.top(), f.bottom() + 5.0);
+        assert_eq!(border_box(&tab, "wrap").height(), 45.0);
+    }
+
+    #[test]
+    fn flex_items_with_huge_base_sizes_are_resolved() {
+        let tab = laid_out_tab("<div id=f class=f><p id=a>a</p><p id=b>b</p></div>",
+            ".f { display: flex; } .f p { flex-basis: 3e38px; }");
+        let (a, b) = (border_box(&tab, "a"), border_box(&tab, "b"));
+        assert!(a.width().is_finite() && b.width().is_finite());
+        assert_eq!(a.left(), border_box(&tab, "f").left());
+    }
+
+    #[test]
+    fn empty_flex_containers_are_laid_out() {
+        let html = "<div id=row></div><div id=column></div><div id=sized></div><p>a <span id=inline></span> b</p>";
+        let css = "div { display: flex; } #column { flex-direction: column; min-height: 15px; max-height: 10px; }
+            #sized { flex-direction: column; height: 40px; } #inline { display: inline-flex; }";
+        let tab = laid_out_tab(html, css);
+        assert_eq!(border_box(&tab, "row").height(), 0.0);
+        assert_eq!(border_box(&tab, "column").height(), 15.0);
+        assert_eq!(border_box(&tab, "sized").height(), 40.0);
+        assert_eq!(border_box(&tab, "inline").size(), Vec2::ZERO);
+    }
+
+    #[test]
+    fn flex_items_are_justified_and_aligned() {
+        let html = "<div id=between><div id=a></div><div id=b></div><div id=c></div></div>
+            <div id=center><div id=d></div><div id=e></div><div id=f></div></div>
+            <div id=column><div id=g></div><div id=h></div></div>";
+        let css = "#between, #center { display: flex; width: 400px; height: 100px; }
+            #between { justify-content: space-between; align-items: flex-start; }
+            #center { justify-content: center; align-items: center; }
+            #between > div, #center > div { width: 50px; height: 20px; }
+            #c { align-self: flex-end; }
+            #f { margin-left: auto; }
+            #column { display: flex; flex-direction: column-reverse; align-items: flex-end; width: 200px; }
+            #g { width: 30px; height: 10px; } #h { width: 40px; height: 20px; }";
+        let tab = laid_out_tab(html, css);
+
+        let between = border_box(&tab, "between");
+        let (a, b, c) = (border_box(&tab, "a"), border_box(&tab, "b"), border_box(&tab, "c"));
+        assert_eq!((a.left(), b.left(), c.right()), (between.left(), between.left() + 175.0, between.right()));
+        assert_eq!(a.top(), between.top());
+        assert_eq!(c.bottom(), between.bottom());
+
+        // The auto margin takes the free space before `justify-content` sees it.
+        let center = border_box(&tab, "center");
+        let (d, e, f) = (border_box(&tab, "d"), border_box(&tab, "e"), border_box(&tab, "f"));
+        assert_eq!(d.left(), center.left());
+        assert_eq!(e.left(), d.right());
+        assert_eq!(f.right(), center.right());
+        assert_eq!(d.top(), center.top() + 40.0);
+
+        // A reversed column stacks from the bottom of a box as high as its items.
+        let column = border_box(&tab, "column");
+        let (g, h) = (border_box(&tab, "g"), border_box(&tab, "h"));
+        assert_eq!(column.height(), 30.0);
+        assert_eq!((h.top(), h.bottom()), (column.top(), g.top()));
+        assert_eq!(g.bottom(), column.bottom());
+        assert_eq!((g.right(), h.right()), (column.right(), column.right()));
+    }
+
+    #[test]
+    fn flex_items_are_painted_and_hit() {
+        let html = "<div id=row>text <a id=link href=#x>link</a></div>";
+        let css = "#row { display: flex; justify-content: flex-end; } #link { padding: 10px; background-color: red; }";
+        let tab = laid_out_tab(html, css);
+        let (row, link) = (border_box(&tab, "row"), border_box(&tab, "link"));
+        assert_eq!(link.right(), row.right());
+        assert!(tab.draw_commands.iter().any(|cmd| matches!(cmd, DrawCommand::DrawRect(rect) if rect.rect == link)));
+
+        // An inline flex container shrinks to its items, whatever its free space.
+        let inline = laid_out_tab("<p>a <span id=inline><b id=x>x</b><b id=y>y</b></span> b</p>",
+            "#inline { display: inline-flex; justify-content: center; column-gap: 7px; } b { width: 20px; }");
+        let (x, y) = (border_box(&inline, "x"), border_box(&inline, "y"));
+        assert_eq!(border_box(&inline, "inline").width(), 47.0);
+        assert_eq!(y.left(), x.right() + 7.0);
+
+        let tab = Arc::new(RwLock::new(tab));
+        let hit = Tab::hit_test(tab.clone(), link.center()).unwrap();
+        let link_element = element_by_id(tab.read().unwrap().nodes.as_ref().unwrap(), "link");
+        assert!(Arc::ptr_eq(&hit, &link_element) || Arc::ptr_eq(hit.read().unwrap().parent.as_ref().unwrap(), &link_element));
+    }
+
+    #[test]
+    fn grid_tracks_are_sized() {
+        let html = "<div id=grid><div id=a></div><div id=b></div><div id=c></div><div id=d></div></div>
+            <div id=fill><i id=e></i><i id=f></i><i id=g></i><i id=h></i></div>";
+        let css = "#grid { display: grid; width: 420px; grid-template-columns: 100px 1fr 2fr; grid-template-rows: 30px; gap: 5px 10px; }
+            #d { height: 40px; }
+            #fill { display: grid; width: 300px; grid-template-columns: repeat(auto-fill, minmax(90px, 1fr)); }
+            i { display: block; height: 10px; }";
+        let tab = laid_out_tab(html, css);
+
+        let grid = border_box(&tab, "grid");
+        let (a, b, c, d) = (border_box(&tab, "a"), border_box(&tab, "b"), border_box(&tab, "c"), border_box(&tab, "d"));
+        assert_eq!((a.left(), a.width(), a.height()), (grid.left(), 100.0, 30.0));
+        assert_eq!((b.left(), b.width()), (grid.left() + 110.0, 100.0));
+        assert_eq!((c.left(), c.width()), (grid.left() + 220.0, 200.0));
+
+        // The fourth item goes on an implicit row, as high as itself.
+        assert_eq!((d.left(), d.top(), d.height()), (grid.left(), grid.top() + 35.0, 40.0));
+        assert_eq!(grid.height(), 75.0);
+
+        // Three columns of at least 90px fit, and share the rest.
+        let fill = border_box(&tab, "fill");
+        let (e, g, h) = (border_box(&tab, "e"), border_box(&tab, "g"), border_box(&tab, "h"));
+        assert_eq!((e.width(), g.left(), g.right()), (100.0, fill.left() + 200.0, fill.right()));
+        assert_eq!((h.left(), h.top()), (fill.left(), e.bottom()));
+    }
+
+    #[test]
+    fn grid_items_are_placed() {
+        let html = "<div id=page><div id=head></div><div id=side></div><div id=main></div>
+            <div id=x></div><div id=y></div><div id=z></div></div>";
+        let css = "#page { display: grid; width: 300px; grid-template-columns: repeat(3, 100px); grid-auto-rows: 20px;
+                grid-template-areas: 'head head head' 'side main main'; }
+            #head { grid-area: head; } #side { grid-area: side; } #main { grid-area: main; }
+            #x { grid-column: 2 / span 2; } #z { grid-row: 3; }";
+        let tab = laid_out_tab(html, css);
+
+        let page = border_box(&tab, "page");
+        let (head, side, main) = (border_box(&tab, "head"), border_box(&tab, "side"), border_box(&tab, "main"));
+        assert_eq!((head.left(), head.top(), head.width()), (page.left(), page.top(), 300.0));
+        assert_eq!((side.left(), side.top(), side.width()), (page.left(), page.top() + 20.0, 100.0));
+        assert_eq!((main.left(), main.top(), main.width()), (page.left() + 100.0, page.top() + 20.0, 200.0));
+
+        // An item locked to a row is placed before the others, which follow each other
+        // without going back to fill holes.
+        let (x, y, z) = (border_box(&tab, "x"), border_box(&tab, "y"), border_box(&tab, "z"));
+        assert_eq!((z.left(), z.top()), (page.left(), page.top() + 40.0));
+        assert_eq!((x.left(), x.top(), x.width()), (page.left() + 100.0, page.top() + 40.0, 200.0));
+        assert_eq!((y.left(), y.top()), (page.left(), page.top() + 60.0));
+        assert_eq!(page.height(), 80.0);
+    }
+
+    #[test]
+    fn grid_lines_spans_and_repetitions_are_clamped() {
+        let html = "<div id=grid><div id=far></div><div id=tall></div></div>";
+        let css = "#grid { display: grid; width: 2000px; grid-template-columns: repeat(100000000, 1px); grid-auto-rows: 1px; }
+            #far { grid-column: 200000000; grid-row: 1; } #tall { grid-column: 1; grid-row: span 4000000000; }";
+        let tab = laid_out_tab(html, css);
+
+        // Each goes no further than the last of the 1000 tracks a grid may have.
+        let grid = border_box(&tab, "grid");
+        let (far, tall) = (border_box(&tab, "far"), border_box(&tab, "tall"));
+        assert_eq!((far.left(), far.width()), (grid.left() + 999.0, 1.0));
+        assert_eq!((tall.left(), tall.height()), (grid.left(), 1000.0));
+        assert_eq!(grid.height(), 1000.0);
+    }
+
+    #[test]
+    fn grid_items_are_aligned_in_their_areas() {
+        let html = "<div id=grid><div id=k></div><div id=l></div></div>
+            <p>a <span id=inline><b id=u>u</b><b id=v>v</b></span> b</p>";
+        let css = "#grid { display: grid; width: 200px; height: 100px; grid-template-columns: 1fr 1fr;
+                justify-items: center; align-items: end; }
+            #k { width: 20px; height: 10px; }
+            #l { justify-self: stretch; align-self: stretch; margin-top: auto; }
+            #inline { display: inline-grid; grid-template-columns: auto auto; column-gap: 5px; } b { width: 20px; }";
+        let tab = laid_out_tab(html, css);
+
+        let grid = border_box(&tab, "grid");
+        let (k, l) = (border_box(&tab, "k"), border_box(&tab, "l"));
+        assert_eq!((k.left(), k.bottom()), (grid.left() + 40.0, grid.bottom()));
+        // An auto margin takes the free space instead of the item stretching.
+        assert_eq!((l.left(), l.width(), l.bottom()), (grid.left() + 100.0, 100.0, grid.bottom()));
+        assert!(l.height() < 100.0);
+
+        // An inline grid container shrinks to its columns.
+        let (u, v) = (border_box(&tab, "u"), border_box(&tab, "v"));
+        assert_eq!(border_box(&tab, "inline").width(), 45.0);
+        assert_eq!(v.left(), u.right() + 5.0);
+    }
+
+    #[test]
+    fn table_columns_are_sized_from_their_cells() {
+        let html = "<table id=t><tr><td id=a><div class=w50></div></td><td id=b><div class=w30></div></td></tr>
+            <tr><td id=c colspan=2><div class=w100></div></td></tr></table>
+            <table id=wide><tr><td id=d><div class=w60></div></td><td id=e><div class=w20></div></td></tr></table>";
+        let css = "#t { border-spacing: 4px; } #wide { width: 200px; border-spacing: 0; } td { padding: 0; }
+            div { height: 10px; } .w20 { width: 20px; } .w30 { width: 30px; } .w50 { width: 50px; }
+            .w60 { width: 60px; } .w100 { width: 100px; }";
+        let tab = laid_out_tab(html, css);
+
+        // The spanning cell widens both columns, in proportion to their widths.
+        let table = border_box(&tab, "t");
+        let (a, b, c) = (border_box(&tab, "a"), border_box(&tab, "b"), border_box(&tab, "c"));
+        assert_eq!((a.left(), a.top(), a.width()), (table.left() + 4.0, table.top() + 4.0, 60.0));
+        assert_eq!((b.left(), b.width()), (a.right() + 4.0, 36.0));
+        assert_eq!((c.left(), c.top(), c.width()), (a.left(), a.bottom() + 4.0, 100.0));
+        assert_eq!((table.width(), table.height()), (108.0, 32.0));
+
+        // A wider table shares the rest in proportion to the columns' widths.
+        let wide = border_box(&tab, "wide");
+        let (d, e) = (border_box(&tab, "d"), border_box(&tab, "e"));
+        assert_eq!((d.left(), d.width()), (wide.left(), 150.0));
+        assert_eq!((e.width(), e.right()), (50.0, wide.right()));
+    }
+
+    #[test]
+    fn table_rows_are_ordered_spanned_and_aligned() {
+        let html = "<table id=t><caption id=cap></caption>
+            <tfoot><tr><td id=f><div></div></td></tr></tfoot>
+            <tbody><tr id=r1><td id=s rowspan=2><div id=tall></div></td><td id=m><div id=mi></div></td></tr>
+            <tr><td id=n><div id=ni></div></td></tr></tbody>
+            <thead><tr><td id=h><div></div></td></tr></thead></table>";
+        let css = "table { border-spacing: 0; } #cap { height: 15px; } td { padding: 0; } div { width: 20px; height: 10px; }
+            #tall { height: 50px; } #n { vertical-align: bottom; }";
+        let tab = laid_out_tab(html, css);
+
+        // The caption comes first, then the header, the body and the footer.
+        let table = border_box(&tab, "t");
+        let (cap, h, f) = (border_box(&tab, "cap"), border_box(&tab, "h"), border_box(&tab, "f"));
+        assert_eq!((cap.top(), cap.height()), (table.top(), 15.0));
+        assert_eq!((h.top(), h.height()), (cap.bottom(), 10.0));
+        assert_eq!((f.top(), f.bottom()), (table.top() + 75.0, table.bottom()));
+
+        // The rows share what the spanning cell lacks, and the row's block covers its cells.
+        let (s, m, n, r1) = (border_box(&tab, "s"), border_box(&tab, "m"), border_box(&tab, "n"), border_box(&tab, "r1"));
+        assert_eq!((s.top(), s.height()), (h.bottom(), 50.0));
+        assert_eq!((m.top(), m.height(), n.top(), n.height()), (s.top(), 25.0, s.top() + 25.0, 25.0));
+        assert_eq!((r1.left(), r1.top(), r1.width(), r1.height()), (table.left(), s.top(), table.width(), 25.0));
+
+        // Body cells are centered by default.
+        assert_eq!(border_box(&tab, "mi").top(), m.top() + 7.5);
+        assert_eq!(border_box(&tab, "ni").bottom(), n.bottom());
+    }
+
+    #[test]
+    fn empty_and_caption_only_tables_are_laid_out() {
+        let html = "<table id=empty></table><table id=row><tr id=r></tr></table>
+            <table id=captioned><caption id=cap>x</caption></table>";
+        let css = "table { border-spacing: 4px; } #cap { height: 15px; }";
+        let tab = laid_out_tab(html, css);
+        assert_eq!(border_box(&tab, "empty").size(), Vec2::ZERO);
+        // A row without cells has spacing around it, but no width.
+        let (row, r) = (border_box(&tab, "row"), border_box(&tab, "r"));
+        assert_eq!((r.width(), r.height()), (0.0, 0.0));
+        assert_eq!((r.top(), row.height()), (row.top() + 4.0, 8.0));
+        let (captioned, cap) = (border_box(&tab, "captioned"), border_box(&tab, "cap"));
+        assert_eq!((cap.top(), captioned.height()), (captioned.top(), 15.0));
+        assert_eq!(cap.width(), captioned.width());
+    }
+
+    #[test]
+    fn table_borders_collapse_and_cells_are_hit() {
+        let html = "<table id=t><tr><td id=a><div></div></td><td id=b><div></div></td></tr></table>";
+        let css = "#t { border-collapse: collapse; } td { padding: 0; border: 2px solid black; }
+            #b { padding: 5px; border-width: 4px; background-color: red; } div { width: 20px; height: 10px; }";
+        let tab = laid_out_tab(html, css);
+
+        // The cells share the wider border between them, and there is no spacing.
+        let table = border_box(&tab, "t");
+        let (a, b) = (border_box(&tab, "a"), border_box(&tab, "b"));
+        assert_eq!((a.left(), a.top(), a.width()), (table.left(), table.top(), 26.0));
+        assert_eq!((b.left(), b.width(), b.height()), (a.right(), 24.0 + 10.0, 28.0));
+        assert_eq!(a.height(), b.height());
+        assert_eq!((table.width(), table.height()), (60.0, 28.0));
+        assert!(tab.draw_commands.iter().any(|cmd| matches!(cmd, DrawCommand::DrawRect(rect) if rect.rect == b)));
+
+        let tab = Arc::new(RwLock::new(tab));
+        let hit = Tab::hit_test(tab.clone(), b.min + Vec2::new(2.0, 6.0)).unwrap();
+        let b_element = element_by_id(tab.read().unwrap().nodes.as_ref().unwrap(), "b");
+        assert!(Arc::ptr_eq(&hit, &b_element));
+    }
+}
@@ -8,6 +8,10 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use native_tls::TlsConnector;
 
+/// Cookies by host: the `name=value` pair sent back to the server, and the cookie's
+/// parameters (such as `httponly` or `samesite`).
+pub type CookieJar = Arc<RwLock<HashMap<String, (String, HashMap<String, String>)>>>;
+
 /// Represents a decomposed HTTP URL.
 ///
 /// This structure holds the individual components of a URL after it has been
@@ -29,6 +33,83 @@ pub struct Response {
     pub content: String
 }
 
+/// A response whose body is still being received, returned by [`Url::request_stream`].
+pub struct ResponseStream {
+    pub headers: HashMap<String, String>,
+    reader: BufReader<Box<dyn ReadWrite>>,
+    /// Bytes of a UTF-8 sequence that was cut off at the end of the last read.
+    pending: Vec<u8>,
+    done: bool,
+}
+
+/// How many bytes of the body `ResponseStream::next_chunk` reads at a time.
+const CHUNK_SIZE: usize = 8192;
+
+impl ResponseStream {
+    /// Reads the rest of the body as raw bytes, for binary resources such as images.
+    pub fn into_bytes(mut self) -> Result<Vec<u8>, String> {
+        let mut bytes = std::mem::take(&mut self.pending);
+        if !self.done {
+            self.reader.read_to_end(&mut bytes).map_err(|error| error.to_string())?;
+        }
+        Ok(bytes)
+    }
+
+    /// Reads the next chunk of the body, blocking until some of it has arrived.
+    ///
+    /// A multi-byte UTF-8 character that is split between two reads is held back until
+    /// the rest of it arrives, and invalid UTF-8 is replaced with U+FFFD.
+    ///
+    /// # Returns
+    /// * `Ok(Some(chunk))` - The next part of the body.
+    /// * `Ok(None)` - The server has closed the connection; the body is complete.
+    /// * `Err(String)` - If reading from the connection fails.
+    pub fn next_chunk(&mut self) -> Result<Option<String>, String> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut buf = [0u8; CHUNK_SIZE];
+        let read = self.reader.read(&mut buf).map_err(|error| error.to_string())?;
+        if read == 0 {
+            self.done = true;
+            if self.pending.is_empty() {
+                return Ok(None);
+            }
+            let rest = String::from_utf8_lossy(&self.pending).into_owned();
+            self.pending.clear();
+            return Ok(Some(rest));
+        }
+        self.pending.extend_from_slice(&buf[..read]);
+
+        let mut chunk = String::new();
+        loop {
+            match std::str::from_utf8(&self.pending) {
+                Ok(text) => {
+                    chunk.push_str(text);
+                    self.pending.clear();
+                    break;
+                }
+                Err(error) => {
+                    let valid = error.valid_up_to();
+                    chunk.push_str(&String::from_utf8_lossy(&self.pending[..valid]));
+                    match error.error_len() {
+                        Some(len) => {
+                            chunk.push('\u{FFFD}');
+                            self.pending.drain(..valid + len);
+                        }
+                        None => {
+                            // An incomplete sequence at the end: wait for the next read.
+                            self.pending.drain(..valid);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Some(chunk))
+    }
+}
+
 
 impl Url {
     /// Fully parses a URL string into a structured `Url` object.
@@ -105,7 +186,26 @@ impl Url {
     /// # Returns
     /// * `Ok(String)` - The decrypted response body.
     /// * `Err(String)` - If the connection, TLS handshake, or parsing fails.
-    pub fn request(&self, body: Option<String>, cookie_jar: Arc<RwLock<HashMap<String, (String, HashMap<String, String>)>>>) -> Result<Response, String> {
+    pub fn request(&self, body: Option<String>, cookie_jar: CookieJar) -> Result<Response, String> {
+        let mut stream = self.request_stream(body, cookie_jar)?;
+        let mut content = String::new();
+        while let Some(chunk) = stream.next_chunk()? {
+            content.push_str(&chunk);
+        }
+        Ok(Response {
+            headers: stream.headers,
+            content,
+        })
+    }
+
+    /// Sends a request like [`Url::request`], but returns as soon as the response headers
+    /// have been read. The body can then be read chunk by chunk, as it arrives, with
+    /// [`ResponseStream::next_chunk`].
+    ///
+    /// # Returns
+    /// * `Ok(ResponseStream)` - The response headers and a reader for the body.
+    /// * `Err(String)` - If the connection, TLS handshake, or header parsing fails.
+    pub fn request_stream(&self, body: Option<String>, cookie_jar: CookieJar) -> Result<ResponseStream, String> {
         // Connect to the host on port 80
         if let Ok(tcp_stream) = TcpStream::connect(format!("{}:{}", self.host, self.port)) {
             let mut stream: Box<dyn ReadWrite> = if self.scheme == "https" {
@@ -201,15 +301,11 @@ impl Url {
                 return Err("Unsupported transfer or content encoding".to_string());
             }
 
-
-            let mut buf = String::new();
-            reader
-                .read_to_string(&mut buf)
-                .map_err(|error| error.to_string())?;
-
-            Ok(Response {
+            Ok(ResponseStream {
                 headers: response_headers,
-                content: buf,
+                reader,
+                pending: Vec::new(),
+                done: false,
             })
         } else {
             Err(format!("Failed to connect to host {}", self.host).to_string())
@@ -261,5 +357,7 @@ impl Url {
     }
 }
 
-trait ReadWrite: Read + Write + Debug {}
-impl<T: Read + Write + Debug> ReadWrite for T {}
+/// A connection, plain or TLS. It is `Send` so that a `ResponseStream` can be read on
+/// another thread while the page is parsed.
+trait ReadWrite: Read + Write + Debug + Send {}
+impl<T: Read + Write + Debug + Send> ReadWrite for T {}